@@ -2,6 +2,7 @@ package keepers
 
 import (
 	"path/filepath"
+	"time"
 
 	"github.com/cosmos/cosmos-sdk/baseapp"
 	"github.com/cosmos/cosmos-sdk/codec"
@@ -73,6 +74,9 @@ import (
 	ibchookstypes "github.com/scrtlabs/SecretNetwork/x/ibc-hooks/types"
 )
 
+// enclaveMetricsInterval is how often the runtime metrics of the enclave are reported
+const enclaveMetricsInterval = 10 * time.Second
+
 type SecretAppKeepers struct {
 	// keepers
 	AccountKeeper    *authkeeper.AccountKeeper
@@ -425,7 +429,7 @@ func (ak *SecretAppKeepers) InitCustomKeepers(
 	computeDir := filepath.Join(homePath, ".compute")
 	// The last arguments can contain custom message handlers, and custom query handlers,
 	// if we want to allow any custom callbacks
-	supportedFeatures := "staking,stargate,ibc3,random"
+	supportedFeatures := "staking,stargate,ibc3,random,padding,nan_canonicalization,softfloat"
 
 	computeKeeper := compute.NewKeeper(
 		appCodec,
@@ -454,6 +458,7 @@ func (ak *SecretAppKeepers) InitCustomKeepers(
 	)
 	ak.ComputeKeeper = &computeKeeper
 	wasmHooks.ContractKeeper = ak.ComputeKeeper
+	computeKeeper.StartEnclaveMetricsReporter(app.Logger(), enclaveMetricsInterval)
 
 	// Compute receive: Switch -> Fee -> Packet Forward -> WASM Hooks
 	var computeStack porttypes.IBCModule
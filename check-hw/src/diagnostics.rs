@@ -0,0 +1,95 @@
+//! Probes of the host platform, that don't need the enclave
+
+use std::arch::x86_64::__cpuid_count;
+use std::path::Path;
+
+use serde::Serialize;
+use sgx_types::{sgx_qe_get_target_info, sgx_quote3_error_t, sgx_target_info_t};
+
+const CPUID_SGX_LEAF: u32 = 0x12;
+/// EPC sections are enumerated from this subleaf of the SGX leaf
+const CPUID_SGX_EPC_SUBLEAF: u32 = 2;
+const CPUID_EPC_SECTION_VALID: u32 = 1;
+
+/// Device nodes of the in-kernel driver, and of the legacy out-of-tree driver
+const SGX_DEVICES: &[&str] = &["/dev/sgx_enclave", "/dev/sgx/enclave", "/dev/isgx"];
+
+#[derive(Serialize, Debug, Default)]
+pub struct SgxInfo {
+    /// The CPU reports SGX support. It may still be disabled in the BIOS
+    pub supported: bool,
+    pub sgx1: bool,
+    pub sgx2: bool,
+    /// Flexible launch control, which the DCAP driver needs
+    pub flc: bool,
+    /// Total size of the EPC, in bytes
+    pub epc_size: u64,
+    pub device: Option<String>,
+}
+
+impl SgxInfo {
+    pub fn available(&self) -> bool {
+        self.supported && self.device.is_some()
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct DcapInfo {
+    /// The quoting enclave could be loaded, so DCAP quotes can be generated
+    pub available: bool,
+    pub error: Option<String>,
+}
+
+pub fn sgx_info() -> SgxInfo {
+    let mut info = SgxInfo {
+        device: SGX_DEVICES
+            .iter()
+            .find(|device| Path::new(device).exists())
+            .map(|device| device.to_string()),
+        ..Default::default()
+    };
+
+    let max_leaf = unsafe { __cpuid_count(0, 0) }.eax;
+    if max_leaf < CPUID_SGX_LEAF {
+        return info;
+    }
+
+    let features = unsafe { __cpuid_count(7, 0) };
+    info.supported = features.ebx & (1 << 2) != 0;
+    info.flc = features.ecx & (1 << 30) != 0;
+    if !info.supported {
+        return info;
+    }
+
+    let capabilities = unsafe { __cpuid_count(CPUID_SGX_LEAF, 0) };
+    info.sgx1 = capabilities.eax & 1 != 0;
+    info.sgx2 = capabilities.eax & (1 << 1) != 0;
+
+    for subleaf in CPUID_SGX_EPC_SUBLEAF.. {
+        let section = unsafe { __cpuid_count(CPUID_SGX_LEAF, subleaf) };
+        if section.eax & 0xf != CPUID_EPC_SECTION_VALID {
+            break;
+        }
+        info.epc_size +=
+            (section.ecx & 0xffff_f000) as u64 | ((section.edx & 0x000f_ffff) as u64) << 32;
+    }
+
+    info
+}
+
+pub fn dcap_info() -> DcapInfo {
+    let mut target_info = sgx_target_info_t::default();
+    let res = unsafe { sgx_qe_get_target_info(&mut target_info) };
+
+    if res == sgx_quote3_error_t::SGX_QL_SUCCESS {
+        DcapInfo {
+            available: true,
+            error: None,
+        }
+    } else {
+        DcapInfo {
+            available: false,
+            error: Some(res.to_string()),
+        }
+    }
+}
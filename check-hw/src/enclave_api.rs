@@ -3,6 +3,9 @@ use std::{
     os::unix::prelude::IntoRawFd,
 };
 
+use lazy_static::lazy_static;
+use sgx_attestation::{collateral_cache::CollateralConfig, ocalls};
+
 use enclave_ffi_types::{
     Ctx, EnclaveBuffer, NodeAuthResult, OcallReturn, UntrustedVmError, UserSpaceBuffer,
 };
@@ -13,7 +16,9 @@ use sgx_types::{
     sgx_report_t, sgx_spid_t, sgx_status_t, sgx_target_info_t, sgx_update_info_bit_t,
 };
 
-include!("../../cosmwasm/packages/sgx-vm/src/attestation_dcap.rs");
+lazy_static! {
+    pub static ref COLLATERAL_CONFIG: CollateralConfig = CollateralConfig::from_env();
+}
 
 // ecalls
 
@@ -23,6 +28,9 @@ extern "C" {
         retval: *mut NodeAuthResult,
         api_key: *const u8,
         api_key_len: u32,
+        report: *mut u8,
+        report_len: u32,
+        report_written: *mut u32,
     ) -> sgx_status_t;
 }
 
@@ -115,6 +123,78 @@ pub fn lookup_ipv4(host: &str, port: u16) -> SocketAddr {
     unreachable!("Cannot lookup address");
 }
 
+#[no_mangle]
+pub extern "C" fn ocall_get_quote_ecdsa_params(
+    p_qe_info: *mut sgx_target_info_t,
+    p_quote_size: *mut u32,
+) -> sgx_status_t {
+    unsafe { ocalls::get_quote_ecdsa_params(p_qe_info, p_quote_size) }
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_get_quote_ecdsa(
+    p_report: *const sgx_report_t,
+    p_quote: *mut u8,
+    n_quote: u32,
+) -> sgx_status_t {
+    unsafe { ocalls::get_quote_ecdsa(p_report, p_quote, n_quote) }
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_get_quote_ecdsa_collateral(
+    p_quote: *const u8,
+    n_quote: u32,
+    p_col: *mut u8,
+    n_col: u32,
+    p_col_size: *mut u32,
+) -> sgx_status_t {
+    unsafe {
+        ocalls::get_quote_ecdsa_collateral(
+            &COLLATERAL_CONFIG,
+            p_quote,
+            n_quote,
+            p_col,
+            n_col,
+            p_col_size,
+        )
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_verify_quote_ecdsa(
+    p_quote: *const u8,
+    n_quote: u32,
+    p_col: *const u8,
+    n_col: u32,
+    p_target_info: *const sgx_target_info_t,
+    time_s: i64,
+    p_qve_report_info: *mut sgx_ql_qe_report_info_t,
+    p_supp_data: *mut u8,
+    n_supp_data: u32,
+    p_supp_data_size: *mut u32,
+    p_time_s: *mut i64,
+    p_collateral_expiration_status: *mut u32,
+    p_qv_result: *mut sgx_ql_qv_result_t,
+) -> sgx_status_t {
+    unsafe {
+        ocalls::verify_quote_ecdsa(
+            p_quote,
+            n_quote,
+            p_col,
+            n_col,
+            p_target_info,
+            time_s,
+            p_qve_report_info,
+            p_supp_data,
+            n_supp_data,
+            p_supp_data_size,
+            p_time_s,
+            p_collateral_expiration_status,
+            p_qv_result,
+        )
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn ocall_sgx_init_quote(
     ret_ti: *mut sgx_target_info_t,
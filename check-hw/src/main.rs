@@ -1,18 +1,27 @@
+mod diagnostics;
 mod enclave;
 mod enclave_api;
+mod report;
 mod types;
 
+use std::process;
+
 use clap::App;
 use lazy_static::lazy_static;
 use sgx_types::sgx_status_t;
 
-use crate::{enclave_api::ecall_check_patch_level, types::EnclaveDoorbell};
+use crate::{
+    enclave_api::{ecall_check_patch_level, COLLATERAL_CONFIG},
+    report::{CheckHwReport, Outcome, StdoutToStderr},
+    types::EnclaveDoorbell,
+};
 
 use enclave_ffi_types::NodeAuthResult;
 
 const ENCLAVE_FILE_TESTNET: &str = "check_hw_enclave_testnet.so";
 const ENCLAVE_FILE_MAINNET: &str = "check_hw_enclave.so";
 const TCS_NUM: u8 = 1;
+const PATCH_LEVEL_REPORT_MAX_LEN: usize = 16 * 1024;
 
 lazy_static! {
     static ref ENCLAVE_DOORBELL: EnclaveDoorbell = {
@@ -35,9 +44,41 @@ fn main() {
                 .long("testnet")
                 .help("Run in testnet mode"),
         )
+        .arg(
+            clap::Arg::with_name("json")
+                .long("json")
+                .help("Print a machine readable report to stdout. Everything else goes to stderr"),
+        )
         .get_matches();
 
     let is_testnet = matches.is_present("testnet");
+    let json = matches.is_present("json");
+
+    let mut report = CheckHwReport::new(is_testnet);
+    let outcome = {
+        let _redirect = if json {
+            Some(StdoutToStderr::new())
+        } else {
+            None
+        };
+        check_hw(is_testnet, &mut report)
+    };
+    report.set_outcome(outcome);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    }
+
+    process::exit(outcome as i32);
+}
+
+fn check_hw(is_testnet: bool, report: &mut CheckHwReport) -> Outcome {
+    report.sgx = diagnostics::sgx_info();
+    report.dcap = diagnostics::dcap_info();
+
+    if let Err(e) = sgx_attestation::collateral_cache::configure_pccs(&COLLATERAL_CONFIG) {
+        println!("Failed to configure the PCCS for DCAP collateral: {}", e);
+    }
 
     println!("Creating enclave instance..");
 
@@ -50,18 +91,33 @@ fn main() {
             "Failed to get enclave access token: {:?} (is enclave currently running or busy?)",
             e
         );
-        return;
+        report.enclave.error = Some(e.to_string());
+        return Outcome::EnclaveLaunchFailed;
     }
 
     let enclave = enclave_access_token.unwrap().enclave;
 
     if let Err(e) = enclave {
         println!("Failed to start enclave: {:?}", e);
-        return;
+        report.enclave.error = Some(e.to_string());
+        return if report.sgx.available() {
+            Outcome::EnclaveLaunchFailed
+        } else {
+            Outcome::SgxUnavailable
+        };
     }
+    report.enclave.launched = true;
+
+    // an enclave built with dcap-only never contacts IAS, so it doesn't need the API key
+    #[cfg(feature = "dcap-only")]
+    let api_key_bytes: &[u8] = {
+        let _ = is_testnet;
+        &[]
+    };
 
+    #[cfg(not(feature = "dcap-only"))]
     #[allow(clippy::if_same_then_else)]
-    let api_key_bytes = if is_testnet {
+    let api_key_bytes: &[u8] = if is_testnet {
         include_bytes!("../../ias_keys/develop/api_key.txt")
     } else {
         include_bytes!("../../ias_keys/production/api_key.txt")
@@ -69,12 +125,17 @@ fn main() {
 
     let eid = enclave.unwrap().geteid();
     let mut retval = NodeAuthResult::Success;
+    let mut patch_level = vec![0u8; PATCH_LEVEL_REPORT_MAX_LEN];
+    let mut patch_level_len: u32 = 0;
     let status = unsafe {
         ecall_check_patch_level(
             eid,
             &mut retval,
             api_key_bytes.as_ptr(),
             api_key_bytes.len() as u32,
+            patch_level.as_mut_ptr(),
+            patch_level.len() as u32,
+            &mut patch_level_len,
         )
     };
 
@@ -82,7 +143,14 @@ fn main() {
         println!(
             "Failed to run hardware verification test (is the correct enclave in the correct path?)"
         );
-        return;
+        return Outcome::CheckFailed;
+    }
+
+    if (patch_level_len as usize) <= patch_level.len() {
+        if let Ok(patch_level) = serde_json::from_slice(&patch_level[..patch_level_len as usize]) {
+            print_tcb_report(&patch_level);
+            report.set_patch_level(patch_level);
+        }
     }
 
     if retval != NodeAuthResult::Success {
@@ -93,4 +161,30 @@ fn main() {
     } else {
         println!("Platform verification successful! You are able to run a mainnet Secret node")
     }
+
+    Outcome::from(retval)
+}
+
+fn print_tcb_report(patch_level: &serde_json::Value) {
+    let report = &patch_level["dcap"]["platform"];
+    if report.is_null() {
+        return;
+    }
+
+    let tcb_status = report["tcb_status"].as_str().unwrap_or("Unknown");
+    match report["tcb_date"].as_str() {
+        Some(date) => println!("DCAP TCB status: {} ({})", tcb_status, date),
+        None => println!("DCAP TCB status: {}", tcb_status),
+    }
+
+    if let Some(ids) = report["advisory_ids"].as_array() {
+        if !ids.is_empty() {
+            let ids: Vec<&str> = ids.iter().filter_map(|id| id.as_str()).collect();
+            println!("DCAP advisory IDs: {}", ids.join(", "));
+        }
+    }
+
+    if report["collateral_expired"] == true {
+        println!("WARNING: DCAP collateral has expired, check your PCCS");
+    }
 }
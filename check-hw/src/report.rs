@@ -0,0 +1,168 @@
+//! The machine readable output of `check-hw --json`, and the exit codes shared with the human
+//! readable output
+
+use std::io::Write;
+
+use enclave_ffi_types::NodeAuthResult;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::diagnostics::{DcapInfo, SgxInfo};
+
+/// The exit code of `check-hw`, from best to worst
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The platform can run a node
+    Success = 0,
+    /// The platform attested, but it needs an update or a configuration change
+    PlatformOutOfDate = 1,
+    /// The platform could not attest
+    AttestationFailed = 2,
+    /// SGX is not supported, or not enabled
+    SgxUnavailable = 3,
+    /// The enclave could not be launched, even though SGX looks available
+    EnclaveLaunchFailed = 4,
+    /// The enclave was launched, but the check could not be run in it
+    CheckFailed = 5,
+}
+
+impl From<NodeAuthResult> for Outcome {
+    fn from(result: NodeAuthResult) -> Self {
+        match result {
+            NodeAuthResult::Success => Outcome::Success,
+            NodeAuthResult::GroupOutOfDate
+            | NodeAuthResult::ConfigurationNeeded
+            | NodeAuthResult::SwHardeningAndConfigurationNeeded => Outcome::PlatformOutOfDate,
+            _ => Outcome::AttestationFailed,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct EnclaveInfo {
+    pub launched: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckHwReport {
+    pub network: &'static str,
+    pub outcome: Outcome,
+    pub exit_code: i32,
+    pub sgx: SgxInfo,
+    pub dcap: DcapInfo,
+    pub enclave: EnclaveInfo,
+    /// `NodeAuthResult` of the check in the enclave
+    pub result: Option<String>,
+    /// The attestation method that succeeded, `dcap` or `epid`
+    pub quote_type: Option<&'static str>,
+    pub tcb_status: Option<String>,
+    pub advisory_ids: Vec<String>,
+    /// Steps of the check in the enclave that failed, e.g. `dcap.get_quote`
+    pub failed_steps: Vec<String>,
+    /// The full `PatchLevelReport` written by `ecall_check_patch_level`
+    pub patch_level: Option<Value>,
+}
+
+impl CheckHwReport {
+    pub fn new(is_testnet: bool) -> Self {
+        Self {
+            network: if is_testnet { "testnet" } else { "mainnet" },
+            outcome: Outcome::CheckFailed,
+            exit_code: Outcome::CheckFailed as i32,
+            sgx: SgxInfo::default(),
+            dcap: DcapInfo::default(),
+            enclave: EnclaveInfo::default(),
+            result: None,
+            quote_type: None,
+            tcb_status: None,
+            advisory_ids: vec![],
+            failed_steps: vec![],
+            patch_level: None,
+        }
+    }
+
+    pub fn set_outcome(&mut self, outcome: Outcome) {
+        self.outcome = outcome;
+        self.exit_code = outcome as i32;
+    }
+
+    /// Fills in the summary fields from the report written by the enclave
+    pub fn set_patch_level(&mut self, patch_level: Value) {
+        let dcap = &patch_level["dcap"];
+        let epid = &patch_level["epid"];
+
+        self.result = patch_level["result"].as_str().map(|s| s.to_string());
+
+        for (name, check) in [("dcap", dcap), ("epid", epid)] {
+            if let Some(step) = check["failed_step"].as_str() {
+                self.failed_steps.push(format!("{}.{}", name, step));
+            }
+        }
+
+        self.quote_type = if dcap["result"] == "Success" {
+            Some("dcap")
+        } else if epid["result"] == "Success" {
+            Some("epid")
+        } else {
+            None
+        };
+
+        // the TCB of the method that was used, or of whichever got far enough to tell
+        let use_epid = self.quote_type == Some("epid") || dcap["platform"].is_null();
+        if use_epid && !epid["platform"].is_null() {
+            self.tcb_status = epid["platform"]["quote_status"]
+                .as_str()
+                .map(|s| s.to_string());
+            self.advisory_ids = string_array(&epid["platform"]["advisory_ids"]);
+        } else if !dcap["platform"].is_null() {
+            self.tcb_status = dcap["platform"]["tcb_status"]
+                .as_str()
+                .map(|s| s.to_string());
+            self.advisory_ids = string_array(&dcap["platform"]["advisory_ids"]);
+        }
+
+        self.patch_level = Some(patch_level);
+    }
+}
+
+fn string_array(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Sends everything written to stdout to stderr while alive, including output of the enclave, so
+/// stdout only has the JSON report
+pub struct StdoutToStderr {
+    saved_stdout: libc::c_int,
+}
+
+impl StdoutToStderr {
+    pub fn new() -> Self {
+        let _ = std::io::stdout().flush();
+        let saved_stdout = unsafe {
+            let saved_stdout = libc::dup(libc::STDOUT_FILENO);
+            libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO);
+            saved_stdout
+        };
+        Self { saved_stdout }
+    }
+}
+
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        unsafe {
+            libc::dup2(self.saved_stdout, libc::STDOUT_FILENO);
+            libc::close(self.saved_stdout);
+        }
+    }
+}
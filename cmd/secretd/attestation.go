@@ -32,6 +32,7 @@ const (
 	flagCustomRegistrationService = "registration-service"
 	flag_no_epid                  = "no-epid"
 	flag_no_dcap                  = "no-dcap"
+	flagPinCpuSvn                 = "pin-cpusvn"
 )
 
 const (
@@ -295,6 +296,72 @@ func MigrateSealings() *cobra.Command {
 	return cmd
 }
 
+func ResealSecrets() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "reseal_secrets [mrenclave|mrsigner]",
+		Short: "Re-seal the enclave secrets with a new sealing policy",
+		Long: `Re-seal every SGX-sealed file with a fresh key, derived from either the enclave measurement
+(mrenclave) or its signer (mrsigner). Files sealed with mrenclave can only be read by this exact
+enclave build, so they must be re-sealed with mrsigner before upgrading. The previous version of
+each file is kept with a .bkp suffix`,
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			var keyPolicy uint16
+			switch args[0] {
+			case "mrenclave":
+				keyPolicy = api.KeyPolicyMrEnclave
+			case "mrsigner":
+				keyPolicy = api.KeyPolicyMrSigner
+			default:
+				return fmt.Errorf("unknown sealing policy %s, expected mrenclave or mrsigner", args[0])
+			}
+
+			pinCpuSvn, err := cmd.Flags().GetBool(flagPinCpuSvn)
+			if err != nil {
+				return err
+			}
+
+			_, err = api.ResealSecrets(keyPolicy, pinCpuSvn)
+			if err != nil {
+				return fmt.Errorf("failed to re-seal the enclave secrets: %s", err)
+			}
+
+			fmt.Printf("Re-sealing succeeded\n")
+			return nil
+		},
+	}
+
+	cmd.Flags().Bool(flagPinCpuSvn, false, "Bind the sealing key to the current CPU microcode version, so "+
+		"the secrets are lost if the microcode is rolled back")
+
+	return cmd
+}
+
+func ListSealedFiles() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "list_sealed_files",
+		Short: "List the enclave sealed files and how they are sealed",
+		Long:  "Print a JSON list of every SGX-sealed file, with its format version and sealing policy",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			list, err := api.ListSealedFiles()
+			if err != nil {
+				return fmt.Errorf("failed to list the sealed files: %s", err)
+			}
+
+			var out bytes.Buffer
+			if err := json.Indent(&out, list, "", "  "); err != nil {
+				return err
+			}
+
+			fmt.Println(out.String())
+			return nil
+		},
+	}
+
+	return cmd
+}
+
 func ConfigureSecret() *cobra.Command {
 	cmd := &cobra.Command{
 		Use: "configure-secret [master-key] [seed]",
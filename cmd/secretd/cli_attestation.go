@@ -93,6 +93,34 @@ func MigrateSealings() *cobra.Command {
 	return cmd
 }
 
+func ResealSecrets() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "reseal_secrets [mrenclave|mrsigner]",
+		Short: "Re-seal the enclave secrets with a new sealing policy",
+		Args:  cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func ListSealedFiles() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "list_sealed_files",
+		Short: "List the enclave sealed files and how they are sealed",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
 func ConfigureSecret() *cobra.Command {
 	cmd := &cobra.Command{
 		Use: "configure-secret [master-key] [seed]",
@@ -180,6 +180,8 @@ func initRootCmd(rootCmd *cobra.Command, encodingConfig app.EncodingConfig) {
 		ParseCert(),
 		DumpBin(),
 		MigrateSealings(),
+		ResealSecrets(),
+		ListSealedFiles(),
 		ConfigureSecret(),
 		HealthCheck(),
 		ResetEnclave(),
@@ -0,0 +1,328 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use enclave_crypto::ed25519::Ed25519PrivateKey;
+use enclave_crypto::{AESKey, Ed25519PublicKey, Kdf, KeyPair, SIVEncryptable, SECRET_KEY_SIZE};
+
+use crate::errors::ClientError;
+use crate::message::{normalize_code_hash, unpad, DecryptedMessage};
+
+pub type IoNonce = [u8; 32];
+
+/// `nonce | user_public_key | ciphertext`, where the ciphertext has at least the SIV tag and a code
+/// hash
+const MIN_MESSAGE_SIZE: usize = 32 + 32 + 16 + 2;
+
+/// Encrypts messages to the enclave and decrypts its outputs with an x25519 tx encryption key.
+///
+/// Every message gets a fresh nonce, and the outputs of a message are encrypted with the key
+/// derived for its nonce, so keep the nonce of a message around to decrypt what it returns.
+pub struct EncryptionUtils {
+    key_pair: KeyPair,
+    io_exchange_pubkey: Ed25519PublicKey,
+}
+
+impl EncryptionUtils {
+    /// Uses a random tx encryption key. `io_exchange_pubkey` is the consensus IO exchange key of
+    /// the chain, as registered in `x/registration`.
+    pub fn new(io_exchange_pubkey: Ed25519PublicKey) -> Result<Self, ClientError> {
+        let key_pair = KeyPair::new().map_err(|_| ClientError::RandomError)?;
+
+        Ok(Self {
+            key_pair,
+            io_exchange_pubkey,
+        })
+    }
+
+    /// Uses the tx encryption key made from `seed`, so the outputs of past txs can be decrypted
+    /// again later
+    pub fn from_seed(io_exchange_pubkey: Ed25519PublicKey, seed: &[u8; SECRET_KEY_SIZE]) -> Self {
+        let mut secret_key = Ed25519PrivateKey::default();
+        secret_key.get_mut().copy_from_slice(seed);
+
+        Self {
+            key_pair: KeyPair::from(secret_key),
+            io_exchange_pubkey,
+        }
+    }
+
+    /// The public tx encryption key, which is sent along with every message
+    pub fn pubkey(&self) -> Ed25519PublicKey {
+        self.key_pair.get_pubkey()
+    }
+
+    /// The key the message with `nonce` and its outputs are encrypted with. This is the key the
+    /// enclave derives in `calc_encryption_key`, and the key a disclosure permit discloses.
+    pub fn encryption_key(&self, nonce: &IoNonce) -> AESKey {
+        let tx_encryption_ikm = self.key_pair.diffie_hellman(&self.io_exchange_pubkey);
+
+        AESKey::new_from_slice(&tx_encryption_ikm).derive_key_from_this(nonce)
+    }
+
+    /// Encrypts `msg` for the contract with `code_hash` with a random nonce. Returns
+    /// `nonce | pubkey | ciphertext`, which is what goes in the `msg` field of
+    /// `MsgExecuteContract`, `MsgInstantiateContract` and `QuerySecretContractRequest`.
+    pub fn encrypt(&self, code_hash: &str, msg: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut nonce = IoNonce::default();
+        getrandom::getrandom(&mut nonce).map_err(|_| ClientError::RandomError)?;
+
+        self.encrypt_with_nonce(&nonce, code_hash, msg)
+    }
+
+    pub fn encrypt_with_nonce(
+        &self,
+        nonce: &IoNonce,
+        code_hash: &str,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        let mut plaintext = normalize_code_hash(code_hash)?.into_bytes();
+        plaintext.extend_from_slice(msg);
+
+        let ciphertext = self
+            .encryption_key(nonce)
+            .encrypt_siv(&plaintext, None)
+            .map_err(|_| ClientError::EncryptionError)?;
+
+        let mut secret_msg = nonce.to_vec();
+        secret_msg.extend_from_slice(&self.pubkey());
+        secret_msg.extend_from_slice(&ciphertext);
+
+        Ok(secret_msg)
+    }
+
+    /// Decrypts a message encrypted with this key, like the submessages a contract sent in a tx
+    /// of ours
+    pub fn decrypt_message(&self, secret_msg: &[u8]) -> Result<DecryptedMessage, ClientError> {
+        let (nonce, ciphertext) = split_secret_msg(secret_msg)?;
+        let plaintext = decrypt(&self.encryption_key(&nonce), ciphertext)?;
+
+        DecryptedMessage::parse(&plaintext)
+    }
+
+    /// See `decrypt`
+    pub fn decrypt(&self, nonce: &IoNonce, ciphertext: &[u8]) -> Result<Vec<u8>, ClientError> {
+        decrypt(&self.encryption_key(nonce), ciphertext)
+    }
+
+    /// See `decrypt_data`
+    pub fn decrypt_data(&self, nonce: &IoNonce, ciphertext: &[u8]) -> Result<Vec<u8>, ClientError> {
+        decrypt_data(&self.encryption_key(nonce), ciphertext)
+    }
+
+    /// See `decrypt_attribute`
+    pub fn decrypt_attribute(
+        &self,
+        nonce: &IoNonce,
+        key: &str,
+        value: &str,
+    ) -> Option<(String, String)> {
+        decrypt_attribute(&self.encryption_key(nonce), key, value)
+    }
+
+    /// See `decrypt_error`
+    pub fn decrypt_error(&self, nonce: &IoNonce, error: &str) -> Result<String, ClientError> {
+        decrypt_error(&self.encryption_key(nonce), error)
+    }
+}
+
+/// The nonce a message was encrypted with, which its outputs are encrypted with as well
+pub fn message_nonce(secret_msg: &[u8]) -> Result<IoNonce, ClientError> {
+    split_secret_msg(secret_msg).map(|(nonce, _)| nonce)
+}
+
+fn split_secret_msg(secret_msg: &[u8]) -> Result<(IoNonce, &[u8]), ClientError> {
+    if secret_msg.len() < MIN_MESSAGE_SIZE {
+        return Err(ClientError::MalformedMessage);
+    }
+
+    let mut nonce = IoNonce::default();
+    nonce.copy_from_slice(&secret_msg[..32]);
+
+    Ok((nonce, &secret_msg[64..]))
+}
+
+/// Decrypts an output of the enclave, and strips its padding if the contract pads its outputs
+pub fn decrypt(key: &AESKey, ciphertext: &[u8]) -> Result<Vec<u8>, ClientError> {
+    if ciphertext.is_empty() {
+        return Ok(vec![]);
+    }
+
+    key.decrypt_siv(ciphertext, None)
+        .map(unpad)
+        .map_err(|_| ClientError::DecryptionError)
+}
+
+/// Decrypts the `data` of a tx response, or the response of a query. The enclave encrypts these
+/// as base64, so this decodes them as well.
+pub fn decrypt_data(key: &AESKey, ciphertext: &[u8]) -> Result<Vec<u8>, ClientError> {
+    let plaintext = decrypt(key, ciphertext)?;
+
+    BASE64
+        .decode(plaintext)
+        .map_err(|_| ClientError::InvalidBase64)
+}
+
+/// Decrypts a base64 encoded ciphertext that holds a string, like encrypted attributes do
+pub fn decrypt_string(key: &AESKey, ciphertext_b64: &str) -> Result<String, ClientError> {
+    let ciphertext = BASE64
+        .decode(ciphertext_b64)
+        .map_err(|_| ClientError::InvalidBase64)?;
+    let plaintext = decrypt(key, &ciphertext)?;
+
+    String::from_utf8(plaintext).map_err(|_| ClientError::InvalidUtf8)
+}
+
+/// Decrypts an encrypted attribute of a `wasm` event. Returns `None` for plaintext attributes,
+/// and for copies of encrypted attributes that were made for other recipients.
+///
+/// Recipients that a contract encrypted an attribute for decrypt it with their own tx encryption
+/// key and the nonce of the tx that emitted it.
+pub fn decrypt_attribute(
+    key: &AESKey,
+    attr_key: &str,
+    attr_value: &str,
+) -> Option<(String, String)> {
+    let attr_key = decrypt_string(key, attr_key).ok()?;
+    let attr_value = decrypt_string(key, attr_value).ok()?;
+
+    Some((attr_key, attr_value))
+}
+
+/// Decrypts the error of a failed tx or query, formatted by the chain like
+/// `encrypted: <base64>: <context>`. Returns the JSON encoded `StdError` of the contract.
+pub fn decrypt_error(key: &AESKey, error: &str) -> Result<String, ClientError> {
+    const PREFIX: &str = "encrypted: ";
+
+    let start = error.find(PREFIX).ok_or(ClientError::UnencryptedError)? + PREFIX.len();
+    let ciphertext_b64 = error[start..].split(':').next().unwrap_or_default();
+    if ciphertext_b64.is_empty() {
+        return Err(ClientError::UnencryptedError);
+    }
+
+    decrypt_string(key, ciphertext_b64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::PADDING_MAGIC;
+
+    const CODE_HASH: &str = "4e3b2dcf4ec1e2bf2a6f66cd3e2d49bb3f47ab6a1e1b0bd0c2d9e1dcc0e4a4a7";
+
+    /// Plays the enclave, which derives the same keys from its side of the exchange
+    fn enclave() -> KeyPair {
+        let mut secret_key = Ed25519PrivateKey::default();
+        secret_key.get_mut().copy_from_slice(&[7u8; 32]);
+        KeyPair::from(secret_key)
+    }
+
+    fn enclave_key(user_public_key: &Ed25519PublicKey, nonce: &IoNonce) -> AESKey {
+        let tx_encryption_ikm = enclave().diffie_hellman(user_public_key);
+        AESKey::new_from_slice(&tx_encryption_ikm).derive_key_from_this(nonce)
+    }
+
+    fn client() -> EncryptionUtils {
+        EncryptionUtils::from_seed(enclave().get_pubkey(), &[1u8; 32])
+    }
+
+    #[test]
+    fn enclave_decrypts_messages() {
+        let client = client();
+        let secret_msg = client.encrypt(CODE_HASH, b"{\"ping\":{}}").unwrap();
+
+        assert_eq!(&secret_msg[32..64], &client.pubkey()[..]);
+
+        let nonce = message_nonce(&secret_msg).unwrap();
+        let plaintext = enclave_key(&client.pubkey(), &nonce)
+            .decrypt_siv(&secret_msg[64..], None)
+            .unwrap();
+        assert_eq!(
+            plaintext,
+            format!("{}{{\"ping\":{{}}}}", CODE_HASH).into_bytes()
+        );
+
+        let decrypted = client.decrypt_message(&secret_msg).unwrap();
+        assert_eq!(decrypted.code_hash, CODE_HASH);
+        assert_eq!(decrypted.msg, b"{\"ping\":{}}".to_vec());
+    }
+
+    #[test]
+    fn same_seed_same_key() {
+        let nonce = [3u8; 32];
+        assert_eq!(
+            client().encryption_key(&nonce).get(),
+            client().encryption_key(&nonce).get()
+        );
+        assert_ne!(
+            client().encryption_key(&nonce).get(),
+            client().encryption_key(&[4u8; 32]).get()
+        );
+    }
+
+    #[test]
+    fn decrypts_outputs() {
+        let client = client();
+        let nonce = [3u8; 32];
+        let key = enclave_key(&client.pubkey(), &nonce);
+
+        // data and query responses are encrypted as base64
+        let data = key.encrypt_siv(b"eyJvayI6dHJ1ZX0=", None).unwrap();
+        assert_eq!(
+            client.decrypt_data(&nonce, &data).unwrap(),
+            b"{\"ok\":true}".to_vec()
+        );
+
+        let attr_key = BASE64.encode(key.encrypt_siv(b"action", None).unwrap());
+        let attr_value = BASE64.encode(key.encrypt_siv(b"transfer", None).unwrap());
+        assert_eq!(
+            client.decrypt_attribute(&nonce, &attr_key, &attr_value),
+            Some(("action".to_string(), "transfer".to_string()))
+        );
+        assert_eq!(client.decrypt_attribute(&nonce, "action", "transfer"), None);
+
+        let error = key
+            .encrypt_siv(b"{\"generic_err\":{\"msg\":\"nope\"}}", None)
+            .unwrap();
+        let error = format!(
+            "encrypted: {}: execute contract failed",
+            BASE64.encode(error)
+        );
+        assert_eq!(
+            client.decrypt_error(&nonce, &error).unwrap(),
+            "{\"generic_err\":{\"msg\":\"nope\"}}"
+        );
+        assert_eq!(
+            client.decrypt_error(&nonce, "out of gas"),
+            Err(ClientError::UnencryptedError)
+        );
+    }
+
+    #[test]
+    fn decrypts_padded_outputs() {
+        let client = client();
+        let nonce = [3u8; 32];
+        let key = enclave_key(&client.pubkey(), &nonce);
+
+        let mut padded = PADDING_MAGIC.to_vec();
+        padded.extend_from_slice(&4u32.to_be_bytes());
+        padded.extend_from_slice(b"e30=");
+        padded.resize(256, 0);
+
+        let data = key.encrypt_siv(&padded, None).unwrap();
+        assert_eq!(client.decrypt_data(&nonce, &data).unwrap(), b"{}".to_vec());
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let nonce = [3u8; 32];
+        let other = EncryptionUtils::from_seed(enclave().get_pubkey(), &[2u8; 32]);
+        let data = enclave_key(&client().pubkey(), &nonce)
+            .encrypt_siv(b"e30=", None)
+            .unwrap();
+
+        assert_eq!(
+            other.decrypt_data(&nonce, &data),
+            Err(ClientError::DecryptionError)
+        );
+    }
+}
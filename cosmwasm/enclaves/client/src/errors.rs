@@ -0,0 +1,28 @@
+use derive_more::Display;
+
+#[derive(Debug, Display, PartialEq, Eq)]
+pub enum ClientError {
+    /// Couldn't generate a key or a nonce
+    #[display(fmt = "failed to generate randomness")]
+    RandomError,
+    #[display(fmt = "failed to encrypt the message")]
+    EncryptionError,
+    /// The ciphertext wasn't encrypted with this key, or was tampered with
+    #[display(fmt = "failed to decrypt the ciphertext")]
+    DecryptionError,
+    #[display(fmt = "invalid base64")]
+    InvalidBase64,
+    #[display(fmt = "invalid UTF-8")]
+    InvalidUtf8,
+    /// Code hashes are the hex encoded SHA-256 of the contract code
+    #[display(fmt = "invalid code hash")]
+    InvalidCodeHash,
+    /// The message is too short or the plaintext has malformed headers
+    #[display(fmt = "malformed message")]
+    MalformedMessage,
+    /// The error string doesn't contain an `encrypted: <base64>:` part
+    #[display(fmt = "the error is not encrypted")]
+    UnencryptedError,
+}
+
+impl std::error::Error for ClientError {}
@@ -0,0 +1,26 @@
+//! Encrypts messages to Secret contracts and decrypts their outputs, without SGX.
+//!
+//! This is the client side of the scheme the enclave implements in `io.rs`: a message is
+//! `nonce | user_public_key | AES-SIV(code_hash | msg)`, with the key derived from the x25519
+//! exchange of the user's tx encryption key with the consensus IO exchange key, and the nonce.
+//! The outputs of the contract (its data, query responses, encrypted attributes, errors, and the
+//! submessages it sends) are encrypted with that same key.
+//!
+//! The primitives are the ones `enclave_crypto` uses, built for the host, so this stays in sync
+//! with the enclave.
+
+mod encryption;
+mod errors;
+mod message;
+
+pub use encryption::{
+    decrypt, decrypt_attribute, decrypt_data, decrypt_error, decrypt_string, message_nonce,
+    EncryptionUtils, IoNonce,
+};
+pub use errors::ClientError;
+pub use message::{
+    normalize_code_hash, DecryptedMessage, ReplyHeader, CODE_HASH_SIZE,
+    REPLY_ENCRYPTION_MAGIC_BYTES,
+};
+
+pub use enclave_crypto::{AESKey, Ed25519PublicKey};
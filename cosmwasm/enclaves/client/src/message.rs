@@ -0,0 +1,196 @@
+use crate::errors::ClientError;
+
+/// The size of a hex encoded code hash
+pub const CODE_HASH_SIZE: usize = 64;
+const SIZE_OF_U64: usize = 8;
+
+/// Same as `cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES`. Marks a reply header in the
+/// plaintext of a submessage.
+pub const REPLY_ENCRYPTION_MAGIC_BYTES: &[u8] = b"REPLY01";
+
+/// Same as `enclave_contract_engine::padding::PADDING_MAGIC`
+pub(crate) const PADDING_MAGIC: &[u8; 8] = b"\0SCRTPAD";
+const PADDING_HEADER_LENGTH: usize = PADDING_MAGIC.len() + 4;
+
+/// Routes the output of a submessage back to the contract that sent it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyHeader {
+    pub msg_id: u64,
+    pub recipient_code_hash: String,
+}
+
+/// The plaintext of an encrypted message, as the enclave sees it:
+/// `code_hash | (REPLY_ENCRYPTION_MAGIC_BYTES | msg_id | recipient_code_hash)* | msg`.
+/// Messages sent by clients have no reply headers, but the submessages contracts send on their
+/// behalf (which are encrypted with the same key) do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedMessage {
+    pub code_hash: String,
+    pub reply_headers: Vec<ReplyHeader>,
+    pub msg: Vec<u8>,
+}
+
+impl DecryptedMessage {
+    pub fn parse(plaintext: &[u8]) -> Result<Self, ClientError> {
+        let (code_hash, mut rest) = split_code_hash(plaintext)?;
+
+        let mut reply_headers = vec![];
+        while rest.starts_with(REPLY_ENCRYPTION_MAGIC_BYTES) {
+            rest = &rest[REPLY_ENCRYPTION_MAGIC_BYTES.len()..];
+            if rest.len() < SIZE_OF_U64 {
+                return Err(ClientError::MalformedMessage);
+            }
+
+            let mut msg_id = [0u8; SIZE_OF_U64];
+            msg_id.copy_from_slice(&rest[..SIZE_OF_U64]);
+            let (recipient_code_hash, next) = split_code_hash(&rest[SIZE_OF_U64..])?;
+
+            reply_headers.push(ReplyHeader {
+                msg_id: u64::from_be_bytes(msg_id),
+                recipient_code_hash,
+            });
+            rest = next;
+        }
+
+        Ok(Self {
+            code_hash,
+            reply_headers,
+            msg: rest.to_vec(),
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut plaintext = self.code_hash.as_bytes().to_vec();
+        for header in &self.reply_headers {
+            plaintext.extend_from_slice(REPLY_ENCRYPTION_MAGIC_BYTES);
+            plaintext.extend_from_slice(&header.msg_id.to_be_bytes());
+            plaintext.extend_from_slice(header.recipient_code_hash.as_bytes());
+        }
+        plaintext.extend_from_slice(&self.msg);
+
+        plaintext
+    }
+}
+
+/// Normalizes a code hash to the lowercase hex the enclave expects
+pub fn normalize_code_hash(code_hash: &str) -> Result<String, ClientError> {
+    match hex::decode(code_hash) {
+        Ok(hash) if hash.len() * 2 == CODE_HASH_SIZE => Ok(hex::encode(hash)),
+        _ => Err(ClientError::InvalidCodeHash),
+    }
+}
+
+fn split_code_hash(data: &[u8]) -> Result<(String, &[u8]), ClientError> {
+    if data.len() < CODE_HASH_SIZE {
+        return Err(ClientError::MalformedMessage);
+    }
+
+    let code_hash =
+        std::str::from_utf8(&data[..CODE_HASH_SIZE]).map_err(|_| ClientError::InvalidCodeHash)?;
+
+    Ok((normalize_code_hash(code_hash)?, &data[CODE_HASH_SIZE..]))
+}
+
+/// Strips the length-hiding padding contracts can opt into. Anything that isn't padded is
+/// returned as is.
+pub(crate) fn unpad(data: Vec<u8>) -> Vec<u8> {
+    if data.len() < PADDING_HEADER_LENGTH || !data.starts_with(PADDING_MAGIC) {
+        return data;
+    }
+
+    let mut length_bytes = [0u8; 4];
+    length_bytes.copy_from_slice(&data[PADDING_MAGIC.len()..PADDING_HEADER_LENGTH]);
+    let length = u32::from_be_bytes(length_bytes) as usize;
+
+    let body = &data[PADDING_HEADER_LENGTH..];
+    if length > body.len() || body[length..].iter().any(|b| *b != 0) {
+        return data;
+    }
+
+    body[..length].to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CODE_HASH: &str = "4e3b2dcf4ec1e2bf2a6f66cd3e2d49bb3f47ab6a1e1b0bd0c2d9e1dcc0e4a4a7";
+    const OTHER_CODE_HASH: &str =
+        "a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1";
+
+    #[test]
+    fn parse_plain_message() {
+        let plaintext = format!("{}{{\"ping\":{{}}}}", CODE_HASH);
+        let parsed = DecryptedMessage::parse(plaintext.as_bytes()).unwrap();
+
+        assert_eq!(parsed.code_hash, CODE_HASH);
+        assert!(parsed.reply_headers.is_empty());
+        assert_eq!(parsed.msg, b"{\"ping\":{}}".to_vec());
+        assert_eq!(parsed.to_vec(), plaintext.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn parse_reply_headers() {
+        let message = DecryptedMessage {
+            code_hash: CODE_HASH.to_string(),
+            reply_headers: vec![
+                ReplyHeader {
+                    msg_id: 7,
+                    recipient_code_hash: OTHER_CODE_HASH.to_string(),
+                },
+                ReplyHeader {
+                    msg_id: u64::MAX,
+                    recipient_code_hash: CODE_HASH.to_string(),
+                },
+            ],
+            msg: b"{}".to_vec(),
+        };
+
+        assert_eq!(DecryptedMessage::parse(&message.to_vec()).unwrap(), message);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert_eq!(
+            DecryptedMessage::parse(b"{}"),
+            Err(ClientError::MalformedMessage)
+        );
+
+        let not_hex = format!("{}{{}}", "z".repeat(CODE_HASH_SIZE));
+        assert_eq!(
+            DecryptedMessage::parse(not_hex.as_bytes()),
+            Err(ClientError::InvalidCodeHash)
+        );
+
+        let mut truncated = CODE_HASH.as_bytes().to_vec();
+        truncated.extend_from_slice(REPLY_ENCRYPTION_MAGIC_BYTES);
+        truncated.extend_from_slice(&[0, 0, 0]);
+        assert_eq!(
+            DecryptedMessage::parse(&truncated),
+            Err(ClientError::MalformedMessage)
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_code_hash(&CODE_HASH.to_uppercase()).unwrap(),
+            CODE_HASH
+        );
+        assert_eq!(
+            normalize_code_hash("abcd"),
+            Err(ClientError::InvalidCodeHash)
+        );
+    }
+
+    #[test]
+    fn unpad_works() {
+        let mut padded = PADDING_MAGIC.to_vec();
+        padded.extend_from_slice(&2u32.to_be_bytes());
+        padded.extend_from_slice(b"{}");
+        padded.resize(64, 0);
+
+        assert_eq!(unpad(padded), b"{}".to_vec());
+        assert_eq!(unpad(b"{}".to_vec()), b"{}".to_vec());
+    }
+}
@@ -1,3 +1,6 @@
+// the IAS constants and helpers are left unused in a DCAP-only build
+#![cfg_attr(feature = "dcap-only", allow(unused))]
+
 #[cfg(feature = "SGX_MODE_HW")]
 use core::mem;
 
@@ -144,6 +147,29 @@ pub fn validate_enclave_version(
         return Ok(());
     }
 
+    #[cfg(feature = "dcap-only")]
+    {
+        let _ = (sign_type, api_key, challenge);
+        res_dcap.map(|_| ())
+    }
+
+    #[cfg(not(feature = "dcap-only"))]
+    {
+        validate_enclave_version_epid(kp, sign_type, api_key, challenge)
+    }
+}
+
+#[cfg(all(
+    feature = "SGX_MODE_HW",
+    feature = "production",
+    not(feature = "dcap-only")
+))]
+fn validate_enclave_version_epid(
+    kp: &KeyPair,
+    sign_type: sgx_quote_sign_type_t,
+    api_key: &[u8],
+    challenge: Option<&[u8]>,
+) -> Result<(), sgx_status_t> {
     // extract private key from KeyPair
     let ecc_handle = SgxEccHandle::new();
     let _result = ecc_handle.open();
@@ -200,7 +226,18 @@ fn remove_all_keys() {
     );
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+/// EPID certificates are signed by IAS, which a DCAP-only enclave never talks to
+#[cfg(all(feature = "SGX_MODE_HW", feature = "dcap-only"))]
+pub fn create_attestation_certificate(
+    _kp: &KeyPair,
+    _sign_type: sgx_quote_sign_type_t,
+    _api_key: &[u8],
+    _challenge: Option<&[u8]>,
+) -> Result<(Vec<u8>, Vec<u8>), sgx_status_t> {
+    Err(sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED)
+}
+
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 pub fn create_attestation_certificate(
     kp: &KeyPair,
     sign_type: sgx_quote_sign_type_t,
@@ -289,15 +326,62 @@ pub fn verify_quote_ecdsa(
     Err(sgx_status_t::SGX_ERROR_NO_DEVICE)
 }
 
+/// Offset of `tcb_level_date_tag` in `sgx_ql_qv_supplemental_t`: the (padded) version followed by
+/// three `time_t`s. It is read by offset since the rest of the struct differs between versions
+/// of the supplemental data
+#[cfg(feature = "SGX_MODE_HW")]
+const SUPP_TCB_LEVEL_DATE_TAG_OFFSET: usize = 32;
+
+/// The outcome of verifying a quote with the QvE, before any policy is applied to it
+#[cfg(feature = "SGX_MODE_HW")]
+pub struct QuoteVerification {
+    pub report_body: sgx_report_body_t,
+    pub qv_result: sgx_ql_qv_result_t,
+    pub collateral_expired: bool,
+    /// The date of the TCB level the platform was matched to, as a unix timestamp
+    pub tcb_level_date_tag: i64,
+}
+
 #[cfg(feature = "SGX_MODE_HW")]
 pub fn verify_quote_ecdsa(
     vec_quote: &[u8],
     vec_coll: &[u8],
     time_s: i64,
 ) -> Result<(sgx_report_body_t, sgx_ql_qv_result_t), sgx_status_t> {
-    //
-    // use sgx_types::sgx_ql_qv_supplemental_t;
+    let verification = verify_quote_ecdsa_qve(vec_quote, vec_coll, time_s)?;
+    check_quote_verification(&verification)?;
+
+    Ok((verification.report_body, verification.qv_result))
+}
+
+/// Applies our policy to a quote that was verified by the QvE
+#[cfg(feature = "SGX_MODE_HW")]
+pub fn check_quote_verification(verification: &QuoteVerification) -> Result<(), sgx_status_t> {
+    match verification.qv_result {
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK => {}
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED => {}
+        _ => {
+            trace!("Quote verification result: {}", verification.qv_result);
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+    };
 
+    if verification.collateral_expired {
+        trace!("DCAP Collateral expired");
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+
+    Ok(())
+}
+
+/// Verifies the quote with the QvE, and verifies the QvE report and identity inside the enclave,
+/// so the result can be trusted even though the QvE was invoked by the untrusted host.
+#[cfg(feature = "SGX_MODE_HW")]
+pub fn verify_quote_ecdsa_qve(
+    vec_quote: &[u8],
+    vec_coll: &[u8],
+    time_s: i64,
+) -> Result<QuoteVerification, sgx_status_t> {
     let mut qe_report: sgx_ql_qe_report_info_t = sgx_ql_qe_report_info_t::default();
     let mut p_supp: [u8; 5000] = [0; 5000];
     let mut n_supp: u32 = 0;
@@ -335,14 +419,10 @@ pub fn verify_quote_ecdsa(
         return Err(rt);
     }
 
-    match qv_result {
-        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK => {}
-        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED => {}
-        _ => {
-            trace!("Quote verification result: {}", qv_result);
-            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
-        }
-    };
+    if n_supp as usize > p_supp.len() {
+        trace!("Supplemental data too large: {}", n_supp);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
 
     // verify the qve report
     if time_s != 0 {
@@ -374,32 +454,15 @@ pub fn verify_quote_ecdsa(
     trace!("exp_status = {}", exp_status);
     trace!("qv_result = {}", qv_result);
 
-    /*
-        if n_supp >= mem::size_of::<sgx_ql_qv_supplemental_t>() as u32 {
-            let p_supp_fmt = p_supp.as_ptr() as *const sgx_ql_qv_supplemental_t;
-            let supp = unsafe { *p_supp_fmt };
-
-            trace!("supp.ver = {}", supp.version);
-            trace!("supp.earliest_issue_date = {}", supp.earliest_issue_date);
-            trace!("supp.latest_issue_date = {}", supp.latest_issue_date);
-            trace!(
-                "supp.earliest_expiration_date = {}",
-                supp.earliest_expiration_date
-            );
-            trace!("supp.tcb_level_date_tag = {}", supp.tcb_level_date_tag);
-            trace!("supp.pck_crl_num = {}", supp.pck_crl_num);
-            trace!("supp.root_ca_crl_num = {}", supp.root_ca_crl_num);
-            trace!("supp.tcb_eval_ref_num = {}", supp.tcb_eval_ref_num);
-            trace!("supp.tcb_cpusvn = {:?}", supp.tcb_cpusvn.svn);
-            trace!("supp.tcb_pce_isvsvn = {}", supp.tcb_pce_isvsvn);
-            trace!("supp.pce_id = {}", supp.pce_id);
-        }
-    */
-
-    if exp_status != 0 {
-        trace!("DCAP Collateral expired");
-        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    let mut tcb_level_date_tag: i64 = 0;
+    if n_supp as usize >= SUPP_TCB_LEVEL_DATE_TAG_OFFSET + 8 {
+        let mut date_tag = [0u8; 8];
+        date_tag.copy_from_slice(
+            &p_supp[SUPP_TCB_LEVEL_DATE_TAG_OFFSET..SUPP_TCB_LEVEL_DATE_TAG_OFFSET + 8],
+        );
+        tcb_level_date_tag = i64::from_le_bytes(date_tag);
     }
+    trace!("tcb_level_date_tag = {}", tcb_level_date_tag);
 
     if vec_quote.len() < mem::size_of::<sgx_quote_t>() {
         trace!("Quote too small");
@@ -413,7 +476,12 @@ pub fn verify_quote_ecdsa(
     trace!("body.mr_enclave = {:?}", report_body.mr_enclave.m);
     trace!("body.report_data = {:?}", report_body.report_data.d);
 
-    Ok((report_body, qv_result))
+    Ok(QuoteVerification {
+        report_body,
+        qv_result,
+        collateral_expired: exp_status != 0,
+        tcb_level_date_tag,
+    })
 }
 
 #[cfg(feature = "SGX_MODE_HW")]
@@ -553,7 +621,7 @@ pub fn get_quote_ecdsa(pub_k: &[u8; 32]) -> Result<(Vec<u8>, Vec<u8>), sgx_statu
 }
 
 //input: pub_k: &sgx_ec256_public_t, todo: make this the pubkey of the node
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 pub fn create_attestation_report(
     pub_k: &[u8; 32],
     sign_type: sgx_quote_sign_type_t,
@@ -780,7 +848,7 @@ pub fn create_attestation_report(
     })
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 fn parse_response_attn_report(resp: &[u8]) -> SgxResult<(String, Vec<u8>, Vec<u8>)> {
     trace!("parse_response_attn_report");
     let mut headers = [httparse::EMPTY_HEADER; 16];
@@ -868,7 +936,7 @@ fn parse_response_attn_report(resp: &[u8]) -> SgxResult<(String, Vec<u8>, Vec<u8
     Ok((attn_report, sig_bytes, sig_cert_bytes))
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 fn parse_response_sigrl(resp: &[u8]) -> Vec<u8> {
     trace!("parse_response_sigrl");
     let mut headers = [httparse::EMPTY_HEADER; 16];
@@ -915,7 +983,7 @@ fn parse_response_sigrl(resp: &[u8]) -> Vec<u8> {
     Vec::new()
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 pub fn make_ias_client_config() -> rustls::ClientConfig {
     let mut config = rustls::ClientConfig::new();
 
@@ -926,7 +994,7 @@ pub fn make_ias_client_config() -> rustls::ClientConfig {
     config
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 #[allow(dead_code)]
 pub fn get_gids_from_sn_tss(_fd: c_int, _cert: Vec<u8>) {
     // trace!("entered get_gids_from_sn_tss fd = {:?}", fd);
@@ -971,7 +1039,7 @@ pub fn get_gids_from_sn_tss(_fd: c_int, _cert: Vec<u8>) {
     //parse_response_sigrl(&plaintext)
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 pub fn get_sigrl_from_intel(fd: c_int, gid: u32, api_key_file: &[u8]) -> Vec<u8> {
     trace!("get_sigrl_from_intel fd = {:?}", fd);
     let config = make_ias_client_config();
@@ -1013,7 +1081,7 @@ pub fn get_sigrl_from_intel(fd: c_int, gid: u32, api_key_file: &[u8]) -> Vec<u8>
 }
 
 // TODO: support pse
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 pub fn get_report_from_intel(
     fd: c_int,
     quote: Vec<u8>,
@@ -1059,10 +1127,62 @@ pub fn get_report_from_intel(
     parse_response_attn_report(&plaintext)
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 fn as_u32_le(array: [u8; 4]) -> u32 {
     (array[0] as u32)
         + ((array[1] as u32) << 8)
         + ((array[2] as u32) << 16)
         + ((array[3] as u32) << 24)
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use std::io::Read;
+    use std::untrusted::fs::File;
+    use std::vec::Vec;
+
+    use sgx_types::sgx_quote_t;
+
+    use super::verify_quote_ecdsa;
+
+    fn load_attestation_dcap() -> (Vec<u8>, Vec<u8>, i64) {
+        let mut vec_quote = vec![];
+        {
+            let mut f =
+                File::open("../execute/src/registration/fixtures/attestation_dcap.quote").unwrap();
+            f.read_to_end(&mut vec_quote).unwrap();
+        }
+
+        let mut vec_coll = vec![];
+        {
+            let mut f = File::open(
+                "../execute/src/registration/fixtures/attestation_dcap.quote.collateral",
+            )
+            .unwrap();
+            f.read_to_end(&mut vec_coll).unwrap();
+        }
+        (vec_quote, vec_coll, 1709649832)
+    }
+
+    pub fn test_attestation_dcap() {
+        let (vec_quote, vec_coll, time_s) = load_attestation_dcap();
+
+        let res = verify_quote_ecdsa(&vec_quote, &vec_coll, time_s);
+        assert!(res.is_ok());
+    }
+
+    pub fn test_attestation_dcap_temper() {
+        let (mut vec_quote, mut vec_coll, time_s) = load_attestation_dcap();
+
+        // tamper with quote
+        let mut my_p_quote = vec_quote.as_mut_ptr() as *mut sgx_quote_t;
+        unsafe {
+            let mut p_report = (*my_p_quote).report_body;
+            let mut p_data = p_report.report_data;
+            p_data.d[6] = p_data.d[6] ^ 4;
+        };
+
+        let res = verify_quote_ecdsa(&vec_quote, &vec_coll, time_s);
+        assert!(!res.is_ok());
+    }
+}
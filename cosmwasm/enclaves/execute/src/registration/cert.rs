@@ -11,11 +11,11 @@ use sgx_types::{
     sgx_update_info_bit_t, SgxResult,
 };
 
-use std::io::BufReader;
 use std::str;
 use std::time::{SystemTime, UNIX_EPOCH};
 use yasna::models::ObjectIdentifier;
 
+use enclave_attestation_report::cert::{extract_asn1_value, get_netscape_comment, Error};
 use enclave_crypto::consts::{SigningMethod, CERTEXPIRYDAYS};
 use enclave_crypto::consts::{MRSIGNER, SIGNING_METHOD};
 use enclave_ffi_types::NodeAuthResult;
@@ -35,15 +35,9 @@ extern "C" {
     ) -> sgx_status_t;
 }
 
-pub const IAS_REPORT_CA: &[u8] = include_bytes!("../../Intel_SGX_Attestation_RootCA.pem");
-
 const ISSUER: &str = "SecretTEE";
 const SUBJECT: &str = "Secret Network Node Certificate";
 
-pub enum Error {
-    GenericError,
-}
-
 pub fn gen_ecc_cert(
     payload: String,
     prv_k: &sgx_ec256_private_t,
@@ -200,47 +194,6 @@ pub fn gen_ecc_cert(
     Ok((key_der, cert_der))
 }
 
-fn extract_asn1_value(cert: &[u8], oid: &[u8]) -> Result<Vec<u8>, Error> {
-    let mut offset = match cert.windows(oid.len()).position(|window| window == oid) {
-        Some(size) => size,
-        None => {
-            return Err(Error::GenericError);
-        }
-    };
-
-    offset += 12; // 11 + TAG (0x04)
-
-    if offset + 2 >= cert.len() {
-        return Err(Error::GenericError);
-    }
-
-    // Obtain Netscape Comment length
-    let mut len = cert[offset] as usize;
-    if len > 0x80 {
-        len = (cert[offset + 1] as usize) * 0x100 + (cert[offset + 2] as usize);
-        offset += 2;
-    }
-
-    // Obtain Netscape Comment
-    offset += 1;
-
-    if offset + len >= cert.len() {
-        return Err(Error::GenericError);
-    }
-
-    let payload = cert[offset..offset + len].to_vec();
-
-    Ok(payload)
-}
-
-pub fn get_netscape_comment(cert_der: &[u8]) -> Result<Vec<u8>, Error> {
-    // Search for Netscape Comment OID
-    let ns_cmt_oid = &[
-        0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x86, 0xF8, 0x42, 0x01, 0x0D,
-    ];
-    extract_asn1_value(cert_der, ns_cmt_oid)
-}
-
 #[allow(dead_code)]
 pub fn get_cert_pubkey(cert_der: &[u8]) -> Result<Vec<u8>, Error> {
     // Search for Public Key prime256v1 OID
@@ -248,26 +201,6 @@ pub fn get_cert_pubkey(cert_der: &[u8]) -> Result<Vec<u8>, Error> {
     extract_asn1_value(cert_der, prime256v1_oid)
 }
 
-pub fn get_ias_auth_config() -> (Vec<u8>, rustls::RootCertStore) {
-    // Verify if the signing cert is issued by Intel CA
-    let mut ias_ca_stripped = IAS_REPORT_CA.to_vec();
-    ias_ca_stripped.retain(|&x| x != 0x0d && x != 0x0a);
-    let head_len = "-----BEGIN CERTIFICATE-----".len();
-    let tail_len = "-----END CERTIFICATE-----".len();
-    let full_len = ias_ca_stripped.len();
-    let ias_ca_core: &[u8] = &ias_ca_stripped[head_len..full_len - tail_len];
-    let ias_cert_dec = base64::decode_config(ias_ca_core, base64::STANDARD).unwrap();
-
-    let mut ca_reader = BufReader::new(IAS_REPORT_CA);
-
-    let mut root_store = rustls::RootCertStore::empty();
-    root_store
-        .add_pem_file(&mut ca_reader)
-        .expect("Failed to add CA");
-
-    (ias_cert_dec, root_store)
-}
-
 #[cfg(not(feature = "SGX_MODE_HW"))]
 pub fn verify_ra_cert(
     cert_der: &[u8],
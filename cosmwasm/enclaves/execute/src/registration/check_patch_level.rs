@@ -1,14 +1,16 @@
 #![allow(unused_imports)]
 
+use core::fmt::Display;
 use core::slice;
 
 use log::error;
+use serde::{Serialize, Serializer};
 
 use enclave_crypto::consts::SIGNATURE_TYPE;
 use enclave_ffi_types::NodeAuthResult;
 use enclave_utils::validate_const_ptr;
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 use crate::registration::attestation::create_attestation_report;
 
 #[cfg(feature = "SGX_MODE_HW")]
@@ -18,7 +20,9 @@ use crate::registration::cert::verify_quote_status;
 use crate::registration::attestation::get_quote_ecdsa_untested;
 
 #[cfg(feature = "SGX_MODE_HW")]
-use crate::registration::attestation::verify_quote_ecdsa;
+use crate::registration::attestation::{check_quote_verification, verify_quote_ecdsa_qve};
+
+use crate::registration::tcb_report::DcapTcbReport;
 
 #[cfg(feature = "SGX_MODE_HW")]
 use enclave_utils::storage::write_to_untrusted;
@@ -30,58 +34,178 @@ use crate::sgx_types::sgx_ql_qv_result_t;
 use crate::registration::cert::check_epid_gid_is_whitelisted;
 
 #[cfg(feature = "SGX_MODE_HW")]
-use crate::registration::print_report::print_platform_info;
+use crate::registration::print_report::{print_platform_updates, EpidPlatformReport};
 
 use crate::registration::report::AttestationReport;
 
+use std::string::{String, ToString};
+
 /// # Safety
 #[no_mangle]
 #[cfg(not(feature = "SGX_MODE_HW"))]
 pub unsafe extern "C" fn ecall_check_patch_level(
     _api_key: *const u8,
     _api_key_len: u32,
+    _report: *mut u8,
+    _report_len: u32,
+    _report_written: *mut u32,
 ) -> NodeAuthResult {
     panic!("unimplemented")
 }
 
 #[cfg(feature = "SGX_MODE_HW")]
-unsafe fn check_patch_level_dcap(pub_k: &[u8; 32]) -> NodeAuthResult {
-    match get_quote_ecdsa_untested(pub_k) {
-        Ok((vec_quote, vec_coll)) => {
-            match verify_quote_ecdsa(&vec_quote, &vec_coll, 0) {
-                Ok(r) => {
-                    if r.1 != sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK {
-                        println!("WARNING: {}", r.1);
-                    }
-
-                    println!("DCAP attestation obtained and verified ok");
-                    return NodeAuthResult::Success;
-                }
-                Err(e) => {
-                    println!("DCAP quote obtained, but failed to verify it: {}", e);
-
-                    let _ = write_to_untrusted(&vec_quote, "dcap_quote.bin");
-                    let _ = write_to_untrusted(&vec_coll, "dcap_collateral.bin");
-                }
-            };
+fn serialize_node_auth_result<S: Serializer>(
+    result: &NodeAuthResult,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:?}", result))
+}
+
+/// The outcome of one of the attestation methods tried by `ecall_check_patch_level`
+#[cfg(feature = "SGX_MODE_HW")]
+#[derive(Serialize, Debug)]
+pub struct AttestationCheck<T> {
+    #[serde(serialize_with = "serialize_node_auth_result")]
+    pub result: NodeAuthResult,
+    /// The step that failed, if any
+    pub failed_step: Option<&'static str>,
+    pub error: Option<String>,
+    /// What the attestation told us about the platform, if we got that far
+    pub platform: Option<T>,
+}
+
+#[cfg(feature = "SGX_MODE_HW")]
+impl<T> AttestationCheck<T> {
+    fn success(platform: T) -> Self {
+        Self {
+            result: NodeAuthResult::Success,
+            failed_step: None,
+            error: None,
+            platform: Some(platform),
+        }
+    }
+
+    fn failed<E: Display>(step: &'static str, result: NodeAuthResult, error: E) -> Self {
+        Self {
+            result,
+            failed_step: Some(step),
+            error: Some(error.to_string()),
+            platform: None,
         }
+    }
+
+    fn with_platform(mut self, platform: T) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+}
+
+/// Everything `ecall_check_patch_level` found out about the platform
+#[cfg(feature = "SGX_MODE_HW")]
+#[derive(Serialize, Debug)]
+pub struct PatchLevelReport {
+    #[serde(serialize_with = "serialize_node_auth_result")]
+    pub result: NodeAuthResult,
+    pub dcap: AttestationCheck<DcapTcbReport>,
+    /// Not tried when built with `dcap-only`
+    pub epid: Option<AttestationCheck<EpidPlatformReport>>,
+}
+
+/// Copies `value` as JSON to `out`. `out_written` is always set to the full size of the JSON, so
+/// the caller can tell if it was truncated.
+///
+/// # Safety
+/// `out` must be valid for `out_len` bytes
+#[cfg(feature = "SGX_MODE_HW")]
+unsafe fn write_json_to<T: Serialize>(
+    value: &T,
+    out: *mut u8,
+    out_len: u32,
+    out_written: *mut u32,
+) {
+    let encoded = match serde_json::to_vec(value) {
+        Ok(encoded) => encoded,
+        Err(_) => return,
+    };
+
+    if out_written.is_null() {
+        return;
+    }
+    *out_written = encoded.len() as u32;
+
+    if out.is_null() || encoded.len() > out_len as usize {
+        return;
+    }
+    slice::from_raw_parts_mut(out, encoded.len()).copy_from_slice(&encoded);
+}
+
+#[cfg(feature = "SGX_MODE_HW")]
+unsafe fn check_patch_level_dcap(pub_k: &[u8; 32]) -> AttestationCheck<DcapTcbReport> {
+    let (vec_quote, vec_coll) = match get_quote_ecdsa_untested(pub_k) {
+        Ok(r) => r,
         Err(e) => {
             println!("Failed to obtain DCAP attestation: {}", e);
+            return AttestationCheck::failed("get_quote", NodeAuthResult::InvalidCert, e);
         }
+    };
+
+    let verification = match verify_quote_ecdsa_qve(&vec_quote, &vec_coll, 0) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("DCAP quote obtained, but failed to verify it: {}", e);
+
+            let _ = write_to_untrusted(&vec_quote, "dcap_quote.bin");
+            let _ = write_to_untrusted(&vec_coll, "dcap_collateral.bin");
+            return AttestationCheck::failed("verify_quote", NodeAuthResult::InvalidCert, e);
+        }
+    };
+
+    let tcb_report = DcapTcbReport::from_verification(
+        verification.qv_result,
+        verification.collateral_expired,
+        verification.tcb_level_date_tag,
+        &vec_coll,
+    );
+
+    if let Err(e) = check_quote_verification(&verification) {
+        println!("DCAP quote obtained, but failed to verify it: {}", e);
+
+        let _ = write_to_untrusted(&vec_quote, "dcap_quote.bin");
+        let _ = write_to_untrusted(&vec_coll, "dcap_collateral.bin");
+        return AttestationCheck::failed("quote_status", NodeAuthResult::InvalidCert, e)
+            .with_platform(tcb_report);
     }
-    NodeAuthResult::InvalidCert
+
+    if verification.qv_result != sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK {
+        println!("WARNING: {}", verification.qv_result);
+    }
+
+    println!("DCAP attestation obtained and verified ok");
+    AttestationCheck::success(tcb_report)
 }
 
-#[cfg(feature = "SGX_MODE_HW")]
+#[cfg(all(feature = "SGX_MODE_HW", not(feature = "dcap-only")))]
 unsafe fn check_patch_level_epid(
     pub_k: &[u8; 32],
     api_key: *const u8,
     api_key_len: u32,
-) -> NodeAuthResult {
-    validate_const_ptr!(api_key, api_key_len as usize, NodeAuthResult::InvalidInput);
+) -> AttestationCheck<EpidPlatformReport> {
+    validate_const_ptr!(
+        api_key,
+        api_key_len as usize,
+        AttestationCheck::failed(
+            "validate_api_key",
+            NodeAuthResult::InvalidInput,
+            "API key is outside enclave memory"
+        )
+    );
     if api_key_len > 100 {
         error!("API key malformed");
-        return NodeAuthResult::InvalidInput;
+        return AttestationCheck::failed(
+            "validate_api_key",
+            NodeAuthResult::InvalidInput,
+            "API key malformed",
+        );
     }
 
     let api_key_slice = slice::from_raw_parts(api_key, api_key_len as usize);
@@ -89,18 +213,23 @@ unsafe fn check_patch_level_epid(
     let signed_report =
         match create_attestation_report(pub_k, SIGNATURE_TYPE, api_key_slice, None, true) {
             Ok(r) => r,
-            Err(_e) => {
+            Err(e) => {
                 error!("Error creating attestation report");
-                return NodeAuthResult::InvalidCert;
+                return AttestationCheck::failed(
+                    "create_attestation_report",
+                    NodeAuthResult::InvalidCert,
+                    e,
+                );
             }
         };
 
-    let payload: String = serde_json::to_string(&signed_report)
-        .map_err(|_| {
+    let payload: String = match serde_json::to_string(&signed_report) {
+        Ok(payload) => payload,
+        Err(e) => {
             error!("Error serializing report. May be malformed, or badly encoded");
-            NodeAuthResult::InvalidCert
-        })
-        .unwrap();
+            return AttestationCheck::failed("serialize_report", NodeAuthResult::InvalidCert, e);
+        }
+    };
 
     // extract private key from KeyPair
     let ecc_handle = sgx_tcrypto::SgxEccHandle::new();
@@ -112,12 +241,19 @@ unsafe fn check_patch_level_epid(
     let (_key_der, cert) = super::cert::gen_ecc_cert(payload, &prv_k, &pub_k, &ecc_handle).unwrap();
     let _result = ecc_handle.close();
 
-    let report = AttestationReport::from_cert(&cert)
-        .map_err(|_| {
+    let report = match AttestationReport::from_cert(&cert) {
+        Ok(report) => report,
+        Err(e) => {
             error!("Failed to create report from certificate");
-            NodeAuthResult::InvalidCert
-        })
-        .unwrap();
+            return AttestationCheck::failed(
+                "parse_report",
+                NodeAuthResult::InvalidCert,
+                format!("{:?}", e),
+            );
+        }
+    };
+
+    let platform = EpidPlatformReport::from_report(&report);
 
     // PERFORM EPID CHECK
     #[cfg(not(feature = "epid_whitelist_disabled"))]
@@ -130,7 +266,12 @@ unsafe fn check_patch_level_epid(
                 Please see https://docs.scrt.network/secret-network-documentation/infrastructure/setting-up-a-node-validator/hardware-setup/patching-your-node \
                 for more information");
         error!("If you think this message appeared in error, please contact us on Telegram or Discord, and attach your quote status from the message above");
-        return NodeAuthResult::BadQuoteStatus;
+        return AttestationCheck::failed(
+            "gid_whitelist",
+            NodeAuthResult::BadQuoteStatus,
+            "EPID group is not whitelisted",
+        )
+        .with_platform(platform);
     }
 
     if report.tcb_eval_data_number < 16 {
@@ -141,7 +282,12 @@ unsafe fn check_patch_level_epid(
             "Tried to attest using old data: {}",
             report.tcb_eval_data_number
         );
-        return NodeAuthResult::GroupOutOfDate;
+        return AttestationCheck::failed(
+            "tcb_evaluation_data_number",
+            NodeAuthResult::GroupOutOfDate,
+            "TCB evaluation data number is out of date",
+        )
+        .with_platform(platform);
     }
 
     // PERFORM STATUS CHECKS
@@ -164,14 +310,22 @@ unsafe fn check_patch_level_epid(
 
     // print platform blob info
     match node_auth_result {
-        NodeAuthResult::GroupOutOfDate | NodeAuthResult::SwHardeningAndConfigurationNeeded => unsafe {
-            print_platform_info(&report);
-            node_auth_result
-        },
-        _ => NodeAuthResult::Success,
+        NodeAuthResult::GroupOutOfDate | NodeAuthResult::SwHardeningAndConfigurationNeeded => {
+            if let Some(updates) = &platform.platform_updates {
+                print_platform_updates(updates);
+            }
+            let quote_status = platform.quote_status.clone();
+            AttestationCheck::failed("quote_status", node_auth_result, quote_status)
+                .with_platform(platform)
+        }
+        _ => AttestationCheck::success(platform),
     }
 }
 
+/// Checks that the platform can attest. Besides the overall result, a `PatchLevelReport` is
+/// written as JSON to `report`, with details about each attestation method and which step of it
+/// failed
+///
 /// # Safety
 /// Don't forget to check the input length of api_key_len
 #[no_mangle]
@@ -179,18 +333,38 @@ unsafe fn check_patch_level_epid(
 pub unsafe extern "C" fn ecall_check_patch_level(
     api_key: *const u8,
     api_key_len: u32,
+    report: *mut u8,
+    report_len: u32,
+    report_written: *mut u32,
 ) -> NodeAuthResult {
     let temp_key_result = enclave_crypto::KeyPair::new().unwrap();
 
-    let res1 = check_patch_level_dcap(&temp_key_result.get_pubkey());
-    let res2 = check_patch_level_epid(&temp_key_result.get_pubkey(), api_key, api_key_len);
+    let dcap = check_patch_level_dcap(&temp_key_result.get_pubkey());
 
-    println!("DCAP attestation: {}", res1);
-    println!("EPID attestation: {}", res2);
+    println!("DCAP attestation: {}", dcap.result);
 
-    if NodeAuthResult::Success == res1 {
-        return res1;
-    }
+    #[cfg(feature = "dcap-only")]
+    let epid = {
+        let _ = (api_key, api_key_len);
+        None
+    };
+
+    #[cfg(not(feature = "dcap-only"))]
+    let epid = {
+        let epid = check_patch_level_epid(&temp_key_result.get_pubkey(), api_key, api_key_len);
+
+        println!("EPID attestation: {}", epid.result);
+
+        Some(epid)
+    };
+
+    let result = match &epid {
+        Some(epid) if dcap.result != NodeAuthResult::Success => epid.result,
+        _ => dcap.result,
+    };
+
+    let patch_level_report = PatchLevelReport { result, dcap, epid };
+    write_json_to(&patch_level_report, report, report_len, report_written);
 
-    res2
+    patch_level_report.result
 }
@@ -1,4 +1,4 @@
-#![cfg_attr(not(feature = "SGX_MODE_HW"), allow(unused))]
+#![cfg_attr(any(not(feature = "SGX_MODE_HW"), feature = "dcap-only"), allow(unused))]
 
 use log::*;
 use sgx_types::sgx_spid_t;
@@ -2,13 +2,15 @@ pub use attestation::create_attestation_certificate;
 pub use offchain::{ecall_get_attestation_report, ecall_init_bootstrap, ecall_init_node};
 pub use onchain::ecall_authenticate_new_node;
 
+use enclave_attestation_report::report;
+pub use enclave_attestation_report::tcb_report;
+
 mod attestation;
 mod cert;
 mod hex;
 mod offchain;
 mod onchain;
 mod persistency;
-mod report;
 mod seed_exchange;
 
 #[cfg(feature = "SGX_MODE_HW")]
@@ -35,10 +37,13 @@ pub mod tests {
             report::tests::test_attestation_report_from_cert_invalid();
             report::tests::test_attestation_report_from_cert_api_version_not_compatible();
             report::tests::test_attestation_report_test();
-            report::tests::test_attestation_dcap();
-            report::tests::test_attestation_dcap_temper();
+            attestation::tests::test_attestation_dcap();
+            attestation::tests::test_attestation_dcap_temper();
             cert::tests::test_certificate_valid();
             cert::tests::test_certificate_invalid_configuration_needed();
+            tcb_report::tests::test_collateral_tcb_info();
+            tcb_report::tests::test_tcb_report_from_verification();
+            tcb_report::tests::test_tcb_report_unknown_level();
         });
 
         if failures != 0 {
@@ -19,7 +19,10 @@ use enclave_crypto::consts::{
 
 use enclave_crypto::{KeyPair, Keychain, KEY_MANAGER, PUBLIC_KEY_SIZE};
 use enclave_utils::pointers::validate_mut_slice;
-use enclave_utils::storage::migrate_file_from_2_17_safe;
+use enclave_utils::storage::{
+    migrate_file_from_2_17_safe, reseal_file_safe, sealed_file_info, KeyPolicy, SealedFileInfo,
+    SealingPolicy,
+};
 use enclave_utils::tx_bytes::TX_BYTES_SEALING_PATH;
 use enclave_utils::validator_set::VALIDATOR_SET_SEALING_PATH;
 use enclave_utils::{validate_const_ptr, validate_mut_ptr};
@@ -443,9 +446,12 @@ pub unsafe extern "C" fn ecall_get_attestation_report(
     let mut size_dcap_q: u32 = 0;
     let mut size_dcap_c: u32 = 0;
 
-    let res_epid = match 1 & flags {
-        0 => get_attestation_report_epid(api_key, api_key_len, &kp),
-        _ => Err(sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED),
+    // a DCAP-only enclave can't create EPID reports
+    let skip_epid = cfg!(feature = "dcap-only") || (1 & flags) != 0;
+    let res_epid = if skip_epid {
+        Err(sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED)
+    } else {
+        get_attestation_report_epid(api_key, api_key_len, &kp)
     };
     if let Ok(ref vec_cert) = res_epid {
         size_epid = vec_cert.len() as u32;
@@ -590,29 +596,111 @@ pub unsafe extern "C" fn ecall_get_genesis_seed(
     }
 }
 
+/// Every secret the enclave keeps sealed on disk
+fn sealed_file_paths() -> [&'static str; 7] {
+    [
+        &REGISTRATION_KEY_SEALING_PATH,
+        &GENESIS_CONSENSUS_SEED_SEALING_PATH,
+        &CURRENT_CONSENSUS_SEED_SEALING_PATH,
+        &REK_PATH,
+        &IRS_PATH,
+        &VALIDATOR_SET_SEALING_PATH,
+        &TX_BYTES_SEALING_PATH,
+    ]
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ecall_migrate_sealing() -> sgx_types::sgx_status_t {
-    if let Err(e) = migrate_file_from_2_17_safe(&REGISTRATION_KEY_SEALING_PATH, true) {
-        return e;
-    }
-    if let Err(e) = migrate_file_from_2_17_safe(&GENESIS_CONSENSUS_SEED_SEALING_PATH, true) {
-        return e;
-    }
-    if let Err(e) = migrate_file_from_2_17_safe(&CURRENT_CONSENSUS_SEED_SEALING_PATH, true) {
-        return e;
-    }
-    if let Err(e) = migrate_file_from_2_17_safe(&REK_PATH, true) {
-        return e;
-    }
-    if let Err(e) = migrate_file_from_2_17_safe(&IRS_PATH, true) {
-        return e;
+    for path in sealed_file_paths().iter() {
+        if let Err(e) = migrate_file_from_2_17_safe(path, true) {
+            return e;
+        }
     }
-    if let Err(e) = migrate_file_from_2_17_safe(&VALIDATOR_SET_SEALING_PATH, true) {
-        return e;
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Set in the `flags` of `ecall_reseal_secrets` to bind the sealing key to the current CPUSVN
+pub const RESEAL_FLAG_PIN_CPU_SVN: u32 = 1;
+
+///
+/// `ecall_reseal_secrets`
+///
+/// Re-seals every sealed secret with a fresh key, derived according to `key_policy`
+/// (`SGX_KEYPOLICY_MRENCLAVE` or `SGX_KEYPOLICY_MRSIGNER`). Files are written in the versioned
+/// sealing format, which older enclaves can't read. The previous version of each file is kept with
+/// a `.bkp` suffix
+///
+/// # Safety
+/// Takes no pointers. It must not run concurrently with other ecalls that read or write the sealed
+/// files, which is the case since it's only called from the CLI before the node starts
+///
+#[no_mangle]
+pub unsafe extern "C" fn ecall_reseal_secrets(key_policy: u32, flags: u32) -> sgx_status_t {
+    let key_policy = match KeyPolicy::from_sgx(key_policy as u16) {
+        Some(policy) if policy.to_sgx() as u32 == key_policy => policy,
+        _ => {
+            error!("Unknown sealing key policy {}", key_policy);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    let policy = SealingPolicy {
+        key_policy,
+        pin_cpu_svn: flags & RESEAL_FLAG_PIN_CPU_SVN != 0,
+    };
+
+    for path in sealed_file_paths().iter() {
+        if let Err(e) = reseal_file_safe(path, policy) {
+            return e;
+        }
     }
-    if let Err(e) = migrate_file_from_2_17_safe(&TX_BYTES_SEALING_PATH, true) {
-        return e;
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+///
+/// `ecall_list_sealed_files`
+///
+/// Writes a JSON list of every sealed secret, with the format version and sealing policy it's
+/// protected by, to `out`. `out_written` is set to the full length of the list even if it doesn't
+/// fit, so the caller can retry with a larger buffer
+///
+/// # Safety
+/// `out` must be valid for `out_len` bytes and `out_written` for a `u32`. Both are checked to be
+/// outside the enclave
+///
+#[no_mangle]
+pub unsafe extern "C" fn ecall_list_sealed_files(
+    out: *mut u8,
+    out_len: u32,
+    out_written: *mut u32,
+) -> sgx_status_t {
+    validate_mut_ptr!(out, out_len as usize, sgx_status_t::SGX_ERROR_UNEXPECTED);
+    validate_mut_ptr!(
+        out_written as *mut u8,
+        std::mem::size_of::<u32>(),
+        sgx_status_t::SGX_ERROR_UNEXPECTED,
+    );
+
+    let files: Vec<SealedFileInfo> = sealed_file_paths()
+        .iter()
+        .map(|path| sealed_file_info(path))
+        .collect();
+
+    let encoded = match serde_json::to_vec(&files) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            error!("Failed to serialize the sealed files list: {}", e);
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    *out_written = encoded.len() as u32;
+    if encoded.len() > out_len as usize {
+        return sgx_status_t::SGX_SUCCESS;
     }
+    slice::from_raw_parts_mut(out, encoded.len()).copy_from_slice(&encoded);
 
     sgx_status_t::SGX_SUCCESS
 }
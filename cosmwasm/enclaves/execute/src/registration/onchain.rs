@@ -4,7 +4,7 @@
 use log::*;
 use std::panic;
 
-use enclave_ffi_types::NodeAuthResult;
+use enclave_ffi_types::{split_combined_cert, NodeAuthResult};
 
 use crate::registration::attestation::verify_quote_ecdsa;
 use crate::registration::cert::verify_ra_report;
@@ -23,8 +23,6 @@ use enclave_crypto::consts::SigningMethod;
 
 use super::cert::verify_ra_cert;
 use super::seed_exchange::encrypt_seed;
-use core::mem;
-use std::slice;
 
 #[cfg(feature = "light-client-validation")]
 use enclave_contract_engine::check_cert_in_current_block;
@@ -44,36 +42,6 @@ fn get_current_block_time_s() -> i64 {
     return 0 as i64;
 }
 
-fn split_combined_cert(cert: *const u8, cert_len: u32) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
-    let mut vec_cert: Vec<u8> = Vec::new();
-    let mut vec_quote: Vec<u8> = Vec::new();
-    let mut vec_coll: Vec<u8> = Vec::new();
-
-    let n0 = mem::size_of::<u32>() as u32 * 3;
-
-    if cert_len >= n0 {
-        let p_cert = cert as *const u32;
-        let s0 = u32::from_le(unsafe { *p_cert });
-        let s1 = u32::from_le(unsafe { *(p_cert.offset(1)) });
-        let s2 = u32::from_le(unsafe { *(p_cert.offset(2)) });
-
-        let size_total = (n0 as u64) + (s0 as u64) + (s1 as u64) + (s2 as u64);
-
-        if size_total <= cert_len as u64 {
-            vec_cert =
-                unsafe { slice::from_raw_parts(cert.offset(n0 as isize), s0 as usize).to_vec() };
-            vec_quote = unsafe {
-                slice::from_raw_parts(cert.offset((n0 + s0) as isize), s1 as usize).to_vec()
-            };
-            vec_coll = unsafe {
-                slice::from_raw_parts(cert.offset((n0 + s0 + s1) as isize), s2 as usize).to_vec()
-            };
-        }
-    }
-
-    (vec_cert, vec_quote, vec_coll)
-}
-
 fn verify_attestation_epid(cert_slice: &[u8], pub_key: &mut [u8; 32]) -> NodeAuthResult {
     let pk = match verify_ra_cert(cert_slice, None, true) {
         Ok(retval) => retval,
@@ -171,7 +139,7 @@ pub unsafe extern "C" fn ecall_authenticate_new_node(
 
     let mut target_public_key: [u8; 32] = [0u8; 32];
 
-    let (vec_cert, vec_quote, vec_coll) = split_combined_cert(cert, cert_len);
+    let (vec_cert, vec_quote, vec_coll) = split_combined_cert(cert_slice);
 
     if vec_quote.is_empty() || vec_coll.is_empty() {
         if vec_cert.is_empty() {
@@ -181,14 +149,14 @@ pub unsafe extern "C" fn ecall_authenticate_new_node(
 
         trace!("EPID attestation");
 
-        let res = verify_attestation_epid(vec_cert.as_slice(), &mut target_public_key);
+        let res = verify_attestation_epid(vec_cert, &mut target_public_key);
         if NodeAuthResult::Success != res {
             return res;
         }
     } else {
         trace!("DCAP attestation");
 
-        let res = verify_attestation_dcap(&vec_quote, &vec_coll, &mut target_public_key);
+        let res = verify_attestation_dcap(vec_quote, vec_coll, &mut target_public_key);
         if NodeAuthResult::Success != res {
             return res;
         }
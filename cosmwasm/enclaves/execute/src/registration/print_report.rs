@@ -5,7 +5,50 @@ use crate::registration::{
 
 use enclave_ffi_types::NodeAuthResult;
 use log::{error, warn};
+use serde::Serialize;
 use sgx_types::{sgx_platform_info_t, sgx_status_t, sgx_update_info_bit_t};
+use std::string::String;
+use std::vec::Vec;
+
+/// The EPID status of the platform, as reported by `ecall_check_patch_level`
+#[derive(Serialize, Debug, Default)]
+pub struct EpidPlatformReport {
+    pub quote_status: String,
+    pub gid: u32,
+    pub advisory_ids: Vec<String>,
+    /// Advisories that are not whitelisted, followed by how to mitigate them if we know
+    pub vulnerable: Vec<String>,
+    pub tcb_evaluation_data_number: u16,
+    /// Only filled in if the platform is out of date or needs configuration
+    pub platform_updates: Option<PlatformUpdates>,
+}
+
+impl EpidPlatformReport {
+    /// # Safety
+    /// Calls `get_platform_updates`
+    pub unsafe fn from_report(report: &AttestationReport) -> Self {
+        Self {
+            quote_status: format!("{:?}", report.sgx_quote_status),
+            gid: report.sgx_quote_body.gid,
+            advisory_ids: report.advisory_ids.0.clone(),
+            vulnerable: report.advisory_ids.vulnerable(),
+            tcb_evaluation_data_number: report.tcb_eval_data_number,
+            platform_updates: match NodeAuthResult::from(&report.sgx_quote_status) {
+                NodeAuthResult::GroupOutOfDate
+                | NodeAuthResult::SwHardeningAndConfigurationNeeded => get_platform_updates(report),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Platform components that need a security update, according to the platform info blob
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct PlatformUpdates {
+    pub ucode: bool,
+    pub csme_fw: bool,
+    pub psw: bool,
+}
 
 pub fn print_local_report_info(cert: &[u8]) {
     let report = match AttestationReport::from_cert(cert) {
@@ -42,45 +85,64 @@ pub fn print_local_report_info(cert: &[u8]) {
     }
 }
 
+/// # Safety
+/// Placeholder
+pub unsafe fn get_platform_updates(report: &AttestationReport) -> Option<PlatformUpdates> {
+    let platform_info = report.platform_info_blob.as_ref()?;
+
+    let mut update_info = sgx_update_info_bit_t::default();
+    let mut rt = sgx_status_t::default();
+    let res = ocall_get_update_info(
+        &mut rt as *mut sgx_status_t,
+        platform_info[4..].as_ptr() as *const sgx_platform_info_t,
+        1,
+        &mut update_info,
+    );
+
+    if res != sgx_status_t::SGX_SUCCESS {
+        error!("Error parsing attestation report {:?}", res);
+        return None;
+    }
+
+    // update_info is only filled in if an update is needed
+    if rt == sgx_status_t::SGX_SUCCESS {
+        return Some(PlatformUpdates::default());
+    }
+
+    Some(PlatformUpdates {
+        ucode: update_info.ucodeUpdate != 0,
+        csme_fw: update_info.csmeFwUpdate != 0,
+        psw: update_info.pswUpdate != 0,
+    })
+}
+
 /// # Safety
 /// Placeholder
 pub unsafe fn print_platform_info(report: &AttestationReport) {
-    if let Some(platform_info) = &report.platform_info_blob {
-        let mut update_info = sgx_update_info_bit_t::default();
-        let mut rt = sgx_status_t::default();
-        let res = ocall_get_update_info(
-            &mut rt as *mut sgx_status_t,
-            platform_info[4..].as_ptr() as *const sgx_platform_info_t,
-            1,
-            &mut update_info,
-        );
-
-        if res != sgx_status_t::SGX_SUCCESS {
-            error!("Error parsing attestation report {:?}", res);
-            return;
-        }
+    if let Some(updates) = get_platform_updates(report) {
+        print_platform_updates(&updates);
+    }
+}
 
-        if rt != sgx_status_t::SGX_SUCCESS {
-            if update_info.ucodeUpdate != 0 {
-                warn!("Processor Firmware Update (ucodeUpdate). A security upgrade for your computing\n\
-                            device is required for this application to continue to provide you with a high degree of\n\
-                            security. Please contact your device manufacturer’s support website for a BIOS update\n\
-                            for this system");
-            }
+pub fn print_platform_updates(updates: &PlatformUpdates) {
+    if updates.ucode {
+        warn!("Processor Firmware Update (ucodeUpdate). A security upgrade for your computing\n\
+                    device is required for this application to continue to provide you with a high degree of\n\
+                    security. Please contact your device manufacturer’s support website for a BIOS update\n\
+                    for this system");
+    }
 
-            if update_info.csmeFwUpdate != 0 {
-                warn!("Intel Manageability Engine Update (csmeFwUpdate). A security upgrade for your\n\
-                            computing device is required for this application to continue to provide you with a high\n\
-                            degree of security. Please contact your device manufacturer’s support website for a\n\
-                            BIOS and/or Intel® Manageability Engine update for this system");
-            }
+    if updates.csme_fw {
+        warn!("Intel Manageability Engine Update (csmeFwUpdate). A security upgrade for your\n\
+                    computing device is required for this application to continue to provide you with a high\n\
+                    degree of security. Please contact your device manufacturer’s support website for a\n\
+                    BIOS and/or Intel® Manageability Engine update for this system");
+    }
 
-            if update_info.pswUpdate != 0 {
-                warn!("Intel SGX Platform Software Update (pswUpdate). A security upgrade for your\n\
-                              computing device is required for this application to continue to provide you with a high\n\
-                              degree of security. Please visit this application’s support website for an Intel SGX\n\
-                              Platform SW update");
-            }
-        }
+    if updates.psw {
+        warn!("Intel SGX Platform Software Update (pswUpdate). A security upgrade for your\n\
+                      computing device is required for this application to continue to provide you with a high\n\
+                      degree of security. Please visit this application’s support website for an Intel SGX\n\
+                      Platform SW update");
     }
 }
@@ -0,0 +1,240 @@
+use derive_more::Display;
+
+use crate::types::EnclaveError;
+
+/// The stage of a contract call that an `EnclaveError` happened in
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy)]
+pub enum EnclaveErrorStage {
+    #[display(fmt = "unknown")]
+    Unknown,
+    /// Parsing the inputs of the call
+    #[display(fmt = "parse")]
+    Parse,
+    /// Verifying the signatures of the tx
+    #[display(fmt = "signature verification")]
+    SignatureVerification,
+    /// Checking the call against the blocks verified by the light client
+    #[display(fmt = "block verification")]
+    BlockVerification,
+    /// Validating the inputs against each other, e.g. the code hash in the message, and against
+    /// the limits of the enclave, e.g. the call depth in the env
+    #[display(fmt = "validation")]
+    Validation,
+    #[display(fmt = "decryption")]
+    Decryption,
+    #[display(fmt = "encryption")]
+    Encryption,
+    /// Loading and instrumenting the contract code
+    #[display(fmt = "wasm loading")]
+    WasmLoading,
+    /// Running the contract code
+    #[display(fmt = "wasm trap")]
+    WasmTrap,
+    #[display(fmt = "out of gas")]
+    OutOfGas,
+    /// Calling out to the host
+    #[display(fmt = "ocall")]
+    Ocall,
+    /// Problems of the enclave itself, like running out of memory
+    #[display(fmt = "enclave")]
+    Enclave,
+}
+
+impl Default for EnclaveErrorStage {
+    fn default() -> Self {
+        EnclaveErrorStage::Unknown
+    }
+}
+
+/// Details of an `EnclaveError` that are safe to publish, since they only depend on public
+/// inputs of the call. This is what lets operators and users tell apart the many failures that
+/// collapse into the same `EnclaveError`.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct EnclaveErrorContext {
+    pub stage: EnclaveErrorStage,
+    /// One of the codes in `error_details`, or 0 if there are no details
+    pub detail: u32,
+}
+
+impl EnclaveErrorContext {
+    pub fn new(stage: EnclaveErrorStage, detail: u32) -> Self {
+        Self { stage, detail }
+    }
+}
+
+impl From<&EnclaveError> for EnclaveErrorContext {
+    fn from(err: &EnclaveError) -> Self {
+        Self::new(err.stage(), error_details::NONE)
+    }
+}
+
+impl EnclaveError {
+    /// A stable code for the error. Codes are never reused, so new variants get new codes no
+    /// matter where they're declared.
+    pub fn code(&self) -> u32 {
+        match self {
+            EnclaveError::FailedOcall { .. } => 1,
+            EnclaveError::ValidationFailure => 2,
+            EnclaveError::InvalidWasm => 3,
+            EnclaveError::CannotInitializeWasmMemory => 4,
+            EnclaveError::WasmModuleWithStart => 5,
+            EnclaveError::WasmModuleWithFP => 6,
+            EnclaveError::FailedGasMeteringInjection => 7,
+            EnclaveError::InternalError => 8,
+            EnclaveError::OutOfGas => 9,
+            EnclaveError::FailedFunctionCall => 10,
+            EnclaveError::ContractPanicUnreachable => 11,
+            EnclaveError::ContractPanicMemoryAccessOutOfBounds => 12,
+            EnclaveError::ContractPanicTableAccessOutOfBounds => 13,
+            EnclaveError::ContractPanicElemUninitialized => 14,
+            EnclaveError::ContractPanicDivisionByZero => 15,
+            EnclaveError::ContractPanicInvalidConversionToInt => 16,
+            EnclaveError::ContractPanicStackOverflow => 17,
+            EnclaveError::ContractPanicIntegerOverflow => 18,
+            EnclaveError::ContractPanicUnexpectedSignature => 19,
+            EnclaveError::FailedSeal => 20,
+            EnclaveError::FailedUnseal => 21,
+            EnclaveError::FailedContractAuthentication => 22,
+            EnclaveError::FailedToDeserialize => 23,
+            EnclaveError::FailedToSerialize => 24,
+            EnclaveError::EncryptionError => 25,
+            EnclaveError::DecryptionError => 26,
+            EnclaveError::MemoryAllocationError => 27,
+            EnclaveError::MemorySafetyAllocationError => 28,
+            EnclaveError::MemoryReadError => 29,
+            EnclaveError::MemoryWriteError => 30,
+            EnclaveError::NotImplemented => 31,
+            EnclaveError::FailedTxVerification => 32,
+            EnclaveError::UnauthorizedWrite => 33,
+            EnclaveError::HostMisbehavior => 34,
+            EnclaveError::Panic => 35,
+            EnclaveError::OutOfMemory => 36,
+            EnclaveError::ExceededRecursionLimit { .. } => 37,
+            EnclaveError::WasmMemoryLimitExceeded { .. } => 38,
+            EnclaveError::Unknown => 0,
+        }
+    }
+
+    /// The stage the error usually happens in. The enclave can report a more specific one in the
+    /// `EnclaveErrorContext` of the error.
+    pub fn stage(&self) -> EnclaveErrorStage {
+        use EnclaveErrorStage::*;
+
+        match self {
+            EnclaveError::FailedOcall { .. } | EnclaveError::HostMisbehavior => Ocall,
+            EnclaveError::ValidationFailure | EnclaveError::ExceededRecursionLimit { .. } => {
+                Validation
+            }
+            EnclaveError::InvalidWasm
+            | EnclaveError::CannotInitializeWasmMemory
+            | EnclaveError::WasmMemoryLimitExceeded { .. }
+            | EnclaveError::WasmModuleWithStart
+            | EnclaveError::WasmModuleWithFP
+            | EnclaveError::FailedGasMeteringInjection => WasmLoading,
+            EnclaveError::OutOfGas => OutOfGas,
+            EnclaveError::FailedFunctionCall
+            | EnclaveError::ContractPanicUnreachable
+            | EnclaveError::ContractPanicMemoryAccessOutOfBounds
+            | EnclaveError::ContractPanicTableAccessOutOfBounds
+            | EnclaveError::ContractPanicElemUninitialized
+            | EnclaveError::ContractPanicDivisionByZero
+            | EnclaveError::ContractPanicInvalidConversionToInt
+            | EnclaveError::ContractPanicStackOverflow
+            | EnclaveError::ContractPanicIntegerOverflow
+            | EnclaveError::ContractPanicUnexpectedSignature
+            | EnclaveError::MemoryReadError
+            | EnclaveError::MemoryWriteError
+            | EnclaveError::UnauthorizedWrite => WasmTrap,
+            EnclaveError::FailedToDeserialize => Parse,
+            EnclaveError::FailedContractAuthentication | EnclaveError::DecryptionError => {
+                Decryption
+            }
+            EnclaveError::EncryptionError => Encryption,
+            EnclaveError::FailedTxVerification => SignatureVerification,
+            EnclaveError::InternalError
+            | EnclaveError::FailedSeal
+            | EnclaveError::FailedUnseal
+            | EnclaveError::FailedToSerialize
+            | EnclaveError::MemoryAllocationError
+            | EnclaveError::MemorySafetyAllocationError
+            | EnclaveError::NotImplemented
+            | EnclaveError::Panic
+            | EnclaveError::OutOfMemory => Enclave,
+            EnclaveError::Unknown => Unknown,
+        }
+    }
+}
+
+/// Codes for `EnclaveErrorContext::detail`. Codes that used to only show up in the enclave logs
+/// keep their values.
+pub mod error_details {
+    pub const NONE: u32 = 0;
+
+    // Block verification
+    pub const WRONG_BLOCK_HEIGHT: u32 = 0xF6AC;
+    pub const WRONG_BLOCK_TIME: u32 = 0xF6AF;
+    pub const NO_VERIFIED_TXS_LEFT: u32 = 0x3555;
+    pub const TX_NOT_IN_BLOCK: u32 = 0x3255;
+
+    // Validation
+    pub const MISSING_CODE_HASH: u32 = 0x5001;
+    pub const MALFORMED_CODE_HASH: u32 = 0x5002;
+    pub const CODE_HASH_MISMATCH: u32 = 0x5003;
+    pub const CONTRACT_KEY_MISMATCH: u32 = 0x5004;
+    pub const CONTRACT_KEY_PROOF_MISMATCH: u32 = 0x5005;
+    pub const CALL_DEPTH_EXCEEDED: u32 = 0x5006;
+
+    // Signature verification
+    pub const UNSUPPORTED_SIGN_MODE: u32 = 0x6001;
+    pub const INVALID_PUBKEY: u32 = 0x6002;
+    pub const SIGNER_NOT_FOUND: u32 = 0x6003;
+    pub const INVALID_SIGNATURE: u32 = 0x6004;
+    pub const SIGNER_MISMATCH: u32 = 0x6005;
+    pub const PARAMS_MISMATCH: u32 = 0x6006;
+
+    // Decryption
+    pub const INPUT_DECRYPTION_FAILED: u32 = 0x7001;
+
+    // Wasm loading
+    pub const MISSING_API_MARKER: u32 = 0x8001;
+    pub const UNSUPPORTED_IMPORT: u32 = 0x8002;
+    pub const UNSUPPORTED_INSTRUCTION: u32 = 0x8003;
+    pub const INVALID_PADDING_BLOCK_SIZE: u32 = 0x8004;
+
+    // Encryption
+    pub const INVALID_ATTRIBUTE_RECIPIENT: u32 = 0x9001;
+    pub const TOO_MANY_ATTRIBUTE_RECIPIENTS: u32 = 0x9002;
+
+    /// A short, stable name for a detail code
+    pub fn name(detail: u32) -> Option<&'static str> {
+        Some(match detail {
+            WRONG_BLOCK_HEIGHT => "wrong block height",
+            WRONG_BLOCK_TIME => "wrong block time",
+            NO_VERIFIED_TXS_LEFT => "no verified txs left in block",
+            TX_NOT_IN_BLOCK => "tx not in block",
+            MISSING_CODE_HASH => "missing code hash",
+            MALFORMED_CODE_HASH => "malformed code hash",
+            CODE_HASH_MISMATCH => "code hash mismatch",
+            CONTRACT_KEY_MISMATCH => "contract key mismatch",
+            CONTRACT_KEY_PROOF_MISMATCH => "contract key proof mismatch",
+            CALL_DEPTH_EXCEEDED => "call depth exceeded",
+            UNSUPPORTED_SIGN_MODE => "unsupported sign mode",
+            INVALID_PUBKEY => "invalid public key",
+            SIGNER_NOT_FOUND => "signer not found",
+            INVALID_SIGNATURE => "invalid signature",
+            SIGNER_MISMATCH => "signer does not match sender",
+            PARAMS_MISMATCH => "message does not match signed tx",
+            INPUT_DECRYPTION_FAILED => "failed to decrypt input",
+            MISSING_API_MARKER => "missing cosmwasm api marker",
+            UNSUPPORTED_IMPORT => "unsupported import",
+            UNSUPPORTED_INSTRUCTION => "unsupported instruction",
+            INVALID_PADDING_BLOCK_SIZE => "invalid padding block size",
+            INVALID_ATTRIBUTE_RECIPIENT => "attribute recipient is not a public key",
+            TOO_MANY_ATTRIBUTE_RECIPIENTS => "too many attribute recipients",
+            _ => return None,
+        })
+    }
+}
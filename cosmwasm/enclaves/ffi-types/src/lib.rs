@@ -1,12 +1,15 @@
 #![no_std]
 #![allow(unused)]
 
+mod errors;
 mod types;
 
+pub use errors::{error_details, EnclaveErrorContext, EnclaveErrorStage};
+
 pub use types::{
-    Ctx, EnclaveBuffer, EnclaveError, HandleResult, HealthCheckResult, InitResult, MigrateResult,
-    NodeAuthResult, OcallReturn, QueryResult, RuntimeConfiguration, UntrustedVmError,
-    UpdateAdminResult, UserSpaceBuffer,
+    AnalyzeCodeResult, Ctx, EnclaveBuffer, EnclaveError, HandleBatchResult, HandleResult,
+    HealthCheckResult, InitResult, MigrateResult, NodeAuthResult, OcallReturn, QueryResult,
+    RuntimeConfiguration, UntrustedVmError, UpdateAdminResult, UserSpaceBuffer,
 };
 
 // On input, the encrypted seed is expected to contain 3 values:
@@ -21,3 +24,36 @@ pub const SINGLE_ENCRYPTED_SEED_SIZE: usize = 48;
 pub const NEWLY_FORMED_SINGLE_ENCRYPTED_SEED_SIZE: usize = SINGLE_ENCRYPTED_SEED_SIZE + 1;
 pub const NEWLY_FORMED_DOUBLE_ENCRYPTED_SEED_SIZE: usize = (2 * SINGLE_ENCRYPTED_SEED_SIZE) + 1;
 pub const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Splits a combined registration certificate, as written by `ecall_get_attestation_report`, into
+/// the EPID certificate, the DCAP quote and the DCAP collateral.
+///
+/// The layout is the sizes of the three parts as little-endian `u32`s, followed by the parts
+/// themselves. If the sizes don't match the input, all three parts are empty.
+pub fn split_combined_cert(cert: &[u8]) -> (&[u8], &[u8], &[u8]) {
+    const HEADER_SIZE: usize = core::mem::size_of::<u32>() * 3;
+
+    if cert.len() < HEADER_SIZE {
+        return (&[], &[], &[]);
+    }
+
+    let size_at = |i: usize| {
+        let mut size = [0u8; 4];
+        size.copy_from_slice(&cert[i * 4..i * 4 + 4]);
+        u32::from_le_bytes(size) as u64
+    };
+    let (s0, s1, s2) = (size_at(0), size_at(1), size_at(2));
+
+    if HEADER_SIZE as u64 + s0 + s1 + s2 > cert.len() as u64 {
+        return (&[], &[], &[]);
+    }
+
+    let (s0, s1, s2) = (s0 as usize, s1 as usize, s2 as usize);
+    let body = &cert[HEADER_SIZE..];
+
+    (
+        &body[..s0],
+        &body[s0..s0 + s1],
+        &body[s0 + s1..s0 + s1 + s2],
+    )
+}
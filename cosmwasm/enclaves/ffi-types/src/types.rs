@@ -3,6 +3,8 @@
 use core::ffi::c_void;
 use derive_more::Display;
 
+use crate::errors::EnclaveErrorContext;
+
 /// This type represents an opaque pointer to a memory address in normal user space.
 #[repr(C)]
 pub struct UserSpaceBuffer {
@@ -78,6 +80,13 @@ pub enum EnclaveError {
     InvalidWasm,
     #[display(fmt = "failed to initialize wasm memory")]
     CannotInitializeWasmMemory,
+    /// The module asked for more initial memory than contracts are allowed to use
+    #[display(
+        fmt = "failed to initialize wasm memory: contract requested {} pages, maximum allowed is {}",
+        requested,
+        allowed
+    )]
+    WasmMemoryLimitExceeded { requested: u32, allowed: u32 },
     /// The WASM module contained a start section, which is not allowed.
     WasmModuleWithStart,
     /// The WASM module contained floating point operations, which is not allowed.
@@ -166,8 +175,13 @@ pub enum EnclaveError {
     Panic,
     #[display(fmt = "enclave ran out of heap memory")]
     OutOfMemory,
-    #[display(fmt = "depth of nested contract calls exceeded")]
-    ExceededRecursionLimit,
+    /// The call is deeper than the configured limit
+    #[display(
+        fmt = "depth of nested contract calls exceeded: call depth {} is above the limit of {}",
+        depth,
+        limit
+    )]
+    ExceededRecursionLimit { depth: u32, limit: u32 },
     /// Unexpected Error happened, no more details available
     #[display(fmt = "unknown error")]
     Unknown,
@@ -177,7 +191,7 @@ pub enum EnclaveError {
 /// enclave while authenticating a new node in the network.
 /// cbindgen:prefix-with-name
 #[repr(C)]
-#[derive(Debug, Display, PartialEq, Eq)]
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy)]
 pub enum NodeAuthResult {
     #[display(fmt = "Enclave quote is valid")]
     Success,
@@ -307,6 +321,10 @@ pub enum InitResult {
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
+        /// The wasm backtrace of `err` if it was a trap, in non-production builds. Null otherwise.
+        backtrace: UserSpaceBuffer,
     },
 }
 
@@ -321,6 +339,28 @@ pub enum HandleResult {
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
+        /// The wasm backtrace of `err` if it was a trap, in non-production builds. Null otherwise.
+        backtrace: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from ecall_handle_batch. The result of each message of the batch is
+/// written separately, this only fails if the batch itself couldn't be read.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum HandleBatchResult {
+    Success {
+        /// The amount of messages that were executed, from the first one on. The batch stops after
+        /// a message that failed or whose response dispatches messages.
+        executed: usize,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
     },
 }
 
@@ -335,6 +375,10 @@ pub enum MigrateResult {
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
+        /// The wasm backtrace of `err` if it was a trap, in non-production builds. Null otherwise.
+        backtrace: UserSpaceBuffer,
     },
 }
 
@@ -346,6 +390,8 @@ pub enum UpdateAdminResult {
     UpdateAdminFailure {
         /// The error that happened in the enclave
         err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
     },
 }
 
@@ -360,5 +406,22 @@ pub enum QueryResult {
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
+        /// The wasm backtrace of `err` if it was a trap, in non-production builds. Null otherwise.
+        backtrace: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from ecall_analyze_code.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum AnalyzeCodeResult {
+    Success,
+    Failure {
+        /// The reason the code was rejected
+        err: EnclaveError,
+        /// Safe to publish details of `err`
+        context: EnclaveErrorContext,
     },
 }
@@ -0,0 +1,71 @@
+use std::io::BufReader;
+use std::vec::Vec;
+
+pub const IAS_REPORT_CA: &[u8] =
+    include_bytes!("../../../execute/Intel_SGX_Attestation_RootCA.pem");
+
+#[derive(Debug)]
+pub enum Error {
+    GenericError,
+}
+
+pub fn extract_asn1_value(cert: &[u8], oid: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut offset = match cert.windows(oid.len()).position(|window| window == oid) {
+        Some(size) => size,
+        None => {
+            return Err(Error::GenericError);
+        }
+    };
+
+    offset += 12; // 11 + TAG (0x04)
+
+    if offset + 2 >= cert.len() {
+        return Err(Error::GenericError);
+    }
+
+    // Obtain Netscape Comment length
+    let mut len = cert[offset] as usize;
+    if len > 0x80 {
+        len = (cert[offset + 1] as usize) * 0x100 + (cert[offset + 2] as usize);
+        offset += 2;
+    }
+
+    // Obtain Netscape Comment
+    offset += 1;
+
+    if offset + len >= cert.len() {
+        return Err(Error::GenericError);
+    }
+
+    let payload = cert[offset..offset + len].to_vec();
+
+    Ok(payload)
+}
+
+pub fn get_netscape_comment(cert_der: &[u8]) -> Result<Vec<u8>, Error> {
+    // Search for Netscape Comment OID
+    let ns_cmt_oid = &[
+        0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x86, 0xF8, 0x42, 0x01, 0x0D,
+    ];
+    extract_asn1_value(cert_der, ns_cmt_oid)
+}
+
+pub fn get_ias_auth_config() -> (Vec<u8>, rustls::RootCertStore) {
+    // Verify if the signing cert is issued by Intel CA
+    let mut ias_ca_stripped = IAS_REPORT_CA.to_vec();
+    ias_ca_stripped.retain(|&x| x != 0x0d && x != 0x0a);
+    let head_len = "-----BEGIN CERTIFICATE-----".len();
+    let tail_len = "-----END CERTIFICATE-----".len();
+    let full_len = ias_ca_stripped.len();
+    let ias_ca_core: &[u8] = &ias_ca_stripped[head_len..full_len - tail_len];
+    let ias_cert_dec = base64::decode_config(ias_ca_core, base64::STANDARD).unwrap();
+
+    let mut ca_reader = BufReader::new(IAS_REPORT_CA);
+
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store
+        .add_pem_file(&mut ca_reader)
+        .expect("Failed to add CA");
+
+    (ias_cert_dec, root_store)
+}
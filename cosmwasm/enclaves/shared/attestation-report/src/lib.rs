@@ -0,0 +1,11 @@
+//! Parsing of the attestation reports and certificates that the enclave produces, shared by the
+//! enclave and by verify-attestation, which audits them offline.
+//!
+//! Inside the enclave this builds against the SGX ports of its dependencies and `sgx_tstd`, and
+//! on the host against their crates.io versions, see the target dependencies in `Cargo.toml`.
+
+extern crate sgx_types;
+
+pub mod cert;
+pub mod report;
+pub mod tcb_report;
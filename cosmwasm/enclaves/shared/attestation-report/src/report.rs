@@ -20,10 +20,7 @@ use uuid::Uuid;
 
 use enclave_ffi_types::NodeAuthResult;
 
-use super::cert::{get_ias_auth_config, get_netscape_comment};
-
-#[cfg(feature = "test")]
-use sgx_types::sgx_quote_t;
+use crate::cert::{get_ias_auth_config, get_netscape_comment};
 
 #[derive(Debug)]
 pub enum Error {
@@ -534,7 +531,7 @@ impl SgxQuote {
     }
 }
 
-#[cfg(all(feature = "SGX_MODE_HW", not(feature = "production")))]
+#[cfg(not(feature = "production"))]
 const WHITELISTED_ADVISORIES: &[&str] = &[
     "INTEL-SA-00334",
     "INTEL-SA-00219",
@@ -543,7 +540,7 @@ const WHITELISTED_ADVISORIES: &[&str] = &[
     "INTEL-SA-00767",
 ];
 
-#[cfg(all(feature = "SGX_MODE_HW", feature = "production"))]
+#[cfg(feature = "production")]
 const WHITELISTED_ADVISORIES: &[&str] = &[
     "INTEL-SA-00334",
     "INTEL-SA-00219",
@@ -571,9 +568,8 @@ lazy_static! {
 #[derive(Debug)]
 pub struct AdvisoryIDs(pub Vec<String>);
 
-#[cfg(feature = "SGX_MODE_HW")]
 impl AdvisoryIDs {
-    pub(crate) fn vulnerable(&self) -> Vec<String> {
+    pub fn vulnerable(&self) -> Vec<String> {
         let mut vulnerable: Vec<String> = vec![];
         for i in self.0.iter() {
             if !WHITELISTED_ADVISORIES.contains(&i.as_str()) {
@@ -608,8 +604,6 @@ impl AttestationReport {
     /// Construct a AttestationReport from a X509 certificate and verify
     /// attestation report with the report_ca_cert which is from the attestation
     /// service provider.
-    // just unused in SW mode
-    #[allow(dead_code)]
     pub fn from_cert(cert: &[u8]) -> Result<Self, Error> {
         let payload = get_netscape_comment(cert).map_err(|_err| {
             error!("Failed to get netscape comment");
@@ -759,8 +753,6 @@ pub mod tests {
     use std::io::Read;
     use std::untrusted::fs::File;
 
-    use crate::registration::attestation::verify_quote_ecdsa;
-
     use super::*;
 
     fn tls_ra_cert_der_test() -> Vec<u8> {
@@ -937,45 +929,4 @@ pub mod tests {
 
         assert!(report.is_ok());
     }
-
-    fn load_attestation_dcap() -> (Vec<u8>, Vec<u8>, i64) {
-        let mut vec_quote = vec![];
-        {
-            let mut f =
-                File::open("../execute/src/registration/fixtures/attestation_dcap.quote").unwrap();
-            f.read_to_end(&mut vec_quote).unwrap();
-        }
-
-        let mut vec_coll = vec![];
-        {
-            let mut f = File::open(
-                "../execute/src/registration/fixtures/attestation_dcap.quote.collateral",
-            )
-            .unwrap();
-            f.read_to_end(&mut vec_coll).unwrap();
-        }
-        (vec_quote, vec_coll, 1709649832)
-    }
-
-    pub fn test_attestation_dcap() {
-        let (vec_quote, vec_coll, time_s) = load_attestation_dcap();
-
-        let res = verify_quote_ecdsa(&vec_quote, &vec_coll, time_s);
-        assert!(res.is_ok());
-    }
-
-    pub fn test_attestation_dcap_temper() {
-        let (mut vec_quote, mut vec_coll, time_s) = load_attestation_dcap();
-
-        // tamper with quote
-        let mut my_p_quote = vec_quote.as_mut_ptr() as *mut sgx_quote_t;
-        unsafe {
-            let mut p_report = (*my_p_quote).report_body;
-            let mut p_data = p_report.report_data;
-            p_data.d[6] = p_data.d[6] ^ 4;
-        };
-
-        let res = verify_quote_ecdsa(&vec_quote, &vec_coll, time_s);
-        assert!(!res.is_ok());
-    }
 }
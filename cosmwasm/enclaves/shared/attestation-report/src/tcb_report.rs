@@ -0,0 +1,187 @@
+//! Structured DCAP TCB information for the platform, as reported by `ecall_check_patch_level`.
+//!
+//! The TCB status and advisory IDs are taken from the TCB info that is part of the quote
+//! collateral, so this must only be used for collateral that was verified by the QvE.
+
+use chrono::DateTime;
+use serde::Serialize;
+use serde_json::Value;
+use sgx_types::sgx_ql_qv_result_t;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// Number of `u32` size fields in the header that precedes the collateral blobs (see
+/// `QlQveCollateral` in sgx-vm)
+const COLLATERAL_HEADER_FIELDS: usize = 8;
+/// Index of the tcb_info size in the collateral header. Every blob before it is skipped
+const COLLATERAL_TCB_INFO_FIELD: usize = 5;
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct DcapTcbReport {
+    pub qv_result: String,
+    /// The status of the platform's TCB level, e.g. `UpToDate` or `SWHardeningNeeded`
+    pub tcb_status: String,
+    pub tcb_date: Option<String>,
+    pub tcb_evaluation_data_number: Option<u64>,
+    pub advisory_ids: Vec<String>,
+    pub collateral_expired: bool,
+}
+
+impl DcapTcbReport {
+    /// `tcb_level_date_tag` is taken from the supplemental data, and identifies the TCB level
+    /// the QvE matched the platform to.
+    pub fn from_verification(
+        qv_result: sgx_ql_qv_result_t,
+        collateral_expired: bool,
+        tcb_level_date_tag: i64,
+        collateral: &[u8],
+    ) -> Self {
+        let mut report = Self {
+            qv_result: format!("{:?}", qv_result),
+            tcb_status: tcb_status_name(qv_result).to_string(),
+            collateral_expired,
+            ..Default::default()
+        };
+
+        let tcb_info = match collateral_tcb_info(collateral)
+            .and_then(|tcb_info| serde_json::from_slice::<Value>(tcb_info).ok())
+        {
+            Some(tcb_info) => tcb_info,
+            None => return report,
+        };
+        let tcb_info = &tcb_info["tcbInfo"];
+
+        report.tcb_evaluation_data_number = tcb_info["tcbEvaluationDataNumber"].as_u64();
+
+        let levels = match tcb_info["tcbLevels"].as_array() {
+            Some(levels) => levels,
+            None => return report,
+        };
+
+        // several levels can share a date, so prefer the one that also matches the QvE result
+        let matching_date = levels.iter().filter(|level| {
+            level["tcbDate"]
+                .as_str()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.timestamp() == tcb_level_date_tag)
+                .unwrap_or(false)
+        });
+        let level = matching_date
+            .clone()
+            .find(|level| level["tcbStatus"].as_str() == Some(report.tcb_status.as_str()))
+            .or_else(|| matching_date.clone().next());
+
+        if let Some(level) = level {
+            if let Some(status) = level["tcbStatus"].as_str() {
+                report.tcb_status = status.to_string();
+            }
+            report.tcb_date = level["tcbDate"].as_str().map(|date| date.to_string());
+            if let Some(ids) = level["advisoryIDs"].as_array() {
+                report.advisory_ids = ids
+                    .iter()
+                    .filter_map(|id| id.as_str().map(|id| id.to_string()))
+                    .collect();
+            }
+        }
+
+        report
+    }
+}
+
+/// Name of the TCB status as it appears in Intel's TCB info
+pub fn tcb_status_name(qv_result: sgx_ql_qv_result_t) -> &'static str {
+    match qv_result {
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK => "UpToDate",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED => "SWHardeningNeeded",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_CONFIG_NEEDED => "ConfigurationNeeded",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_CONFIG_AND_SW_HARDENING_NEEDED => {
+            "ConfigurationAndSWHardeningNeeded"
+        }
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE => "OutOfDate",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE_CONFIG_NEEDED => {
+            "OutOfDateConfigurationNeeded"
+        }
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_REVOKED => "Revoked",
+        _ => "Unknown",
+    }
+}
+
+/// Returns the TCB info JSON from a serialized collateral, without its trailing NUL
+fn collateral_tcb_info(collateral: &[u8]) -> Option<&[u8]> {
+    let mut sizes = [0usize; COLLATERAL_HEADER_FIELDS];
+    for (i, size) in sizes.iter_mut().enumerate() {
+        let mut field = [0u8; 4];
+        field.copy_from_slice(collateral.get(i * 4..i * 4 + 4)?);
+        *size = u32::from_le_bytes(field) as usize;
+    }
+
+    let offs =
+        COLLATERAL_HEADER_FIELDS * 4 + sizes[1..COLLATERAL_TCB_INFO_FIELD].iter().sum::<usize>();
+    let tcb_info = collateral.get(offs..offs + sizes[COLLATERAL_TCB_INFO_FIELD])?;
+
+    match tcb_info.iter().position(|b| *b == 0) {
+        Some(end) => Some(&tcb_info[..end]),
+        None => Some(tcb_info),
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::{collateral_tcb_info, DcapTcbReport};
+    use sgx_types::sgx_ql_qv_result_t;
+    use std::io::Read;
+    use std::untrusted::fs::File;
+    use std::vec::Vec;
+
+    // 2020-11-11T00:00:00Z
+    const TCB_LEVEL_DATE_TAG: i64 = 1605052800;
+
+    fn load_collateral() -> Vec<u8> {
+        let mut vec_coll = vec![];
+        let mut f =
+            File::open("../execute/src/registration/fixtures/attestation_dcap.collateral").unwrap();
+        f.read_to_end(&mut vec_coll).unwrap();
+        vec_coll
+    }
+
+    pub fn test_collateral_tcb_info() {
+        let vec_coll = load_collateral();
+
+        let tcb_info = collateral_tcb_info(&vec_coll).unwrap();
+        assert!(tcb_info.starts_with(b"{\"tcbInfo\":"));
+        assert!(tcb_info.ends_with(b"}"));
+
+        assert!(collateral_tcb_info(&vec_coll[..100]).is_none());
+    }
+
+    pub fn test_tcb_report_from_verification() {
+        let vec_coll = load_collateral();
+
+        let report = DcapTcbReport::from_verification(
+            sgx_ql_qv_result_t::SGX_QL_QV_RESULT_CONFIG_AND_SW_HARDENING_NEEDED,
+            false,
+            TCB_LEVEL_DATE_TAG,
+            &vec_coll,
+        );
+
+        assert_eq!(report.tcb_status, "ConfigurationAndSWHardeningNeeded");
+        assert_eq!(report.tcb_date.as_deref(), Some("2020-11-11T00:00:00Z"));
+        assert_eq!(report.tcb_evaluation_data_number, Some(10));
+        assert!(report.advisory_ids.is_empty());
+    }
+
+    pub fn test_tcb_report_unknown_level() {
+        let vec_coll = load_collateral();
+
+        let report = DcapTcbReport::from_verification(
+            sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE,
+            true,
+            0,
+            &vec_coll,
+        );
+
+        assert_eq!(report.tcb_status, "OutOfDate");
+        assert_eq!(report.tcb_date, None);
+        assert!(report.collateral_expired);
+    }
+}
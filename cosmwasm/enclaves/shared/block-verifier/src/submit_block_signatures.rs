@@ -4,6 +4,7 @@ use tendermint_proto::Protobuf;
 
 use sgx_types::sgx_status_t;
 
+use enclave_utils::metrics;
 use enclave_utils::{validate_const_ptr, validate_input_length, validate_mut_ptr};
 use log::error;
 
@@ -44,6 +45,8 @@ pub unsafe fn submit_block_signatures_impl(
     in_encrypted_random_len: u32,
     decrypted_random: &mut [u8; 32],
 ) -> sgx_status_t {
+    let _timer = metrics::BLOCK_VERIFICATION.start_timer();
+
     if let Err(e) = validate_inputs(
         in_header,
         in_header_len,
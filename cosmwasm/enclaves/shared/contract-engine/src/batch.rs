@@ -0,0 +1,275 @@
+//!
+//! Execution of several messages of the same block in one enclave entry.
+//!
+//! Every message still goes through all the checks of a single `ecall_handle`, but the checks
+//! that only depend on the block or on the contract are done once per batch: the block info is
+//! verified against the light client for the first message, and the key of each contract is
+//! validated the first time the contract shows up.
+//!
+//! All the messages of a batch call the same contract, which the host passes once. The host
+//! encodes the messages one after the other, each as the handle type followed by the env, msg and
+//! sig_info, every one of them prefixed by its length as a big endian u32.
+//!
+//! A batch stops after a message that failed or whose response dispatches messages, because the
+//! host has to deal with those before the next message may run. The host passes the messages
+//! that didn't run in its next batch.
+//!
+use log::*;
+
+use cw_types_generic::BaseEnv;
+use cw_types_v010::types::{CanonicalAddr, ContractKey};
+use enclave_cosmos_types::types::ContractCode;
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::EnclaveError;
+
+use crate::contract_validation::validate_contract_key;
+#[cfg(feature = "light-client-validation")]
+use crate::contract_validation::verify_block_info;
+use crate::external::results::HandleSuccess;
+use crate::io::{V010WasmOutput, V1WasmOutput, WasmOutput};
+
+/// One message of a batch, borrowed from the batch buffer
+pub struct BatchedHandle<'a> {
+    pub handle_type: u8,
+    pub env: &'a [u8],
+    pub msg: &'a [u8],
+    pub sig_info: &'a [u8],
+}
+
+/// Splits the batch buffer into its messages. `count` is the amount of messages the host says
+/// it sent, and the buffer must hold exactly that many.
+pub fn decode_batch(mut batch: &[u8], count: usize) -> Result<Vec<BatchedHandle>, EnclaveError> {
+    let mut handles = Vec::with_capacity(count);
+
+    while !batch.is_empty() {
+        let handle_type = batch[0];
+        batch = &batch[1..];
+
+        let env = take_field(&mut batch)?;
+        let msg = take_field(&mut batch)?;
+        let sig_info = take_field(&mut batch)?;
+
+        handles.push(BatchedHandle {
+            handle_type,
+            env,
+            msg,
+            sig_info,
+        });
+    }
+
+    if handles.len() != count {
+        warn!(
+            "Batch holds {} messages, but {} were expected",
+            handles.len(),
+            count
+        );
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+
+    Ok(handles)
+}
+
+fn take_field<'a>(batch: &mut &'a [u8]) -> Result<&'a [u8], EnclaveError> {
+    const LENGTH_SIZE: usize = std::mem::size_of::<u32>();
+
+    if batch.len() < LENGTH_SIZE {
+        warn!("Batch ended in the middle of a field length");
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+    let (length, rest) = batch.split_at(LENGTH_SIZE);
+    let mut length_bytes = [0u8; LENGTH_SIZE];
+    length_bytes.copy_from_slice(length);
+    let length = u32::from_be_bytes(length_bytes) as usize;
+
+    if rest.len() < length {
+        warn!("Batch ended in the middle of a field");
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+    let (field, rest) = rest.split_at(length);
+    *batch = rest;
+    Ok(field)
+}
+
+/// Whether the batch has to stop after a message with this result: after a failure, and after a
+/// response that dispatches messages
+pub fn ends_batch(result: &Result<HandleSuccess, EnclaveError>) -> bool {
+    let output = match result {
+        Ok(HandleSuccess { output }) => output,
+        Err(_) => return true,
+    };
+
+    match serde_json::from_slice::<WasmOutput>(output) {
+        Ok(WasmOutput {
+            v010: Some(V010WasmOutput { ok: Some(ok), .. }),
+            ..
+        }) => !ok.messages.is_empty(),
+        Ok(WasmOutput {
+            v1: Some(V1WasmOutput { ok: Some(ok), .. }),
+            ..
+        }) => !ok.messages.is_empty(),
+        // errors the contract returned
+        _ => true,
+    }
+}
+
+/// The gas that's left for the messages of a batch that didn't run yet
+pub struct BatchGas {
+    left: u64,
+}
+
+impl BatchGas {
+    pub fn new(gas_left: u64) -> Self {
+        Self { left: gas_left }
+    }
+
+    /// The gas limit of the next message, whose own limit is `gas_limit`
+    pub fn limit_for(&self, gas_limit: u64) -> u64 {
+        gas_limit.min(self.left)
+    }
+
+    /// Charges the batch for the gas a message used
+    pub fn charge(&mut self, used_gas: u64) {
+        self.left = self.left.saturating_sub(used_gas);
+    }
+}
+
+/// The checks that were already done for the messages of a batch that ran so far. A single
+/// `ecall_handle` uses an empty one, so it always runs every check.
+#[derive(Default)]
+pub struct BlockBatch {
+    /// The height and time of the block that were verified against the light client
+    #[cfg_attr(not(feature = "light-client-validation"), allow(dead_code))]
+    verified_block: Option<(u64, u64)>,
+    validated_contract_keys: Vec<ValidatedContractKey>,
+}
+
+struct ValidatedContractKey {
+    contract_address: CanonicalAddr,
+    code_hash: [u8; HASH_SIZE],
+    contract_key: Option<ContractKey>,
+}
+
+impl BlockBatch {
+    #[cfg(feature = "light-client-validation")]
+    pub fn verify_block_info(&mut self, base_env: &BaseEnv) -> Result<(), EnclaveError> {
+        let block = (base_env.0.block.height, base_env.0.block.time);
+        if self.verified_block == Some(block) {
+            return Ok(());
+        }
+
+        verify_block_info(base_env)?;
+        self.verified_block = Some(block);
+        Ok(())
+    }
+
+    pub fn validate_contract_key(
+        &mut self,
+        base_env: &BaseEnv,
+        canonical_contract_address: &CanonicalAddr,
+        contract_code: &ContractCode,
+    ) -> Result<(), EnclaveError> {
+        let code_hash = contract_code.hash();
+        let already_validated = self.validated_contract_keys.iter().any(|validated| {
+            validated.contract_address == *canonical_contract_address
+                && validated.code_hash == code_hash
+                && validated.contract_key == base_env.0.contract_key
+        });
+        if already_validated {
+            trace!("Contract key was already validated in this batch");
+            return Ok(());
+        }
+
+        validate_contract_key(base_env, canonical_contract_address, contract_code)?;
+        self.validated_contract_keys.push(ValidatedContractKey {
+            contract_address: canonical_contract_address.clone(),
+            code_hash,
+            contract_key: base_env.0.contract_key.clone(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn encode(handle_type: u8, fields: [&[u8]; 3]) -> Vec<u8> {
+        let mut encoded = vec![handle_type];
+        for field in fields.iter() {
+            encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(field);
+        }
+        encoded
+    }
+
+    pub fn test_decode_batch() {
+        let mut batch = encode(0, [b"env", b"msg", b"sig"]);
+        batch.extend(encode(1, [b"", b"msg2", b"sig2"]));
+
+        let handles = decode_batch(&batch, 2).unwrap();
+        assert_eq!(handles.len(), 2);
+        assert_eq!(handles[0].handle_type, 0);
+        assert_eq!(handles[0].env, b"env");
+        assert_eq!(handles[0].msg, b"msg");
+        assert_eq!(handles[1].handle_type, 1);
+        assert_eq!(handles[1].env, b"");
+        assert_eq!(handles[1].sig_info, b"sig2");
+    }
+
+    pub fn test_decode_batch_rejects_malformed() {
+        let batch = encode(0, [b"env", b"msg", b"sig"]);
+
+        assert!(decode_batch(&batch, 2).is_err());
+        assert!(decode_batch(&batch[..batch.len() - 1], 1).is_err());
+        assert!(decode_batch(&batch[..3], 1).is_err());
+    }
+
+    pub fn test_ends_batch() {
+        use cw_types_v010::types::{ContractResult, CosmosMsg, Empty};
+
+        let output = |messages: Vec<CosmosMsg>| {
+            let output = WasmOutput {
+                v010: Some(V010WasmOutput {
+                    ok: Some(ContractResult {
+                        messages,
+                        log: vec![],
+                        data: None,
+                    }),
+                    err: None,
+                }),
+                ..WasmOutput::default()
+            };
+            Ok(HandleSuccess {
+                output: serde_json::to_vec(&output).unwrap(),
+            })
+        };
+
+        assert!(!ends_batch(&output(vec![])));
+        assert!(ends_batch(&output(vec![CosmosMsg::Custom(Empty {})])));
+
+        let contract_error = WasmOutput {
+            v010: Some(V010WasmOutput {
+                ok: None,
+                err: Some(serde_json::json!({"generic_err": {"msg": "failed"}})),
+            }),
+            ..WasmOutput::default()
+        };
+        assert!(ends_batch(&Ok(HandleSuccess {
+            output: serde_json::to_vec(&contract_error).unwrap(),
+        })));
+        assert!(ends_batch(&Err(EnclaveError::FailedFunctionCall)));
+    }
+
+    pub fn test_batch_gas_is_charged_cumulatively() {
+        let mut batch_gas = BatchGas::new(100);
+
+        assert_eq!(batch_gas.limit_for(60), 60);
+        batch_gas.charge(60);
+        // the second message may only use what the first one left, whatever its own limit
+        assert_eq!(batch_gas.limit_for(60), 40);
+        batch_gas.charge(40);
+        assert_eq!(batch_gas.limit_for(60), 0);
+        batch_gas.charge(10);
+        assert_eq!(batch_gas.limit_for(60), 0);
+    }
+}
@@ -0,0 +1,22 @@
+//!
+//! Validation of contract code when it's stored.
+//!
+//! The host used to validate uploads on its own, while the enclave only ran its own checks when a
+//! contract was first instantiated, so the two could disagree and broken contracts were only caught
+//! after they were on chain. Once the chain set its engine params, the enclave runs all of its
+//! checks when the code is stored, under those params, and the upload fails if any of them does.
+//!
+use enclave_cosmos_types::types::ContractCode;
+use enclave_ffi_types::EnclaveError;
+
+use crate::engine_params::EngineParams;
+use crate::wasm3::module_cache::analyze_new_code;
+
+/// Validates code that is being stored under the given JSON encoded engine params
+pub fn analyze_code(code: &[u8], engine_params: &[u8]) -> Result<(), EnclaveError> {
+    let engine_params = EngineParams::from_slice(engine_params)?;
+    let contract_code = ContractCode::new(code);
+    analyze_new_code(&contract_code, &engine_params)?;
+
+    Ok(())
+}
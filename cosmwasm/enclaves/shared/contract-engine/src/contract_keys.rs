@@ -0,0 +1,323 @@
+//!
+//! Keys owned by contracts.
+//!
+//! Every contract can derive its own keys from the consensus seed, its contract key and a path of
+//! its choosing. The keys are the same on every node and survive migrations, since they're bound
+//! to the original contract key. The signing and key agreement keys never leave the enclave:
+//! contracts only get to see the public keys and whatever is computed with them, so they can
+//! custody keys for other chains without keeping the private keys in their state.
+//!
+//! The one exception is `ContractKeys::secret`, which returns a symmetric secret to the contract,
+//! e.g. for encrypting data it keeps outside its state. It's derived for its own purpose, so it
+//! can never reveal one of the private keys.
+//!
+use core::convert::TryInto;
+
+use log::*;
+
+use enclave_crypto::{hkdf_sha_256, AESKey, KeyPair, WasmApiCryptoError, HASH_SIZE, KEY_MANAGER};
+
+use crate::contract_validation::ContractKey;
+
+/// Paths are picked by the contract, this just keeps them from getting silly
+pub const MAX_KEY_PATH_LENGTH: usize = 256;
+
+/// Each purpose derives a different key from the same path, so e.g. a signing key can never be
+/// read out through `contract_secret`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyPurpose {
+    Secret,
+    Secp256k1,
+    Ed25519,
+    X25519,
+}
+
+impl KeyPurpose {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            KeyPurpose::Secret => b"secret",
+            KeyPurpose::Secp256k1 => b"secp256k1",
+            KeyPurpose::Ed25519 => b"ed25519",
+            KeyPurpose::X25519 => b"x25519",
+        }
+    }
+}
+
+fn derive_from_root(
+    root: &AESKey,
+    contract_key: &ContractKey,
+    purpose: KeyPurpose,
+    path: &[u8],
+) -> AESKey {
+    // contract_key has a fixed length and no purpose is a prefix of another, so the concatenation
+    // of the info parts is unambiguous
+    hkdf_sha_256(
+        root.get(),
+        &[contract_key.as_slice(), purpose.as_bytes(), path],
+    )
+}
+
+/// The keys of a single contract. All errors are meant to be returned to the contract.
+pub struct ContractKeys<'a> {
+    root: AESKey,
+    contract_key: &'a ContractKey,
+}
+
+impl<'a> ContractKeys<'a> {
+    pub fn new(contract_key: &'a ContractKey) -> Result<Self, WasmApiCryptoError> {
+        let root = KEY_MANAGER
+            .get_contract_owned_keys_secret()
+            .map_err(|err| {
+                error!(
+                    "Failed to get the root of the contract owned keys: {:?}",
+                    err
+                );
+                WasmApiCryptoError::GenericErr
+            })?;
+
+        Ok(Self::from_root(root, contract_key))
+    }
+
+    fn from_root(root: AESKey, contract_key: &'a ContractKey) -> Self {
+        Self { root, contract_key }
+    }
+
+    fn derive(&self, purpose: KeyPurpose, path: &[u8]) -> Result<AESKey, WasmApiCryptoError> {
+        if path.len() > MAX_KEY_PATH_LENGTH {
+            return Err(WasmApiCryptoError::InvalidKeyPathFormat);
+        }
+
+        Ok(derive_from_root(
+            &self.root,
+            self.contract_key,
+            purpose,
+            path,
+        ))
+    }
+
+    fn secp256k1_signing_key(
+        &self,
+        path: &[u8],
+    ) -> Result<secp256k1::SecretKey, WasmApiCryptoError> {
+        let key = self.derive(KeyPurpose::Secp256k1, path)?;
+
+        // Only fails if the key is zero or not below the curve order, which hkdf output won't be
+        secp256k1::SecretKey::from_slice(key.get()).map_err(|err| {
+            error!("Derived an invalid secp256k1 key: {:?}", err);
+            WasmApiCryptoError::GenericErr
+        })
+    }
+
+    fn ed25519_signing_key(
+        &self,
+        path: &[u8],
+    ) -> Result<ed25519_zebra::SigningKey, WasmApiCryptoError> {
+        let key = self.derive(KeyPurpose::Ed25519, path)?;
+
+        Ok(ed25519_zebra::SigningKey::from(*key.get()))
+    }
+
+    fn x25519_keypair(&self, path: &[u8]) -> Result<KeyPair, WasmApiCryptoError> {
+        let key = self.derive(KeyPurpose::X25519, path)?;
+
+        Ok(KeyPair::from(key))
+    }
+
+    /// A symmetric secret for the contract to use as it likes
+    pub fn secret(&self, path: &[u8]) -> Result<Vec<u8>, WasmApiCryptoError> {
+        Ok(self.derive(KeyPurpose::Secret, path)?.get().to_vec())
+    }
+
+    /// The compressed, 33 byte, public key
+    pub fn secp256k1_pubkey(&self, path: &[u8]) -> Result<Vec<u8>, WasmApiCryptoError> {
+        let signing_key = self.secp256k1_signing_key(path)?;
+
+        let secp = secp256k1::Secp256k1::new();
+        Ok(secp256k1::PublicKey::from_secret_key(&secp, &signing_key)
+            .serialize()
+            .to_vec())
+    }
+
+    /// Returns the compact signature on `message_hash`.
+    ///
+    /// Unlike `secp256k1_sign`, this signs a hash and not a message, since other chains don't
+    /// necessarily use sha256
+    pub fn secp256k1_sign(
+        &self,
+        path: &[u8],
+        message_hash: &[u8],
+    ) -> Result<Vec<u8>, WasmApiCryptoError> {
+        if message_hash.len() != HASH_SIZE {
+            return Err(WasmApiCryptoError::InvalidHashFormat);
+        }
+
+        let secp256k1_msg = secp256k1::Message::from_slice(message_hash).map_err(|err| {
+            debug!(
+                "Failed to create a secp256k1 message from message_hash: {:?}",
+                err
+            );
+            WasmApiCryptoError::GenericErr
+        })?;
+
+        let signing_key = self.secp256k1_signing_key(path)?;
+
+        let secp = secp256k1::Secp256k1::new();
+        Ok(secp
+            .sign_ecdsa(&secp256k1_msg, &signing_key)
+            .serialize_compact()
+            .to_vec())
+    }
+
+    pub fn ed25519_pubkey(&self, path: &[u8]) -> Result<Vec<u8>, WasmApiCryptoError> {
+        let signing_key = self.ed25519_signing_key(path)?;
+
+        let pubkey: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+        Ok(pubkey.to_vec())
+    }
+
+    pub fn ed25519_sign(&self, path: &[u8], message: &[u8]) -> Result<Vec<u8>, WasmApiCryptoError> {
+        let signing_key = self.ed25519_signing_key(path)?;
+
+        let sig: [u8; 64] = signing_key.sign(message).into();
+        Ok(sig.to_vec())
+    }
+
+    pub fn x25519_pubkey(&self, path: &[u8]) -> Result<Vec<u8>, WasmApiCryptoError> {
+        Ok(self.x25519_keypair(path)?.get_pubkey().to_vec())
+    }
+
+    /// The shared secret of the key at `path` and `public_key`
+    pub fn x25519_ecdh(
+        &self,
+        path: &[u8],
+        public_key: &[u8],
+    ) -> Result<Vec<u8>, WasmApiCryptoError> {
+        let public_key: [u8; 32] = public_key
+            .try_into()
+            .map_err(|_| WasmApiCryptoError::InvalidPubkeyFormat)?;
+
+        Ok(self
+            .x25519_keypair(path)?
+            .diffie_hellman(&public_key)
+            .to_vec())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::{derive_from_root, ContractKeys, KeyPurpose, MAX_KEY_PATH_LENGTH};
+    use core::convert::TryFrom;
+    use enclave_crypto::{AESKey, WasmApiCryptoError};
+
+    pub fn test_contract_keys_derivation() {
+        let root = AESKey::new_from_slice(&[7u8; 32]);
+        let contract_a = [1u8; 64];
+        let contract_b = [2u8; 64];
+
+        let key = derive_from_root(&root, &contract_a, KeyPurpose::Secp256k1, b"eth/0");
+
+        // deterministic
+        assert_eq!(
+            key.get(),
+            derive_from_root(&root, &contract_a, KeyPurpose::Secp256k1, b"eth/0").get()
+        );
+
+        // separated by contract, purpose and path
+        assert_ne!(
+            key.get(),
+            derive_from_root(&root, &contract_b, KeyPurpose::Secp256k1, b"eth/0").get()
+        );
+        assert_ne!(
+            key.get(),
+            derive_from_root(&root, &contract_a, KeyPurpose::Secret, b"eth/0").get()
+        );
+        assert_ne!(
+            key.get(),
+            derive_from_root(&root, &contract_a, KeyPurpose::Secp256k1, b"eth/1").get()
+        );
+
+        // and by root
+        let other_root = AESKey::new_from_slice(&[8u8; 32]);
+        assert_ne!(
+            key.get(),
+            derive_from_root(&other_root, &contract_a, KeyPurpose::Secp256k1, b"eth/0").get()
+        );
+    }
+
+    pub fn test_contract_keys_secp256k1_sign() {
+        let contract_key = [1u8; 64];
+        let keys = ContractKeys::from_root(AESKey::new_from_slice(&[7u8; 32]), &contract_key);
+
+        let message_hash = [3u8; 32];
+        let sig = keys.secp256k1_sign(b"eth/0", &message_hash).unwrap();
+        let pubkey = keys.secp256k1_pubkey(b"eth/0").unwrap();
+        assert_eq!(pubkey.len(), 33);
+
+        let secp = secp256k1::Secp256k1::verification_only();
+        let msg = secp256k1::Message::from_slice(&message_hash).unwrap();
+        let sig = secp256k1::ecdsa::Signature::from_compact(&sig).unwrap();
+        let pubkey = secp256k1::PublicKey::from_slice(&pubkey).unwrap();
+        assert!(secp.verify_ecdsa(&msg, &sig, &pubkey).is_ok());
+
+        // signed with the key of this path only
+        let other_pubkey = keys.secp256k1_pubkey(b"eth/1").unwrap();
+        let other_pubkey = secp256k1::PublicKey::from_slice(&other_pubkey).unwrap();
+        assert!(secp.verify_ecdsa(&msg, &sig, &other_pubkey).is_err());
+
+        assert!(matches!(
+            keys.secp256k1_sign(b"eth/0", &message_hash[..31]),
+            Err(WasmApiCryptoError::InvalidHashFormat)
+        ));
+        assert!(matches!(
+            keys.secp256k1_sign(&[0u8; MAX_KEY_PATH_LENGTH + 1], &message_hash),
+            Err(WasmApiCryptoError::InvalidKeyPathFormat)
+        ));
+    }
+
+    pub fn test_contract_keys_ed25519_sign() {
+        let contract_key = [1u8; 64];
+        let keys = ContractKeys::from_root(AESKey::new_from_slice(&[7u8; 32]), &contract_key);
+
+        let sig = keys.ed25519_sign(b"sol/0", b"message").unwrap();
+        let pubkey = keys.ed25519_pubkey(b"sol/0").unwrap();
+
+        let sig = ed25519_zebra::Signature::try_from(sig.as_slice()).unwrap();
+        let pubkey = ed25519_zebra::VerificationKey::try_from(pubkey.as_slice()).unwrap();
+        assert!(pubkey.verify(&sig, b"message").is_ok());
+        assert!(pubkey.verify(&sig, b"another message").is_err());
+    }
+
+    pub fn test_contract_keys_x25519_ecdh() {
+        let root = AESKey::new_from_slice(&[7u8; 32]);
+        let contract_a = [1u8; 64];
+        let contract_b = [2u8; 64];
+        let keys_a = ContractKeys::from_root(root, &contract_a);
+        let keys_b = ContractKeys::from_root(root, &contract_b);
+
+        let pubkey_a = keys_a.x25519_pubkey(b"dh").unwrap();
+        let pubkey_b = keys_b.x25519_pubkey(b"dh").unwrap();
+        assert_ne!(pubkey_a, pubkey_b);
+
+        let shared_a = keys_a.x25519_ecdh(b"dh", &pubkey_b).unwrap();
+        let shared_b = keys_b.x25519_ecdh(b"dh", &pubkey_a).unwrap();
+        assert_eq!(shared_a, shared_b);
+
+        assert!(matches!(
+            keys_a.x25519_ecdh(b"dh", &pubkey_b[..31]),
+            Err(WasmApiCryptoError::InvalidPubkeyFormat)
+        ));
+    }
+
+    pub fn test_contract_secret_is_not_a_private_key() {
+        let contract_key = [1u8; 64];
+        let keys = ContractKeys::from_root(AESKey::new_from_slice(&[7u8; 32]), &contract_key);
+
+        let secret = keys.secret(b"path").unwrap();
+        let signing_key = keys.secp256k1_signing_key(b"path").unwrap();
+        assert_ne!(secret.as_slice(), &signing_key.secret_bytes()[..]);
+        assert_ne!(
+            secret.as_slice(),
+            keys.x25519_keypair(b"path").unwrap().get_privkey()
+        );
+    }
+}
@@ -11,8 +11,10 @@ use cw_types_v010::types::CanonicalAddr;
 use enclave_cosmos_types::types::{ContractCode, HandleType, SigInfo, VerifyParamsType};
 use enclave_crypto::Ed25519PublicKey;
 use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_utils::recursion_depth;
 use log::*;
 
+use crate::batch::BlockBatch;
 use crate::cosmwasm_config::ContractOperation;
 
 #[cfg(feature = "light-client-validation")]
@@ -39,7 +41,7 @@ use crate::hardcoded_admins::is_hardcoded_contract_admin;
 use super::contract_validation::{
     generate_contract_key, validate_contract_key, validate_msg, verify_params, ContractKey,
 };
-use super::gas::WasmCosts;
+use super::engine_params::{EngineParams, EngineRules};
 use super::io::{
     finalize_raw_output, manipulate_callback_sig_for_plaintext, post_process_output,
     set_all_logs_to_plaintext,
@@ -92,7 +94,8 @@ pub fn init(
 
     // let duration = start.elapsed();
     // trace!("Time elapsed in extract_base_env is: {:?}", duration);
-    let query_depth = extract_query_depth(env)?;
+    let rules = extract_engine_rules(env)?;
+    let call_depth = extract_call_depth(env, &rules)?;
 
     //let start = Instant::now();
     let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
@@ -157,10 +160,11 @@ pub fn init(
     let mut engine = start_engine(
         context,
         gas_limit,
+        rules,
         &contract_code,
         &og_contract_key,
         ContractOperation::Init,
-        query_depth,
+        call_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
@@ -215,6 +219,7 @@ pub fn init(
         &canonical_sender_address,
         false,
         false,
+        engine.padding_block_size(),
     )?;
 
     // let duration = start.elapsed();
@@ -290,7 +295,8 @@ pub fn migrate(
 
     // let duration = start.elapsed();
     // trace!("Time elapsed in extract_base_env is: {:?}", duration);
-    let query_depth = extract_query_depth(env)?;
+    let rules = extract_engine_rules(env)?;
+    let call_depth = extract_call_depth(env, &rules)?;
 
     //let start = Instant::now();
     let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
@@ -363,10 +369,11 @@ pub fn migrate(
     let mut engine = start_engine(
         context,
         gas_limit,
+        rules,
         &contract_code,
         &og_contract_key,
         ContractOperation::Migrate,
-        query_depth,
+        call_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
@@ -416,6 +423,7 @@ pub fn migrate(
         &canonical_sender_address,
         false,
         false,
+        engine.padding_block_size(),
     )?;
 
     // let duration = start.elapsed();
@@ -521,6 +529,32 @@ pub fn handle(
     msg: &[u8],
     sig_info: &[u8],
     handle_type: u8,
+) -> Result<HandleSuccess, EnclaveError> {
+    handle_in_batch(
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        env,
+        msg,
+        sig_info,
+        handle_type,
+        &mut BlockBatch::default(),
+    )
+}
+
+/// Like `handle`, but skips the checks that were already done for earlier messages of `batch`
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+pub fn handle_in_batch(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8],
+    sig_info: &[u8],
+    handle_type: u8,
+    batch: &mut BlockBatch,
 ) -> Result<HandleSuccess, EnclaveError> {
     trace!("Starting handle");
 
@@ -535,15 +569,16 @@ pub fn handle(
     let base_env: BaseEnv = extract_base_env(env)?;
 
     #[cfg(feature = "light-client-validation")]
-    verify_block_info(&base_env)?;
+    batch.verify_block_info(&base_env)?;
 
-    let query_depth = extract_query_depth(env)?;
+    let rules = extract_engine_rules(env)?;
+    let call_depth = extract_call_depth(env, &rules)?;
 
     let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
 
     let canonical_contract_address = to_canonical(contract_address)?;
 
-    validate_contract_key(&base_env, &canonical_contract_address, &contract_code)?;
+    batch.validate_contract_key(&base_env, &canonical_contract_address, &contract_code)?;
 
     let parsed_sig_info: SigInfo = extract_sig_info(sig_info)?;
 
@@ -609,10 +644,11 @@ pub fn handle(
     let mut engine = start_engine(
         context,
         gas_limit,
+        rules,
         &contract_code,
         &og_contract_key,
         ContractOperation::Handle,
-        query_depth,
+        call_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
@@ -688,6 +724,7 @@ pub fn handle(
             &canonical_sender_address,
             false,
             is_ibc_msg(parsed_handle_type),
+            engine.padding_block_size(),
         )?;
     } else {
         let mut raw_output =
@@ -745,7 +782,8 @@ pub fn query(
     let contract_hash = contract_code.hash();
 
     let base_env: BaseEnv = extract_base_env(env)?;
-    let query_depth = extract_query_depth(env)?;
+    let rules = extract_engine_rules(env)?;
+    let call_depth = extract_call_depth(env, &rules)?;
 
     let (_, contract_address, _, _) = base_env.get_verification_params();
 
@@ -769,10 +807,11 @@ pub fn query(
     let mut engine = start_engine(
         context,
         gas_limit,
+        rules,
         &contract_code,
         &og_contract_key,
         ContractOperation::Query,
-        query_depth,
+        call_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
@@ -797,6 +836,7 @@ pub fn query(
         &CanonicalAddr(Binary(Vec::new())), // Not used for queries (used only for replies)
         true,
         false,
+        engine.padding_block_size(),
     )?;
 
     Ok(QuerySuccess { output })
@@ -806,10 +846,11 @@ pub fn query(
 fn start_engine(
     context: Ctx,
     gas_limit: u64,
+    rules: EngineRules,
     contract_code: &ContractCode,
     og_contract_key: &ContractKey,
     operation: ContractOperation,
-    query_depth: u32,
+    call_depth: u32,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     timestamp: u64,
@@ -817,13 +858,13 @@ fn start_engine(
     crate::wasm3::Engine::new(
         context,
         gas_limit,
-        WasmCosts::default(),
+        rules,
         contract_code,
         *og_contract_key,
         operation,
         nonce,
         user_public_key,
-        query_depth,
+        call_depth,
         timestamp,
     )
 }
@@ -845,17 +886,18 @@ fn extract_base_env(env: &[u8]) -> Result<BaseEnv, EnclaveError> {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct EnvWithQD {
-    query_depth: u32,
+struct EnvWithCallDepth {
+    call_depth: u32,
 }
 
-/// Extract the query_depth from the env parameter.
+/// Extract the call_depth from the env parameter, and make sure a call this deep may run under the
+/// given rules. The host reports the depth, see `enclave_utils::recursion_depth`.
 ///
 /// This is done in a separate method and type definition in order
-/// to simplify the code and avoid further coupling of the query depth
+/// to simplify the code and avoid further coupling of the call depth
 /// parameter and the CW Env type.
-fn extract_query_depth(env: &[u8]) -> Result<u32, EnclaveError> {
-    serde_json::from_slice::<EnvWithQD>(env)
+fn extract_call_depth(env: &[u8], rules: &EngineRules) -> Result<u32, EnclaveError> {
+    let call_depth = serde_json::from_slice::<EnvWithCallDepth>(env)
         .map_err(|err| {
             warn!(
                 "error while deserializing env into json {:?}: {}",
@@ -865,7 +907,67 @@ fn extract_query_depth(env: &[u8]) -> Result<u32, EnclaveError> {
             EnclaveError::FailedToDeserialize
         })
         .map(|env| {
-            trace!("env.query_depth: {:?}", env);
-            env.query_depth
+            trace!("env.call_depth: {:?}", env);
+            env.call_depth
+        })?;
+
+    if rules.limits_every_call() {
+        recursion_depth::check_call_depth(call_depth, rules.max_call_depth())?;
+    }
+    Ok(call_depth)
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvWithEngineParams {
+    #[serde(default)]
+    engine_params: Option<EngineParams>,
+}
+
+/// Extract the rules the call runs under from the env parameter. The host leaves the engine params
+/// out of the env until the chain sets them.
+fn extract_engine_rules(env: &[u8]) -> Result<EngineRules, EnclaveError> {
+    serde_json::from_slice::<EnvWithEngineParams>(env)
+        .map_err(|err| {
+            warn!(
+                "error while deserializing env into json {:?}: {}",
+                String::from_utf8_lossy(env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })
+        .map(|env| {
+            trace!("env.engine_params: {:?}", env);
+            EngineRules::new(env.engine_params)
         })
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_extract_call_depth_follows_engine_rules() {
+        let env = br#"{"call_depth":11}"#;
+
+        // before the chain set its engine params, only nested queries are held to the limit
+        assert!(matches!(
+            extract_call_depth(env, &EngineRules::Legacy),
+            Ok(11)
+        ));
+
+        let rules = EngineRules::Params(EngineParams {
+            max_memory_pages: 192,
+            max_call_depth: 10,
+        });
+        assert!(matches!(
+            extract_call_depth(br#"{"call_depth":10}"#, &rules),
+            Ok(10)
+        ));
+        assert!(matches!(
+            extract_call_depth(env, &rules),
+            Err(EnclaveError::ExceededRecursionLimit {
+                depth: 11,
+                limit: 10
+            })
+        ));
+    }
+}
@@ -12,7 +12,8 @@ use enclave_cosmos_types::types::{
 };
 use enclave_crypto::traits::VerifyingKey;
 use enclave_crypto::{sha_256, AESKey, Hmac, Kdf, HASH_SIZE, KEY_MANAGER};
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+use enclave_utils::error_context::with_context;
 use protobuf::Message;
 
 use crate::hardcoded_admins::is_code_hash_allowed;
@@ -72,12 +73,20 @@ pub fn verify_block_info(base_env: &BaseEnv) -> Result<(), EnclaveError> {
     let verified_msgs = VERIFIED_BLOCK_MESSAGES.lock().unwrap();
     if verified_msgs.height() != base_env.0.block.height {
         error!("wrong height for this block - 0xF6AC");
-        return Err(EnclaveError::ValidationFailure);
+        return Err(with_context(
+            EnclaveError::ValidationFailure,
+            EnclaveErrorStage::BlockVerification,
+            error_details::WRONG_BLOCK_HEIGHT,
+        ));
     }
 
     if verified_msgs.time() != base_env.0.block.time as i128 {
         error!("wrong time for this block - 0xF6AF");
-        return Err(EnclaveError::ValidationFailure);
+        return Err(with_context(
+            EnclaveError::ValidationFailure,
+            EnclaveErrorStage::BlockVerification,
+            error_details::WRONG_BLOCK_TIME,
+        ));
     }
 
     Ok(())
@@ -109,6 +118,11 @@ pub fn check_tx_in_current_block(tx_sign_bytes: &[u8]) -> bool {
 
     if remaining_msgs == 0 {
         error!("Failed to validate message, error 0x3555");
+        enclave_utils::error_context::record(
+            &EnclaveError::ValidationFailure,
+            EnclaveErrorStage::BlockVerification,
+            error_details::NO_VERIFIED_TXS_LEFT,
+        );
         return false;
     }
 
@@ -126,6 +140,11 @@ pub fn check_tx_in_current_block(tx_sign_bytes: &[u8]) -> bool {
     }
 
     error!("Failed to validate message, error 0x3255");
+    enclave_utils::error_context::record(
+        &EnclaveError::ValidationFailure,
+        EnclaveErrorStage::BlockVerification,
+        error_details::TX_NOT_IN_BLOCK,
+    );
 
     // if this message fails to verify we have to fail the rest of the block, so we won't get any other messages
     verified_msgs.clear();
@@ -280,7 +299,11 @@ pub fn validate_current_contract_key(
         Ok(())
     } else {
         warn!("Failed to authenticated the contract");
-        Err(EnclaveError::FailedContractAuthentication)
+        Err(with_context(
+            EnclaveError::FailedContractAuthentication,
+            EnclaveErrorStage::Validation,
+            error_details::CONTRACT_KEY_MISMATCH,
+        ))
     }
 }
 
@@ -316,7 +339,11 @@ pub fn validate_contract_key(
 
         if sent_contract_key_proof != contract_key_proof {
             error!("Failed to validate contract key proof for a migrated contract");
-            return Err(EnclaveError::ValidationFailure);
+            return Err(with_context(
+                EnclaveError::ValidationFailure,
+                EnclaveErrorStage::Validation,
+                error_details::CONTRACT_KEY_PROOF_MISMATCH,
+            ));
         }
 
         Ok(())
@@ -442,7 +469,11 @@ pub fn validate_basic_msg(
 ) -> Result<ValidatedMessage, EnclaveError> {
     if data_for_validation.is_none() && msg.len() < HEX_ENCODED_HASH_SIZE {
         warn!("Malformed message - expected contract code hash to be prepended to the msg");
-        return Err(EnclaveError::ValidationFailure);
+        return Err(with_context(
+            EnclaveError::ValidationFailure,
+            EnclaveErrorStage::Validation,
+            error_details::MISSING_CODE_HASH,
+        ));
     }
 
     let mut received_contract_hash: [u8; HEX_ENCODED_HASH_SIZE] = [0u8; HEX_ENCODED_HASH_SIZE];
@@ -492,7 +523,11 @@ pub fn validate_basic_msg(
 
     let decoded_hash: Vec<u8> = hex::decode(&received_contract_hash[..]).map_err(|_| {
         warn!("Got message with malformed contract hash");
-        EnclaveError::ValidationFailure
+        with_context(
+            EnclaveError::ValidationFailure,
+            EnclaveErrorStage::Validation,
+            error_details::MALFORMED_CODE_HASH,
+        )
     })?;
 
     if decoded_hash != contract_hash {
@@ -501,10 +536,13 @@ pub fn validate_basic_msg(
             warn!("Message contains mismatched contract hash, but it's allowed");
         } else {
             warn!("Message contains mismatched contract hash, and it's not allowed");
-            return Err(EnclaveError::ValidationFailure);
         }
 
-        return Err(EnclaveError::ValidationFailure);
+        return Err(with_context(
+            EnclaveError::ValidationFailure,
+            EnclaveErrorStage::Validation,
+            error_details::CODE_HASH_MISMATCH,
+        ));
     }
 
     while validated_msg.len() >= REPLY_ENCRYPTION_MAGIC_BYTES.len()
@@ -594,7 +632,11 @@ fn verify_signature(sig_info: &SigInfo, sender: &CanonicalAddr) -> Result<(), En
         )
         .map_err(|err| {
             warn!("Signature verification failed: {:?}", err);
-            EnclaveError::FailedTxVerification
+            with_context(
+                EnclaveError::FailedTxVerification,
+                EnclaveErrorStage::SignatureVerification,
+                error_details::INVALID_SIGNATURE,
+            )
         })?;
 
     let signer_addr = sender_public_key.get_address();
@@ -605,7 +647,11 @@ fn verify_signature(sig_info: &SigInfo, sender: &CanonicalAddr) -> Result<(), En
             sender,
             signer_addr
         );
-        return Err(EnclaveError::FailedTxVerification);
+        return Err(with_context(
+            EnclaveError::FailedTxVerification,
+            EnclaveErrorStage::SignatureVerification,
+            error_details::SIGNER_MISMATCH,
+        ));
     }
 
     Ok(())
@@ -641,7 +687,11 @@ fn verify_input(
 
     if !is_verified {
         warn!("Parameter verification failed");
-        return Err(EnclaveError::FailedTxVerification);
+        return Err(with_context(
+            EnclaveError::FailedTxVerification,
+            EnclaveErrorStage::SignatureVerification,
+            error_details::PARAMS_MISMATCH,
+        ));
     }
 
     Ok(())
@@ -663,7 +713,11 @@ fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKe
                     .sender_public_key(sender)
                     .ok_or_else(|| {
                         warn!("Couldn't find message sender in auth_info.signer_infos");
-                        EnclaveError::FailedTxVerification
+                        with_context(
+                            EnclaveError::FailedTxVerification,
+                            EnclaveErrorStage::SignatureVerification,
+                            error_details::SIGNER_NOT_FOUND,
+                        )
                     })?;
 
             Ok(sender_public_key.clone())
@@ -672,11 +726,11 @@ fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKe
             let any_pub_key =
                 AnyProto::parse_from_bytes(&sign_info.public_key.0).map_err(|err| {
                     warn!("failed to parse public key as Any: {:?}", err);
-                    EnclaveError::FailedTxVerification
+                    invalid_pubkey()
                 })?;
             let public_key = CosmosPubKey::from_proto(&any_pub_key).map_err(|err| {
                 warn!("failure to parse pubkey: {:?}", err);
-                EnclaveError::FailedTxVerification
+                invalid_pubkey()
             })?;
 
             Ok(public_key)
@@ -685,11 +739,11 @@ fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKe
             let any_pub_key =
                 AnyProto::parse_from_bytes(&sign_info.public_key.0).map_err(|err| {
                     warn!("failed to parse public key as Any: {:?}", err);
-                    EnclaveError::FailedTxVerification
+                    invalid_pubkey()
                 })?;
             let public_key = CosmosPubKey::from_proto(&any_pub_key).map_err(|err| {
                 warn!("failure to parse pubkey: {:?}", err);
-                EnclaveError::FailedTxVerification
+                invalid_pubkey()
             })?;
 
             Ok(public_key)
@@ -699,11 +753,23 @@ fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKe
                 "get_signer(): unsupported signature mode: {:?}",
                 sign_info.sign_mode
             );
-            Err(EnclaveError::FailedTxVerification)
+            Err(with_context(
+                EnclaveError::FailedTxVerification,
+                EnclaveErrorStage::SignatureVerification,
+                error_details::UNSUPPORTED_SIGN_MODE,
+            ))
         }
     }
 }
 
+fn invalid_pubkey() -> EnclaveError {
+    with_context(
+        EnclaveError::FailedTxVerification,
+        EnclaveErrorStage::SignatureVerification,
+        error_details::INVALID_PUBKEY,
+    )
+}
+
 // extract sdk_messages from sign_bytes
 // sign_byte might be in Amino format
 fn get_sdk_messages_from_sign_bytes(
@@ -7,6 +7,12 @@ pub mod api_marker {
 
 pub mod features {
     pub const RANDOM: &str = "requires_random";
+    /// Optionally followed by `_<block size>`, see `crate::padding`
+    pub const PADDING: &str = "requires_padding";
+    /// Floats are allowed, and their NaNs canonicalized, see `crate::wasm3::nan_canonicalization`
+    pub const NAN_CANONICALIZATION: &str = "requires_nan_canonicalization";
+    /// The name `NAN_CANONICALIZATION` was first asked for under, kept as an alias of it
+    pub const SOFTFLOAT: &str = "requires_softfloat";
 }
 
 /// Right now ContractOperation is used to detect queris and prevent state changes
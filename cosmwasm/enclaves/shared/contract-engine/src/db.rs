@@ -13,6 +13,7 @@ use enclave_crypto::{sha_256, AESKey, Kdf, SIVEncryptable, KEY_MANAGER};
 use crate::external::{ecalls, ocalls};
 
 use enclave_utils::kv_cache::KvCache;
+use enclave_utils::metrics;
 
 use super::contract_validation::ContractKey;
 use super::errors::WasmEngineError;
@@ -51,6 +52,7 @@ pub fn write_multiple_keys(
     let x = serde_json::to_vec(&keys).unwrap();
     let len = x.len();
     let ptr = x.as_ptr();
+    metrics::OCALL_MULTIPLE_WRITE_DB.record(len);
 
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
@@ -336,6 +338,7 @@ fn read_db(context: &Ctx, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), WasmEngi
             OcallReturn::Panic => return Err(WasmEngineError::Panic),
         }
     };
+    metrics::OCALL_READ_DB.record(key.len() + value.as_ref().map_or(0, Vec::len));
 
     Ok((value, gas_used))
 }
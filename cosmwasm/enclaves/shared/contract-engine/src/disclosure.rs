@@ -0,0 +1,318 @@
+//!
+//! Disclosure of the IO keys of past txs to auditors.
+//!
+//! The outputs of a tx can be decrypted by anyone who has the key `calc_encryption_key` derived for
+//! it. Given a disclosure permit (see `enclave_cosmos_types::disclosure`), the enclave re-derives the
+//! keys of the txs the user listed, and returns each of them encrypted to the auditor with the same
+//! scheme, so the auditor decrypts them like any other tx output: with their own x25519 key and the
+//! nonce of the tx.
+//!
+//! Each nonce is listed with the hash of the tx the user took it from. The enclave can't look the
+//! tx up, but the hash is covered by both the wallet signature and the encryption key proof, so
+//! the disclosure labels every key with the tx the user bound it to, and the auditor checks that
+//! this tx carries the nonce.
+//!
+//! Tx outputs are encrypted with the IO exchange key of the consensus seed the chain had at the
+//! time, and the enclave only derives keys and checks proofs with the current one. After the seed
+//! rotates, permits have to be made again against the new IO exchange key, and the keys of txs
+//! encrypted before the rotation can't be disclosed.
+//!
+use log::*;
+use serde::Serialize;
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::HumanAddr;
+use enclave_cosmos_types::disclosure::DisclosurePermit;
+use enclave_crypto::{
+    constant_time_eq, AESKey, Ed25519PublicKey, Hmac, Kdf, KeyPair, SIVEncryptable, KEY_MANAGER,
+};
+use enclave_ffi_types::EnclaveError;
+
+#[derive(Serialize)]
+struct DisclosedTxKey {
+    /// The tx the user bound the nonce to
+    tx_hash: String,
+    /// The nonce the tx was encrypted with, which the auditor matches against the tx
+    nonce: Binary,
+    /// The IO key of the tx, encrypted to the auditor
+    encrypted_key: Binary,
+}
+
+#[derive(Serialize)]
+struct Disclosure {
+    /// The account that signed the permit
+    signer: HumanAddr,
+    encryption_pubkey: Binary,
+    txs: Vec<DisclosedTxKey>,
+}
+
+fn to_x25519_pubkey(key: &Binary, name: &str) -> Result<Ed25519PublicKey, EnclaveError> {
+    if key.len() != 32 {
+        warn!("Disclosure permit has an invalid {}", name);
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+
+    let mut pubkey: Ed25519PublicKey = [0u8; 32];
+    pubkey.copy_from_slice(key.as_slice());
+    Ok(pubkey)
+}
+
+/// Verifies a disclosure permit and returns the JSON encoded disclosure for the auditor
+pub fn disclose_tx_keys(permit: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    let permit = DisclosurePermit::from_bytes(permit).map_err(|_| {
+        warn!("Failed to parse disclosure permit");
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let io_exchange_keypair = KEY_MANAGER
+        .get_consensus_io_exchange_keypair()
+        .map_err(|err| {
+            error!("Failed to get the consensus IO exchange key: {:?}", err);
+            EnclaveError::InternalError
+        })?;
+
+    let disclosure = disclose_permitted_tx_keys(permit, &io_exchange_keypair.current)?;
+
+    serde_json::to_vec(&disclosure).map_err(|err| {
+        error!("Failed to serialize the disclosure: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Same derivation as `calc_encryption_key`, with the IO exchange key passed in
+fn tx_encryption_key(
+    io_exchange_keypair: &KeyPair,
+    nonce: &[u8],
+    user_public_key: &Ed25519PublicKey,
+) -> AESKey {
+    AESKey::new_from_slice(&io_exchange_keypair.diffie_hellman(user_public_key))
+        .derive_key_from_this(nonce)
+}
+
+fn disclose_permitted_tx_keys(
+    permit: DisclosurePermit,
+    io_exchange_keypair: &KeyPair,
+) -> Result<Disclosure, EnclaveError> {
+    let signer = permit.verify().map_err(|err| {
+        warn!("Failed to verify disclosure permit signature: {:?}", err);
+        EnclaveError::ValidationFailure
+    })?;
+
+    let encryption_pubkey =
+        to_x25519_pubkey(&permit.params.encryption_pubkey, "encryption_pubkey")?;
+    let auditor_pubkey = to_x25519_pubkey(&permit.params.auditor_pubkey, "auditor_pubkey")?;
+
+    // Anyone can see the encryption pubkey and nonces of a tx, so make sure whoever made the permit
+    // also holds the private key
+    let tx_encryption_ikm = io_exchange_keypair.diffie_hellman(&encryption_pubkey);
+    let expected_proof =
+        AESKey::new_from_slice(&tx_encryption_ikm).sign_sha_256(&permit.sign_bytes());
+
+    if !constant_time_eq(&expected_proof, permit.encryption_key_proof.as_slice()) {
+        warn!("Disclosure permit has an invalid encryption key proof");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let mut txs = Vec::with_capacity(permit.params.txs.len());
+    for tx in permit.params.txs.iter() {
+        if tx.nonce.len() != 32 {
+            warn!(
+                "Disclosure permit has an invalid nonce for tx {}",
+                tx.tx_hash
+            );
+            return Err(EnclaveError::FailedToDeserialize);
+        }
+
+        let tx_key =
+            tx_encryption_key(io_exchange_keypair, tx.nonce.as_slice(), &encryption_pubkey);
+        let auditor_key =
+            tx_encryption_key(io_exchange_keypair, tx.nonce.as_slice(), &auditor_pubkey);
+
+        let encrypted_key = auditor_key.encrypt_siv(tx_key.get(), None).map_err(|err| {
+            error!("Failed to encrypt the key of tx {}: {:?}", tx.tx_hash, err);
+            EnclaveError::EncryptionError
+        })?;
+
+        txs.push(DisclosedTxKey {
+            tx_hash: tx.tx_hash.clone(),
+            nonce: tx.nonce.clone(),
+            encrypted_key: Binary(encrypted_key),
+        });
+    }
+
+    let signer = HumanAddr::from_canonical(&signer).map_err(|err| {
+        error!("Failed to encode the disclosure signer: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    Ok(Disclosure {
+        signer,
+        encryption_pubkey: permit.params.encryption_pubkey,
+        txs,
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::{disclose_permitted_tx_keys, tx_encryption_key};
+
+    use cw_types_v010::encoding::Binary;
+    use cw_types_v010::types::HumanAddr;
+    use enclave_cosmos_types::disclosure::DisclosurePermit;
+    use enclave_cosmos_types::traits::CosmosAminoPubkey;
+    use enclave_crypto::ed25519::Ed25519PrivateKey;
+    use enclave_crypto::secp256k1::Secp256k1PubKey;
+    use enclave_crypto::{sha_256, AESKey, Hmac, KeyPair, SIVEncryptable};
+    use enclave_ffi_types::EnclaveError;
+
+    fn x25519_keypair(secret: u8) -> KeyPair {
+        let mut secret_key = Ed25519PrivateKey::default();
+        secret_key.get_mut().copy_from_slice(&[secret; 32]);
+        KeyPair::from(secret_key)
+    }
+
+    const TX_HASH: &str = "5D6F1C2B8E3A4F7D9C0B1A2E3F4D5C6B7A8E9F0D1C2B3A4E5F6D7C8B9A0E1F2D";
+
+    /// Builds a permit for one tx, signed by `wallet_key` and proven with `user`
+    fn disclosure_permit(
+        io_exchange_keypair: &KeyPair,
+        wallet_key: &secp256k1::SecretKey,
+        user: &KeyPair,
+        auditor: &KeyPair,
+        nonce: &[u8; 32],
+    ) -> DisclosurePermit {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let wallet_pubkey = secp256k1::PublicKey::from_secret_key(&secp, wallet_key).serialize();
+
+        let permit = serde_json::json!({
+            "params": {
+                "permit_name": "audit",
+                "chain_id": "secret-4",
+                "auditor_pubkey": Binary(auditor.get_pubkey().to_vec()),
+                "encryption_pubkey": Binary(user.get_pubkey().to_vec()),
+                "txs": [{ "nonce": Binary(nonce.to_vec()), "tx_hash": TX_HASH }],
+            },
+            "signature": {
+                "pub_key": {
+                    "type": "tendermint/PubKeySecp256k1",
+                    "value": Binary(wallet_pubkey.to_vec()),
+                },
+                "signature": "",
+            },
+            "encryption_key_proof": "",
+        });
+        let mut permit: DisclosurePermit = serde_json::from_value(permit).unwrap();
+
+        sign_permit(&mut permit, wallet_key);
+        prove_permit(&mut permit, io_exchange_keypair, user);
+        permit
+    }
+
+    fn sign_permit(permit: &mut DisclosurePermit, wallet_key: &secp256k1::SecretKey) {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let msg = secp256k1::Message::from_slice(&sha_256(&permit.sign_bytes())).unwrap();
+        permit.signature.signature = Binary(
+            secp.sign_ecdsa(&msg, wallet_key)
+                .serialize_compact()
+                .to_vec(),
+        );
+    }
+
+    fn prove_permit(permit: &mut DisclosurePermit, io_exchange_keypair: &KeyPair, user: &KeyPair) {
+        permit.encryption_key_proof = Binary(
+            AESKey::new_from_slice(&user.diffie_hellman(&io_exchange_keypair.get_pubkey()))
+                .sign_sha_256(&permit.sign_bytes())
+                .to_vec(),
+        );
+    }
+
+    pub fn test_disclose_tx_keys() {
+        let io_exchange_keypair = x25519_keypair(5);
+        let user = x25519_keypair(6);
+        let auditor = x25519_keypair(7);
+        let wallet_key = secp256k1::SecretKey::from_slice(&[9u8; 32]).unwrap();
+        let nonce = [3u8; 32];
+
+        let permit = disclosure_permit(&io_exchange_keypair, &wallet_key, &user, &auditor, &nonce);
+        let wallet_pubkey = Secp256k1PubKey::new(permit.signature.pub_key.value.0.clone());
+
+        let disclosure = disclose_permitted_tx_keys(permit, &io_exchange_keypair).unwrap();
+
+        assert_eq!(
+            disclosure.signer,
+            HumanAddr::from_canonical(&wallet_pubkey.get_address()).unwrap()
+        );
+        assert_eq!(disclosure.encryption_pubkey.as_slice(), &user.get_pubkey());
+        assert_eq!(disclosure.txs.len(), 1);
+        assert_eq!(disclosure.txs[0].tx_hash, TX_HASH);
+        assert_eq!(disclosure.txs[0].nonce.as_slice(), &nonce);
+
+        // the auditor decrypts the key like any other tx output, and gets the key of the tx
+        let auditor_key = tx_encryption_key(&auditor, &nonce, &io_exchange_keypair.get_pubkey());
+        let tx_key = auditor_key
+            .decrypt_siv(disclosure.txs[0].encrypted_key.as_slice(), None)
+            .unwrap();
+        let expected = tx_encryption_key(&user, &nonce, &io_exchange_keypair.get_pubkey());
+        assert_eq!(tx_key.as_slice(), expected.get());
+    }
+
+    pub fn test_disclose_tx_keys_invalid_proof() {
+        let io_exchange_keypair = x25519_keypair(5);
+        let user = x25519_keypair(6);
+        let auditor = x25519_keypair(7);
+        let wallet_key = secp256k1::SecretKey::from_slice(&[9u8; 32]).unwrap();
+        let nonce = [3u8; 32];
+
+        // proven against another IO exchange key, so by someone who doesn't hold the user's key,
+        // or before the consensus seed rotated
+        let other_io_exchange_keypair = x25519_keypair(8);
+        let permit = disclosure_permit(
+            &other_io_exchange_keypair,
+            &wallet_key,
+            &user,
+            &auditor,
+            &nonce,
+        );
+
+        assert!(matches!(
+            disclose_permitted_tx_keys(permit, &io_exchange_keypair),
+            Err(EnclaveError::ValidationFailure)
+        ));
+    }
+    pub fn test_disclose_tx_keys_binds_tx_hash() {
+        let io_exchange_keypair = x25519_keypair(5);
+        let user = x25519_keypair(6);
+        let auditor = x25519_keypair(7);
+        let wallet_key = secp256k1::SecretKey::from_slice(&[9u8; 32]).unwrap();
+        let nonce = [3u8; 32];
+        let other_tx_hash = "AB12".repeat(16);
+
+        // the nonce is bound to another tx after the user signed the permit
+        let mut permit =
+            disclosure_permit(&io_exchange_keypair, &wallet_key, &user, &auditor, &nonce);
+        permit.params.txs[0].tx_hash = other_tx_hash.clone();
+        assert!(matches!(
+            disclose_permitted_tx_keys(permit, &io_exchange_keypair),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        // and again by someone who holds the wallet key, but not the user's encryption key
+        let mut permit =
+            disclosure_permit(&io_exchange_keypair, &wallet_key, &user, &auditor, &nonce);
+        permit.params.txs[0].tx_hash = other_tx_hash.clone();
+        sign_permit(&mut permit, &wallet_key);
+        assert!(matches!(
+            disclose_permitted_tx_keys(permit, &io_exchange_keypair),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        // the user can bind it to another tx, and the disclosure says so
+        let mut permit =
+            disclosure_permit(&io_exchange_keypair, &wallet_key, &user, &auditor, &nonce);
+        permit.params.txs[0].tx_hash = other_tx_hash.clone();
+        sign_permit(&mut permit, &wallet_key);
+        prove_permit(&mut permit, &io_exchange_keypair, &user);
+        let disclosure = disclose_permitted_tx_keys(permit, &io_exchange_keypair).unwrap();
+        assert_eq!(disclosure.txs[0].tx_hash, other_tx_hash);
+    }
+}
@@ -0,0 +1,102 @@
+//! The consensus critical limits of the contract engine.
+//!
+//! The chain keeps them in its state, and only changes them in the upgrade handler of a release,
+//! so every node switches to new ones at the same height. The host passes them with every call
+//! (see `EngineParams` in go-cosmwasm). Before the chain first set them, calls come without them,
+//! and contracts run under the rules they always ran under.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::gas::WasmCosts;
+
+/// The memory limit contracts always ran under, 12 MiB
+const LEGACY_MAX_MEMORY_PAGES: u32 = 192;
+/// The depth limit nested queries always ran under. Executes, submessages and replies were only
+/// held to the host's limit.
+const LEGACY_MAX_QUERY_DEPTH: u32 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EngineParams {
+    /// The most wasm memory pages (64 KiB each) a contract may use
+    pub max_memory_pages: u32,
+    /// The deepest contract call that may run, counting submessages, replies and queries
+    pub max_call_depth: u32,
+}
+
+impl EngineParams {
+    pub fn from_slice(params: &[u8]) -> Result<Self, EnclaveError> {
+        serde_json::from_slice(params).map_err(|err| {
+            warn!(
+                "error while deserializing engine params from json {:?}: {}",
+                String::from_utf8_lossy(params),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })
+    }
+}
+
+/// The rules a contract call runs under. Modules are instrumented for the rules they run under, so
+/// they're cached per rules too.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EngineRules {
+    /// Calls made before the chain set its engine params
+    Legacy,
+    Params(EngineParams),
+}
+
+impl EngineRules {
+    pub fn new(params: Option<EngineParams>) -> Self {
+        params.map_or(EngineRules::Legacy, EngineRules::Params)
+    }
+
+    /// The most wasm memory pages a contract may use
+    pub fn max_memory_pages(&self) -> u32 {
+        match self {
+            EngineRules::Legacy => LEGACY_MAX_MEMORY_PAGES,
+            EngineRules::Params(params) => params.max_memory_pages,
+        }
+    }
+
+    /// The deepest contract call that may run. Before the chain set its engine params, the enclave
+    /// only held nested queries to it, and the host sent every other call at depth 1.
+    pub fn max_call_depth(&self) -> u32 {
+        match self {
+            EngineRules::Legacy => LEGACY_MAX_QUERY_DEPTH,
+            EngineRules::Params(params) => params.max_call_depth,
+        }
+    }
+
+    /// Whether calls deeper than `max_call_depth` are refused, and not only queries
+    pub fn limits_every_call(&self) -> bool {
+        matches!(self, EngineRules::Params(_))
+    }
+
+    /// Whether contracts may ask for their encrypted outputs to be padded, see `crate::padding`
+    pub fn supports_padding(&self) -> bool {
+        matches!(self, EngineRules::Params(_))
+    }
+
+    /// Whether contracts may use floats, with their NaNs canonicalized, see
+    /// `crate::wasm3::nan_canonicalization`
+    pub fn canonicalizes_nans(&self) -> bool {
+        matches!(self, EngineRules::Params(_))
+    }
+
+    /// Whether contracts that use instructions wasm3 doesn't run are rejected when they're
+    /// instantiated, see `crate::wasm3::validation::validate_instructions`
+    pub fn validates_instructions(&self) -> bool {
+        matches!(self, EngineRules::Params(_))
+    }
+
+    /// The costs contracts are metered with
+    pub fn wasm_costs(&self) -> WasmCosts {
+        match self {
+            EngineRules::Legacy => WasmCosts::legacy(),
+            EngineRules::Params(_) => WasmCosts::default(),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use std::ffi::c_void;
 use std::panic;
+#[cfg(feature = "simulator")]
+use std::sync::Mutex as SgxMutex;
+#[cfg(not(feature = "simulator"))]
 use std::sync::SgxMutex;
 
 use lazy_static::lazy_static;
@@ -7,17 +10,24 @@ use log::*;
 
 use sgx_types::sgx_status_t;
 
+#[cfg(not(feature = "simulator"))]
+use enclave_crypto::HASH_SIZE;
+
 use enclave_ffi_types::{
-    Ctx, EnclaveBuffer, EnclaveError, HandleResult, HealthCheckResult, InitResult, MigrateResult,
-    QueryResult, RuntimeConfiguration, UpdateAdminResult,
+    AnalyzeCodeResult, Ctx, EnclaveBuffer, EnclaveError, HandleBatchResult, HandleResult,
+    HealthCheckResult, InitResult, MigrateResult, QueryResult, RuntimeConfiguration,
+    UpdateAdminResult,
 };
 
-use enclave_utils::{oom_handler, validate_const_ptr, validate_input_length, validate_mut_ptr};
+use enclave_utils::{
+    error_context, oom_handler, validate_const_ptr, validate_input_length, validate_mut_ptr,
+};
 
+use crate::batch::{decode_batch, ends_batch, BatchGas, BatchedHandle, BlockBatch};
 use crate::external::results::{
-    result_handle_success_to_handleresult, result_init_success_to_initresult,
-    result_migrate_success_to_result, result_query_success_to_queryresult,
-    result_update_admin_success_to_result,
+    result_analyze_code_success_to_result, result_handle_success_to_handleresult,
+    result_init_success_to_initresult, result_migrate_success_to_result,
+    result_query_success_to_queryresult, result_update_admin_success_to_result, EcallFailure,
 };
 
 lazy_static! {
@@ -30,6 +40,11 @@ const MAX_MSG_LENGTH: usize = 2_048_000; // 2 MiB
 const MAX_ADDRESS_LENGTH: usize = 65; // canonical can be 20 or 32 bytes, humanized can be 45 or 65
 const MAX_PROOF_LENGTH: usize = 32; // output of sha256
 const MAX_WASM_LENGHT: usize = 3_145_728; // 3 MiB, larger Wasm ATM is 1,990,361 bytes (1.6 MiB)
+#[cfg(not(feature = "simulator"))]
+const MAX_PREWARM_CODE_HASHES_LENGTH: usize = 32_000; // 1000 code hashes
+const MAX_PERMIT_LENGTH: usize = 102_400; // 100 KiB, enough for MAX_DISCLOSED_TXS txs
+const MAX_BATCH_SIZE: usize = 256; // messages
+const MAX_BATCH_LENGTH: usize = 33_554_432; // 32 MiB
 
 /// # Safety
 /// Always use protection
@@ -108,6 +123,52 @@ fn ecall_configure_runtime_impl(config: RuntimeConfiguration) -> sgx_status_t {
     sgx_status_t::SGX_SUCCESS
 }
 
+/// `ecall_prewarm_module_cache`
+///
+/// Loads the sealed modules of the given code hashes into the module cache, so the first calls
+/// after a restart don't have to instrument them again. `code_hashes` is a concatenation of code
+/// hashes, hottest first. Meant to be called in the background, right after
+/// `ecall_configure_runtime`.
+///
+/// # Safety
+/// Always use protection
+#[cfg(not(feature = "simulator"))]
+#[no_mangle]
+pub unsafe extern "C" fn ecall_prewarm_module_cache(
+    code_hashes: *const u8,
+    code_hashes_len: usize,
+) -> sgx_status_t {
+    validate_input_length!(
+        code_hashes_len,
+        "code_hashes",
+        MAX_PREWARM_CODE_HASHES_LENGTH,
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+    validate_const_ptr!(
+        code_hashes,
+        code_hashes_len,
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+
+    let code_hashes = std::slice::from_raw_parts(code_hashes, code_hashes_len);
+    if code_hashes.len() % HASH_SIZE != 0 {
+        warn!("code hashes for prewarming the module cache are malformed");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let code_hashes: Vec<[u8; HASH_SIZE]> = code_hashes
+        .chunks_exact(HASH_SIZE)
+        .map(|code_hash| {
+            let mut hash = [0u8; HASH_SIZE];
+            hash.copy_from_slice(code_hash);
+            hash
+        })
+        .collect();
+    crate::wasm3::module_cache::prewarm_module_cache(&code_hashes);
+
+    sgx_status_t::SGX_SUCCESS
+}
+
 /// Take a pointer as returned by `ecall_allocate` and recover the Vec<u8> inside of it.
 /// # Safety
 ///  This is a text
@@ -153,9 +214,11 @@ pub unsafe extern "C" fn ecall_init(
     admin: *const u8,
     admin_len: usize,
 ) -> InitResult {
+    let _error_frame = error_context::enter();
+
     if let Err(err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return InitResult::Failure { err };
+        return InitResult::failure(err);
     }
 
     let failed_call = || result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
@@ -195,7 +258,7 @@ pub unsafe extern "C" fn ecall_init(
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return InitResult::Failure { err };
+        return InitResult::failure(err);
     }
 
     if let Ok(res) = result {
@@ -205,14 +268,10 @@ pub unsafe extern "C" fn ecall_init(
 
         if oom_handler::get_then_clear_oom_happened() {
             error!("Call ecall_init failed because the enclave ran out of memory!");
-            InitResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
+            InitResult::failure(EnclaveError::OutOfMemory)
         } else {
             error!("Call ecall_init panicked unexpectedly!");
-            InitResult::Failure {
-                err: EnclaveError::Panic,
-            }
+            InitResult::failure(EnclaveError::Panic)
         }
     }
 }
@@ -234,9 +293,11 @@ pub unsafe extern "C" fn ecall_handle(
     sig_info_len: usize,
     handle_type: u8,
 ) -> HandleResult {
+    let _error_frame = error_context::enter();
+
     if let Err(err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return HandleResult::Failure { err };
+        return HandleResult::failure(err);
     }
 
     let failed_call =
@@ -274,7 +335,7 @@ pub unsafe extern "C" fn ecall_handle(
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return HandleResult::Failure { err };
+        return HandleResult::failure(err);
     }
 
     if let Ok(res) = result {
@@ -284,14 +345,163 @@ pub unsafe extern "C" fn ecall_handle(
 
         if oom_handler::get_then_clear_oom_happened() {
             error!("Call ecall_handle failed because the enclave ran out of memory!");
-            HandleResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
+            HandleResult::failure(EnclaveError::OutOfMemory)
         } else {
             error!("Call ecall_handle panicked unexpectedly!");
-            HandleResult::Failure {
-                err: EnclaveError::Panic,
-            }
+            HandleResult::failure(EnclaveError::Panic)
+        }
+    }
+}
+
+/// Executes the messages of a batch one after the other, as if each of them was passed to
+/// `ecall_handle` with the same contract, and writes the result and used gas of each to `results`
+/// and `used_gas`. All the messages must belong to the same block. The batch stops after a message
+/// that failed or whose response dispatches messages, see `crate::batch`, and returns how many
+/// of the messages were executed.
+///
+/// Every message is limited to its own entry of `gas_limits` and to what's left of `gas_left`
+/// after the messages before it, just like it would be if the host charged for each message
+/// before running the next one.
+///
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_handle_batch(
+    context: Ctx,
+    gas_left: u64,
+    gas_limits: *const u64,
+    contract: *const u8,
+    contract_len: usize,
+    batch: *const u8,
+    batch_len: usize,
+    batch_size: usize,
+    results: *mut HandleResult,
+    used_gas: *mut u64,
+) -> HandleBatchResult {
+    let _error_frame = error_context::enter();
+
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return HandleBatchResult::failure(err);
+    }
+
+    let failed_call = || HandleBatchResult::failure(EnclaveError::FailedFunctionCall);
+    validate_input_length!(batch_size, "batch_size", MAX_BATCH_SIZE, failed_call());
+    validate_input_length!(batch_len, "batch", MAX_BATCH_LENGTH, failed_call());
+    validate_input_length!(contract_len, "contract", MAX_WASM_LENGHT, failed_call());
+    validate_const_ptr!(contract, contract_len, failed_call());
+    validate_const_ptr!(batch, batch_len, failed_call());
+    validate_const_ptr!(
+        gas_limits as _,
+        batch_size * std::mem::size_of::<u64>(),
+        failed_call()
+    );
+    validate_mut_ptr!(
+        results as _,
+        batch_size * std::mem::size_of::<HandleResult>(),
+        failed_call()
+    );
+    validate_mut_ptr!(
+        used_gas as _,
+        batch_size * std::mem::size_of::<u64>(),
+        failed_call()
+    );
+
+    let contract = std::slice::from_raw_parts(contract, contract_len);
+    let batch = std::slice::from_raw_parts(batch, batch_len);
+    let handles = match decode_batch(batch, batch_size) {
+        Ok(handles) => handles,
+        Err(err) => return HandleBatchResult::failure(err),
+    };
+    let gas_limits = std::slice::from_raw_parts(gas_limits, batch_size);
+
+    let mut block_batch = BlockBatch::default();
+    let mut batch_gas = BatchGas::new(gas_left);
+    let mut executed = 0;
+    for (index, handle) in handles.iter().enumerate() {
+        let gas_limit = batch_gas.limit_for(gas_limits[index]);
+        let mut handle_used_gas = 0_u64;
+        let (result, stop) = handle_batched(
+            &mut block_batch,
+            context.unsafe_clone(),
+            gas_limit,
+            &mut handle_used_gas,
+            contract,
+            handle,
+        );
+        batch_gas.charge(handle_used_gas);
+        std::ptr::write(results.add(index), result);
+        std::ptr::write(used_gas.add(index), handle_used_gas);
+        executed += 1;
+        if stop {
+            break;
+        }
+    }
+
+    HandleBatchResult::Success { executed }
+}
+
+/// Executes one message of a batch the same way `ecall_handle` does. Also returns whether the
+/// batch has to stop after it.
+unsafe fn handle_batched(
+    block_batch: &mut BlockBatch,
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    handle: &BatchedHandle,
+) -> (HandleResult, bool) {
+    let _error_frame = error_context::enter();
+
+    let failed_call = || {
+        (
+            result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall)),
+            true,
+        )
+    };
+    validate_input_length!(handle.env.len(), "env", MAX_ENV_LENGTH, failed_call());
+    validate_input_length!(handle.msg.len(), "msg", MAX_MSG_LENGTH, failed_call());
+    validate_input_length!(
+        handle.sig_info.len(),
+        "sig_info",
+        MAX_SIG_INFO_LENGTH,
+        failed_call()
+    );
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut local_used_gas = 0_u64;
+        let result = crate::contract_operations::handle_in_batch(
+            context,
+            gas_limit,
+            &mut local_used_gas,
+            contract,
+            handle.env,
+            handle.msg,
+            handle.sig_info,
+            handle.handle_type,
+            block_batch,
+        );
+        *used_gas = local_used_gas;
+        let stop = ends_batch(&result);
+        (result_handle_success_to_handleresult(result), stop)
+    }));
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return (HandleResult::failure(err), true);
+    }
+
+    if let Ok(res) = result {
+        res
+    } else {
+        *used_gas = gas_limit / 2;
+
+        if oom_handler::get_then_clear_oom_happened() {
+            error!("Call ecall_handle_batch failed because the enclave ran out of memory!");
+            (HandleResult::failure(EnclaveError::OutOfMemory), true)
+        } else {
+            error!("Call ecall_handle_batch panicked unexpectedly!");
+            (HandleResult::failure(EnclaveError::Panic), true)
         }
     }
 }
@@ -337,9 +547,11 @@ unsafe fn ecall_query_impl(
     msg: *const u8,
     msg_len: usize,
 ) -> QueryResult {
+    let _error_frame = error_context::enter();
+
     if let Err(err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return QueryResult::Failure { err };
+        return QueryResult::failure(err);
     }
 
     let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
@@ -371,7 +583,7 @@ unsafe fn ecall_query_impl(
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return QueryResult::Failure { err };
+        return QueryResult::failure(err);
     }
 
     if let Ok(res) = result {
@@ -381,14 +593,10 @@ unsafe fn ecall_query_impl(
 
         if oom_handler::get_then_clear_oom_happened() {
             error!("Call ecall_query failed because the enclave ran out of memory!");
-            QueryResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
+            QueryResult::failure(EnclaveError::OutOfMemory)
         } else {
             error!("Call ecall_query panicked unexpectedly!");
-            QueryResult::Failure {
-                err: EnclaveError::Panic,
-            }
+            QueryResult::failure(EnclaveError::Panic)
         }
     }
 }
@@ -413,9 +621,11 @@ pub unsafe extern "C" fn ecall_migrate(
     admin_proof: *const u8,
     admin_proof_len: usize,
 ) -> MigrateResult {
+    let _error_frame = error_context::enter();
+
     if let Err(err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return MigrateResult::Failure { err };
+        return MigrateResult::failure(err);
     }
 
     let failed_call = || result_migrate_success_to_result(Err(EnclaveError::FailedFunctionCall));
@@ -466,7 +676,7 @@ pub unsafe extern "C" fn ecall_migrate(
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return MigrateResult::Failure { err };
+        return MigrateResult::failure(err);
     }
 
     if let Ok(res) = result {
@@ -476,14 +686,10 @@ pub unsafe extern "C" fn ecall_migrate(
 
         if oom_handler::get_then_clear_oom_happened() {
             error!("Call ecall_migrate failed because the enclave ran out of memory!");
-            MigrateResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
+            MigrateResult::failure(EnclaveError::OutOfMemory)
         } else {
             error!("Call ecall_migrate panicked unexpectedly!");
-            MigrateResult::Failure {
-                err: EnclaveError::Panic,
-            }
+            MigrateResult::failure(EnclaveError::Panic)
         }
     }
 }
@@ -503,9 +709,11 @@ pub unsafe extern "C" fn ecall_update_admin(
     new_admin: *const u8,
     new_admin_len: usize,
 ) -> UpdateAdminResult {
+    let _error_frame = error_context::enter();
+
     if let Err(err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return UpdateAdminResult::UpdateAdminFailure { err };
+        return UpdateAdminResult::failure(err);
     }
 
     let failed_call =
@@ -555,21 +763,74 @@ pub unsafe extern "C" fn ecall_update_admin(
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return UpdateAdminResult::UpdateAdminFailure { err };
+        return UpdateAdminResult::failure(err);
     }
 
     if let Ok(res) = result {
         res
     } else if oom_handler::get_then_clear_oom_happened() {
         error!("Call ecall_update_admin failed because the enclave ran out of memory!");
-        UpdateAdminResult::UpdateAdminFailure {
-            err: EnclaveError::OutOfMemory,
-        }
+        UpdateAdminResult::failure(EnclaveError::OutOfMemory)
     } else {
         error!("Call ecall_update_admin panicked unexpectedly!");
-        UpdateAdminResult::UpdateAdminFailure {
-            err: EnclaveError::Panic,
-        }
+        UpdateAdminResult::failure(EnclaveError::Panic)
+    }
+}
+
+/// `ecall_analyze_code`
+///
+/// Runs every check the enclave has for contract code when it's stored, under the JSON encoded
+/// engine params of the chain. The instrumented module is sealed, so the first instantiation is
+/// faster.
+///
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_analyze_code(
+    code: *const u8,
+    code_len: usize,
+    engine_params: *const u8,
+    engine_params_len: usize,
+) -> AnalyzeCodeResult {
+    let _error_frame = error_context::enter();
+
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return AnalyzeCodeResult::failure(err);
+    }
+
+    let failed_call =
+        || result_analyze_code_success_to_result(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(code, code_len, failed_call());
+    validate_const_ptr!(engine_params, engine_params_len, failed_call());
+    validate_input_length!(code_len, "code", MAX_WASM_LENGHT, failed_call());
+    validate_input_length!(
+        engine_params_len,
+        "engine_params",
+        MAX_ENV_LENGTH,
+        failed_call()
+    );
+
+    let code = std::slice::from_raw_parts(code, code_len);
+    let engine_params = std::slice::from_raw_parts(engine_params, engine_params_len);
+    let result = panic::catch_unwind(|| {
+        let result = crate::code_analysis::analyze_code(code, engine_params);
+        result_analyze_code_success_to_result(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return AnalyzeCodeResult::failure(err);
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_analyze_code failed because the enclave ran out of memory!");
+        AnalyzeCodeResult::failure(EnclaveError::OutOfMemory)
+    } else {
+        error!("Call ecall_analyze_code panicked unexpectedly!");
+        AnalyzeCodeResult::failure(EnclaveError::Panic)
     }
 }
 
@@ -580,6 +841,95 @@ pub unsafe extern "C" fn ecall_health_check() -> HealthCheckResult {
     HealthCheckResult::Success
 }
 
+/// `ecall_disclose_tx_keys`
+///
+/// Verifies a disclosure permit and writes the JSON encoded disclosure to `out`. `out_written` is
+/// set to the full length of the disclosure even if it doesn't fit, so the caller can retry with a
+/// larger buffer
+///
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_disclose_tx_keys(
+    permit: *const u8,
+    permit_len: usize,
+    out: *mut u8,
+    out_len: u32,
+    out_written: *mut u32,
+) -> sgx_status_t {
+    validate_input_length!(
+        permit_len,
+        "permit",
+        MAX_PERMIT_LENGTH,
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+    validate_const_ptr!(
+        permit,
+        permit_len,
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+    validate_mut_ptr!(out, out_len as usize, sgx_status_t::SGX_ERROR_UNEXPECTED);
+    validate_mut_ptr!(
+        out_written as *mut u8,
+        std::mem::size_of::<u32>(),
+        sgx_status_t::SGX_ERROR_UNEXPECTED,
+    );
+
+    let permit = std::slice::from_raw_parts(permit, permit_len);
+    let disclosure = match crate::disclosure::disclose_tx_keys(permit) {
+        Ok(disclosure) => disclosure,
+        Err(err) => {
+            warn!("Failed to disclose tx keys: {}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    *out_written = disclosure.len() as u32;
+    if disclosure.len() > out_len as usize {
+        return sgx_status_t::SGX_SUCCESS;
+    }
+    std::slice::from_raw_parts_mut(out, disclosure.len()).copy_from_slice(&disclosure);
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// `ecall_get_enclave_metrics`
+///
+/// Writes the JSON encoded runtime metrics of the enclave to `out`. `out_written` is set to the
+/// full length of the metrics even if they don't fit, so the caller can retry with a larger buffer
+///
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_enclave_metrics(
+    out: *mut u8,
+    out_len: u32,
+    out_written: *mut u32,
+) -> sgx_status_t {
+    validate_mut_ptr!(out, out_len as usize, sgx_status_t::SGX_ERROR_UNEXPECTED);
+    validate_mut_ptr!(
+        out_written as *mut u8,
+        std::mem::size_of::<u32>(),
+        sgx_status_t::SGX_ERROR_UNEXPECTED,
+    );
+
+    let metrics = match serde_json::to_vec(&enclave_utils::metrics::snapshot()) {
+        Ok(metrics) => metrics,
+        Err(err) => {
+            warn!("Failed to serialize the enclave metrics: {}", err);
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    *out_written = metrics.len() as u32;
+    if metrics.len() > out_len as usize {
+        return sgx_status_t::SGX_SUCCESS;
+    }
+    std::slice::from_raw_parts_mut(out, metrics.len()).copy_from_slice(&metrics);
+
+    sgx_status_t::SGX_SUCCESS
+}
+
 #[cfg(feature = "test")]
 pub mod tests {
     use super::*;
@@ -1,3 +1,11 @@
 pub mod ecalls;
+#[cfg(not(feature = "simulator"))]
 pub mod ocalls;
 pub mod results;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+
+// The simulator serves the ocalls itself, with the same signatures, so the rest of the engine
+// doesn't need to know which one it's talking to
+#[cfg(feature = "simulator")]
+pub use self::simulator as ocalls;
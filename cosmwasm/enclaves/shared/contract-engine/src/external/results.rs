@@ -1,12 +1,108 @@
 use sgx_types::sgx_status_t;
 
 use enclave_ffi_types::{
-    EnclaveError, HandleResult, InitResult, MigrateResult, QueryResult, UntrustedVmError,
-    UpdateAdminResult, UserSpaceBuffer,
+    AnalyzeCodeResult, EnclaveError, HandleResult, InitResult, MigrateResult, QueryResult,
+    UntrustedVmError, UpdateAdminResult, UserSpaceBuffer,
 };
 
+use enclave_utils::error_context;
+
 use crate::external::ocalls::ocall_allocate;
 
+/// Builds the failure of an ecall, along with the context recorded for its error
+pub trait EcallFailure {
+    fn failure(err: EnclaveError) -> Self;
+}
+
+/// Hands the wasm backtrace recorded for the error of the ecall, if there is one, to the host
+fn wasm_backtrace() -> UserSpaceBuffer {
+    let null = UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    };
+
+    match error_context::take_backtrace() {
+        Some(backtrace) => unsafe {
+            let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
+            match ocall_allocate(
+                user_buffer.as_mut_ptr(),
+                backtrace.as_ptr(),
+                backtrace.len(),
+            ) {
+                sgx_status_t::SGX_SUCCESS => user_buffer.assume_init(),
+                _ => null,
+            }
+        },
+        None => null,
+    }
+}
+
+impl EcallFailure for InitResult {
+    fn failure(err: EnclaveError) -> Self {
+        InitResult::Failure {
+            context: error_context::take(&err),
+            backtrace: wasm_backtrace(),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for HandleResult {
+    fn failure(err: EnclaveError) -> Self {
+        HandleResult::Failure {
+            context: error_context::take(&err),
+            backtrace: wasm_backtrace(),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for HandleBatchResult {
+    fn failure(err: EnclaveError) -> Self {
+        HandleBatchResult::Failure {
+            context: error_context::take(&err),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for MigrateResult {
+    fn failure(err: EnclaveError) -> Self {
+        MigrateResult::Failure {
+            context: error_context::take(&err),
+            backtrace: wasm_backtrace(),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for UpdateAdminResult {
+    fn failure(err: EnclaveError) -> Self {
+        UpdateAdminResult::UpdateAdminFailure {
+            context: error_context::take(&err),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for QueryResult {
+    fn failure(err: EnclaveError) -> Self {
+        QueryResult::Failure {
+            context: error_context::take(&err),
+            backtrace: wasm_backtrace(),
+            err,
+        }
+    }
+}
+
+impl EcallFailure for AnalyzeCodeResult {
+    fn failure(err: EnclaveError) -> Self {
+        AnalyzeCodeResult::Failure {
+            context: error_context::take(&err),
+            err,
+        }
+    }
+}
+
 /// This struct is returned from module initialization.
 pub struct InitSuccess {
     /// The output of the calculation
@@ -28,11 +124,9 @@ pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveErro
                 match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
                     sgx_status_t::SGX_SUCCESS => { /* continue */ }
                     _ => {
-                        return InitResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
+                        return InitResult::failure(EnclaveError::FailedOcall {
+                            vm_error: UntrustedVmError::default(),
+                        })
                     }
                 }
                 user_buffer.assume_init()
@@ -43,7 +137,7 @@ pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveErro
                 admin_proof,
             }
         }
-        Err(err) => InitResult::Failure { err },
+        Err(err) => InitResult::failure(err),
     }
 }
 
@@ -63,11 +157,9 @@ pub fn result_handle_success_to_handleresult(
                 match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
                     sgx_status_t::SGX_SUCCESS => { /* continue */ }
                     _ => {
-                        return HandleResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
+                        return HandleResult::failure(EnclaveError::FailedOcall {
+                            vm_error: UntrustedVmError::default(),
+                        })
                     }
                 }
                 user_buffer.assume_init()
@@ -76,7 +168,7 @@ pub fn result_handle_success_to_handleresult(
                 output: user_buffer,
             }
         }
-        Err(err) => HandleResult::Failure { err },
+        Err(err) => HandleResult::failure(err),
     }
 }
 
@@ -102,11 +194,9 @@ pub fn result_migrate_success_to_result(
                 match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
                     sgx_status_t::SGX_SUCCESS => { /* continue */ }
                     _ => {
-                        return MigrateResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
+                        return MigrateResult::failure(EnclaveError::FailedOcall {
+                            vm_error: UntrustedVmError::default(),
+                        })
                     }
                 }
                 user_buffer.assume_init()
@@ -117,7 +207,7 @@ pub fn result_migrate_success_to_result(
                 new_contract_key_proof,
             }
         }
-        Err(err) => MigrateResult::Failure { err },
+        Err(err) => MigrateResult::failure(err),
     }
 }
 
@@ -133,7 +223,7 @@ pub fn result_update_admin_success_to_result(
         Ok(UpdateAdminSuccess { new_admin_proof }) => {
             UpdateAdminResult::UpdateAdminSuccess { new_admin_proof }
         }
-        Err(err) => UpdateAdminResult::UpdateAdminFailure { err },
+        Err(err) => UpdateAdminResult::failure(err),
     }
 }
 
@@ -153,11 +243,9 @@ pub fn result_query_success_to_queryresult(
                 match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
                     sgx_status_t::SGX_SUCCESS => { /* continue */ }
                     _ => {
-                        return QueryResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
+                        return QueryResult::failure(EnclaveError::FailedOcall {
+                            vm_error: UntrustedVmError::default(),
+                        })
                     }
                 }
                 user_buffer.assume_init()
@@ -166,6 +254,15 @@ pub fn result_query_success_to_queryresult(
                 output: user_buffer,
             }
         }
-        Err(err) => QueryResult::Failure { err },
+        Err(err) => QueryResult::failure(err),
+    }
+}
+
+pub fn result_analyze_code_success_to_result(
+    result: Result<(), EnclaveError>,
+) -> AnalyzeCodeResult {
+    match result {
+        Ok(()) => AnalyzeCodeResult::Success,
+        Err(err) => AnalyzeCodeResult::failure(err),
     }
 }
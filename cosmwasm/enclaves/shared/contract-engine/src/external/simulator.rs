@@ -0,0 +1,235 @@
+//! Stand-ins for the ocalls, used when the engine is built with the `simulator` feature.
+//!
+//! In the enclave, every ocall bubbles up to the untrusted side of the node, which forwards it to
+//! `x/compute` with the `Ctx` it was handed in the ecall. In the simulator there's no untrusted
+//! side, so `Ctx` points to a `SimulatorHost` instead, and these functions call it directly. They
+//! keep the signatures of the real ocalls, so the rest of the engine can't tell the difference.
+
+use std::ffi::c_void;
+
+use log::*;
+
+use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError, UserSpaceBuffer};
+use sgx_types::sgx_status_t;
+
+use crate::external::ecalls;
+
+/// What the engine needs from the chain. Errors are only logged, the engine sees them as failed
+/// ocalls like it would in the enclave.
+pub trait SimulatorHost {
+    /// Returns the (encrypted) value stored at `key`, and the gas used to read it
+    fn read_db(&mut self, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), String>;
+
+    /// Returns the gas used to write `value` to `key`
+    fn write_db(&mut self, key: &[u8], value: &[u8]) -> Result<u64, String>;
+
+    /// Returns the gas used to remove `key`
+    fn remove_db(&mut self, key: &[u8]) -> Result<u64, String>;
+
+    /// Answers a `QueryRequest` of a contract. The answer is a JSON encoded
+    /// `SystemResult<StdResult<Binary>>`, and comes with the gas it used.
+    fn query_chain(
+        &mut self,
+        query: &[u8],
+        query_depth: u32,
+        gas_limit: u64,
+    ) -> Result<(Vec<u8>, u64), String>;
+}
+
+/// Calls `f` with a `Ctx` that routes the ocalls of the engine to `host`.
+/// The `Ctx` must not outlive the call.
+pub fn with_host<T, F>(host: &mut dyn SimulatorHost, f: F) -> T
+where
+    F: FnOnce(Ctx) -> T,
+{
+    let mut host = host;
+    let context = Ctx {
+        data: (&mut host) as *mut &mut dyn SimulatorHost as *mut c_void,
+    };
+
+    f(context)
+}
+
+/// # Safety
+/// `context` must come from `with_host`
+unsafe fn host_from_context<'a>(context: &Ctx) -> &'a mut dyn SimulatorHost {
+    &mut **(context.data as *mut &mut dyn SimulatorHost)
+}
+
+/// Copies `value` into a buffer the engine can recover, like the untrusted side does with
+/// `ecall_allocate`
+unsafe fn allocate_enclave_buffer(value: Option<Vec<u8>>) -> EnclaveBuffer {
+    match value {
+        Some(value) => ecalls::ecall_allocate(value.as_ptr(), value.len()),
+        None => EnclaveBuffer::default(),
+    }
+}
+
+/// # Safety
+/// Same as the real ocall
+pub unsafe fn ocall_allocate(
+    retval: *mut UserSpaceBuffer,
+    buffer: *const u8,
+    length: usize,
+) -> sgx_status_t {
+    let data = std::slice::from_raw_parts(buffer, length).to_vec();
+    *retval = UserSpaceBuffer {
+        ptr: Box::into_raw(Box::new(data)) as *mut c_void,
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// Same as the real ocall
+pub unsafe fn ocall_read_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    value: *mut EnclaveBuffer,
+    key: *const u8,
+    key_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+
+    *retval = match host_from_context(&context).read_db(key) {
+        Ok((result, gas)) => {
+            *gas_used = gas;
+            *value = allocate_enclave_buffer(result);
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("simulated read_db failed: {}", err);
+            OcallReturn::Failure
+        }
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// Same as the real ocall
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn ocall_query_chain(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    gas_limit: u64,
+    value: *mut EnclaveBuffer,
+    query: *const u8,
+    query_len: usize,
+    query_depth: u32,
+) -> sgx_status_t {
+    let query = std::slice::from_raw_parts(query, query_len);
+
+    *retval = match host_from_context(&context).query_chain(query, query_depth, gas_limit) {
+        Ok((answer, gas)) => {
+            *gas_used = gas;
+            *value = allocate_enclave_buffer(Some(answer));
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("simulated query_chain failed: {}", err);
+            OcallReturn::Failure
+        }
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// Same as the real ocall
+pub unsafe fn ocall_remove_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    key: *const u8,
+    key_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+
+    *retval = match host_from_context(&context).remove_db(key) {
+        Ok(gas) => {
+            *gas_used = gas;
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("simulated remove_db failed: {}", err);
+            OcallReturn::Failure
+        }
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// Same as the real ocall
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn ocall_write_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+    let value = std::slice::from_raw_parts(value, value_len);
+
+    *retval = match host_from_context(&context).write_db(key, value) {
+        Ok(gas) => {
+            *gas_used = gas;
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("simulated write_db failed: {}", err);
+            OcallReturn::Failure
+        }
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// Same as the real ocall
+pub unsafe fn ocall_multiple_write_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    keys: *const u8,
+    keys_len: usize,
+) -> sgx_status_t {
+    let keys = std::slice::from_raw_parts(keys, keys_len);
+    let keys: Vec<(Vec<u8>, Vec<u8>)> = match serde_json::from_slice(keys) {
+        Ok(keys) => keys,
+        Err(err) => {
+            warn!("simulated multiple_write_db got malformed keys: {:?}", err);
+            *retval = OcallReturn::Failure;
+            return sgx_status_t::SGX_SUCCESS;
+        }
+    };
+
+    let host = host_from_context(&context);
+    let mut total_gas = 0;
+    for (key, value) in keys.iter() {
+        match host.write_db(key, value) {
+            Ok(gas) => total_gas += gas,
+            Err(err) => {
+                warn!("simulated multiple_write_db failed: {}", err);
+                *retval = OcallReturn::Failure;
+                return sgx_status_t::SGX_SUCCESS;
+            }
+        }
+    }
+
+    *gas_used = total_gas;
+    *retval = OcallReturn::Success;
+
+    sgx_status_t::SGX_SUCCESS
+}
@@ -5,16 +5,45 @@ pub use pwasm_utils::{inject_gas_counter, rules};
 pub const WRITE_BASE_GAS: u64 = 2_000;
 pub const READ_BASE_GAS: u64 = 1_000;
 
+/// Bump this whenever `WasmCosts::default` or the cost of instructions changes, so modules that
+/// were instrumented with the old costs aren't used
+pub const GAS_SCHEDULE_VERSION: u32 = 3;
+
 /// Wasm cost table
+///
+/// Every instruction belongs to a class, and costs the weight of its class. wasm3 charges each
+/// basic block up front, for the sum of the weights of its instructions, so a branch that leaves
+/// a block early doesn't pay for the instructions it skipped. Instructions whose work depends on
+/// an operand pay for it when they run: `memory.grow` per page and `memory.copy`/`memory.fill`
+/// per byte.
+///
+/// The weights are relative to `regular`, and follow how much wasm3 spends on each class: locals
+/// are register moves, calls set up a frame, indirect calls also check the signature of the
+/// callee, and bulk memory operations go through the memory bounds checks. Bump
+/// `GAS_SCHEDULE_VERSION` after changing a weight. `make bench-gas-report` prints the cost of each
+/// function of `bench-contract` under them.
+///
+/// Calls made before the chain set its engine params are metered as they always were, see
+/// `WasmCosts::legacy`.
 pub struct WasmCosts {
     /// Default opcode cost
     pub regular: u32,
     /// Div operations multiplier.
     pub div: u32,
-    /// Div operations multiplier.
+    /// Mul operations multiplier.
     pub mul: u32,
     /// Memory (load/store) operations multiplier.
     pub mem: u32,
+    /// Local and global variable access (`local.get`, `global.set`, ...)
+    pub local: u32,
+    /// Blocks and branches (`block`, `loop`, `if`, `br_if`, `br_table`, ...)
+    pub control_flow: u32,
+    /// Direct function call
+    pub call: u32,
+    /// Function call through a table
+    pub call_indirect: u32,
+    /// Bulk memory operations (`memory.copy`, `memory.fill`, `memory.init`, ...)
+    pub bulk_memory: u32,
     // /// General static query of U256 value from env-info
     // pub static_u256: u32,
     // /// General static query of Address value from env-info
@@ -23,8 +52,8 @@ pub struct WasmCosts {
     pub initial_mem: u32,
     /// Grow memory cost, per page (64kb)
     pub grow_mem: u32,
-    // /// Memory copy cost, per byte
-    // pub memcpy: u32,
+    /// `memory.copy` and `memory.fill` cost, per byte, on top of `bulk_memory`
+    pub memcpy: u32,
     // /// Max stack height (native WebAssembly stack limiter)
     // pub max_stack_height: u32,
     // /// Cost of wasm opcode is calculated as TABLE_ENTRY_COST * `opcodes_mul` / `opcodes_div`
@@ -51,6 +80,14 @@ pub struct WasmCosts {
     pub external_secp256k1_sign: u32,
     /// Cost invoking ed25519_sign from WASM
     pub external_ed25519_sign: u32,
+    /// Cost invoking query_permit_verify from WASM
+    pub external_query_permit_verify: u32,
+    /// Cost invoking viewing_key_verify from WASM
+    pub external_viewing_key_verify: u32,
+    /// Cost of deriving a contract owned key, on top of using it
+    pub external_contract_key_derive: u32,
+    /// Cost invoking contract_x25519_ecdh from WASM, on top of deriving the key
+    pub external_x25519_ecdh: u32,
     pub external_check_gas_used: u32,
     pub external_minimum_gas_evaporate: u32,
 }
@@ -58,15 +95,20 @@ pub struct WasmCosts {
 impl Default for WasmCosts {
     fn default() -> Self {
         WasmCosts {
-            regular: 1,
-            div: 16,
-            mul: 4,
-            mem: 2,
+            regular: 2,
+            div: 8,
+            mul: 3,
+            mem: 3,
+            local: 1,
+            control_flow: 2,
+            call: 10,
+            call_indirect: 14,
+            bulk_memory: 16,
             // static_u256: 64,
             // static_address: 40,
             initial_mem: 8192,
             grow_mem: 8192,
-            // memcpy: 1,
+            memcpy: 1,
             // max_stack_height: 64 * 1024,
             // opcodes_mul: 3,
             // opcodes_div: 8,
@@ -80,12 +122,31 @@ impl Default for WasmCosts {
             external_ed25519_batch_verify_each: 70000,
             external_secp256k1_sign: 100000,
             external_ed25519_sign: 75000,
+            external_query_permit_verify: 110000,
+            external_viewing_key_verify: 10000,
+            external_contract_key_derive: 10000,
+            external_x25519_ecdh: 50000,
             external_check_gas_used: 8192,
             external_minimum_gas_evaporate: 8000,
         }
     }
 }
 
+impl WasmCosts {
+    /// The costs of calls made before the chain set its engine params. wasm3 charges them a flat
+    /// cost per instruction, so of these only the costs of `memory.grow` and of the host functions
+    /// apply to them.
+    pub fn legacy() -> Self {
+        WasmCosts {
+            regular: 1,
+            div: 16,
+            mul: 4,
+            mem: 2,
+            ..Self::default()
+        }
+    }
+}
+
 #[cfg(feature = "wasmi-engine")]
 pub fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     rules::Set::new(wasm_costs.regular, {
@@ -1,16 +1,19 @@
 use crate::contract_validation::ReplyParams;
 use core::fmt;
+use std::convert::TryInto;
 
 /// This contains all the user-facing functions. In these functions we will be using
 /// the consensus_io_exchange_keypair and a user-generated key to create a symmetric key
 /// that is unique to the user and the enclave
 ///
+use super::padding::pad;
 use super::types::{IoNonce, SecretMessage};
 use cw_types_v010::encoding::Binary;
 use cw_types_v010::types::{CanonicalAddr, Coin, LogAttribute};
 use cw_types_v1::results::{Event, Reply, ReplyOn, SubMsg, SubMsgResponse, SubMsgResult};
 
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+use enclave_utils::error_context::with_context;
 
 use enclave_crypto::{AESKey, Ed25519PublicKey, Kdf, SIVEncryptable, KEY_MANAGER};
 
@@ -136,6 +139,7 @@ fn encrypt_serializable<T>(
     val: &T,
     reply_params: &Option<Vec<ReplyParams>>,
     should_append_all_reply_params: bool,
+    padding: Option<u32>,
 ) -> Result<String, EnclaveError>
 where
     T: ?Sized + Serialize,
@@ -147,7 +151,13 @@ where
 
     let trimmed = serialized.trim_start_matches('"').trim_end_matches('"');
 
-    encrypt_preserialized_string(key, trimmed, reply_params, should_append_all_reply_params)
+    encrypt_preserialized_string(
+        key,
+        trimmed,
+        reply_params,
+        should_append_all_reply_params,
+        padding,
+    )
 }
 
 // use this to encrypt a String that has already been serialized.  When that is the case, if
@@ -158,6 +168,7 @@ fn encrypt_preserialized_string(
     val: &str,
     reply_params: &Option<Vec<ReplyParams>>,
     should_append_all_reply_params: bool,
+    padding: Option<u32>,
 ) -> Result<String, EnclaveError> {
     let serialized = match reply_params {
         Some(v) => {
@@ -175,6 +186,7 @@ fn encrypt_preserialized_string(
         }
         None => val.as_bytes().to_vec(),
     };
+    let serialized = pad(&serialized, padding);
     let encrypted_data = key
         .encrypt_siv(serialized.as_slice(), None)
         .map_err(|err| {
@@ -202,6 +214,7 @@ pub fn post_process_output(
     sender_addr: &CanonicalAddr,
     is_query_output: bool,
     is_ibc_output: bool,
+    padding: Option<u32>,
 ) -> Result<Vec<u8>, EnclaveError> {
     let mut raw_output = deserialize_output(output)?;
     raw_output = attach_reply_headers_to_submsgs(raw_output, contract_hash, &reply_params)?;
@@ -211,6 +224,7 @@ pub fn post_process_output(
         contract_addr,
         &reply_params,
         is_ibc_output,
+        padding,
     )?;
     raw_output = create_callback_sig_for_submsgs(raw_output, contract_addr)?;
     raw_output = adapt_output_for_reply(raw_output, &reply_params, secret_msg, sender_addr)?;
@@ -499,12 +513,14 @@ fn deserialize_output(output: Vec<u8>) -> Result<RawWasmOutput, EnclaveError> {
 /// * `contract_addr` - The address of the contract whose output we are processing.
 /// * `reply_params` - An optional vector describing the caller chain. Needed because the
 ///         immediate caller to this contract will be appended to every field.
+/// * `padding` - The block size the contract asked every encrypted field to be padded to, if any.
 fn encrypt_output(
     mut output: RawWasmOutput,
     secret_msg: &SecretMessage,
     contract_addr: &CanonicalAddr,
     reply_params: &Option<Vec<ReplyParams>>,
     is_ibc_output: bool,
+    padding: Option<u32>,
 ) -> Result<RawWasmOutput, EnclaveError> {
     // The output we receive from a contract could be a reply to a caller contract (via the "reply" endpoint).
     // Therefore if reply_recipient_contract_hash is "Some", we append it to any encrypted data besides submessages that are irrelevant for replies.
@@ -518,11 +534,12 @@ fn encrypt_output(
 
     match &mut output {
         RawWasmOutput::Err { err, .. } => {
-            let encrypted_err = encrypt_serializable(&encryption_key, err, reply_params, false)?;
+            let encrypted_err =
+                encrypt_serializable(&encryption_key, err, reply_params, false, padding)?;
             *err = format_generic_error_message(Value::String(encrypted_err));
         }
         RawWasmOutput::QueryOkV010 { ok } | RawWasmOutput::QueryOkV1 { ok } => {
-            *ok = encrypt_serializable(&encryption_key, ok, reply_params, false)?;
+            *ok = encrypt_serializable(&encryption_key, ok, reply_params, false, padding)?;
         }
         RawWasmOutput::OkV010 { ok, .. } => {
             for msg in &mut ok.messages {
@@ -533,16 +550,27 @@ fn encrypt_output(
                         secret_msg.nonce,
                         secret_msg.user_public_key,
                         contract_addr,
+                        padding,
                     )?;
                 }
             }
 
+            let recipients_logs =
+                encrypt_attributes_for_recipients(&ok.log, &[], secret_msg, padding)?;
+
             // v0.10: The logs that will be emitted as part of a "wasm" event.
             for log in ok.log.iter_mut().filter(|log| log.encrypted) {
-                log.key = encrypt_preserialized_string(&encryption_key, &log.key, &None, false)?;
-                log.value =
-                    encrypt_preserialized_string(&encryption_key, &log.value, &None, false)?;
+                log.key =
+                    encrypt_preserialized_string(&encryption_key, &log.key, &None, false, padding)?;
+                log.value = encrypt_preserialized_string(
+                    &encryption_key,
+                    &log.value,
+                    &None,
+                    false,
+                    padding,
+                )?;
             }
+            ok.log.extend(recipients_logs);
 
             if let Some(data) = &mut ok.data {
                 *data = Binary::from_base64(&encrypt_serializable(
@@ -550,6 +578,7 @@ fn encrypt_output(
                     data,
                     reply_params,
                     false,
+                    padding,
                 )?)?;
             }
         }
@@ -559,6 +588,7 @@ fn encrypt_output(
                 &mut ok.attributes,
                 &mut ok.events,
                 secret_msg,
+                padding,
             )?;
             if let Some(data) = &mut ok.data {
                 if is_ibc_output {
@@ -571,6 +601,7 @@ fn encrypt_output(
                     data,
                     reply_params,
                     false,
+                    padding,
                 )?)?;
             }
         }
@@ -580,6 +611,7 @@ fn encrypt_output(
                 &mut ok.attributes,
                 &mut ok.events,
                 secret_msg,
+                padding,
             )?;
 
             ok.acknowledgement = Binary::from_base64(&encrypt_serializable(
@@ -587,6 +619,7 @@ fn encrypt_output(
                 &ok.acknowledgement,
                 reply_params,
                 false,
+                padding,
             )?)?;
         }
         RawWasmOutput::OkIBCOpenChannel { ok: _ } => {}
@@ -595,38 +628,154 @@ fn encrypt_output(
     Ok(output)
 }
 
+/// Maximum amount of extra recipients an encrypted attribute can have
+const MAX_ATTRIBUTE_RECIPIENTS: usize = 16;
+
+/// Encrypts an attribute for every party other than the sender that should be able to read it.
+/// The key is derived exactly like the sender's, but with the recipient's public key, so recipients
+/// decrypt these like their own outputs (using the nonce of the tx). The copies look like any other
+/// encrypted attribute, so recipients find theirs by trying to decrypt, and nobody else learns who
+/// they are.
+///
+/// Recipients are x25519 tx encryption public keys. The enclave doesn't know which key an address
+/// uses, so contracts that notify addresses have to keep the keys their users registered with them
+/// and pass those.
+fn encrypt_attribute_for_recipients(
+    attr: &LogAttribute,
+    recipients: &[Binary],
+    padding: Option<u32>,
+    recipient_key: impl Fn(&Ed25519PublicKey) -> AESKey,
+) -> Result<Vec<LogAttribute>, EnclaveError> {
+    if recipients.len() > MAX_ATTRIBUTE_RECIPIENTS {
+        warn!(
+            "attribute has {} recipients, which is more than the maximum of {}",
+            recipients.len(),
+            MAX_ATTRIBUTE_RECIPIENTS
+        );
+        return Err(with_context(
+            EnclaveError::FailedToDeserialize,
+            EnclaveErrorStage::Encryption,
+            error_details::TOO_MANY_ATTRIBUTE_RECIPIENTS,
+        ));
+    }
+
+    let mut encrypted_attributes = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let recipient_public_key: Ed25519PublicKey =
+            recipient.as_slice().try_into().map_err(|_| {
+                warn!(
+                    "got an attribute recipient that is not a 32 byte public key: {:?}",
+                    recipient
+                );
+                with_context(
+                    EnclaveError::FailedToDeserialize,
+                    EnclaveErrorStage::Encryption,
+                    error_details::INVALID_ATTRIBUTE_RECIPIENT,
+                )
+            })?;
+        let encryption_key = recipient_key(&recipient_public_key);
+
+        encrypted_attributes.push(LogAttribute {
+            key: encrypt_preserialized_string(&encryption_key, &attr.key, &None, false, padding)?,
+            value: encrypt_preserialized_string(
+                &encryption_key,
+                &attr.value,
+                &None,
+                false,
+                padding,
+            )?,
+            encrypted: true,
+            recipients: vec![],
+        });
+    }
+
+    Ok(encrypted_attributes)
+}
+
+/// The copies of the encrypted `attributes` for their recipients, see
+/// `encrypt_attribute_for_recipients`. `extra_recipients` can read all of them.
+fn encrypt_attributes_for_recipients(
+    attributes: &[LogAttribute],
+    extra_recipients: &[Binary],
+    secret_msg: &SecretMessage,
+    padding: Option<u32>,
+) -> Result<Vec<LogAttribute>, EnclaveError> {
+    let recipient_key =
+        |public_key: &Ed25519PublicKey| calc_encryption_key(&secret_msg.nonce, public_key);
+
+    let mut encrypted_attributes = vec![];
+    for attr in attributes.iter().filter(|attr| attr.encrypted) {
+        let mut recipients = extra_recipients.to_vec();
+        recipients.extend(attr.recipients.iter().cloned());
+
+        encrypted_attributes.extend(encrypt_attribute_for_recipients(
+            attr,
+            &recipients,
+            padding,
+            recipient_key,
+        )?);
+    }
+
+    Ok(encrypted_attributes)
+}
+
 fn encrypt_v1_non_result_fields<T: Clone + fmt::Debug + PartialEq>(
     messages: &mut [SubMsg<T>],
-    attributes: &mut [LogAttribute],
+    attributes: &mut Vec<LogAttribute>,
     events: &mut [Event],
     secret_msg: &SecretMessage,
+    padding: Option<u32>,
 ) -> Result<(), EnclaveError> {
     let encryption_key = calc_encryption_key(&secret_msg.nonce, &secret_msg.user_public_key);
 
     for sub_msg in messages.iter_mut() {
-        encrypt_wasm_submsg(sub_msg, secret_msg)?;
+        encrypt_wasm_submsg(sub_msg, secret_msg, padding)?;
+    }
+
+    // Attributes that other parties should be able to read are encrypted for them before they are
+    // encrypted for the sender
+    let recipients_attributes =
+        encrypt_attributes_for_recipients(attributes, &[], secret_msg, padding)?;
+
+    let mut recipients_event_attributes = vec![];
+    for event in events.iter() {
+        recipients_event_attributes.push(encrypt_attributes_for_recipients(
+            &event.attributes,
+            &event.recipients,
+            secret_msg,
+            padding,
+        )?);
     }
 
     // v1: The attributes that will be emitted as part of a "wasm" event.
     for attr in attributes.iter_mut().filter(|attr| attr.encrypted) {
-        attr.key = encrypt_preserialized_string(&encryption_key, &attr.key, &None, false)?;
-        attr.value = encrypt_preserialized_string(&encryption_key, &attr.value, &None, false)?;
+        attr.key = encrypt_preserialized_string(&encryption_key, &attr.key, &None, false, padding)?;
+        attr.value =
+            encrypt_preserialized_string(&encryption_key, &attr.value, &None, false, padding)?;
     }
 
     // v1: Extra, custom events separate from the main wasm one. These will have "wasm-"" prepended to the type.
     for event in events.iter_mut() {
         for attr in event.attributes.iter_mut().filter(|attr| attr.encrypted) {
-            attr.key = encrypt_preserialized_string(&encryption_key, &attr.key, &None, false)?;
-            attr.value = encrypt_preserialized_string(&encryption_key, &attr.value, &None, false)?;
+            attr.key =
+                encrypt_preserialized_string(&encryption_key, &attr.key, &None, false, padding)?;
+            attr.value =
+                encrypt_preserialized_string(&encryption_key, &attr.value, &None, false, padding)?;
         }
     }
 
+    attributes.extend(recipients_attributes);
+    for (event, event_attributes) in events.iter_mut().zip(recipients_event_attributes) {
+        event.attributes.extend(event_attributes);
+    }
+
     Ok(())
 }
 
 fn encrypt_wasm_submsg<T: Clone + fmt::Debug + PartialEq>(
     sub_msg: &mut SubMsg<T>,
     secret_msg: &SecretMessage,
+    padding: Option<u32>,
 ) -> Result<(), EnclaveError> {
     // Messages other than Wasm (Bank, Staking, etc.) are kept plaintext
     if let cw_types_v1::results::CosmosMsg::Wasm(wasm_msg) = &mut sub_msg.msg {
@@ -635,7 +784,7 @@ fn encrypt_wasm_submsg<T: Clone + fmt::Debug + PartialEq>(
             | cw_types_v1::results::WasmMsg::Execute { msg, .. }
             | cw_types_v1::results::WasmMsg::Migrate { msg, .. } => {
                 let mut msg_to_encrypt = SecretMessage {
-                    msg: msg.as_slice().to_vec(),
+                    msg: pad(msg.as_slice(), padding),
                     nonce: secret_msg.nonce,
                     user_public_key: secret_msg.user_public_key,
                 };
@@ -863,6 +1012,7 @@ fn get_reply_info_for_output(
         &reply_params.as_ref().unwrap()[0].sub_msg_id.to_string(),
         reply_params,
         should_append_all_reply_params,
+        None,
     )?)?;
 
     let reply = Reply {
@@ -896,6 +1046,7 @@ fn encrypt_v010_wasm_msg(
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     contract_addr: &CanonicalAddr,
+    padding: Option<u32>,
 ) -> Result<(), EnclaveError> {
     match wasm_msg {
         cw_types_v010::types::WasmMsg::Execute {
@@ -920,6 +1071,7 @@ fn encrypt_v010_wasm_msg(
                 nonce,
                 user_public_key,
             )?;
+            msg_to_pass.msg = pad(&msg_to_pass.msg, padding);
 
             msg_to_pass.encrypt_in_place()?;
             *msg = Binary::from(msg_to_pass.to_vec().as_slice());
@@ -944,6 +1096,7 @@ fn encrypt_v010_wasm_msg(
                 nonce,
                 user_public_key,
             )?;
+            msg_to_pass.msg = pad(&msg_to_pass.msg, padding);
 
             msg_to_pass.encrypt_in_place()?;
             *msg = Binary::from(msg_to_pass.to_vec().as_slice());
@@ -1035,3 +1188,96 @@ pub fn create_callback_signature(
 pub fn format_generic_error_message(encrypted_err: Value) -> Value {
     json!({"generic_err":{"msg":encrypted_err}})
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn decrypt(key: &AESKey, encrypted: &str) -> Vec<u8> {
+        key.decrypt_siv(&base64::decode(encrypted).unwrap(), None)
+            .unwrap()
+    }
+
+    // stands in for the DH with the consensus IO key, which needs the key manager
+    fn recipient_key(public_key: &Ed25519PublicKey) -> AESKey {
+        AESKey::new_from_slice(public_key)
+    }
+
+    pub fn test_encrypt_attribute_for_recipients() {
+        let attr = LogAttribute {
+            key: "to".to_string(),
+            value: "secret1receiver".to_string(),
+            encrypted: true,
+            recipients: vec![],
+        };
+        let recipients = vec![Binary(vec![1u8; 32]), Binary(vec![2u8; 32])];
+
+        let encrypted =
+            encrypt_attribute_for_recipients(&attr, &recipients, None, recipient_key).unwrap();
+        assert_eq!(encrypted.len(), 2);
+
+        for (copy, recipient) in encrypted.iter().zip(recipients.iter()) {
+            let key = recipient_key(recipient.as_slice().try_into().unwrap());
+            assert_eq!(decrypt(&key, &copy.key), b"to");
+            assert_eq!(decrypt(&key, &copy.value), b"secret1receiver");
+            assert!(copy.encrypted);
+            assert!(copy.recipients.is_empty());
+        }
+
+        // each copy is only readable by its recipient
+        let other_key = recipient_key(&[2u8; 32]);
+        assert!(other_key
+            .decrypt_siv(&base64::decode(&encrypted[0].value).unwrap(), None)
+            .is_err());
+    }
+
+    pub fn test_encrypt_attribute_for_recipients_padding() {
+        let attr = LogAttribute {
+            key: "amount".to_string(),
+            value: "1".to_string(),
+            encrypted: true,
+            recipients: vec![],
+        };
+
+        let encrypted = encrypt_attribute_for_recipients(
+            &attr,
+            &[Binary(vec![1u8; 32])],
+            Some(64),
+            recipient_key,
+        )
+        .unwrap();
+
+        let key = recipient_key(&[1u8; 32]);
+        let padded = decrypt(&key, &encrypted[0].value);
+        assert_eq!(padded.len(), 64);
+        assert_eq!(crate::padding::unpad(padded), b"1");
+    }
+
+    pub fn test_encrypt_attribute_for_invalid_recipients() {
+        let attr = LogAttribute {
+            key: "to".to_string(),
+            value: "secret1receiver".to_string(),
+            encrypted: true,
+            recipients: vec![],
+        };
+
+        // addresses aren't supported, only public keys
+        let address = Binary(b"secret1receiver".to_vec());
+        assert_eq!(
+            encrypt_attribute_for_recipients(&attr, &[address], None, recipient_key)
+                .map(|_| ())
+                .unwrap_err()
+                .code(),
+            EnclaveError::FailedToDeserialize.code()
+        );
+
+        let too_many = vec![Binary(vec![1u8; 32]); MAX_ATTRIBUTE_RECIPIENTS + 1];
+        assert!(encrypt_attribute_for_recipients(&attr, &too_many, None, recipient_key).is_err());
+
+        assert!(
+            encrypt_attribute_for_recipients(&attr, &[], None, recipient_key)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
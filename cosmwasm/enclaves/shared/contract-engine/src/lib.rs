@@ -2,16 +2,24 @@
 #![feature(vec_into_raw_parts)]
 
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(not(target_env = "sgx"), not(feature = "simulator")))]
 extern crate sgx_tstd as std;
 
+#[cfg(not(feature = "simulator"))]
 extern crate sgx_rand;
+#[cfg(feature = "simulator")]
+extern crate rand as sgx_rand;
 extern crate sgx_types;
 
+mod batch;
+mod code_analysis;
+mod contract_keys;
 mod contract_operations;
 mod contract_validation;
 mod cosmwasm_config;
 mod db;
+mod disclosure;
+mod engine_params;
 mod errors;
 mod execute_message;
 pub mod external;
@@ -22,6 +30,7 @@ mod input_validation;
 mod io;
 mod message;
 mod message_utils;
+mod padding;
 mod query_chain;
 mod random;
 mod reply_message;
@@ -31,11 +40,18 @@ pub(crate) mod types;
 pub mod wasm3;
 
 pub use contract_operations::{handle, init, query};
+#[cfg(feature = "simulator")]
+pub use external::simulator::{with_host, SimulatorHost};
 #[cfg(feature = "light-client-validation")]
 pub use contract_validation::{check_cert_in_current_block, check_tx_in_current_block};
 
 #[cfg(feature = "test")]
 pub mod tests {
+    use crate::batch;
+    use crate::contract_keys;
+    use crate::disclosure;
+    use crate::io;
+    use crate::padding;
     use crate::types;
 
     /// Catch failures like the standard test runner, and print similar information per test.
@@ -62,6 +78,29 @@ pub mod tests {
 
         count_failures!(failures, {
             types::tests::test_new_from_slice();
+            contract_keys::tests::test_contract_keys_derivation();
+            contract_keys::tests::test_contract_keys_secp256k1_sign();
+            contract_keys::tests::test_contract_keys_ed25519_sign();
+            contract_keys::tests::test_contract_keys_x25519_ecdh();
+            contract_keys::tests::test_contract_secret_is_not_a_private_key();
+            disclosure::tests::test_disclose_tx_keys();
+            disclosure::tests::test_disclose_tx_keys_invalid_proof();
+            disclosure::tests::test_disclose_tx_keys_binds_tx_hash();
+            padding::tests::test_pad_unpad();
+            padding::tests::test_unpad_unpadded();
+            padding::tests::test_parse_padding_feature();
+            io::tests::test_encrypt_attribute_for_recipients();
+            io::tests::test_encrypt_attribute_for_recipients_padding();
+            io::tests::test_encrypt_attribute_for_invalid_recipients();
+            batch::tests::test_decode_batch();
+            batch::tests::test_decode_batch_rejects_malformed();
+            batch::tests::test_ends_batch();
+            batch::tests::test_batch_gas_is_charged_cumulatively();
+            contract_operations::tests::test_extract_call_depth_follows_engine_rules();
+            enclave_utils::error_context::tests::test_nested_ecall_keeps_outer_context();
+            enclave_utils::error_context::tests::test_take_matches_whole_error();
+            enclave_utils::recursion_depth::tests::test_check_call_depth_fails_in_validation();
+            enclave_utils::metrics::tests::test_histogram_snapshot_is_cumulative();
         });
 
         if failures != 0 {
@@ -0,0 +1,169 @@
+//!
+//! Length-hiding padding for encrypted contract outputs.
+//!
+//! Contracts that export `requires_padding` (or `requires_padding_<block size>`) get their
+//! encrypted outputs padded before encryption, so the ciphertext length only reveals the number of
+//! blocks. Padded plaintexts are framed as `PADDING_MAGIC | u32 BE length | plaintext | zeros`, and
+//! `SecretMessage::try_decrypt` strips the framing, so padded submessages, replies and query
+//! responses are transparent to the receiving contract. Clients that decrypt padded outputs
+//! should do the same.
+//!
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::features;
+
+/// Can't be the start of a JSON document, and is unlikely to start anything else
+pub const PADDING_MAGIC: &[u8; 8] = b"\0SCRTPAD";
+const PADDING_HEADER_LENGTH: usize = PADDING_MAGIC.len() + 4;
+
+pub const DEFAULT_PADDING_BLOCK_SIZE: u32 = 256;
+pub const MIN_PADDING_BLOCK_SIZE: u32 = 32;
+pub const MAX_PADDING_BLOCK_SIZE: u32 = 8192;
+
+/// Parses the block size out of a `requires_padding[_<block size>]` export. Returns `Ok(None)` for
+/// exports that aren't a padding feature at all, and an error if the block size isn't a power of
+/// two between `MIN_PADDING_BLOCK_SIZE` and `MAX_PADDING_BLOCK_SIZE`.
+pub fn parse_padding_feature(export_name: &str) -> Result<Option<u32>, EnclaveError> {
+    if export_name == features::PADDING {
+        return Ok(Some(DEFAULT_PADDING_BLOCK_SIZE));
+    }
+
+    let block_size = match export_name
+        .strip_prefix(features::PADDING)
+        .and_then(|suffix| suffix.strip_prefix('_'))
+    {
+        Some(block_size) => block_size,
+        None => return Ok(None),
+    };
+
+    match block_size.parse::<u32>() {
+        Ok(block_size)
+            if block_size.is_power_of_two()
+                && (MIN_PADDING_BLOCK_SIZE..=MAX_PADDING_BLOCK_SIZE).contains(&block_size) =>
+        {
+            Ok(Some(block_size))
+        }
+        _ => {
+            debug!(
+                "{} is not a supported padding block size, found in export {}",
+                block_size, export_name
+            );
+            Err(EnclaveError::InvalidWasm)
+        }
+    }
+}
+
+/// Pads `plaintext` to a multiple of `block_size`. Does nothing when `block_size` is `None`.
+pub fn pad(plaintext: &[u8], block_size: Option<u32>) -> Vec<u8> {
+    let block_size = match block_size {
+        Some(x) if x > 0 => x as usize,
+        _ => return plaintext.to_vec(),
+    };
+
+    let unpadded_length = PADDING_HEADER_LENGTH + plaintext.len();
+    let padded_length = ((unpadded_length + block_size - 1) / block_size) * block_size;
+
+    let mut padded = Vec::with_capacity(padded_length);
+    padded.extend_from_slice(PADDING_MAGIC);
+    padded.extend_from_slice(&(plaintext.len() as u32).to_be_bytes());
+    padded.extend_from_slice(plaintext);
+    padded.resize(padded_length, 0);
+
+    padded
+}
+
+/// Strips the padding added by `pad`. Anything that isn't padded is returned as is.
+pub fn unpad(data: Vec<u8>) -> Vec<u8> {
+    if data.len() < PADDING_HEADER_LENGTH || !data.starts_with(PADDING_MAGIC) {
+        return data;
+    }
+
+    let mut length_bytes = [0u8; 4];
+    length_bytes.copy_from_slice(&data[PADDING_MAGIC.len()..PADDING_HEADER_LENGTH]);
+    let length = u32::from_be_bytes(length_bytes) as usize;
+
+    let body = &data[PADDING_HEADER_LENGTH..];
+    if length > body.len() || body[length..].iter().any(|b| *b != 0) {
+        return data;
+    }
+
+    body[..length].to_vec()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_pad_unpad() {
+        for len in [0usize, 1, 243, 244, 245, 1000].iter() {
+            let plaintext = vec![7u8; *len];
+            let padded = pad(&plaintext, Some(256));
+
+            assert_eq!(padded.len() % 256, 0);
+            assert!(padded.len() >= plaintext.len() + PADDING_HEADER_LENGTH);
+            assert_eq!(unpad(padded), plaintext);
+        }
+
+        // outputs of the same bucket look the same
+        assert_eq!(
+            pad(b"{\"balance\":\"1\"}", Some(64)).len(),
+            pad(b"{\"balance\":\"1000000000\"}", Some(64)).len()
+        );
+
+        // no padding without a block size
+        assert_eq!(pad(b"plain", None), b"plain".to_vec());
+    }
+
+    pub fn test_unpad_unpadded() {
+        assert_eq!(unpad(b"{\"ok\":{}}".to_vec()), b"{\"ok\":{}}".to_vec());
+        assert_eq!(unpad(vec![]), Vec::<u8>::new());
+
+        // magic with a bad length or non-zero padding is left alone
+        let mut bad_length = PADDING_MAGIC.to_vec();
+        bad_length.extend_from_slice(&100u32.to_be_bytes());
+        bad_length.extend_from_slice(b"short");
+        assert_eq!(unpad(bad_length.clone()), bad_length);
+
+        let mut dirty = pad(b"data", Some(32));
+        let last = dirty.len() - 1;
+        dirty[last] = 1;
+        assert_eq!(unpad(dirty.clone()), dirty);
+    }
+
+    pub fn test_parse_padding_feature() {
+        assert!(matches!(
+            parse_padding_feature("requires_padding"),
+            Ok(Some(DEFAULT_PADDING_BLOCK_SIZE))
+        ));
+        assert!(matches!(
+            parse_padding_feature("requires_padding_1024"),
+            Ok(Some(1024))
+        ));
+        assert!(matches!(
+            parse_padding_feature("requires_paddingx"),
+            Ok(None)
+        ));
+        assert!(matches!(parse_padding_feature("requires_random"), Ok(None)));
+
+        for invalid in [
+            "requires_padding_1000",
+            "requires_padding_16",
+            "requires_padding_16384",
+            "requires_padding_",
+            "requires_padding_big",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(
+                    parse_padding_feature(invalid),
+                    Err(EnclaveError::InvalidWasm)
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -5,7 +5,7 @@ use sgx_types::sgx_status_t;
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError};
 
 use enclave_crypto::Ed25519PublicKey;
-use enclave_utils::recursion_depth;
+use enclave_utils::{metrics, recursion_depth};
 
 use super::errors::WasmEngineError;
 use crate::external::{ecalls, ocalls};
@@ -20,17 +20,18 @@ use cw_types_v010::{
 
 pub fn encrypt_and_query_chain(
     query: &[u8],
-    query_depth: u32,
+    call_depth: u32,
+    max_call_depth: u32,
     context: &Ctx,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     gas_used: &mut u64,
     gas_limit: u64,
 ) -> Result<Vec<u8>, WasmEngineError> {
-    if let Some(answer) = check_recursion_limit(query_depth) {
+    if let Some(answer) = check_recursion_limit(call_depth, max_call_depth) {
         return serialize_error_response(&answer);
     }
-    let new_query_depth = query_depth + 1;
+    let new_call_depth = call_depth + 1;
 
     let mut query_struct: QueryRequest = match serde_json::from_slice(query) {
         Ok(query_struct) => query_struct,
@@ -55,7 +56,7 @@ pub fn encrypt_and_query_chain(
     // Call query_chain (this bubbles up to x/compute via ocalls and FFI to Go code)
     // This returns the answer from x/compute
     let (result, query_used_gas) =
-        query_chain(context, &encrypted_query, new_query_depth, gas_limit);
+        query_chain(context, &encrypted_query, new_call_depth, gas_limit);
     *gas_used = query_used_gas;
     let encrypted_answer_as_vec = result?;
 
@@ -148,7 +149,7 @@ pub fn encrypt_and_query_chain(
 fn query_chain(
     context: &Ctx,
     query: &[u8],
-    query_depth: u32,
+    call_depth: u32,
     gas_limit: u64,
 ) -> (Result<Vec<u8>, WasmEngineError>, u64) {
     let mut ocall_return = OcallReturn::Success;
@@ -165,7 +166,7 @@ fn query_chain(
             enclave_buffer.as_mut_ptr(),
             query.as_ptr(),
             query.len(),
-            query_depth,
+            call_depth,
         );
 
         trace!("ocall_query_chain returned with gas {}", gas_used);
@@ -193,16 +194,21 @@ fn query_chain(
             OcallReturn::Panic => return (Err(WasmEngineError::Panic), gas_used),
         }
     };
+    metrics::OCALL_QUERY_CHAIN.record(query.len() + value.len());
 
     (Ok(value), gas_used)
 }
 
 /// Check whether the query is allowed to run.
 ///
-/// We make sure that a recursion limit is in place in order to
-/// mitigate cases where the enclave runs out of memory.
-fn check_recursion_limit(query_depth: u32) -> Option<SystemResult<StdResult<Binary>>> {
-    if recursion_depth::limit_reached(query_depth) {
+/// The query runs one level deeper than the calling contract, so the call depth limit makes sure
+/// that the enclave doesn't run out of memory on nested queries. Contracts get a `SystemError`
+/// they can handle, rather than failing the whole call.
+fn check_recursion_limit(
+    call_depth: u32,
+    max_call_depth: u32,
+) -> Option<SystemResult<StdResult<Binary>>> {
+    if recursion_depth::limit_reached(call_depth, max_call_depth) {
         debug!(
             "Recursion limit reached while performing nested queries. Returning error to contract."
         );
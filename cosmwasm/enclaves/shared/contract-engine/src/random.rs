@@ -7,6 +7,9 @@ use cw_types_v010::encoding::Binary;
 use lazy_static::lazy_static;
 use log::trace;
 
+#[cfg(feature = "simulator")]
+use std::sync::Mutex as SgxMutex;
+#[cfg(not(feature = "simulator"))]
 use std::sync::SgxMutex;
 
 #[derive(Default, Clone, Copy, Debug)]
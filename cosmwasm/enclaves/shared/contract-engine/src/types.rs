@@ -2,9 +2,11 @@ use log::*;
 use serde::{Deserialize, Serialize};
 
 use enclave_crypto::{AESKey, Ed25519PublicKey, SIVEncryptable};
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+use enclave_utils::error_context::with_context;
 
 use super::io::calc_encryption_key;
+use super::padding::unpad;
 
 pub type IoNonce = [u8; 32];
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -48,6 +50,8 @@ impl SecretMessage {
         let key = self.encryption_key();
 
         if let Ok(msg) = key.decrypt_siv(self.msg.as_slice(), None) {
+            // Padding is transparent to whoever consumes the message
+            let msg = unpad(msg);
             trace!(
                 "input after decryption: {:?}",
                 String::from_utf8_lossy(&msg)
@@ -64,7 +68,11 @@ impl SecretMessage {
             Some(msg) => Ok(msg),
             None => {
                 error!("got an error while trying to decrypt the msg");
-                Err(EnclaveError::DecryptionError)
+                Err(with_context(
+                    EnclaveError::DecryptionError,
+                    EnclaveErrorStage::Decryption,
+                    error_details::INPUT_DECRYPTION_FAILED,
+                ))
             }
         }
     }
@@ -0,0 +1,162 @@
+//! Symbolic backtraces of wasm traps, for non-production builds.
+//!
+//! wasm3 doesn't tell us where a trap happened, so we instrument the module to keep a shadow call
+//! stack: every function reports its frame to the host when it's entered and when it returns.
+//! When a trap unwinds the wasm stack, the frames that never returned are exactly the call stack
+//! at the trap. The instrumentation is added after gas metering, so it doesn't cost any gas.
+
+// Production builds never trace calls
+#![cfg_attr(feature = "production", allow(dead_code))]
+
+use walrus::ir::{dfs_pre_order_mut, Block, Call, Instr, InstrSeq, InstrSeqId, VisitorMut};
+use walrus::{FunctionId, Module, ValType};
+
+/// Name of the imported function that is called when a function is entered
+pub const IMPORT_TRACE_ENTER: &str = "__secret_trace_enter";
+/// Name of the imported function that is called when a function returns
+pub const IMPORT_TRACE_EXIT: &str = "__secret_trace_exit";
+
+/// The innermost frames that are shown in a backtrace
+const MAX_FRAMES: usize = 32;
+
+/// Instruments every function in the module to report its frame to the host, and returns the
+/// names of the frames. Names come from the name section of the module, if it has one.
+pub fn add_call_tracing(module: &mut Module) -> Vec<String> {
+    let function_names: Vec<String> = module
+        .funcs
+        .iter_local()
+        .map(|(id, _)| {
+            module
+                .funcs
+                .get(id)
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("<func {}>", id.index()))
+        })
+        .collect();
+
+    let enter_type = module.types.add(&[ValType::I32], &[]);
+    let (trace_enter, _) = module.add_import_func("env", IMPORT_TRACE_ENTER, enter_type);
+    let exit_type = module.types.add(&[], &[]);
+    let (trace_exit, _) = module.add_import_func("env", IMPORT_TRACE_EXIT, exit_type);
+
+    for (frame, (_, func)) in module.funcs.iter_local_mut().enumerate() {
+        let entry = func.entry_block();
+        let ty = func.block(entry).ty;
+
+        // Move the body of the function into a block of its own, so we can report the return
+        // after it no matter how it ends.
+        let body = std::mem::take(&mut func.block_mut(entry).instrs);
+        let inner = func.builder_mut().dangling_instr_seq(ty).id();
+        func.block_mut(inner).instrs = body;
+
+        dfs_pre_order_mut(
+            &mut ReturnTracer {
+                entry,
+                inner,
+                trace_exit,
+            },
+            func,
+            inner,
+        );
+
+        func.builder_mut()
+            .func_body()
+            .i32_const(frame as i32)
+            .call(trace_enter)
+            .instr(Block { seq: inner })
+            .call(trace_exit);
+    }
+
+    function_names
+}
+
+/// Retargets the branches out of the function to the block that now holds its body, and reports
+/// the early returns.
+struct ReturnTracer {
+    entry: InstrSeqId,
+    inner: InstrSeqId,
+    trace_exit: FunctionId,
+}
+
+impl VisitorMut for ReturnTracer {
+    fn start_instr_seq_mut(&mut self, seq: &mut InstrSeq) {
+        if !seq.instrs.iter().any(|(instr, _)| is_return(instr)) {
+            return;
+        }
+
+        let mut instrs = Vec::with_capacity(seq.instrs.len() + 1);
+        for (instr, loc) in seq.instrs.drain(..) {
+            if is_return(&instr) {
+                instrs.push((
+                    Instr::from(Call {
+                        func: self.trace_exit,
+                    }),
+                    Default::default(),
+                ));
+            }
+            instrs.push((instr, loc));
+        }
+        seq.instrs = instrs;
+    }
+
+    fn visit_instr_seq_id_mut(&mut self, seq: &mut InstrSeqId) {
+        if *seq == self.entry {
+            *seq = self.inner;
+        }
+    }
+}
+
+fn is_return(instr: &Instr) -> bool {
+    matches!(instr, Instr::Return(_))
+}
+
+/// The frames of the functions that were entered and haven't returned yet
+#[derive(Default)]
+pub struct CallStack {
+    function_names: Vec<String>,
+    frames: Vec<u32>,
+}
+
+impl CallStack {
+    pub fn new(function_names: Vec<String>) -> Self {
+        Self {
+            function_names,
+            frames: vec![],
+        }
+    }
+
+    pub fn enter(&mut self, frame: u32) {
+        self.frames.push(frame);
+    }
+
+    pub fn exit(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Formats the frames that are left after a trap, innermost first, and clears them
+    pub fn take_backtrace(&mut self) -> Option<String> {
+        if self.frames.is_empty() {
+            return None;
+        }
+
+        let mut backtrace = String::from("wasm backtrace:");
+        for (depth, frame) in self.frames.iter().rev().take(MAX_FRAMES).enumerate() {
+            let name = self
+                .function_names
+                .get(*frame as usize)
+                .map(String::as_str)
+                .unwrap_or("<unknown>");
+            backtrace.push_str(&format!("\n  {}: {}", depth, name));
+        }
+        if self.frames.len() > MAX_FRAMES {
+            backtrace.push_str(&format!(
+                "\n  ... {} more frames",
+                self.frames.len() - MAX_FRAMES
+            ));
+        }
+
+        self.frames.clear();
+        Some(backtrace)
+    }
+}
@@ -6,6 +6,7 @@ use walrus::{
     ir::*, FunctionBuilder, FunctionId, GlobalId, InitExpr, LocalFunction, Module, ValType,
 };
 
+use crate::engine_params::EngineRules;
 use crate::errors::{WasmEngineError, WasmEngineResult};
 use crate::gas::WasmCosts;
 use enclave_ffi_types::EnclaveError;
@@ -50,8 +51,10 @@ pub fn use_gas<C>(instance: &wasm3::Instance<C>, amount: u64) -> WasmEngineResul
     Ok(())
 }
 
-/// Inject gas metering instrumentation into the module.
-pub fn add_metering(module: &mut Module, gas_costs: &WasmCosts) {
+/// Inject gas metering instrumentation into the module, as the given rules meter it.
+pub fn add_metering(module: &mut Module, rules: &EngineRules) {
+    let gas_costs = &rules.wasm_costs();
+
     let gas_limit_global =
         module
             .globals
@@ -65,49 +68,233 @@ pub fn add_metering(module: &mut Module, gas_costs: &WasmCosts) {
         .exports
         .add(EXPORT_GAS_LIMIT_EXHAUSTED, gas_limit_exhausted_global);
 
-    let memory_grow_meter = create_memory_grow_meter(
-        module,
-        gas_costs,
-        gas_limit_global,
-        gas_limit_exhausted_global,
-    );
+    let meters = OperandMeters {
+        memory_grow: create_operand_meter(
+            module,
+            gas_costs.grow_mem,
+            memory_grow_pages(rules),
+            gas_limit_global,
+            gas_limit_exhausted_global,
+        ),
+        bulk_memory: match rules {
+            EngineRules::Legacy => None,
+            EngineRules::Params(_) => Some(create_operand_meter(
+                module,
+                gas_costs.memcpy,
+                UnaryOp::I64ExtendUI32,
+                gas_limit_global,
+                gas_limit_exhausted_global,
+            )),
+        },
+    };
 
     for (_, func) in module.funcs.iter_local_mut() {
         transform_function(
             func,
+            rules,
             gas_costs,
             gas_limit_global,
             gas_limit_exhausted_global,
-            memory_grow_meter,
+            &meters,
         );
     }
 }
 
-// todo copy from pwasm_utils
-/// Instruction cost function.
-fn instruction_cost(_instr: &Instr, _gas_costs: &WasmCosts) -> u64 {
-    // Currently default to 1 for all instructions.
-    2
+/// How the page count of `memory.grow` is widened before it's charged for. It's unsigned, but
+/// calls from before the chain set its engine params charge for it as if it were signed, like
+/// they always did.
+fn memory_grow_pages(rules: &EngineRules) -> UnaryOp {
+    match rules {
+        EngineRules::Legacy => UnaryOp::I64ExtendSI32,
+        EngineRules::Params(_) => UnaryOp::I64ExtendUI32,
+    }
+}
+
+/// Functions that charge for the work an instruction does according to its last operand, and
+/// return the operand so the instruction can run right after the call.
+struct OperandMeters {
+    /// Charges `memory.grow` per page
+    memory_grow: FunctionId,
+    /// Charges `memory.copy` and `memory.fill` per byte. Calls made before the chain set its
+    /// engine params don't pay for the bytes.
+    bulk_memory: Option<FunctionId>,
+}
+
+impl OperandMeters {
+    fn for_instr(&self, instr: &Instr) -> Option<FunctionId> {
+        match instr {
+            Instr::MemoryGrow { .. } => Some(self.memory_grow),
+            Instr::MemoryCopy { .. } | Instr::MemoryFill { .. } => self.bulk_memory,
+            _ => None,
+        }
+    }
+}
+
+/// The cost of every instruction of calls made before the chain set its engine params
+const LEGACY_INSTRUCTION_COST: u64 = 2;
+
+/// Instruction cost function. See `WasmCosts` for the classes of instructions.
+fn instruction_cost(instr: &Instr, gas_costs: &WasmCosts) -> u64 {
+    let cost = match instr {
+        Instr::LocalGet { .. }
+        | Instr::LocalSet { .. }
+        | Instr::LocalTee { .. }
+        | Instr::GlobalGet { .. }
+        | Instr::GlobalSet { .. } => gas_costs.local,
+        Instr::Block { .. }
+        | Instr::Loop { .. }
+        | Instr::IfElse { .. }
+        | Instr::Br { .. }
+        | Instr::BrIf { .. }
+        | Instr::BrTable { .. }
+        | Instr::Return { .. }
+        | Instr::Unreachable { .. } => gas_costs.control_flow,
+        Instr::Call { .. } => gas_costs.call,
+        Instr::CallIndirect { .. } => gas_costs.call_indirect,
+        Instr::Load { .. }
+        | Instr::Store { .. }
+        | Instr::MemorySize { .. }
+        | Instr::MemoryGrow { .. } => gas_costs.mem,
+        Instr::MemoryCopy { .. }
+        | Instr::MemoryFill { .. }
+        | Instr::MemoryInit { .. }
+        | Instr::DataDrop { .. }
+        | Instr::TableCopy { .. }
+        | Instr::TableInit { .. }
+        | Instr::ElemDrop { .. } => gas_costs.bulk_memory,
+        Instr::Binop(Binop { op }) => match op {
+            BinaryOp::I32Mul | BinaryOp::I64Mul | BinaryOp::F32Mul | BinaryOp::F64Mul => {
+                gas_costs.mul
+            }
+            BinaryOp::I32DivS
+            | BinaryOp::I32DivU
+            | BinaryOp::I32RemS
+            | BinaryOp::I32RemU
+            | BinaryOp::I64DivS
+            | BinaryOp::I64DivU
+            | BinaryOp::I64RemS
+            | BinaryOp::I64RemU
+            | BinaryOp::F32Div
+            | BinaryOp::F64Div => gas_costs.div,
+            _ => gas_costs.regular,
+        },
+        _ => gas_costs.regular,
+    };
+    cost as u64
+}
+
+/// Whether control may leave the instruction sequence right after this instruction, which ends
+/// the basic block it's in. Nested blocks end it too, because they can branch out of the
+/// sequence.
+fn ends_basic_block(instr: &Instr) -> bool {
+    matches!(
+        instr,
+        Instr::Block { .. }
+            | Instr::Loop { .. }
+            | Instr::IfElse { .. }
+            | Instr::Br { .. }
+            | Instr::BrIf { .. }
+            | Instr::BrTable { .. }
+            | Instr::Return { .. }
+            | Instr::Unreachable { .. }
+    )
+}
+
+/// Splits an instruction sequence into its basic blocks, as the location of the first
+/// instruction of each block and the cost of the block. Blocks that cost nothing are left out.
+fn basic_blocks(instrs: &[(Instr, InstrLocId)], gas_costs: &WasmCosts) -> Vec<(usize, u64)> {
+    let mut blocks = vec![];
+    let mut block_start = 0;
+    let mut block_cost = 0;
+
+    for (loc, (instr, _)) in instrs.iter().enumerate() {
+        block_cost += instruction_cost(instr, gas_costs);
+        if ends_basic_block(instr) {
+            blocks.push((block_start, block_cost));
+            block_start = loc + 1;
+            block_cost = 0;
+        }
+    }
+    blocks.push((block_start, block_cost));
+
+    blocks.retain(|&(_, cost)| cost > 0);
+    blocks
+}
+
+/// Returns the locations in an instruction sequence where the given rules charge for gas, and how
+/// much they charge there. Calls made before the chain set its engine params pay for the whole
+/// sequence when it starts, at a flat cost per instruction, like they always did.
+fn metered_blocks(
+    instrs: &[(Instr, InstrLocId)],
+    rules: &EngineRules,
+    gas_costs: &WasmCosts,
+) -> Vec<(usize, u64)> {
+    match rules {
+        EngineRules::Legacy => vec![(0, instrs.len() as u64 * LEGACY_INSTRUCTION_COST)],
+        EngineRules::Params(_) => basic_blocks(instrs, gas_costs),
+    }
+}
+
+/// The static cost of a function, as metered by `add_metering` under the chain's engine params
+pub struct FunctionCost {
+    /// Index of the function in the module
+    pub index: usize,
+    /// Name of the function in the name section, if the module has one
+    pub name: Option<String>,
+    /// Number of metered basic blocks
+    pub basic_blocks: usize,
+    /// Cost of running each basic block once
+    pub total_cost: u64,
+    /// Cost of the most expensive basic block
+    pub max_block_cost: u64,
+}
+
+/// Returns the static cost of each function of the module that `add_metering` would charge under
+/// the chain's engine params.
+pub fn function_costs(module: &Module, gas_costs: &WasmCosts) -> Vec<FunctionCost> {
+    module
+        .funcs
+        .iter_local()
+        .map(|(func_id, func)| {
+            let mut function_cost = FunctionCost {
+                index: func_id.index(),
+                name: module.funcs.get(func_id).name.clone(),
+                basic_blocks: 0,
+                total_cost: 0,
+                max_block_cost: 0,
+            };
+            for (_, block) in func.blocks() {
+                for (_, block_cost) in basic_blocks(&block.instrs, gas_costs) {
+                    function_cost.basic_blocks += 1;
+                    function_cost.total_cost += block_cost;
+                    function_cost.max_block_cost = function_cost.max_block_cost.max(block_cost);
+                }
+            }
+            function_cost
+        })
+        .collect()
 }
 
 fn transform_function(
     func: &mut LocalFunction,
+    rules: &EngineRules,
     gas_costs: &WasmCosts,
     gas_limit_global: GlobalId,
     gas_limit_exhausted_global: GlobalId,
-    memory_grow_meter: FunctionId,
+    meters: &OperandMeters,
 ) {
     // get the list of "original" blocks before we start adding more.
     let block_ids: Vec<_> = func.blocks().map(|(block_id, _block)| block_id).collect();
-    // for each block, prepend it with metering instructions
+    // for each block, prepend each of its metered blocks with metering instructions
     for block_id in block_ids {
         inject_metering(
             func,
             block_id,
+            rules,
             gas_costs,
             gas_limit_global,
             gas_limit_exhausted_global,
-            memory_grow_meter,
+            meters,
         );
     }
 }
@@ -118,40 +305,62 @@ const METERING_INSTRUCTION_COUNT: usize = 8;
 fn inject_metering(
     func: &mut LocalFunction,
     block_id: InstrSeqId,
+    rules: &EngineRules,
     gas_costs: &WasmCosts,
     gas_limit_global: GlobalId,
     gas_limit_exhausted_global: GlobalId,
-    memory_grow_meter: FunctionId,
+    meters: &OperandMeters,
 ) {
+    let blocks = metered_blocks(&func.block(block_id).instrs, rules, gas_costs);
+    let block_charges: Vec<_> = blocks
+        .into_iter()
+        .map(|(block_start, block_cost)| {
+            let charge = metering_instrs(
+                func,
+                block_cost as i64,
+                gas_limit_global,
+                gas_limit_exhausted_global,
+            );
+            (block_start, charge)
+        })
+        .collect();
+
     let block = func.block_mut(block_id);
-    let block_instrs = &mut block.instrs;
-    let block_len = block_instrs.len();
-    let block_cost: u64 = block_instrs
-        .iter()
-        .map(|(inst, _instr_loc)| instruction_cost(inst, gas_costs))
-        .sum();
-    let block_cost = block_cost as i64;
-
-    // find all location in the block that use Instr::MemoryGrow
-    let mut grow_locations = vec![];
-    for (loc, (instr, _)) in block_instrs.iter().enumerate() {
-        if let Instr::MemoryGrow { .. } = instr {
-            grow_locations.push(loc);
+    let block_instrs = std::mem::take(&mut block.instrs);
+    let mut new_instrs =
+        Vec::with_capacity(block_instrs.len() + block_charges.len() * METERING_INSTRUCTION_COUNT);
+    let mut block_charges = block_charges.into_iter().peekable();
+
+    for (loc, (instr, instr_loc)) in block_instrs.into_iter().enumerate() {
+        // Charge for each metered block before its first instruction
+        if let Some((block_start, _)) = block_charges.peek() {
+            if *block_start == loc {
+                let (_, mut charge) = block_charges.next().unwrap();
+                new_instrs.append(&mut charge);
+            }
         }
-    }
 
-    // Prepend instances of Instr::MemoryGrow with a call to the memory grow meter.
-    // This is done in reverse because the indices are locations in the
-    // underlying instruction array. Doing this in order would invalidate the
-    // indices.
-    for loc in grow_locations.into_iter().rev() {
-        let call_grow_meter = Instr::from(Call {
-            func: memory_grow_meter,
-        });
-        // using Default is fine - it's the same as what `InstrSeqBuilder::instr_at` does.
-        block_instrs.insert(loc, (call_grow_meter, Default::default()));
+        // Prepend instructions that do work according to their operand with a call to its meter.
+        if let Some(meter) = meters.for_instr(&instr) {
+            let call_meter = Instr::from(Call { func: meter });
+            // using Default is fine - it's the same as what `InstrSeqBuilder::instr_at` does.
+            new_instrs.push((call_meter, Default::default()));
+        }
+
+        new_instrs.push((instr, instr_loc));
     }
 
+    func.block_mut(block_id).instrs = new_instrs;
+}
+
+/// Builds the instructions that charge `block_cost`, or trap if the gas left is lower. They leave
+/// the operand stack as they found it, so they're valid anywhere, including in multi-value blocks.
+fn metering_instrs(
+    func: &mut LocalFunction,
+    block_cost: i64,
+    gas_limit_global: GlobalId,
+    gas_limit_exhausted_global: GlobalId,
+) -> Vec<(Instr, InstrLocId)> {
     let builder = func.builder_mut();
     let mut builder = builder.dangling_instr_seq(None);
     let seq = builder
@@ -174,59 +383,261 @@ fn inject_metering(
         .binop(BinaryOp::I64Sub)
         .global_set(gas_limit_global);
 
-    let mut new_instrs = Vec::with_capacity(block_len + METERING_INSTRUCTION_COUNT);
-    new_instrs.append(seq.instrs_mut());
-
-    let block = func.block_mut(block_id);
-    new_instrs.extend_from_slice(block);
-    block.instrs = new_instrs;
+    std::mem::take(seq.instrs_mut())
 }
 
-fn create_memory_grow_meter(
+/// Creates a function that charges `unit_cost` for each unit of its i32 operand, widened with
+/// `extend`, and returns it.
+fn create_operand_meter(
     module: &mut Module,
-    gas_costs: &WasmCosts,
+    unit_cost: u32,
+    extend: UnaryOp,
     gas_limit_global: GlobalId,
     gas_limit_exhausted_global: GlobalId,
 ) -> FunctionId {
     // function input
-    let num_pages = module.locals.add(ValType::I32);
-    // cache cost of memory grow
-    let grow_cost = module.locals.add(ValType::I64);
+    let units = module.locals.add(ValType::I32);
+    // cache the cost
+    let cost = module.locals.add(ValType::I64);
 
     let mut func = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
 
     func.func_body()
-        // multiply the number of pages by the grow cost
-        .local_get(num_pages)
-        // num_pages as i64
-        .unop(UnaryOp::I64ExtendSI32)
-        .i64_const(gas_costs.grow_mem as i64)
+        // multiply the number of units by the unit cost
+        .local_get(units)
+        // units as i64
+        .unop(extend)
+        .i64_const(unit_cost as i64)
         .binop(BinaryOp::I64Mul)
         // save the cost
-        .local_set(grow_cost)
-        // from here it's very similar to the code in `fn inject_metering()`.
-        // if unsigned(globals[gas_limit]) < unsigned(grow_cost) { throw(); }
+        .local_set(cost)
+        // from here it's very similar to the code in `fn metering_instrs()`.
+        // if unsigned(globals[gas_limit]) < unsigned(cost) { throw(); }
         .global_get(gas_limit_global)
-        .local_get(grow_cost)
+        .local_get(cost)
         .binop(BinaryOp::I64LtU)
         .if_else(
             None,
             |then| {
-                then.local_get(grow_cost)
+                then.local_get(cost)
                     .global_set(gas_limit_exhausted_global)
                     .unreachable();
             },
             |_else| {},
         )
-        // globals[gas_limit] -= grow_cost;
+        // globals[gas_limit] -= cost;
         .global_get(gas_limit_global)
-        .local_get(grow_cost)
+        .local_get(cost)
         .binop(BinaryOp::I64Sub)
         .global_set(gas_limit_global)
-        // return the original number of pages for the MemoryGrow instruction
-        // right after this function call.
-        .local_get(num_pages);
+        // return the original operand for the instruction right after this function call.
+        .local_get(units);
 
     // register the function
-    func.finish(vec![num_pages], &mut module.funcs)
+    func.finish(vec![units], &mut module.funcs)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use walrus::FunctionKind;
+
+    use crate::engine_params::EngineParams;
+
+    fn current_rules() -> EngineRules {
+        EngineRules::Params(EngineParams {
+            max_memory_pages: 192,
+            max_call_depth: 10,
+        })
+    }
+
+    /// A function that counts down its argument in a loop, and then drops a constant. The loop
+    /// body holds two basic blocks, split by the `br_if` that repeats it.
+    fn count_down_module() -> Module {
+        let mut module = Module::default();
+        let counter = module.locals.add(ValType::I32);
+
+        let mut func = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+        func.name("count_down".to_string());
+        func.func_body().loop_(None, |body| {
+            let loop_id = body.id();
+            body.local_get(counter)
+                .i32_const(1)
+                .binop(BinaryOp::I32Sub)
+                .local_tee(counter)
+                .br_if(loop_id)
+                .i32_const(0)
+                .drop();
+        });
+        func.finish(vec![counter], &mut module.funcs);
+
+        module
+    }
+
+    pub fn test_function_costs_split_basic_blocks() {
+        let module = count_down_module();
+        let costs = function_costs(&module, &WasmCosts::default());
+
+        assert_eq!(costs.len(), 1);
+        let cost = &costs[0];
+        assert_eq!(cost.name.as_deref(), Some("count_down"));
+        // the loop itself, the body up to the br_if, and the rest of the body
+        assert_eq!(cost.basic_blocks, 3);
+        // loop: control flow = 2
+        // local.get, i32.const, i32.sub, local.tee, br_if: 1 + 2 + 2 + 1 + 2 = 8
+        // i32.const, drop: 2 + 2 = 4
+        assert_eq!(cost.total_cost, 14);
+        assert_eq!(cost.max_block_cost, 8);
+    }
+
+    pub fn test_add_metering_charges_each_basic_block() {
+        let mut module = count_down_module();
+        add_metering(&mut module, &current_rules());
+
+        let (_, func) = module
+            .funcs
+            .iter_local()
+            .find(|(func_id, _)| module.funcs.get(*func_id).name.as_deref() == Some("count_down"))
+            .unwrap();
+
+        // every metered basic block traps in its own `then` block when it runs out of gas
+        let traps = func
+            .blocks()
+            .flat_map(|(_, block)| block.instrs.iter())
+            .filter(|(instr, _)| matches!(instr, Instr::Unreachable { .. }))
+            .count();
+        assert_eq!(traps, 3);
+
+        let entry = func.block(func.entry_block());
+        assert_eq!(entry.instrs.len(), METERING_INSTRUCTION_COUNT + 1);
+    }
+
+    pub fn test_add_metering_charges_legacy_calls_per_instruction_sequence() {
+        let mut module = count_down_module();
+        add_metering(&mut module, &EngineRules::Legacy);
+
+        let (_, func) = module
+            .funcs
+            .iter_local()
+            .find(|(func_id, _)| module.funcs.get(*func_id).name.as_deref() == Some("count_down"))
+            .unwrap();
+
+        // what the metering instructions at the start of a sequence charge
+        let charged = |seq: InstrSeqId| match &func.block(seq).instrs[1] {
+            (
+                Instr::Const(Const {
+                    value: Value::I64(cost),
+                }),
+                _,
+            ) => *cost,
+            (instr, _) => panic!("expected the cost of the sequence, found {:?}", instr),
+        };
+
+        // the entry sequence only holds the loop, and pays for it alone
+        let entry = func.block(func.entry_block());
+        assert_eq!(entry.instrs.len(), METERING_INSTRUCTION_COUNT + 1);
+        assert_eq!(charged(func.entry_block()), 2);
+
+        // the loop body pays for all of its 7 instructions up front, even though the br_if
+        // leaves before the last two
+        let body = match &entry.instrs[METERING_INSTRUCTION_COUNT] {
+            (Instr::Loop(Loop { seq }), _) => *seq,
+            (instr, _) => panic!("expected the loop, found {:?}", instr),
+        };
+        assert_eq!(charged(body), 14);
+        assert_eq!(
+            func.block(body).instrs.len(),
+            METERING_INSTRUCTION_COUNT + 7
+        );
+    }
+
+    pub fn test_add_metering_charges_bulk_memory_per_byte() {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, 1, None);
+        let dst = module.locals.add(ValType::I32);
+        let src = module.locals.add(ValType::I32);
+        let len = module.locals.add(ValType::I32);
+
+        let mut func = FunctionBuilder::new(
+            &mut module.types,
+            &[ValType::I32, ValType::I32, ValType::I32],
+            &[],
+        );
+        func.name("copy".to_string());
+        func.func_body()
+            .local_get(dst)
+            .local_get(src)
+            .local_get(len)
+            .memory_copy(memory, memory);
+        func.finish(vec![dst, src, len], &mut module.funcs);
+
+        add_metering(&mut module, &current_rules());
+
+        let (_, func) = module
+            .funcs
+            .iter_local()
+            .find(|(func_id, _)| module.funcs.get(*func_id).name.as_deref() == Some("copy"))
+            .unwrap();
+        let instrs = &func.block(func.entry_block()).instrs;
+
+        // the length on top of the stack goes through the meter right before the copy
+        assert_eq!(instrs.len(), METERING_INSTRUCTION_COUNT + 5);
+        assert!(matches!(instrs[instrs.len() - 2], (Instr::Call { .. }, _)));
+        assert!(matches!(
+            instrs[instrs.len() - 1],
+            (Instr::MemoryCopy { .. }, _)
+        ));
+    }
+
+    /// Returns the instructions of the meter that `add_metering` puts before a `memory.grow`
+    fn memory_grow_meter(rules: &EngineRules) -> Vec<Instr> {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, 1, None);
+        let pages = module.locals.add(ValType::I32);
+
+        let mut func = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+        func.name("grow".to_string());
+        func.func_body().local_get(pages).memory_grow(memory).drop();
+        func.finish(vec![pages], &mut module.funcs);
+
+        add_metering(&mut module, rules);
+
+        let (_, func) = module
+            .funcs
+            .iter_local()
+            .find(|(func_id, _)| module.funcs.get(*func_id).name.as_deref() == Some("grow"))
+            .unwrap();
+        let instrs = &func.block(func.entry_block()).instrs;
+        let meter = match &instrs[instrs.len() - 3] {
+            (Instr::Call(call), _) => call.func,
+            (instr, _) => panic!("expected the memory.grow meter, found {:?}", instr),
+        };
+
+        match &module.funcs.get(meter).kind {
+            FunctionKind::Local(meter) => meter
+                .blocks()
+                .flat_map(|(_, block)| block.instrs.iter().map(|(instr, _)| instr.clone()))
+                .collect(),
+            _ => panic!("the memory.grow meter is not a local function"),
+        }
+    }
+
+    pub fn test_add_metering_widens_memory_grow_by_rules() {
+        // whether the meter widens the page count as a signed or as an unsigned number
+        let signed = |instrs: Vec<Instr>| {
+            instrs.iter().find_map(|instr| match instr {
+                Instr::Unop(Unop {
+                    op: UnaryOp::I64ExtendSI32,
+                }) => Some(true),
+                Instr::Unop(Unop {
+                    op: UnaryOp::I64ExtendUI32,
+                }) => Some(false),
+                _ => None,
+            })
+        };
+
+        assert_eq!(signed(memory_grow_meter(&EngineRules::Legacy)), Some(true));
+        assert_eq!(signed(memory_grow_meter(&current_rules())), Some(false));
+    }
 }
@@ -13,32 +13,43 @@ use wasm3::{Instance, Memory, Trap};
 
 use cw_types_v010::consts::BECH32_PREFIX_ACC_ADDR;
 use cw_types_v010::encoding::Binary;
+use enclave_cosmos_types::permit::Permit;
 use enclave_cosmos_types::types::{ContractCode, HandleType};
-use enclave_crypto::{sha_256, Ed25519PublicKey, WasmApiCryptoError};
+use enclave_crypto::{constant_time_eq, sha_256, Ed25519PublicKey, WasmApiCryptoError, HASH_SIZE};
 use enclave_ffi_types::{Ctx, EnclaveError};
 
+use crate::contract_keys::ContractKeys;
 use crate::contract_validation::ContractKey;
 use crate::cosmwasm_config::ContractOperation;
 use crate::db::read_from_encrypted_state;
 use crate::db::{remove_from_encrypted_state, write_multiple_keys};
+use crate::engine_params::EngineRules;
 use crate::errors::{ToEnclaveError, ToEnclaveResult, WasmEngineError, WasmEngineResult};
 use crate::gas::{WasmCosts, READ_BASE_GAS, WRITE_BASE_GAS};
 use crate::query_chain::encrypt_and_query_chain;
 use crate::random::MSG_COUNTER;
 use crate::types::IoNonce;
 
+use backtrace::CallStack;
 use gas::{get_exhausted_amount, get_remaining_gas, use_gas};
 use module_cache::create_module_instance;
 
+mod backtrace;
 mod gas;
 pub mod module_cache;
+mod nan_canonicalization;
+#[cfg(not(feature = "simulator"))]
+mod sealed_module_cache;
 mod validation;
 // use std::time::Instant;
 
+pub use gas::FunctionCost;
+
 type Wasm3RsError = wasm3::Error;
 type Wasm3RsResult<T> = Result<T, wasm3::Error>;
 
 use crate::wasm3::gas::EXPORT_GAS_LIMIT;
+use enclave_utils::error_context;
 use enclave_utils::kv_cache::KvCache;
 
 macro_rules! debug_err {
@@ -112,7 +123,8 @@ pub struct Context {
     gas_limit: u64,
     gas_used_externally: u64,
     gas_costs: WasmCosts,
-    query_depth: u32,
+    call_depth: u32,
+    max_call_depth: u32,
     operation: ContractOperation,
     og_contract_key: ContractKey,
     user_nonce: IoNonce,
@@ -120,6 +132,7 @@ pub struct Context {
     kv_cache: KvCache,
     last_error: Option<WasmEngineError>,
     timestamp: u64,
+    call_stack: CallStack,
 }
 
 impl Context {
@@ -196,30 +209,53 @@ fn check_execution_result<T>(
     context: &mut Context,
     result: Result<T, wasm3::Error>,
 ) -> Result<T, EnclaveError> {
-    result.map_err(|err| match err {
-        // If Unreachable was executed, and "exhausted" isn't 0, that means we ran out of gas.
-        wasm3::Error::UnreachableExecuted if get_exhausted_amount(instance) != 0 => {
-            debug!(
-                "Detected out of gas! Limit: {}, Remaining: {}, Exhausted: {}",
-                context.gas_limit,
-                get_remaining_gas(instance),
-                get_exhausted_amount(instance)
-            );
-            EnclaveError::OutOfGas
+    result.map_err(|err| {
+        let err = match err {
+            // If Unreachable was executed, and "exhausted" isn't 0, that means we ran out of gas.
+            wasm3::Error::UnreachableExecuted if get_exhausted_amount(instance) != 0 => {
+                debug!(
+                    "Detected out of gas! Limit: {}, Remaining: {}, Exhausted: {}",
+                    context.gas_limit,
+                    get_remaining_gas(instance),
+                    get_exhausted_amount(instance)
+                );
+                EnclaveError::OutOfGas
+            }
+            // Otherwise, check if a hook set an error, in which case we propagate it.
+            err => match context.take_last_error() {
+                Some(err) => err.into(),
+                None => err.to_enclave_error(),
+            },
+        };
+
+        // The call stack is only traced in non-production builds
+        if let Some(backtrace) = context.call_stack.take_backtrace() {
+            report_backtrace(&err, backtrace);
         }
-        // Otherwise, check if a hook set an error, in which case we propagate it.
-        err => match context.take_last_error() {
-            Some(err) => err.into(),
-            None => err.to_enclave_error(),
-        },
+
+        err
     })
 }
 
+/// Hands the backtrace of a trap to the host along with the error, and to the contract developer
+/// through `debug_print`
+fn report_backtrace(err: &EnclaveError, backtrace: String) {
+    let backtrace = format!("{}\n{}", err, backtrace);
+    debug!("contract trapped: {}", backtrace);
+
+    #[cfg(feature = "debug-print")]
+    info!("debug_print: {}", backtrace);
+
+    error_context::record_backtrace(backtrace);
+}
+
 pub struct Engine {
     context: Context,
     gas_limit: u64,
     used_gas: u64,
     environment: wasm3::Environment,
+    /// The most memory pages the runtime lets the contract grow to
+    max_memory_pages: u32,
     code: Vec<u8>,
     api_version: CosmWasmApiVersion,
     #[allow(dead_code)]
@@ -231,23 +267,24 @@ impl Engine {
     pub fn new(
         context: Ctx,
         gas_limit: u64,
-        gas_costs: WasmCosts,
+        rules: EngineRules,
         contract_code: &ContractCode,
         og_contract_key: ContractKey,
         operation: ContractOperation,
         user_nonce: IoNonce,
         user_public_key: Ed25519PublicKey,
-        query_depth: u32,
+        call_depth: u32,
         timestamp: u64,
     ) -> Result<Engine, EnclaveError> {
-        let versioned_code = create_module_instance(contract_code, &gas_costs, operation)?;
+        let versioned_code = create_module_instance(contract_code, &rules, operation)?;
         let kv_cache = KvCache::new();
         let context = Context {
             context,
-            query_depth,
+            call_depth,
+            max_call_depth: rules.max_call_depth(),
             gas_limit,
             gas_used_externally: 0,
-            gas_costs,
+            gas_costs: rules.wasm_costs(),
             operation,
             og_contract_key,
             user_nonce,
@@ -255,6 +292,7 @@ impl Engine {
             kv_cache,
             last_error: None,
             timestamp,
+            call_stack: CallStack::new(versioned_code.function_names),
         };
 
         debug!("setting up runtime");
@@ -270,6 +308,7 @@ impl Engine {
             gas_limit,
             used_gas: 0,
             environment,
+            max_memory_pages: rules.max_memory_pages(),
             code: versioned_code.code,
             api_version: versioned_code.version,
             features: versioned_code.features,
@@ -283,7 +322,7 @@ impl Engine {
         // let start = Instant::now();
         let runtime = self
             .environment
-            .new_runtime::<Context>(1024 * 60, Some(192 /* 12 MiB */))
+            .new_runtime::<Context>(1024 * 60, Some(self.max_memory_pages))
             .to_enclave_result()?;
         // let duration = start.elapsed();
         // trace!("Time elapsed in environment.new_runtime is: {:?}", duration);
@@ -334,6 +373,7 @@ impl Engine {
         result
     }
 
+    /// New contracts may only import these, see `validation::SUPPORTED_IMPORTS`
     fn link_host_functions(instance: &mut wasm3::Instance<Context>) -> Wasm3RsResult<()> {
         link_fn(instance, "db_read", host_read_db)?;
         link_fn(instance, "db_write", host_write_db)?;
@@ -356,9 +396,44 @@ impl Engine {
         link_fn(instance, "ed25519_batch_verify", host_ed25519_batch_verify)?;
         link_fn(instance, "secp256k1_sign", host_secp256k1_sign)?;
         link_fn(instance, "ed25519_sign", host_ed25519_sign)?;
+        link_fn(instance, "query_permit_verify", host_query_permit_verify)?;
+        link_fn(instance, "viewing_key_verify", host_viewing_key_verify)?;
+        link_fn(instance, "contract_secret", host_contract_secret)?;
+        link_fn(
+            instance,
+            "contract_secp256k1_pubkey",
+            host_contract_secp256k1_pubkey,
+        )?;
+        link_fn(
+            instance,
+            "contract_secp256k1_sign",
+            host_contract_secp256k1_sign,
+        )?;
+        link_fn(
+            instance,
+            "contract_ed25519_pubkey",
+            host_contract_ed25519_pubkey,
+        )?;
+        link_fn(
+            instance,
+            "contract_ed25519_sign",
+            host_contract_ed25519_sign,
+        )?;
+        link_fn(
+            instance,
+            "contract_x25519_pubkey",
+            host_contract_x25519_pubkey,
+        )?;
+        link_fn(instance, "contract_x25519_ecdh", host_contract_x25519_ecdh)?;
         link_fn_no_args(instance, "check_gas", host_check_gas_used)?;
         link_fn(instance, "gas_evaporate", host_gas_evaporate)?;
 
+        #[cfg(not(feature = "production"))]
+        {
+            link_fn(instance, backtrace::IMPORT_TRACE_ENTER, host_trace_enter)?;
+            link_fn_no_args(instance, backtrace::IMPORT_TRACE_EXIT, host_trace_exit)?;
+        }
+
         //    DbReadIndex = 0,
         //     DbWriteIndex = 1,
         //     DbRemoveIndex = 2,
@@ -396,6 +471,14 @@ impl Engine {
         &self.features
     }
 
+    /// The block size the contract asked its encrypted outputs to be padded to, if any
+    pub fn padding_block_size(&self) -> Option<u32> {
+        self.features.iter().find_map(|feature| match feature {
+            ContractFeature::Padding(block_size) => Some(*block_size),
+            _ => None,
+        })
+    }
+
     pub fn migrate(&mut self, env: &CwEnv, msg: Vec<u8>) -> Result<Vec<u8>, EnclaveError> {
         let api_version = self.get_api_version();
 
@@ -1274,7 +1357,8 @@ fn host_query_chain(
     let mut used_gas: u64 = 0;
     let answer = encrypt_and_query_chain(
         &query_buffer,
-        context.query_depth,
+        context.call_depth,
+        context.max_call_depth,
         &context.context,
         context.user_nonce,
         context.user_public_key,
@@ -1312,6 +1396,25 @@ fn host_debug_print(
     Ok(())
 }
 
+#[cfg(not(feature = "production"))]
+fn host_trace_enter(
+    context: &mut Context,
+    _instance: &wasm3::Instance<Context>,
+    frame: i32,
+) -> WasmEngineResult<()> {
+    context.call_stack.enter(frame as u32);
+    Ok(())
+}
+
+#[cfg(not(feature = "production"))]
+fn host_trace_exit(
+    context: &mut Context,
+    _instance: &wasm3::Instance<Context>,
+) -> WasmEngineResult<()> {
+    context.call_stack.exit();
+    Ok(())
+}
+
 fn host_secp256k1_verify(
     context: &mut Context,
     instance: &wasm3::Instance<Context>,
@@ -1863,6 +1966,309 @@ fn host_ed25519_sign(
     Ok(to_low_half(ptr_to_region_in_wasm_vm) as i64)
 }
 
+fn host_query_permit_verify(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (permit_ptr, token_ptr): (i32, i32),
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_query_permit_verify as u64;
+    use_gas(instance, used_gas)?;
+
+    let permit_data = read_from_memory(instance, permit_ptr as u32)
+        .map_err(debug_err!(err => "query_permit_verify error while trying to read permit from wasm memory: {err}"))?;
+    let token_data = read_from_memory(instance, token_ptr as u32)
+        .map_err(debug_err!(err => "query_permit_verify error while trying to read token address from wasm memory: {err}"))?;
+
+    trace!(
+        "query_permit_verify() was called from WASM code with permit {:?} and token {:?}",
+        String::from_utf8_lossy(&permit_data),
+        String::from_utf8_lossy(&token_data),
+    );
+
+    let permit = match Permit::from_bytes(&permit_data) {
+        Ok(x) => x,
+        Err(err) => {
+            debug!("query_permit_verify() malformed permit: {:?}", err);
+            return Ok(to_high_half(WasmApiCryptoError::InvalidPermitFormat as u32) as i64);
+        }
+    };
+
+    let token_allowed = std::str::from_utf8(&token_data)
+        .map(|token| permit.allows_token(token))
+        .unwrap_or(false);
+    if !token_allowed {
+        debug!("query_permit_verify() permit doesn't apply to this token");
+        return Ok(to_high_half(WasmApiCryptoError::PermitTokenNotAllowed as u32) as i64);
+    }
+
+    let signer = match permit.verify() {
+        Ok(x) => x,
+        Err(err) => {
+            debug!("query_permit_verify() failed to verify permit: {:?}", err);
+            return Ok(to_high_half(WasmApiCryptoError::PermitVerificationFailed as u32) as i64);
+        }
+    };
+
+    let signer = match bech32::encode(BECH32_PREFIX_ACC_ADDR, signer.as_slice().to_base32()) {
+        Ok(x) => x,
+        Err(err) => {
+            debug!(
+                "query_permit_verify() failed to encode signer as bech32: {:?}",
+                err
+            );
+            return Ok(to_high_half(WasmApiCryptoError::GenericErr as u32) as i64);
+        }
+    };
+
+    let ptr_to_region_in_wasm_vm = write_to_memory(instance, signer.as_bytes()).map_err(|err| {
+        debug!(
+            "query_permit_verify() error while trying to allocate and write the signer {:?} to the WASM VM",
+            &signer,
+        );
+        err
+    })?;
+
+    // Return pointer to the allocated buffer with the value written to it
+    Ok(to_low_half(ptr_to_region_in_wasm_vm) as i64)
+}
+
+fn host_viewing_key_verify(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (key_ptr, key_hash_ptr): (i32, i32),
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_viewing_key_verify as u64;
+    use_gas(instance, used_gas)?;
+
+    let key_data = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "viewing_key_verify error while trying to read key from wasm memory: {err}"))?;
+    let key_hash_data = read_from_memory(instance, key_hash_ptr as u32)
+        .map_err(debug_err!(err => "viewing_key_verify error while trying to read key_hash from wasm memory: {err}"))?;
+
+    // the key itself is a secret, so don't log it
+    trace!(
+        "viewing_key_verify() was called from WASM code with key_hash {:x?} (len {:?} should be 32)",
+        &key_hash_data,
+        key_hash_data.len()
+    );
+
+    // check key_hash input
+    if key_hash_data.len() != HASH_SIZE {
+        return Ok(WasmApiCryptoError::InvalidHashFormat as i32);
+    }
+
+    // Same hash as `secret_toolkit::viewing_key`. The comparison must not leak how much of the
+    // hash matched
+    if constant_time_eq(&sha_256(&key_data), &key_hash_data) {
+        // return 0 == success, the key matches
+        Ok(0)
+    } else {
+        // return 1 == failed, wrong key
+        Ok(1)
+    }
+}
+
+/// Writes the result of a contract owned key operation to the WASM VM, or returns the error code
+/// to the contract. Errors are debug logged before they get here.
+fn write_contract_key_result(
+    instance: &wasm3::Instance<Context>,
+    fn_name: &str,
+    result: Result<Vec<u8>, WasmApiCryptoError>,
+) -> WasmEngineResult<i64> {
+    let value = match result {
+        Ok(value) => value,
+        Err(err) => return Ok(to_high_half(err as u32) as i64),
+    };
+
+    let ptr_to_region_in_wasm_vm = write_to_memory(instance, &value).map_err(|err| {
+        debug!(
+            "{}() error while trying to allocate and write the result to the WASM VM",
+            fn_name,
+        );
+        err
+    })?;
+
+    // Return pointer to the allocated buffer with the value written to it
+    Ok(to_low_half(ptr_to_region_in_wasm_vm) as i64)
+}
+
+fn host_contract_secret(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    path_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64;
+    use_gas(instance, used_gas)?;
+
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_secret error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_secret() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result =
+        ContractKeys::new(&context.og_contract_key).and_then(|keys| keys.secret(&path_data));
+
+    write_contract_key_result(instance, "contract_secret", result)
+}
+
+fn host_contract_secp256k1_pubkey(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    path_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64;
+    use_gas(instance, used_gas)?;
+
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_secp256k1_pubkey error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_secp256k1_pubkey() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result = ContractKeys::new(&context.og_contract_key)
+        .and_then(|keys| keys.secp256k1_pubkey(&path_data));
+
+    write_contract_key_result(instance, "contract_secp256k1_pubkey", result)
+}
+
+fn host_contract_secp256k1_sign(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (message_hash_ptr, path_ptr): (i32, i32),
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64
+        + context.gas_costs.external_secp256k1_sign as u64;
+    use_gas(instance, used_gas)?;
+
+    let message_hash_data = read_from_memory(instance, message_hash_ptr as u32)
+        .map_err(debug_err!(err => "contract_secp256k1_sign error while trying to read message_hash from wasm memory: {err}"))?;
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_secp256k1_sign error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_secp256k1_sign() was called from WASM code with message_hash {:x?} (len {:?} should be 32)",
+        &message_hash_data,
+        message_hash_data.len()
+    );
+    trace!(
+        "contract_secp256k1_sign() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result = ContractKeys::new(&context.og_contract_key)
+        .and_then(|keys| keys.secp256k1_sign(&path_data, &message_hash_data));
+
+    write_contract_key_result(instance, "contract_secp256k1_sign", result)
+}
+
+fn host_contract_ed25519_pubkey(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    path_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64;
+    use_gas(instance, used_gas)?;
+
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_ed25519_pubkey error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_ed25519_pubkey() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result = ContractKeys::new(&context.og_contract_key)
+        .and_then(|keys| keys.ed25519_pubkey(&path_data));
+
+    write_contract_key_result(instance, "contract_ed25519_pubkey", result)
+}
+
+fn host_contract_ed25519_sign(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (message_ptr, path_ptr): (i32, i32),
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64
+        + context.gas_costs.external_ed25519_sign as u64;
+    use_gas(instance, used_gas)?;
+
+    let message_data = read_from_memory(instance, message_ptr as u32)
+        .map_err(debug_err!(err => "contract_ed25519_sign error while trying to read message from wasm memory: {err}"))?;
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_ed25519_sign error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_ed25519_sign() was called from WASM code with message {:x?}",
+        &message_data,
+    );
+    trace!(
+        "contract_ed25519_sign() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result = ContractKeys::new(&context.og_contract_key)
+        .and_then(|keys| keys.ed25519_sign(&path_data, &message_data));
+
+    write_contract_key_result(instance, "contract_ed25519_sign", result)
+}
+
+fn host_contract_x25519_pubkey(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    path_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64;
+    use_gas(instance, used_gas)?;
+
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_x25519_pubkey error while trying to read path from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_x25519_pubkey() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+
+    let result =
+        ContractKeys::new(&context.og_contract_key).and_then(|keys| keys.x25519_pubkey(&path_data));
+
+    write_contract_key_result(instance, "contract_x25519_pubkey", result)
+}
+
+fn host_contract_x25519_ecdh(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (path_ptr, public_key_ptr): (i32, i32),
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_contract_key_derive as u64
+        + context.gas_costs.external_x25519_ecdh as u64;
+    use_gas(instance, used_gas)?;
+
+    let path_data = read_from_memory(instance, path_ptr as u32)
+        .map_err(debug_err!(err => "contract_x25519_ecdh error while trying to read path from wasm memory: {err}"))?;
+    let public_key = read_from_memory(instance, public_key_ptr as u32)
+        .map_err(debug_err!(err => "contract_x25519_ecdh error while trying to read public_key from wasm memory: {err}"))?;
+
+    trace!(
+        "contract_x25519_ecdh() was called from WASM code with path {:?}",
+        String::from_utf8_lossy(&path_data)
+    );
+    trace!(
+        "contract_x25519_ecdh() was called from WASM code with public_key {:x?} (len {:?} should be 32)",
+        &public_key,
+        public_key.len()
+    );
+
+    let result = ContractKeys::new(&context.og_contract_key)
+        .and_then(|keys| keys.x25519_ecdh(&path_data, &public_key));
+
+    write_contract_key_result(instance, "contract_x25519_ecdh", result)
+}
+
 fn get_encryption_salt(timestamp: u64) -> Vec<u8> {
     let mut encryption_salt: Vec<u8> = vec![];
 
@@ -1920,7 +2326,11 @@ fn host_check_gas_used(
 
 #[cfg(feature = "test")]
 pub mod tests {
+    use super::gas;
+    use super::module_cache;
+    use super::nan_canonicalization;
     use super::shuffle_cache;
+    use super::validation;
     use crate::count_failures;
     use crate::wasm3::Binary;
 
@@ -1930,6 +2340,20 @@ pub mod tests {
 
         count_failures!(failures, {
             cache_shuffle_works();
+            module_cache::tests::test_put_counting_evictions();
+            module_cache::tests::test_padding_follows_engine_rules();
+            module_cache::tests::test_nan_canonicalization_follows_engine_rules();
+            module_cache::tests::test_unsupported_instructions_follow_engine_rules();
+            gas::tests::test_function_costs_split_basic_blocks();
+            gas::tests::test_add_metering_charges_each_basic_block();
+            gas::tests::test_add_metering_charges_legacy_calls_per_instruction_sequence();
+            gas::tests::test_add_metering_charges_bulk_memory_per_byte();
+            gas::tests::test_add_metering_widens_memory_grow_by_rules();
+            validation::tests::test_validate_instructions();
+            validation::tests::test_validate_memory_caps_maximum_by_rules();
+            nan_canonicalization::tests::test_rewrite_floats_calls_implementations();
+            nan_canonicalization::tests::test_rewrite_floats_ignores_integers();
+            nan_canonicalization::tests::test_rewritten_floats_produce_canonical_nans();
         });
 
         // The test doesn't work for some reason
@@ -1,39 +1,66 @@
+#[cfg(feature = "simulator")]
+use std::sync::RwLock as SgxRwLock;
+#[cfg(not(feature = "simulator"))]
 use std::sync::SgxRwLock;
 
+use std::hash::Hash;
+
 use lazy_static::lazy_static;
 use log::*;
 use lru::LruCache;
+use serde::{Deserialize, Serialize};
 
 use cw_types_generic::{ContractFeature, CosmWasmApiVersion};
 
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+use enclave_utils::error_context::with_context;
+use enclave_utils::metrics;
 
 use enclave_cosmos_types::types::ContractCode;
 use enclave_crypto::HASH_SIZE;
 
-use super::{gas, validation};
+#[cfg(not(feature = "production"))]
+use super::backtrace;
+#[cfg(not(feature = "simulator"))]
+use super::sealed_module_cache;
+use super::{gas, nan_canonicalization, validation};
 use crate::cosmwasm_config::ContractOperation;
 use crate::cosmwasm_config::{api_marker, features};
+use crate::engine_params::{EngineParams, EngineRules};
 use crate::gas::WasmCosts;
+use crate::padding::parse_padding_feature;
+
+/// Bump this whenever the way modules are analyzed or instrumented changes, so modules sealed by
+/// older engines aren't used
+pub const ENGINE_VERSION: u32 = 7;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VersionedCode {
     pub code: Vec<u8>,
     pub version: CosmWasmApiVersion,
     pub features: Vec<ContractFeature>,
+    /// Names of the frames in wasm backtraces. Empty in production builds.
+    pub function_names: Vec<String>,
 }
 
 impl VersionedCode {
-    pub fn new(code: Vec<u8>, version: CosmWasmApiVersion, features: Vec<ContractFeature>) -> Self {
+    pub fn new(
+        code: Vec<u8>,
+        version: CosmWasmApiVersion,
+        features: Vec<ContractFeature>,
+        function_names: Vec<String>,
+    ) -> Self {
         Self {
             code,
             version,
             features,
+            function_names,
         }
     }
 }
 
 lazy_static! {
-    static ref MODULE_CACHE: SgxRwLock<LruCache<[u8; HASH_SIZE], VersionedCode>> =
+    static ref MODULE_CACHE: SgxRwLock<LruCache<([u8; HASH_SIZE], EngineRules), VersionedCode>> =
         SgxRwLock::new(LruCache::new(0));
 }
 
@@ -44,7 +71,7 @@ pub fn configure_module_cache(cap: usize) {
 
 pub fn create_module_instance(
     contract_code: &ContractCode,
-    gas_costs: &WasmCosts,
+    rules: &EngineRules,
     operation: ContractOperation,
 ) -> Result<VersionedCode, EnclaveError> {
     trace!("fetching module from cache");
@@ -53,69 +80,145 @@ pub fn create_module_instance(
     // If the cache is disabled, don't try to use it and just compile the module.
     if cache.cap() == 0 {
         trace!("cache is disabled, building module");
-        return analyze_module(contract_code, gas_costs, operation);
+        metrics::MODULE_CACHE_MISSES.inc();
+        return analyze_module(contract_code, rules, operation);
     }
     trace!("cache is enabled");
 
     // Try to fetch a cached instance
-    let mut code = None;
-    let mut api_version = CosmWasmApiVersion::Invalid;
-    let mut features = vec![];
     trace!("peeking in cache");
-    let peek_result = cache.peek(&contract_code.hash());
-    if let Some(VersionedCode {
-        code: cached_code,
-        version: cached_ver,
-        features: cached_features,
-    }) = peek_result
-    {
-        trace!("found instance in cache!");
-        code = Some(cached_code.clone());
-        api_version = *cached_ver;
-        features = cached_features.clone();
-    }
+    let cache_key = (contract_code.hash(), *rules);
+    let cached_code = cache.peek(&cache_key).cloned();
 
     drop(cache); // Release read lock
 
-    // if we couldn't find the code in the cache, analyze it now
-    if code.is_none() {
-        trace!("code not found in cache! analyzing now");
-        let versioned_code = analyze_module(contract_code, gas_costs, operation)?;
-        code = Some(versioned_code.code);
-        api_version = versioned_code.version;
-        features = versioned_code.features;
-    }
+    let versioned_code = match cached_code {
+        Some(versioned_code) => {
+            trace!("found instance in cache!");
+            metrics::MODULE_CACHE_HITS.inc();
+            versioned_code
+        }
+        // if we couldn't find the code in the cache, look for it on disk or analyze it now
+        None => {
+            metrics::MODULE_CACHE_MISSES.inc();
+            load_or_analyze_module(contract_code, rules, operation)?
+        }
+    };
 
-    // If we analyzed the code in the previous step, insert it to the LRU cache
+    // Insert the code to the LRU cache, which also updates its LRU value
     trace!("updating cache");
     let mut cache = MODULE_CACHE.write().unwrap();
-    if let Some(code) = code.clone() {
-        trace!("storing code in cache");
-        cache.put(
-            contract_code.hash(),
-            VersionedCode::new(code, api_version, features.clone()),
-        );
-    } else {
-        // Touch the cache to update the LRU value
-        trace!("updating LRU without storing anything");
-        cache.get(&contract_code.hash());
+    put_counting_evictions(&mut cache, cache_key, versioned_code.clone());
+
+    trace!("returning built instance");
+    Ok(versioned_code)
+}
+
+/// Puts the value in the cache, and counts the entry it evicts to make room for it, if any
+fn put_counting_evictions<K: Hash + Eq, V>(cache: &mut LruCache<K, V>, key: K, value: V) {
+    if cache.len() >= cache.cap() && !cache.contains(&key) {
+        metrics::MODULE_CACHE_EVICTIONS.inc();
+    }
+    cache.put(key, value);
+}
+
+fn load_or_analyze_module(
+    contract_code: &ContractCode,
+    rules: &EngineRules,
+    operation: ContractOperation,
+) -> Result<VersionedCode, EnclaveError> {
+    #[cfg(not(feature = "simulator"))]
+    if let Some(versioned_code) = sealed_module_cache::load(&contract_code.hash(), rules) {
+        trace!("found module in the sealed cache");
+        return Ok(versioned_code);
     }
 
-    let code = code.unwrap();
+    trace!("code not found in cache! analyzing now");
+    let versioned_code = analyze_module(contract_code, rules, operation)?;
 
-    trace!("returning built instance");
-    Ok(VersionedCode::new(code, api_version, features))
+    #[cfg(not(feature = "simulator"))]
+    sealed_module_cache::store(&contract_code.hash(), rules, &versioned_code);
+
+    Ok(versioned_code)
+}
+
+/// Loads sealed modules into the cache, hottest first, so the first calls after a restart don't
+/// have to analyze them again. Stops once the cache is full, so it never evicts anything.
+/// Modules are loaded under the rules they were sealed for.
+/// Returns the amount of modules that were loaded.
+#[cfg(not(feature = "simulator"))]
+pub fn prewarm_module_cache(code_hashes: &[[u8; HASH_SIZE]]) -> usize {
+    let mut loaded = 0;
+
+    for code_hash in code_hashes {
+        let cache = MODULE_CACHE.read().unwrap();
+        if cache.len() >= cache.cap() {
+            break;
+        }
+        drop(cache); // Don't hold the lock while unsealing
+
+        if let Some((rules, versioned_code)) = sealed_module_cache::load_any(code_hash) {
+            let cache_key = (*code_hash, rules);
+            let mut cache = MODULE_CACHE.write().unwrap();
+            if cache.len() < cache.cap() && !cache.contains(&cache_key) {
+                cache.put(cache_key, versioned_code);
+                loaded += 1;
+            }
+        }
+    }
+
+    debug!("prewarmed module cache with {} modules", loaded);
+    loaded
+}
+
+/// Analyzes code that is being stored, under the engine params of the chain. On top of everything
+/// `analyze_module` checks when a contract is instantiated, this rejects imports we don't link, so
+/// broken contracts fail when they're uploaded rather than when they're first used. The
+/// instrumented module is sealed, so the first instantiation doesn't have to build it again.
+pub fn analyze_new_code(
+    contract_code: &ContractCode,
+    engine_params: &EngineParams,
+) -> Result<VersionedCode, EnclaveError> {
+    let rules = EngineRules::Params(*engine_params);
+    let module = parse_module(contract_code.code())?;
+    validation::validate_imports(&module)?;
+    let versioned_code = analyze_parsed_module(module, &rules, ContractOperation::Init)?;
+
+    #[cfg(not(feature = "simulator"))]
+    sealed_module_cache::store(&contract_code.hash(), &rules, &versioned_code);
+
+    Ok(versioned_code)
+}
+
+fn parse_module(code: &[u8]) -> Result<walrus::Module, EnclaveError> {
+    walrus::ModuleConfig::new()
+        .generate_producers_section(false)
+        .parse(code)
+        .map_err(|_| EnclaveError::InvalidWasm)
+}
+
+/// Returns the static cost the gas metering gives each function of `code` under the chain's engine
+/// params. This is what `wasm-gas-report` prints.
+pub fn function_costs(code: &[u8]) -> Result<Vec<gas::FunctionCost>, EnclaveError> {
+    let module = parse_module(code)?;
+    Ok(gas::function_costs(&module, &WasmCosts::default()))
 }
 
 pub fn analyze_module(
     contract_code: &ContractCode,
-    gas_costs: &WasmCosts,
+    rules: &EngineRules,
     operation: ContractOperation,
 ) -> Result<VersionedCode, EnclaveError> {
-    let mut module = walrus::ModuleConfig::new()
-        .generate_producers_section(false)
-        .parse(contract_code.code())
-        .map_err(|_| EnclaveError::InvalidWasm)?;
+    let module = parse_module(contract_code.code())?;
+    analyze_parsed_module(module, rules, operation)
+}
+
+fn analyze_parsed_module(
+    mut module: walrus::Module,
+    rules: &EngineRules,
+    operation: ContractOperation,
+) -> Result<VersionedCode, EnclaveError> {
+    let _timer = metrics::INSTRUMENTATION.start_timer();
 
     for import in module.imports.iter() {
         trace!("import {:?}", import)
@@ -133,7 +236,11 @@ pub fn analyze_module(
         Some(Export { name, .. }) if name == api_marker::V1 => CosmWasmApiVersion::V1,
         _ => {
             error!("Invalid cosmwasm api version2");
-            return Err(EnclaveError::InvalidWasm);
+            return Err(with_context(
+                EnclaveError::InvalidWasm,
+                EnclaveErrorStage::WasmLoading,
+                error_details::MISSING_API_MARKER,
+            ));
         }
     };
 
@@ -143,26 +250,191 @@ pub fn analyze_module(
         .iter()
         .any(|exp| exp.name == features::RANDOM);
 
-    let features = if random_enabled {
+    let mut features = vec![];
+    if random_enabled {
         debug!("Found supported features: random");
-        vec![ContractFeature::Random]
-    } else {
-        vec![]
-    };
+        features.push(ContractFeature::Random);
+    }
+
+    // Invalid block sizes are only rejected when a contract is instantiated, like unsupported
+    // instructions, so they can't break contracts that already exist. Before the chain set its
+    // engine params, the padding exports are just exports.
+    let mut padding_block_size = None;
+    if rules.supports_padding() {
+        for export in module.exports.iter() {
+            match parse_padding_feature(&export.name) {
+                Ok(Some(block_size)) => {
+                    padding_block_size = Some(block_size);
+                    break;
+                }
+                Ok(None) => {}
+                Err(_) if matches!(operation, ContractOperation::Init) => {
+                    return Err(with_context(
+                        EnclaveError::InvalidWasm,
+                        EnclaveErrorStage::WasmLoading,
+                        error_details::INVALID_PADDING_BLOCK_SIZE,
+                    ));
+                }
+                Err(_) => debug!("Ignoring the invalid padding feature {}", export.name),
+            }
+        }
+    }
+
+    if let Some(block_size) = padding_block_size {
+        debug!("Found supported features: padding ({} bytes)", block_size);
+        features.push(ContractFeature::Padding(block_size));
+    }
+
+    // Before the chain set its engine params, modules with floats are rejected like they always were
+    let nan_canonicalization_enabled = rules.canonicalizes_nans()
+        && module.exports.iter().any(|exp| {
+            exp.name == features::NAN_CANONICALIZATION || exp.name == features::SOFTFLOAT
+        });
+
+    if nan_canonicalization_enabled {
+        debug!("Found supported features: nan_canonicalization");
+        features.push(ContractFeature::NanCanonicalization);
+    }
     drop(exports);
 
-    validation::validate_memory(&mut module)?;
+    validation::validate_memory(&mut module, rules)?;
+
+    if operation.is_init() && rules.validates_instructions() {
+        validation::validate_instructions(&module)?;
+    }
 
-    if let ContractOperation::Init = operation {
+    if nan_canonicalization_enabled {
+        nan_canonicalization::rewrite_floats(&mut module);
+    } else if let ContractOperation::Init = operation {
         if module.has_floats() {
             debug!("contract was found to contain floating point operations");
             return Err(EnclaveError::WasmModuleWithFP);
         }
     }
 
-    gas::add_metering(&mut module, gas_costs);
+    gas::add_metering(&mut module, rules);
+
+    // Traced after metering, so tracing doesn't cost gas
+    #[cfg(not(feature = "production"))]
+    let function_names = backtrace::add_call_tracing(&mut module);
+    #[cfg(feature = "production")]
+    let function_names = vec![];
 
     let code = module.emit_wasm();
 
-    Ok(VersionedCode::new(code, cosmwasm_api_version, features))
+    Ok(VersionedCode::new(
+        code,
+        cosmwasm_api_version,
+        features,
+        function_names,
+    ))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use walrus::{
+        ir::{BinaryOp, Value},
+        FunctionBuilder, InstrSeqBuilder, Module,
+    };
+
+    /// A cosmwasm v1 module with a function with the given body, exported under each of `exports`
+    fn module_with_exports(
+        exports: &[&str],
+        make_body: impl FnOnce(&mut InstrSeqBuilder),
+    ) -> Module {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, 1, None);
+        module.exports.add("memory", memory);
+        let mut func = FunctionBuilder::new(&mut module.types, &[], &[]);
+        make_body(&mut func.func_body());
+        let func = func.finish(vec![], &mut module.funcs);
+        for name in [api_marker::V1].iter().chain(exports) {
+            module.exports.add(name, func);
+        }
+        module
+    }
+
+    fn params_rules() -> EngineRules {
+        EngineRules::Params(EngineParams {
+            max_memory_pages: 192,
+            max_call_depth: 10,
+        })
+    }
+
+    pub fn test_padding_follows_engine_rules() {
+        let analyze = |exports: &[&str], rules: &EngineRules| {
+            let module = module_with_exports(exports, |_| {});
+            analyze_parsed_module(module, rules, ContractOperation::Init)
+        };
+
+        let padded = analyze(&["requires_padding_1024"], &params_rules()).unwrap();
+        assert_eq!(padded.features, vec![ContractFeature::Padding(1024)]);
+        assert!(analyze(&["requires_padding_1000"], &params_rules()).is_err());
+
+        // before the chain set its engine params, padding exports are just exports
+        let unpadded = analyze(&["requires_padding_1024"], &EngineRules::Legacy).unwrap();
+        assert!(unpadded.features.is_empty());
+        assert!(analyze(&["requires_padding_1000"], &EngineRules::Legacy).is_ok());
+    }
+
+    pub fn test_nan_canonicalization_follows_engine_rules() {
+        let analyze = |exports: &[&str], rules: &EngineRules| {
+            let module = module_with_exports(exports, |body| {
+                body.f32_const(0.0)
+                    .f32_const(0.0)
+                    .binop(BinaryOp::F32Div)
+                    .drop();
+            });
+            analyze_parsed_module(module, rules, ContractOperation::Init)
+        };
+
+        for feature in [features::NAN_CANONICALIZATION, features::SOFTFLOAT].iter() {
+            let canonicalized = analyze(&[*feature], &params_rules()).unwrap();
+            assert_eq!(
+                canonicalized.features,
+                vec![ContractFeature::NanCanonicalization]
+            );
+
+            // before the chain set its engine params, floats are rejected like they always were
+            assert!(matches!(
+                analyze(&[*feature], &EngineRules::Legacy),
+                Err(EnclaveError::WasmModuleWithFP)
+            ));
+        }
+        assert!(matches!(
+            analyze(&[], &params_rules()),
+            Err(EnclaveError::WasmModuleWithFP)
+        ));
+    }
+
+    pub fn test_put_counting_evictions() {
+        let evictions = metrics::MODULE_CACHE_EVICTIONS.get();
+        let mut cache = LruCache::new(2);
+
+        put_counting_evictions(&mut cache, 1, "one");
+        put_counting_evictions(&mut cache, 2, "two");
+        // replacing a cached value doesn't evict anything
+        put_counting_evictions(&mut cache, 1, "uno");
+        assert_eq!(metrics::MODULE_CACHE_EVICTIONS.get(), evictions);
+
+        put_counting_evictions(&mut cache, 3, "three");
+        assert_eq!(metrics::MODULE_CACHE_EVICTIONS.get(), evictions + 1);
+        assert!(!cache.contains(&2));
+        assert_eq!(cache.peek(&1), Some(&"uno"));
+    }
+
+    pub fn test_unsupported_instructions_follow_engine_rules() {
+        let analyze = |rules: &EngineRules, operation: ContractOperation| {
+            let module = module_with_exports(&[], |body| {
+                body.const_(Value::V128(0)).drop();
+            });
+            analyze_parsed_module(module, rules, operation)
+        };
+
+        assert!(analyze(&params_rules(), ContractOperation::Init).is_err());
+        assert!(analyze(&params_rules(), ContractOperation::Handle).is_ok());
+        // before the chain set its engine params, wasm3 is left to fail on them like it always did
+        assert!(analyze(&EngineRules::Legacy, ContractOperation::Init).is_ok());
+    }
 }
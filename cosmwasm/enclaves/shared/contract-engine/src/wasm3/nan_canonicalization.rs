@@ -0,0 +1,320 @@
+//!
+//! Deterministic floating point for contracts that export `requires_nan_canonicalization` (or its
+//! alias `requires_softfloat`), once the chain set its engine params.
+//!
+//! IEEE 754 fixes the result of every float operation wasm has bit for bit, except for the sign
+//! and payload of the NaNs an operation produces, which are up to the CPU. So instead of rejecting
+//! modules with floats, the analyzer rewrites every operation that may produce a NaN into a call
+//! to an implementation injected into the module, which does the operation and replaces a NaN
+//! result with the canonical NaN. The operations still run on the CPU, only their NaNs change.
+//! Loads, stores, constants, reinterpretations, `abs`, `neg` and
+//! `copysign` only move bits around, and conversions to integers trap on NaN, so they're left as
+//! they are.
+//!
+//! This takes the place of rewriting floats into calls to a software float implementation: that
+//! would give the same results, because the NaNs are the only part of a result IEEE 754 leaves
+//! open, and be much slower.
+//!
+//! The rewriting happens before the gas metering is injected, so the injected implementations
+//! are metered like any other function of the contract.
+//!
+use std::collections::BTreeMap;
+
+use log::*;
+
+use walrus::{ir::*, FunctionBuilder, FunctionId, LocalId, Module, ValType};
+
+/// Bits of the NaN every NaN result of an f32 operation is replaced with
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+/// Bits of the NaN every NaN result of an f64 operation is replaced with
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// A float operation whose result may be a NaN
+enum FloatOp {
+    Binary(BinaryOp, ValType),
+    Unary(UnaryOp, ValType, ValType),
+}
+
+impl FloatOp {
+    fn from_instr(instr: &Instr) -> Option<Self> {
+        match instr {
+            Instr::Binop(Binop { op }) => match op {
+                BinaryOp::F32Add
+                | BinaryOp::F32Sub
+                | BinaryOp::F32Mul
+                | BinaryOp::F32Div
+                | BinaryOp::F32Min
+                | BinaryOp::F32Max => Some(FloatOp::Binary(*op, ValType::F32)),
+                BinaryOp::F64Add
+                | BinaryOp::F64Sub
+                | BinaryOp::F64Mul
+                | BinaryOp::F64Div
+                | BinaryOp::F64Min
+                | BinaryOp::F64Max => Some(FloatOp::Binary(*op, ValType::F64)),
+                _ => None,
+            },
+            Instr::Unop(Unop { op }) => match op {
+                UnaryOp::F32Ceil
+                | UnaryOp::F32Floor
+                | UnaryOp::F32Trunc
+                | UnaryOp::F32Nearest
+                | UnaryOp::F32Sqrt => Some(FloatOp::Unary(*op, ValType::F32, ValType::F32)),
+                UnaryOp::F64Ceil
+                | UnaryOp::F64Floor
+                | UnaryOp::F64Trunc
+                | UnaryOp::F64Nearest
+                | UnaryOp::F64Sqrt => Some(FloatOp::Unary(*op, ValType::F64, ValType::F64)),
+                UnaryOp::F32DemoteF64 => Some(FloatOp::Unary(*op, ValType::F64, ValType::F32)),
+                UnaryOp::F64PromoteF32 => Some(FloatOp::Unary(*op, ValType::F32, ValType::F64)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Name of the injected implementation of the operation
+    fn name(&self) -> String {
+        match self {
+            FloatOp::Binary(op, _) => format!("canonical_nan_{:?}", op),
+            FloatOp::Unary(op, _, _) => format!("canonical_nan_{:?}", op),
+        }
+    }
+}
+
+/// Rewrites the float operations of the module that may produce a NaN into calls to injected
+/// implementations that canonicalize the NaNs. Returns the number of rewritten operations.
+pub fn rewrite_floats(module: &mut Module) -> usize {
+    let float_ops: Vec<FloatOp> = module
+        .funcs
+        .iter_local()
+        .flat_map(|(_, func)| func.blocks())
+        .flat_map(|(_, block)| block.instrs.iter())
+        .filter_map(|(instr, _)| FloatOp::from_instr(instr))
+        .collect();
+
+    // inject each implementation once, no matter how many times the operation is used
+    let mut implementations = BTreeMap::new();
+    for float_op in float_ops {
+        let name = float_op.name();
+        if !implementations.contains_key(&name) {
+            let func_id = inject_implementation(module, &float_op, name.clone());
+            implementations.insert(name, func_id);
+        }
+    }
+
+    let mut rewritten = 0;
+    for (func_id, func) in module.funcs.iter_local_mut() {
+        if implementations.values().any(|&id| id == func_id) {
+            continue;
+        }
+
+        let block_ids: Vec<_> = func.blocks().map(|(block_id, _block)| block_id).collect();
+        for block_id in block_ids {
+            for (instr, _) in func.block_mut(block_id).instrs.iter_mut() {
+                let implementation = FloatOp::from_instr(instr)
+                    .and_then(|float_op| implementations.get(&float_op.name()));
+                if let Some(&implementation) = implementation {
+                    *instr = Instr::from(Call {
+                        func: implementation,
+                    });
+                    rewritten += 1;
+                }
+            }
+        }
+    }
+
+    debug!(
+        "rewrote {} float operations into {} NaN canonicalizing functions",
+        rewritten,
+        implementations.len()
+    );
+    rewritten
+}
+
+fn inject_implementation(module: &mut Module, float_op: &FloatOp, name: String) -> FunctionId {
+    let (params, result_type) = match float_op {
+        FloatOp::Binary(_, val_type) => (vec![*val_type, *val_type], *val_type),
+        FloatOp::Unary(_, param_type, result_type) => (vec![*param_type], *result_type),
+    };
+    let args: Vec<LocalId> = params
+        .iter()
+        .map(|val_type| module.locals.add(*val_type))
+        .collect();
+    let result = module.locals.add(result_type);
+
+    let mut func = FunctionBuilder::new(&mut module.types, &params, &[result_type]);
+    func.name(name);
+
+    let mut body = func.func_body();
+    for arg in &args {
+        body.local_get(*arg);
+    }
+    match float_op {
+        FloatOp::Binary(op, _) => body.binop(*op),
+        FloatOp::Unary(op, _, _) => body.unop(*op),
+    };
+    body.local_set(result);
+
+    // select(canonical NaN, result, result != result)
+    match result_type {
+        ValType::F32 => body
+            .f32_const(f32::from_bits(CANONICAL_NAN_F32))
+            .local_get(result)
+            .local_get(result)
+            .local_get(result)
+            .binop(BinaryOp::F32Ne),
+        _ => body
+            .f64_const(f64::from_bits(CANONICAL_NAN_F64))
+            .local_get(result)
+            .local_get(result)
+            .local_get(result)
+            .binop(BinaryOp::F64Ne),
+    };
+    body.select(None);
+
+    func.finish(args, &mut module.funcs)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use walrus::LocalFunction;
+
+    /// A function that returns `sqrt(a + b) + a`, and one that doesn't use floats
+    fn float_module() -> Module {
+        let mut module = Module::default();
+
+        let a = module.locals.add(ValType::F64);
+        let b = module.locals.add(ValType::F64);
+        let mut func = FunctionBuilder::new(
+            &mut module.types,
+            &[ValType::F64, ValType::F64],
+            &[ValType::F64],
+        );
+        func.name("float_math".to_string());
+        func.func_body()
+            .local_get(a)
+            .local_get(b)
+            .binop(BinaryOp::F64Add)
+            .unop(UnaryOp::F64Sqrt)
+            .local_get(a)
+            .binop(BinaryOp::F64Add);
+        func.finish(vec![a, b], &mut module.funcs);
+
+        let x = module.locals.add(ValType::I32);
+        let mut func = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
+        func.name("int_math".to_string());
+        func.func_body()
+            .local_get(x)
+            .i32_const(1)
+            .binop(BinaryOp::I32Add);
+        func.finish(vec![x], &mut module.funcs);
+
+        module
+    }
+
+    fn function_named<'a>(module: &'a Module, name: &str) -> Option<&'a LocalFunction> {
+        module
+            .funcs
+            .iter_local()
+            .find(|(func_id, _)| module.funcs.get(*func_id).name.as_deref() == Some(name))
+            .map(|(_, func)| func)
+    }
+
+    pub fn test_rewrite_floats_calls_implementations() {
+        let mut module = float_module();
+        assert_eq!(rewrite_floats(&mut module), 3);
+
+        let add = module.funcs.by_name("canonical_nan_F64Add").unwrap();
+        let sqrt = module.funcs.by_name("canonical_nan_F64Sqrt").unwrap();
+        // the second addition uses the same implementation
+        assert_eq!(module.funcs.iter_local().count(), 4);
+
+        let float_math = function_named(&module, "float_math").unwrap();
+        let calls: Vec<FunctionId> = float_math
+            .block(float_math.entry_block())
+            .instrs
+            .iter()
+            .filter_map(|(instr, _)| match instr {
+                Instr::Call(Call { func }) => Some(*func),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, vec![add, sqrt, add]);
+
+        // the implementations canonicalize their result
+        let implementation = function_named(&module, "canonical_nan_F64Sqrt").unwrap();
+        let body = &implementation.block(implementation.entry_block()).instrs;
+        assert!(matches!(body.last(), Some((Instr::Select { .. }, _))));
+    }
+
+    /// Runs the function exported as `name` under wasm3
+    fn call<ARGS, RET>(module: &mut Module, name: &str, args: ARGS) -> RET
+    where
+        ARGS: wasm3::WasmArgs,
+        RET: wasm3::WasmType,
+    {
+        let code = module.emit_wasm();
+        let environment = wasm3::Environment::new().unwrap();
+        let runtime = environment.new_runtime::<()>(1024 * 60, None).unwrap();
+        let instance = runtime
+            .load_module(environment.parse_module(&code).unwrap())
+            .unwrap();
+        let function = instance.find_function::<ARGS, RET>(name).unwrap();
+        function.call_with_context(&mut (), args).unwrap()
+    }
+
+    pub fn test_rewritten_floats_produce_canonical_nans() {
+        let mut module = Module::default();
+
+        // 0 / 0 and sqrt(-1) give a NaN with the sign bit set on x86, which isn't canonical
+        let a = module.locals.add(ValType::F64);
+        let b = module.locals.add(ValType::F64);
+        let mut func = FunctionBuilder::new(
+            &mut module.types,
+            &[ValType::F64, ValType::F64],
+            &[ValType::F64],
+        );
+        func.func_body()
+            .local_get(a)
+            .local_get(b)
+            .binop(BinaryOp::F64Div);
+        let div = func.finish(vec![a, b], &mut module.funcs);
+        module.exports.add("div", div);
+
+        let x = module.locals.add(ValType::F32);
+        let mut func = FunctionBuilder::new(&mut module.types, &[ValType::F32], &[ValType::F32]);
+        func.func_body().local_get(x).unop(UnaryOp::F32Sqrt);
+        let sqrt = func.finish(vec![x], &mut module.funcs);
+        module.exports.add("sqrt", sqrt);
+
+        assert_eq!(rewrite_floats(&mut module), 2);
+
+        let nan: f64 = call(&mut module, "div", (0.0_f64, 0.0_f64));
+        assert_eq!(nan.to_bits(), CANONICAL_NAN_F64);
+        // the payload of a NaN operand doesn't make it to the result either
+        let payload = f64::from_bits(0x7ff8_0000_0000_0001);
+        let nan: f64 = call(&mut module, "div", (payload, 1.0_f64));
+        assert_eq!(nan.to_bits(), CANONICAL_NAN_F64);
+        let nan: f32 = call(&mut module, "sqrt", -1.0_f32);
+        assert_eq!(nan.to_bits(), CANONICAL_NAN_F32);
+
+        // results that aren't NaNs are left alone
+        let quotient: f64 = call(&mut module, "div", (1.0_f64, 4.0_f64));
+        assert_eq!(quotient, 0.25);
+    }
+
+    pub fn test_rewrite_floats_ignores_integers() {
+        let mut module = Module::default();
+        let x = module.locals.add(ValType::I64);
+        let mut func = FunctionBuilder::new(&mut module.types, &[ValType::I64], &[ValType::I64]);
+        func.func_body()
+            .local_get(x)
+            .i64_const(3)
+            .binop(BinaryOp::I64Mul);
+        func.finish(vec![x], &mut module.funcs);
+
+        assert_eq!(rewrite_floats(&mut module), 0);
+        assert_eq!(module.funcs.iter_local().count(), 1);
+    }
+}
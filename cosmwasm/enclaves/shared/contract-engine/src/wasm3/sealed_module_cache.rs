@@ -0,0 +1,170 @@
+//! Instrumented modules, sealed to the untrusted disk so they survive enclave restarts.
+//!
+//! Modules are sealed with the MRENCLAVE policy, so only this exact enclave build can read them,
+//! and the MAC of the sealed file is checked when it's unsealed. The host can still delete, swap
+//! or replay files, so each file also records the code hash and the versions it was built for,
+//! and anything that doesn't match is treated as a miss and rebuilt.
+//!
+//! The directory is bounded: once it holds more than `SEALED_MODULE_CACHE_LIMIT` modules, the ones
+//! sealed longest ago are deleted. Their timestamps come from the host, which could delete them
+//! anyway, so at worst a module gets instrumented again.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::untrusted::fs;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use enclave_crypto::consts::SEALED_MODULE_CACHE_PATH;
+use enclave_crypto::HASH_SIZE;
+use enclave_utils::storage::{seal_with_policy, unseal, KeyPolicy, SealingPolicy};
+
+use super::module_cache::{VersionedCode, ENGINE_VERSION};
+use crate::engine_params::EngineRules;
+use crate::gas::GAS_SCHEDULE_VERSION;
+
+/// The cache can always be rebuilt, so there's no reason to survive microcode rollbacks or to let
+/// other enclave builds read it
+const POLICY: SealingPolicy = SealingPolicy {
+    key_policy: KeyPolicy::MrEnclave,
+    pin_cpu_svn: false,
+};
+
+/// How many modules are kept on disk. The host prewarms at most its 100 hottest contracts, so this
+/// leaves room for contracts that are only called now and then.
+const SEALED_MODULE_CACHE_LIMIT: usize = 256;
+
+#[derive(Serialize, Deserialize)]
+struct SealedModule {
+    engine_version: u32,
+    gas_schedule_version: u32,
+    /// The rules the module was instrumented for
+    rules: EngineRules,
+    code_hash: [u8; HASH_SIZE],
+    code: VersionedCode,
+}
+
+fn module_path(code_hash: &[u8; HASH_SIZE]) -> String {
+    Path::new(SEALED_MODULE_CACHE_PATH.as_str())
+        .join(format!("{}.sealed", hex::encode(code_hash)))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Loads the module of `code_hash`, if it was sealed by this enclave for the current engine and gas
+/// schedule, and for `rules`
+pub fn load(code_hash: &[u8; HASH_SIZE], rules: &EngineRules) -> Option<VersionedCode> {
+    match load_any(code_hash) {
+        Some((sealed_rules, code)) if sealed_rules == *rules => Some(code),
+        Some(_) => {
+            debug!("cached module was sealed for other rules, ignoring it");
+            None
+        }
+        None => None,
+    }
+}
+
+/// Loads the module of `code_hash` along with the rules it was sealed for, if it was sealed by
+/// this enclave for the current engine and gas schedule
+pub fn load_any(code_hash: &[u8; HASH_SIZE]) -> Option<(EngineRules, VersionedCode)> {
+    let path = module_path(code_hash);
+    if !fs::try_exists(&path).unwrap_or(false) {
+        return None;
+    }
+
+    // `unseal` checks the MAC of the file
+    let sealed: SealedModule = match unseal(&path)
+        .ok()
+        .and_then(|bytes| bincode2::deserialize(&bytes).ok())
+    {
+        Some(sealed) => sealed,
+        None => {
+            warn!("couldn't unseal cached module {}, ignoring it", path);
+            return None;
+        }
+    };
+
+    if sealed.code_hash != *code_hash
+        || sealed.engine_version != ENGINE_VERSION
+        || sealed.gas_schedule_version != GAS_SCHEDULE_VERSION
+    {
+        debug!("cached module {} is stale, ignoring it", path);
+        return None;
+    }
+
+    trace!("loaded sealed module {}", path);
+    Some((sealed.rules, sealed.code))
+}
+
+/// Seals the module of `code_hash`, instrumented for `rules`. Failures only cost us the next
+/// restart, so they're logged and otherwise ignored.
+pub fn store(code_hash: &[u8; HASH_SIZE], rules: &EngineRules, code: &VersionedCode) {
+    if let Err(err) = fs::create_dir_all(SEALED_MODULE_CACHE_PATH.as_str()) {
+        warn!("couldn't create the sealed module cache: {}", err);
+        return;
+    }
+
+    let sealed = SealedModule {
+        engine_version: ENGINE_VERSION,
+        gas_schedule_version: GAS_SCHEDULE_VERSION,
+        rules: *rules,
+        code_hash: *code_hash,
+        code: code.clone(),
+    };
+    let bytes = match bincode2::serialize(&sealed) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!("couldn't serialize module for sealing: {}", err);
+            return;
+        }
+    };
+
+    let path = module_path(code_hash);
+    if let Err(err) = seal_with_policy(&bytes, &path, POLICY) {
+        warn!("couldn't seal module {}: {}", path, err);
+        return;
+    }
+
+    evict_oldest();
+}
+
+/// Deletes the modules sealed longest ago, until at most `SEALED_MODULE_CACHE_LIMIT` are left
+fn evict_oldest() {
+    let entries = match fs::read_dir(SEALED_MODULE_CACHE_PATH.as_str()) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("couldn't list the sealed module cache: {}", err);
+            return;
+        }
+    };
+
+    let mut modules: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .map_or(false, |ext| ext == "sealed")
+        })
+        .map(|entry| {
+            let sealed_at = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (sealed_at, entry.path())
+        })
+        .collect();
+    if modules.len() <= SEALED_MODULE_CACHE_LIMIT {
+        return;
+    }
+
+    modules.sort();
+    let excess = modules.len() - SEALED_MODULE_CACHE_LIMIT;
+    for (_, path) in modules.into_iter().take(excess) {
+        debug!("evicting sealed module {}", path.display());
+        if let Err(err) = fs::remove_file(&path) {
+            warn!("couldn't evict sealed module {}: {}", path.display(), err);
+        }
+    }
+}
@@ -1,10 +1,46 @@
 use log::*;
 
-use walrus::Module;
+use walrus::{ir::*, ImportKind, Module};
 
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+use enclave_utils::error_context::with_context;
 
-pub fn validate_memory(module: &mut Module) -> Result<(), EnclaveError> {
+use crate::engine_params::EngineRules;
+
+/// The host functions linked into every instance by `Engine::link_host_functions`. Keep the two in
+/// sync.
+const SUPPORTED_IMPORTS: &[&str] = &[
+    "db_read",
+    "db_write",
+    "db_remove",
+    "canonicalize_address",
+    "humanize_address",
+    "query_chain",
+    "addr_canonicalize",
+    "addr_humanize",
+    "addr_validate",
+    "debug_print",
+    "debug",
+    "secp256k1_verify",
+    "secp256k1_recover_pubkey",
+    "ed25519_verify",
+    "ed25519_batch_verify",
+    "secp256k1_sign",
+    "ed25519_sign",
+    "query_permit_verify",
+    "viewing_key_verify",
+    "contract_secret",
+    "contract_secp256k1_pubkey",
+    "contract_secp256k1_sign",
+    "contract_ed25519_pubkey",
+    "contract_ed25519_sign",
+    "contract_x25519_pubkey",
+    "contract_x25519_ecdh",
+    "check_gas",
+    "gas_evaporate",
+];
+
+pub fn validate_memory(module: &mut Module, rules: &EngineRules) -> Result<(), EnclaveError> {
     // Verify that there is no start function defined.
     if module.start.is_some() {
         return Err(EnclaveError::WasmModuleWithStart);
@@ -15,20 +51,190 @@ pub fn validate_memory(module: &mut Module) -> Result<(), EnclaveError> {
         return Err(EnclaveError::CannotInitializeWasmMemory);
     }
 
+    let maximum_allowed_pages = rules.max_memory_pages();
     for memory in module.memories.iter_mut() {
         let requested_initial_pages: u32 = memory.initial;
-        let maximum_allowed_pages: u32 = 192; // 12 MiB
 
         if requested_initial_pages > maximum_allowed_pages {
             error!(
                 "WASM Requested to initialize with {} pages, maximum allowed is {}",
                 requested_initial_pages, maximum_allowed_pages
             );
-            return Err(EnclaveError::CannotInitializeWasmMemory);
+            return Err(match rules {
+                EngineRules::Legacy => EnclaveError::CannotInitializeWasmMemory,
+                EngineRules::Params(_) => EnclaveError::WasmMemoryLimitExceeded {
+                    requested: requested_initial_pages,
+                    allowed: maximum_allowed_pages,
+                },
+            });
         }
 
-        memory.maximum = Some(maximum_allowed_pages);
+        memory.maximum = match rules {
+            EngineRules::Legacy => Some(maximum_allowed_pages),
+            // Contracts that declare a lower maximum of their own keep it
+            EngineRules::Params(_) => {
+                Some(memory.maximum.map_or(maximum_allowed_pages, |maximum| {
+                    maximum.min(maximum_allowed_pages)
+                }))
+            }
+        };
+    }
+
+    Ok(())
+}
+
+/// Verifies that the module only imports functions we link. Only checked when code is stored,
+/// since contracts that were stored before this check may import functions they never call.
+pub fn validate_imports(module: &Module) -> Result<(), EnclaveError> {
+    for import in module.imports.iter() {
+        let is_function = matches!(import.kind, ImportKind::Function(_));
+        if import.module != "env"
+            || !is_function
+            || !SUPPORTED_IMPORTS.contains(&import.name.as_str())
+        {
+            warn!(
+                "contract requires unsupported import {}.{}",
+                import.module, import.name
+            );
+            return Err(with_context(
+                EnclaveError::InvalidWasm,
+                EnclaveErrorStage::WasmLoading,
+                error_details::UNSUPPORTED_IMPORT,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Verifies that the module only uses instructions wasm3 runs: the MVP, and the sign extension,
+/// multi-value and non-trapping float to int proposals, and `memory.copy` and `memory.fill` from
+/// the bulk memory proposal. Newer Rust toolchains emit all of these by default. Only checked
+/// when a contract is instantiated, like floats, and only once the chain set its engine params.
+pub fn validate_instructions(module: &Module) -> Result<(), EnclaveError> {
+    for (_, func) in module.funcs.iter_local() {
+        for (_, block) in func.blocks() {
+            for (instr, _) in block.instrs.iter() {
+                if is_supported_instruction(instr) {
+                    continue;
+                }
+
+                warn!("contract uses unsupported instruction {:?}", instr);
+                return Err(with_context(
+                    EnclaveError::InvalidWasm,
+                    EnclaveErrorStage::WasmLoading,
+                    error_details::UNSUPPORTED_INSTRUCTION,
+                ));
+            }
+        }
     }
 
     Ok(())
 }
+
+fn is_supported_instruction(instr: &Instr) -> bool {
+    !matches!(
+        instr,
+        // bulk memory, other than memory.copy and memory.fill
+        Instr::MemoryInit { .. }
+            | Instr::DataDrop { .. }
+            | Instr::TableInit { .. }
+            | Instr::TableCopy { .. }
+            | Instr::ElemDrop { .. }
+            // reference types
+            | Instr::TableGet { .. }
+            | Instr::TableSet { .. }
+            | Instr::TableGrow { .. }
+            | Instr::TableSize { .. }
+            | Instr::TableFill { .. }
+            | Instr::RefNull { .. }
+            | Instr::RefIsNull { .. }
+            | Instr::RefFunc { .. }
+            // threads
+            | Instr::AtomicRmw { .. }
+            | Instr::Cmpxchg { .. }
+            | Instr::AtomicNotify { .. }
+            | Instr::AtomicWait { .. }
+            | Instr::AtomicFence { .. }
+            // simd
+            | Instr::LoadSimd { .. }
+            | Instr::V128Bitselect { .. }
+            | Instr::I8x16Swizzle { .. }
+            | Instr::I8x16Shuffle { .. }
+            | Instr::Const(Const {
+                value: Value::V128(_)
+            })
+    )
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use walrus::{DataId, DataKind, FunctionBuilder, InstrSeqBuilder, MemoryId};
+
+    use crate::engine_params::EngineParams;
+
+    /// A module with a memory, a passive data segment and a function with the given body
+    fn module_with_body(make_body: impl FnOnce(&mut InstrSeqBuilder, MemoryId, DataId)) -> Module {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, 1, None);
+        let data = module.data.add(DataKind::Passive, vec![1, 2, 3]);
+        let mut func = FunctionBuilder::new(&mut module.types, &[], &[]);
+        make_body(&mut func.func_body(), memory, data);
+        func.finish(vec![], &mut module.funcs);
+        module
+    }
+
+    pub fn test_validate_instructions() {
+        let supported = module_with_body(|body, memory, _data| {
+            body.i32_const(0)
+                .i32_const(0)
+                .i32_const(8)
+                .memory_fill(memory)
+                .i32_const(-1)
+                .unop(UnaryOp::I32Extend8S)
+                .drop();
+        });
+        assert!(validate_instructions(&supported).is_ok());
+
+        let unsupported = module_with_body(|body, memory, data| {
+            body.i32_const(0)
+                .i32_const(0)
+                .i32_const(3)
+                .memory_init(memory, data);
+        });
+        assert!(validate_instructions(&unsupported).is_err());
+    }
+
+    pub fn test_validate_memory_caps_maximum_by_rules() {
+        let rules = EngineRules::Params(EngineParams {
+            max_memory_pages: 256,
+            max_call_depth: 10,
+        });
+        let memory_maximum = |rules: &EngineRules, initial: u32, maximum: Option<u32>| {
+            let mut module = Module::default();
+            let memory = module.memories.add_local(false, initial, maximum);
+            validate_memory(&mut module, rules).map(|_| module.memories.get(memory).maximum)
+        };
+
+        // Before the chain set its params, the maximum was always the legacy limit
+        assert_eq!(
+            memory_maximum(&EngineRules::Legacy, 1, Some(2)).unwrap(),
+            Some(192)
+        );
+        assert!(matches!(
+            memory_maximum(&EngineRules::Legacy, 200, None),
+            Err(EnclaveError::CannotInitializeWasmMemory)
+        ));
+
+        assert_eq!(memory_maximum(&rules, 1, Some(2)).unwrap(), Some(2));
+        assert_eq!(memory_maximum(&rules, 200, None).unwrap(), Some(256));
+        assert!(matches!(
+            memory_maximum(&rules, 300, None),
+            Err(EnclaveError::WasmMemoryLimitExceeded {
+                requested: 300,
+                allowed: 256
+            })
+        ));
+    }
+}
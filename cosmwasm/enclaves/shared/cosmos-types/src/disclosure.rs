@@ -0,0 +1,135 @@
+//!
+//! Disclosure permits, which let a user grant an auditor access to the outputs of some of their
+//! past transactions.
+//!
+//! Like query permits, the permit is an amino JSON sign doc signed offline by the user's wallet,
+//! with a single `disclosure_permit` message. Since tx outputs are encrypted with the user's
+//! x25519 tx encryption key and not their wallet key, the permit also carries a proof that whoever
+//! made it holds that key: an HMAC-SHA256 of the sign bytes, keyed with the shared secret of the
+//! tx encryption key and the consensus IO exchange key.
+//!
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::CanonicalAddr;
+use enclave_crypto::CryptoError;
+
+use crate::permit::{permit_sign_bytes, PermitSignature};
+use crate::types::AminoSdkMsg;
+
+/// Keeps the size of a single disclosure bounded
+pub const MAX_DISCLOSED_TXS: usize = 100;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct DisclosurePermit {
+    pub params: DisclosureParams,
+    pub signature: PermitSignature,
+    /// HMAC-SHA256 of `sign_bytes()`, keyed with the DH of the tx encryption key and the consensus
+    /// IO exchange key
+    pub encryption_key_proof: Binary,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct DisclosureParams {
+    pub permit_name: String,
+    pub chain_id: String,
+    /// x25519 public key the tx keys are encrypted to
+    pub auditor_pubkey: Binary,
+    /// x25519 public key the user encrypted the txs with
+    pub encryption_pubkey: Binary,
+    pub txs: Vec<DisclosedTx>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DisclosedTx {
+    /// The nonce the tx was encrypted with
+    pub nonce: Binary,
+    /// The hex hash of the tx the nonce belongs to. The enclave can't check which tx a nonce came
+    /// from, but the hash is signed and proven along with the nonce, so the auditor knows which tx
+    /// the user bound each key to and checks that it carries the nonce.
+    pub tx_hash: String,
+}
+
+impl DisclosurePermit {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let permit: Self = serde_json::from_slice(bytes).map_err(|err| {
+            debug!("Failed to parse disclosure permit: {:?}", err);
+            CryptoError::ParsingError
+        })?;
+
+        if permit.params.txs.len() > MAX_DISCLOSED_TXS {
+            debug!(
+                "Disclosure permit lists {} txs, more than the maximum of {}",
+                permit.params.txs.len(),
+                MAX_DISCLOSED_TXS
+            );
+            return Err(CryptoError::ParsingError);
+        }
+
+        for tx in permit.params.txs.iter() {
+            if tx.tx_hash.len() != 64 || !tx.tx_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                debug!("Disclosure permit has an invalid tx hash {:?}", tx.tx_hash);
+                return Err(CryptoError::ParsingError);
+            }
+        }
+
+        Ok(permit)
+    }
+
+    /// The bytes the wallet signed
+    pub fn sign_bytes(&self) -> Vec<u8> {
+        permit_sign_bytes(
+            &self.params.chain_id,
+            AminoSdkMsg::DisclosurePermit {
+                auditor_pubkey: self.params.auditor_pubkey.clone(),
+                encryption_pubkey: self.params.encryption_pubkey.clone(),
+                permit_name: self.params.permit_name.clone(),
+                txs: self.params.txs.clone(),
+            },
+        )
+    }
+
+    /// Verifies the wallet signature on the permit, and returns the address of the account that
+    /// signed it. The encryption key proof is checked by the caller, since it needs the consensus
+    /// IO exchange key.
+    pub fn verify(&self) -> Result<CanonicalAddr, CryptoError> {
+        self.signature.verify(&self.sign_bytes())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::DisclosurePermit;
+
+    const PERMIT: &str = r#"{"params":{"permit_name":"audit","chain_id":"secret-4","auditor_pubkey":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=","encryption_pubkey":"AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=","txs":[{"nonce":"AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=","tx_hash":"AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12"}]},"signature":{"pub_key":{"type":"tendermint/PubKeySecp256k1","value":"A5M49l32ZrV+SDsPnoRv8fH7ivNC4gEX9prvd4RwvRaL"},"signature":"hw/Mo3ZZYu1pEiDdymElFkuCuJzg9soDHw+4DxK7cL9rafiyykh7VynS+guotRAKXhfYMwCiyWmiznc6R+UlsQ=="},"encryption_key_proof":"BAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ="}"#;
+
+    pub fn test_disclosure_sign_bytes() {
+        let permit = DisclosurePermit::from_bytes(PERMIT.as_bytes()).unwrap();
+
+        let expected = r#"{"account_number":"0","chain_id":"secret-4","fee":{"amount":[{"amount":"0","denom":"uscrt"}],"gas":"1"},"memo":"","msgs":[{"type":"disclosure_permit","value":{"auditor_pubkey":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=","encryption_pubkey":"AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=","permit_name":"audit","txs":[{"nonce":"AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=","tx_hash":"AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12"}]}}],"sequence":"0"}"#;
+        assert_eq!(permit.sign_bytes(), expected.as_bytes().to_vec());
+
+        // the signature is of a query permit, not of this
+        assert!(permit.verify().is_err());
+    }
+
+    pub fn test_disclosure_too_many_txs() {
+        let tx = r#"{"nonce":"AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=","tx_hash":"AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12AB12"}"#;
+        let txs = vec![tx; super::MAX_DISCLOSED_TXS + 1].join(",");
+        let permit = PERMIT.replace(&format!("[{}]", tx), &format!("[{}]", txs));
+
+        assert!(DisclosurePermit::from_bytes(permit.as_bytes()).is_err());
+    }
+
+    pub fn test_disclosure_invalid_tx_hash() {
+        let not_hex = "XY12".repeat(16);
+        let too_long = "AB12".repeat(17);
+
+        for tx_hash in &["AB12", "", not_hex.as_str(), too_long.as_str()] {
+            let permit = PERMIT.replace(&"AB12".repeat(16), tx_hash);
+
+            assert!(DisclosurePermit::from_bytes(permit.as_bytes()).is_err());
+        }
+    }
+}
@@ -1,15 +1,19 @@
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(not(target_env = "sgx"), not(feature = "simulator")))]
 extern crate sgx_tstd as std;
 
+pub mod disclosure;
 pub mod multisig;
+pub mod permit;
 pub mod single_address;
 pub mod traits;
 pub mod types;
 
 #[cfg(feature = "test")]
 pub mod tests {
+    use crate::disclosure;
     use crate::multisig;
+    use crate::permit;
 
     /// Catch failures like the standard test runner, and print similar information per test.
     /// Tests can only fail by panicking, not by returning a `Result` type.
@@ -43,6 +47,13 @@ pub mod tests {
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_only_prefix();
             multisig::tests_decode_multisig_signature::test_decode_sig_length_zero();
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_wrong_length();
+            permit::tests::test_permit_sign_bytes();
+            permit::tests::test_permit_verify();
+            permit::tests::test_permit_verify_tampered();
+            permit::tests::test_permit_verify_wrong_key_type();
+            disclosure::tests::test_disclosure_sign_bytes();
+            disclosure::tests::test_disclosure_too_many_txs();
+            disclosure::tests::test_disclosure_invalid_tx_hash();
         });
 
         if failures != 0 {
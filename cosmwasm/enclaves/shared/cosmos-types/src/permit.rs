@@ -0,0 +1,188 @@
+//!
+//! SNIP-24 query permits.
+//!
+//! A permit is an amino JSON sign doc with a single `query_permit` message, signed offline by the
+//! querier's wallet. Contracts used to verify them in wasm (see `secret_toolkit::permit::validate`);
+//! this does the same natively. Revocation is left to the contract, since it's kept in its state.
+//!
+//! See https://github.com/SecretFoundation/SNIPs/blob/master/SNIP-24.md
+//!
+use log::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use cosmos_proto::tx::signing::SignMode;
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::CanonicalAddr;
+use enclave_crypto::secp256k1::Secp256k1PubKey;
+use enclave_crypto::traits::VerifyingKey;
+use enclave_crypto::CryptoError;
+
+use crate::traits::CosmosAminoPubkey;
+use crate::types::{AminoSdkMsg, StdSignDoc};
+
+const PERMIT_FEE_DENOM: &str = "uscrt";
+/// The amino type of the only kind of key permits can be signed with
+const PERMIT_PUBKEY_TYPE: &str = "tendermint/PubKeySecp256k1";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PermitParams {
+    pub allowed_tokens: Vec<String>,
+    pub permit_name: String,
+    pub chain_id: String,
+    /// Kept as JSON, since the meaning of each permission is up to the contract
+    pub permissions: Vec<Value>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PermitSignature {
+    pub pub_key: PermitPubKey,
+    pub signature: Binary,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PermitPubKey {
+    /// Must be `tendermint/PubKeySecp256k1`
+    #[serde(rename = "type")]
+    pub key_type: String,
+    pub value: Binary,
+}
+
+impl Permit {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        serde_json::from_slice(bytes).map_err(|err| {
+            debug!("Failed to parse permit: {:?}", err);
+            CryptoError::ParsingError
+        })
+    }
+
+    pub fn allows_token(&self, token: &str) -> bool {
+        self.params
+            .allowed_tokens
+            .iter()
+            .any(|allowed| allowed == token)
+    }
+
+    /// The bytes the wallet signed
+    pub fn sign_bytes(&self) -> Vec<u8> {
+        permit_sign_bytes(
+            &self.params.chain_id,
+            AminoSdkMsg::QueryPermit {
+                allowed_tokens: self.params.allowed_tokens.clone(),
+                permissions: self.params.permissions.clone(),
+                permit_name: self.params.permit_name.clone(),
+            },
+        )
+    }
+
+    /// Verifies the signature on the permit, and returns the address of the account that signed it
+    pub fn verify(&self) -> Result<CanonicalAddr, CryptoError> {
+        self.signature.verify(&self.sign_bytes())
+    }
+}
+
+impl PermitSignature {
+    /// Verifies this is a signature on `sign_bytes`, and returns the address of the account that
+    /// signed them
+    pub fn verify(&self, sign_bytes: &[u8]) -> Result<CanonicalAddr, CryptoError> {
+        if self.pub_key.key_type != PERMIT_PUBKEY_TYPE {
+            debug!(
+                "Permit signed with an unsupported key type {:?}",
+                self.pub_key.key_type
+            );
+            return Err(CryptoError::ParsingError);
+        }
+
+        let pubkey = Secp256k1PubKey::new(self.pub_key.value.0.clone());
+
+        pubkey.verify_bytes(
+            sign_bytes,
+            self.signature.as_slice(),
+            SignMode::SIGN_MODE_LEGACY_AMINO_JSON,
+        )?;
+
+        Ok(pubkey.get_address())
+    }
+}
+
+/// Builds the amino JSON sign doc wallets sign for permits, with `msg` as its only message
+pub fn permit_sign_bytes(chain_id: &str, msg: AminoSdkMsg) -> Vec<u8> {
+    let sign_doc = StdSignDoc {
+        account_number: "0".to_string(),
+        chain_id: chain_id.to_string(),
+        fee: json!({
+            "amount": [{ "amount": "0", "denom": PERMIT_FEE_DENOM }],
+            "gas": "1",
+        }),
+        memo: String::new(),
+        msgs: vec![msg],
+        sequence: "0".to_string(),
+    };
+
+    // Amino JSON sorts object keys, which going through `Value` does for us.
+    // Serializing these types can't fail
+    serde_json::to_value(&sign_doc)
+        .and_then(|sign_doc| serde_json::to_vec(&sign_doc))
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::Permit;
+    use cw_types_v010::types::CanonicalAddr;
+
+    const TOKEN: &str = "secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq";
+
+    // signed on pulsar-2 by secret1399pyvvk3hvwgxwt3udkslsc5jl3rqv4yshfrl
+    const PERMIT: &str = r#"{"params":{"chain_id":"pulsar-2","permit_name":"memo_secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq","allowed_tokens":["secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq"],"permissions":["history"]},"signature":{"pub_key":{"type":"tendermint/PubKeySecp256k1","value":"A5M49l32ZrV+SDsPnoRv8fH7ivNC4gEX9prvd4RwvRaL"},"signature":"hw/Mo3ZZYu1pEiDdymElFkuCuJzg9soDHw+4DxK7cL9rafiyykh7VynS+guotRAKXhfYMwCiyWmiznc6R+UlsQ=="}}"#;
+
+    const SIGNER: [u8; 20] = [
+        0x89, 0x4a, 0x12, 0x31, 0x96, 0x8d, 0xd8, 0xe4, 0x19, 0xcb, 0x8f, 0x1b, 0x68, 0x7e, 0x18,
+        0xa4, 0xbf, 0x11, 0x81, 0x95,
+    ];
+
+    pub fn test_permit_sign_bytes() {
+        let permit = Permit::from_bytes(PERMIT.as_bytes()).unwrap();
+
+        let expected = format!(
+            r#"{{"account_number":"0","chain_id":"pulsar-2","fee":{{"amount":[{{"amount":"0","denom":"uscrt"}}],"gas":"1"}},"memo":"","msgs":[{{"type":"query_permit","value":{{"allowed_tokens":["{token}"],"permissions":["history"],"permit_name":"memo_{token}"}}}}],"sequence":"0"}}"#,
+            token = TOKEN
+        );
+        assert_eq!(permit.sign_bytes(), expected.into_bytes());
+    }
+
+    pub fn test_permit_verify() {
+        let permit = Permit::from_bytes(PERMIT.as_bytes()).unwrap();
+
+        assert!(permit.allows_token(TOKEN));
+        assert!(!permit.allows_token("secret1another"));
+        assert_eq!(
+            permit.verify().unwrap(),
+            CanonicalAddr::from_vec(SIGNER.to_vec())
+        );
+    }
+
+    pub fn test_permit_verify_tampered() {
+        let tampered = PERMIT.replace(r#"["history"]"#, r#"["balance"]"#);
+        let permit = Permit::from_bytes(tampered.as_bytes()).unwrap();
+        assert!(permit.verify().is_err());
+
+        let tampered = PERMIT.replace("pulsar-2", "secret-4");
+        let permit = Permit::from_bytes(tampered.as_bytes()).unwrap();
+        assert!(permit.verify().is_err());
+
+        assert!(Permit::from_bytes(b"{\"params\":{}}").is_err());
+    }
+
+    pub fn test_permit_verify_wrong_key_type() {
+        let wrong_type = PERMIT.replace("tendermint/PubKeySecp256k1", "tendermint/PubKeyEd25519");
+        let permit = Permit::from_bytes(wrong_type.as_bytes()).unwrap();
+        assert!(permit.verify().is_err());
+    }
+}
@@ -5,6 +5,7 @@ use proto::tx::signing::SignMode;
 use protobuf::Message;
 use serde::{Deserialize, Serialize};
 
+use crate::disclosure::DisclosedTx;
 use crate::multisig::MultisigThresholdPubKey;
 
 use enclave_crypto::{
@@ -226,10 +227,13 @@ pub struct SigInfo {
 }
 
 // Should be in sync with https://github.com/cosmos/cosmos-sdk/blob/v0.38.3/x/auth/types/stdtx.go#L216
-#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub struct StdSignDoc {
     pub account_number: String,
     pub chain_id: String,
+    /// Not verified for txs, only used to build the sign docs of permits
+    #[serde(default)]
+    pub fee: serde_json::Value,
     pub memo: String,
     pub msgs: Vec<AminoSdkMsg>,
     pub sequence: String,
@@ -340,6 +344,19 @@ pub enum AminoSdkMsg {
         sender: HumanAddr,
         contract: HumanAddr,
     },
+    /// SNIP-24 query permit, see `permit::Permit`
+    QueryPermit {
+        allowed_tokens: Vec<String>,
+        permissions: Vec<serde_json::Value>,
+        permit_name: String,
+    },
+    /// See `disclosure::DisclosurePermit`
+    DisclosurePermit {
+        auditor_pubkey: Binary,
+        encryption_pubkey: Binary,
+        permit_name: String,
+        txs: Vec<DisclosedTx>,
+    },
     // The core IBC messages don't support Amino
     #[serde(other, deserialize_with = "deserialize_ignore_any")]
     Other,
@@ -474,7 +491,10 @@ impl AminoSdkMsg {
 
                 Ok(DirectSdkMsg::MsgClearAdmin { sender, contract })
             }
-            Self::Other => Ok(DirectSdkMsg::Other),
+            // permits are only signed offline, so they don't mean anything in a tx
+            Self::QueryPermit { .. } | Self::DisclosurePermit { .. } | Self::Other => {
+                Ok(DirectSdkMsg::Other)
+            }
         }
     }
 }
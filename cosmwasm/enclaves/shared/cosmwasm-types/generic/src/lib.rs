@@ -29,6 +29,10 @@ pub enum CosmWasmApiVersion {
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum ContractFeature {
     Random,
+    /// Pad encrypted outputs to a multiple of this many bytes
+    Padding(u32),
+    /// Floats are allowed, and the NaNs they produce are canonicalized
+    NanCanonicalization,
 }
 
 pub type BaseAddr = HumanAddr;
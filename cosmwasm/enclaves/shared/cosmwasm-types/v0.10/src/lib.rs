@@ -1,5 +1,5 @@
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(not(target_env = "sgx"), not(feature = "simulator")))]
 extern crate sgx_tstd as std;
 
 pub mod coins;
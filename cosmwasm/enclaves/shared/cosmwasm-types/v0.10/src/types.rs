@@ -379,6 +379,12 @@ pub struct LogAttribute {
     #[serde(default = "bool_true")]
     #[serde(skip_serializing)]
     pub encrypted: bool,
+    /// nonstandard addition, only used in deserialization like `encrypted`.
+    /// x25519 public keys of parties that should be able to decrypt this attribute
+    /// in addition to the sender, e.g. the receiver of a transfer.
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub recipients: Vec<Binary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -408,6 +414,7 @@ pub fn log<K: ToString, V: ToString>(key: K, value: V) -> LogAttribute {
         key: key.to_string(),
         value: value.to_string(),
         encrypted: true,
+        recipients: vec![],
     }
 }
 
@@ -417,5 +424,6 @@ pub fn plaintext_log<K: ToString, V: ToString>(key: K, value: V) -> LogAttribute
         key: key.to_string(),
         value: value.to_string(),
         encrypted: false,
+        recipients: vec![],
     }
 }
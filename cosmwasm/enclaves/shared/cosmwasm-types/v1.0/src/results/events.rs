@@ -1,3 +1,4 @@
+use cw_types_v010::encoding::Binary;
 use cw_types_v010::types::LogAttribute;
 use serde::{Deserialize, Serialize};
 /// A full [*Cosmos SDK* event].
@@ -19,4 +20,9 @@ pub struct Event {
     ///
     /// [*Cosmos SDK* docs]: https://docs.cosmos.network/v0.42/core/events.html
     pub attributes: Vec<LogAttribute>,
+    /// nonstandard addition, only used in deserialization.
+    /// Like `LogAttribute::recipients`, for all the encrypted attributes of the event.
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub recipients: Vec<Binary>,
 }
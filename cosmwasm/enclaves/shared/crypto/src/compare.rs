@@ -0,0 +1,7 @@
+use ring::constant_time::verify_slices_are_equal;
+
+/// Compares two slices in constant time, so comparing against a secret doesn't leak how much of it
+/// matched. Slices of different lengths are never equal
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    verify_slices_are_equal(a, b).is_ok()
+}
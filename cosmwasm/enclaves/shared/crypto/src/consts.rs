@@ -29,6 +29,9 @@ pub const NODE_EXCHANGE_KEY_FILE: &str = "new_node_seed_exchange_keypair.sealed"
 pub const NODE_ENCRYPTED_SEED_KEY_GENESIS_FILE: &str = "consensus_seed.sealed";
 pub const NODE_ENCRYPTED_SEED_KEY_CURRENT_FILE: &str = "consensus_seed_current.sealed";
 
+/// Directory of the sealed, gas-instrumented contract modules
+pub const SEALED_MODULE_CACHE_DIR: &str = "module_cache";
+
 #[cfg(feature = "random")]
 pub const REK_SEALED_FILE_NAME: &str = "rek.sealed";
 #[cfg(feature = "random")]
@@ -118,6 +121,13 @@ lazy_static! {
     .to_str()
     .unwrap_or(DEFAULT_SGX_SECRET_PATH)
     .to_string();
+    pub static ref SEALED_MODULE_CACHE_PATH: String = path::Path::new(
+        &env::var(SCRT_SGX_STORAGE_ENV_VAR).unwrap_or_else(|_| DEFAULT_SGX_SECRET_PATH.to_string())
+    )
+    .join(SEALED_MODULE_CACHE_DIR)
+    .to_str()
+    .unwrap_or(DEFAULT_SGX_SECRET_PATH)
+    .to_string();
 }
 
 #[cfg(feature = "random")]
@@ -138,6 +148,11 @@ lazy_static! {
     .to_string();
 }
 
+/// The consensus seed the simulator runs with. It's public, so nothing encrypted by the simulator
+/// is secret.
+#[cfg(feature = "simulator")]
+pub const SIMULATOR_CONSENSUS_SEED: [u8; 32] = *b"secret-network-simulator-seed!!!";
+
 pub const CONSENSUS_SEED_EXCHANGE_KEYPAIR_DERIVE_ORDER: u32 = 1;
 pub const CONSENSUS_IO_EXCHANGE_KEYPAIR_DERIVE_ORDER: u32 = 2;
 pub const CONSENSUS_STATE_IKM_DERIVE_ORDER: u32 = 3;
@@ -146,6 +161,7 @@ pub const RANDOMNESS_ENCRYPTION_KEY_SECRET_DERIVE_ORDER: u32 = 5;
 pub const INITIAL_RANDOMNESS_SEED_SECRET_DERIVE_ORDER: u32 = 6;
 pub const ADMIN_PROOF_SECRET_DERIVE_ORDER: u32 = 7;
 pub const CONTRACT_KEY_PROOF_SECRET_DERIVE_ORDER: u32 = 8;
+pub const CONTRACT_OWNED_KEYS_SECRET_DERIVE_ORDER: u32 = 10;
 
 pub const ENCRYPTED_KEY_MAGIC_BYTES: &[u8; 6] = b"secret";
 pub const CONSENSUS_SEED_VERSION: u16 = 2;
@@ -42,4 +42,8 @@ pub enum WasmApiCryptoError {
     BatchErr = 7,
     GenericErr = 10,
     InvalidPrivateKeyFormat = 1000, // Assaf: 1000 to not collide with CosmWasm someday
+    InvalidPermitFormat = 1001,
+    PermitTokenNotAllowed = 1002,
+    PermitVerificationFailed = 1003,
+    InvalidKeyPathFormat = 1004,
 }
@@ -6,6 +6,17 @@ use enclave_ffi_types::EnclaveError;
 use lazy_static::lazy_static;
 use log::*;
 
+#[cfg(not(feature = "host"))]
+use std::sgxfs;
+
+/// Nothing is ever sealed on the host, so there's nothing to remove
+#[cfg(feature = "host")]
+mod sgxfs {
+    pub fn remove<P>(_path: P) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 // For phase 1 of the seed rotation, all consensus secrets come in two parts:
 // 1. The genesis seed generated on 15 September 2020
 // 2. The current seed
@@ -40,6 +51,7 @@ pub struct Keychain {
     registration_key: Option<KeyPair>,
     admin_proof_secret: Option<AESKey>,
     contract_key_proof_secret: Option<AESKey>,
+    contract_owned_keys_secret: Option<AESKey>,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -55,6 +67,7 @@ lazy_static! {
 #[allow(clippy::new_without_default)]
 impl Keychain {
     pub fn new() -> Self {
+        #[cfg(not(feature = "simulator"))]
         let consensus_seed: Option<SeedsHolder<Seed>> = match (
             Seed::unseal(GENESIS_CONSENSUS_SEED_SEALING_PATH.as_str()),
             Seed::unseal(CURRENT_CONSENSUS_SEED_SEALING_PATH.as_str()),
@@ -77,6 +90,10 @@ impl Keychain {
             }
         };
 
+        // There are no sealed files in the simulator, so it always runs with a well known seed
+        #[cfg(feature = "simulator")]
+        let consensus_seed = Some(Self::simulator_seeds());
+
         let registration_key = Self::unseal_registration_key();
 
         let mut x = Keychain {
@@ -93,6 +110,7 @@ impl Keychain {
             random_encryption_key: None,
             admin_proof_secret: None,
             contract_key_proof_secret: None,
+            contract_owned_keys_secret: None,
         };
 
         let _ = x.generate_consensus_master_keys();
@@ -100,6 +118,17 @@ impl Keychain {
         x
     }
 
+    #[cfg(feature = "simulator")]
+    fn simulator_seeds() -> SeedsHolder<Seed> {
+        let mut seed = Seed::default();
+        seed.as_mut().copy_from_slice(&SIMULATOR_CONSENSUS_SEED);
+
+        SeedsHolder {
+            genesis: seed,
+            current: seed,
+        }
+    }
+
     fn unseal_registration_key() -> Option<KeyPair> {
         match KeyPair::unseal(REGISTRATION_KEY_SEALING_PATH.as_str()) {
             Ok(k) => Some(k),
@@ -214,10 +243,17 @@ impl Keychain {
         })
     }
 
+    pub fn get_contract_owned_keys_secret(&self) -> Result<AESKey, CryptoError> {
+        self.contract_owned_keys_secret.ok_or_else(|| {
+            error!("Error accessing contract_owned_keys_secret (does not exist, or was not initialized)");
+            CryptoError::ParsingError
+        })
+    }
+
     pub fn reseal_registration_key(&mut self) -> Result<(), EnclaveError> {
         match Self::unseal_registration_key() {
             Some(kp) => {
-                if let Err(_e) = std::sgxfs::remove(&*REGISTRATION_KEY_SEALING_PATH) {
+                if let Err(_e) = sgxfs::remove(&*REGISTRATION_KEY_SEALING_PATH) {
                     error!("Failed to reseal registration key - error code 0xC11");
                     return Err(EnclaveError::FailedSeal);
                 };
@@ -263,7 +299,7 @@ impl Keychain {
             "Removing genesis consensus seed in {}",
             *GENESIS_CONSENSUS_SEED_SEALING_PATH
         );
-        if let Err(_e) = std::sgxfs::remove(GENESIS_CONSENSUS_SEED_SEALING_PATH.as_str()) {
+        if let Err(_e) = sgxfs::remove(GENESIS_CONSENSUS_SEED_SEALING_PATH.as_str()) {
             debug!("Error removing genesis consensus_seed");
             return false;
         }
@@ -272,7 +308,7 @@ impl Keychain {
             "Removing current consensus seed in {}",
             *CURRENT_CONSENSUS_SEED_SEALING_PATH
         );
-        if let Err(_e) = std::sgxfs::remove(CURRENT_CONSENSUS_SEED_SEALING_PATH.as_str()) {
+        if let Err(_e) = sgxfs::remove(CURRENT_CONSENSUS_SEED_SEALING_PATH.as_str()) {
             debug!("Error removing genesis consensus_seed");
             return false;
         }
@@ -479,6 +515,22 @@ impl Keychain {
             hex::encode(contract_key_proof_secret.get())
         );
 
+        // contract_owned_keys_secret
+        // the root of the keys contracts derive for themselves, see contract-engine/src/contract_keys.rs
+
+        let contract_owned_keys_secret = self
+            .consensus_seed
+            .unwrap()
+            .current
+            .derive_key_from_this(&CONTRACT_OWNED_KEYS_SECRET_DERIVE_ORDER.to_be_bytes());
+
+        self.contract_owned_keys_secret = Some(contract_owned_keys_secret);
+
+        trace!(
+            "contract_owned_keys_secret: {:?}",
+            hex::encode(contract_owned_keys_secret.get())
+        );
+
         Ok(())
     }
 
@@ -1,19 +1,23 @@
-#![cfg_attr(not(target_env = "sgx"), no_std)]
+#![cfg_attr(all(not(target_env = "sgx"), not(feature = "host")), no_std)]
 #![cfg_attr(target_env = "sgx", feature(rustc_private))]
 
+#[cfg(not(feature = "host"))]
 extern crate sgx_trts;
 extern crate sgx_types;
 
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
 extern crate alloc;
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(not(target_env = "sgx"), not(feature = "host")))]
 extern crate sgx_tstd as std;
 
+mod compare;
 pub mod consts;
 mod errors;
 pub(crate) mod kdf;
 pub mod key_manager;
 mod keys;
+#[cfg(not(feature = "host"))]
+pub mod sealing;
 mod storage;
 pub mod traits;
 
@@ -27,6 +31,7 @@ mod rng;
 
 pub mod hash;
 
+pub use compare::constant_time_eq;
 pub use errors::{CryptoError, WasmApiCryptoError};
 pub use key_manager::Keychain;
 pub use key_manager::KEY_MANAGER;
@@ -60,10 +65,14 @@ pub mod tests {
     }
 
     pub fn run_tests() {
-        let failures = 0;
+        let mut failures = 0;
 
         count_failures!(failures, {
             // todo: add encryption and other tests here
+            crate::sealing::tests::test_header_roundtrip();
+            crate::sealing::tests::test_seal_unseal_with_policy();
+            crate::sealing::tests::test_seal_keeps_policy();
+            crate::sealing::tests::test_unseal_tampered();
         });
 
         if failures != 0 {
@@ -1,6 +1,13 @@
 use crate::CryptoError;
+#[cfg(not(feature = "host"))]
 use sgx_trts::trts::rsgx_read_rand;
 
+#[cfg(not(feature = "host"))]
 pub fn rand_slice(rand: &mut [u8]) -> Result<(), CryptoError> {
     rsgx_read_rand(rand).map_err(|_e| CryptoError::RandomError {})
 }
+
+#[cfg(feature = "host")]
+pub fn rand_slice(rand: &mut [u8]) -> Result<(), CryptoError> {
+    getrandom::getrandom(rand).map_err(|_e| CryptoError::RandomError {})
+}
@@ -0,0 +1,478 @@
+//!
+//! Versioned format for sealed files.
+//!
+//! Files sealed by older enclaves use the SGX protected file system (`SgxFile`), which always
+//! derives its key from MRSIGNER and the CPUSVN of the platform that sealed them. Files in this
+//! format start with a header that records the key policy that was used, so it can be chosen
+//! per node and changed later by re-sealing (see `ecall_reseal_secrets`).
+//!
+//! Layout, all integers are little-endian:
+//!
+//! | offset | size | field                                       |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 8    | magic, `SCRTSEAL`                           |
+//! | 8      | 2    | format version                              |
+//! | 10     | 2    | key policy, `SGX_KEYPOLICY_*`               |
+//! | 12     | 2    | flags, bit 0 is set if the CPUSVN is pinned |
+//! | 14     | 2    | ISVSVN                                      |
+//! | 16     | 16   | CPUSVN, all zeros unless pinned             |
+//! | 32     | 32   | key id                                      |
+//! | 64     | 12   | IV                                          |
+//! | 76     | 16   | MAC                                         |
+//! | 92     | ...  | ciphertext                                  |
+//!
+//! The first 76 bytes are authenticated as additional data.
+//!
+use core::convert::TryInto;
+use log::*;
+use sgx_trts::trts::rsgx_read_rand;
+use sgx_types::*;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sgxfs::SgxFile;
+use std::untrusted::fs;
+use std::untrusted::fs::File;
+use std::vec::Vec;
+
+pub const SEALED_FILE_MAGIC: &[u8; 8] = b"SCRTSEAL";
+pub const SEALED_FILE_VERSION: u16 = 1;
+/// The version we report for files sealed with the SGX protected file system
+pub const LEGACY_SEALED_FILE_VERSION: u16 = 0;
+
+const FLAG_PIN_CPU_SVN: u16 = 1;
+const HEADER_SIZE: usize = 76;
+const IV_SIZE: usize = 12;
+const MAC_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Only this exact enclave build can unseal
+    MrEnclave,
+    /// Any enclave signed by the same key, with the same or a higher ISVSVN, can unseal
+    MrSigner,
+}
+
+impl KeyPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyPolicy::MrEnclave => "mrenclave",
+            KeyPolicy::MrSigner => "mrsigner",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mrenclave" => Some(KeyPolicy::MrEnclave),
+            "mrsigner" => Some(KeyPolicy::MrSigner),
+            _ => None,
+        }
+    }
+
+    pub fn to_sgx(self) -> u16 {
+        match self {
+            KeyPolicy::MrEnclave => SGX_KEYPOLICY_MRENCLAVE,
+            KeyPolicy::MrSigner => SGX_KEYPOLICY_MRSIGNER,
+        }
+    }
+
+    pub fn from_sgx(key_policy: u16) -> Option<Self> {
+        match key_policy {
+            SGX_KEYPOLICY_MRENCLAVE => Some(KeyPolicy::MrEnclave),
+            SGX_KEYPOLICY_MRSIGNER => Some(KeyPolicy::MrSigner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SealingPolicy {
+    pub key_policy: KeyPolicy,
+    /// Derive the key from the CPUSVN of the sealing platform instead of the lowest one. This
+    /// stops platforms with older microcode from unsealing, at the price of losing the file if
+    /// the microcode is ever rolled back
+    pub pin_cpu_svn: bool,
+}
+
+/// What `SgxFile` does, and so what every legacy sealed file uses
+impl Default for SealingPolicy {
+    fn default() -> Self {
+        Self {
+            key_policy: KeyPolicy::MrSigner,
+            pin_cpu_svn: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SealedFileHeader {
+    pub version: u16,
+    pub policy: SealingPolicy,
+    pub isv_svn: u16,
+    pub cpu_svn: [u8; 16],
+    key_id: [u8; 32],
+    iv: [u8; IV_SIZE],
+}
+
+impl SealedFileHeader {
+    fn new(policy: SealingPolicy) -> SgxResult<Self> {
+        let report = unsafe { &*sgx_self_report() };
+
+        let mut header = Self {
+            version: SEALED_FILE_VERSION,
+            policy,
+            isv_svn: report.body.isv_svn,
+            cpu_svn: if policy.pin_cpu_svn {
+                report.body.cpu_svn.svn
+            } else {
+                [0u8; 16]
+            },
+            key_id: [0u8; 32],
+            iv: [0u8; IV_SIZE],
+        };
+        rsgx_read_rand(&mut header.key_id)?;
+        rsgx_read_rand(&mut header.iv)?;
+
+        Ok(header)
+    }
+
+    fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let flags = if self.policy.pin_cpu_svn {
+            FLAG_PIN_CPU_SVN
+        } else {
+            0
+        };
+
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..8].copy_from_slice(SEALED_FILE_MAGIC);
+        bytes[8..10].copy_from_slice(&self.version.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.policy.key_policy.to_sgx().to_le_bytes());
+        bytes[12..14].copy_from_slice(&flags.to_le_bytes());
+        bytes[14..16].copy_from_slice(&self.isv_svn.to_le_bytes());
+        bytes[16..32].copy_from_slice(&self.cpu_svn);
+        bytes[32..64].copy_from_slice(&self.key_id);
+        bytes[64..76].copy_from_slice(&self.iv);
+        bytes
+    }
+
+    /// Parses the header at the start of `bytes`. Fails if the header is malformed, or was written
+    /// by a newer version of the format
+    pub fn parse(bytes: &[u8]) -> SgxResult<Self> {
+        if bytes.len() < HEADER_SIZE || !is_versioned(bytes) {
+            warn!("sealed file header is malformed");
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+
+        let read_u16 = |offs: usize| u16::from_le_bytes([bytes[offs], bytes[offs + 1]]);
+
+        let version = read_u16(8);
+        if version != SEALED_FILE_VERSION {
+            warn!("unsupported sealed file version {}", version);
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+
+        let key_policy = match KeyPolicy::from_sgx(read_u16(10)) {
+            Some(key_policy) => key_policy,
+            None => {
+                warn!("unsupported sealing key policy {}", read_u16(10));
+                return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+            }
+        };
+
+        Ok(Self {
+            version,
+            policy: SealingPolicy {
+                key_policy,
+                pin_cpu_svn: read_u16(12) & FLAG_PIN_CPU_SVN != 0,
+            },
+            isv_svn: read_u16(14),
+            cpu_svn: bytes[16..32].try_into().unwrap(),
+            key_id: bytes[32..64].try_into().unwrap(),
+            iv: bytes[64..76].try_into().unwrap(),
+        })
+    }
+
+    fn seal_key(&self) -> SgxResult<sgx_key_128bit_t> {
+        let mut key_request = sgx_key_request_t {
+            key_name: SGX_KEYSELECT_SEAL,
+            key_policy: self.policy.key_policy.to_sgx(),
+            misc_mask: TSEAL_DEFAULT_MISCMASK,
+            isv_svn: self.isv_svn,
+            ..Default::default()
+        };
+
+        key_request.attribute_mask.flags = TSEAL_DEFAULT_FLAGSMASK;
+        key_request.attribute_mask.xfrm = 0x0;
+
+        key_request.cpu_svn.svn = self.cpu_svn;
+        key_request.key_id.id = self.key_id;
+
+        let mut key = sgx_key_128bit_t::default();
+        match unsafe { sgx_get_key(&key_request, &mut key) } {
+            sgx_status_t::SGX_SUCCESS => Ok(key),
+            err_code => {
+                warn!("gen key failed: {}", err_code);
+                Err(err_code)
+            }
+        }
+    }
+}
+
+/// Whether `bytes` starts like a file in the versioned format. Anything else is assumed to be a
+/// legacy protected file system file
+pub fn is_versioned(bytes: &[u8]) -> bool {
+    bytes.starts_with(SEALED_FILE_MAGIC)
+}
+
+/// Reads the header of the sealed file at `filepath`. Returns `None` if there is no such file, or if
+/// it's a legacy file
+pub fn read_header(filepath: &str) -> SgxResult<Option<SealedFileHeader>> {
+    if !fs::try_exists(filepath).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let mut bytes = vec![];
+    File::open(filepath)
+        .and_then(|file| file.take(HEADER_SIZE as u64).read_to_end(&mut bytes))
+        .map_err(|err| {
+            error!("Reading sealed file '{}' failed: {}", filepath, err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })?;
+
+    if !is_versioned(&bytes) {
+        return Ok(None);
+    }
+
+    SealedFileHeader::parse(&bytes).map(Some)
+}
+
+/// Seals `data` into `filepath` in the versioned format, with the given policy.
+///
+/// The file is replaced atomically, so a crash can't leave a secret half written
+pub fn seal_with_policy(data: &[u8], filepath: &str, policy: SealingPolicy) -> SgxResult<()> {
+    let header = SealedFileHeader::new(policy)?;
+    let key = header.seal_key()?;
+    let aad = header.to_bytes();
+
+    let mut sealed = vec![0u8; HEADER_SIZE + MAC_SIZE + data.len()];
+    sealed[..HEADER_SIZE].copy_from_slice(&aad);
+    let (mac, ciphertext) = sealed[HEADER_SIZE..].split_at_mut(MAC_SIZE);
+
+    let mut tag = sgx_aes_gcm_128bit_tag_t::default();
+    let res = unsafe {
+        sgx_rijndael128GCM_encrypt(
+            &key,
+            data.as_ptr(),
+            data.len() as u32,
+            ciphertext.as_mut_ptr(),
+            header.iv.as_ptr(),
+            IV_SIZE as u32,
+            aad.as_ptr(),
+            aad.len() as u32,
+            &mut tag,
+        )
+    };
+    if res != sgx_status_t::SGX_SUCCESS {
+        error!("Encrypting sealed file '{}' failed: {}", filepath, res);
+        return Err(res);
+    }
+    mac.copy_from_slice(&tag);
+
+    // the data must be on disk before the rename, and the rename before we report success,
+    // otherwise a crash could still replace the secret with an empty or missing file
+    let tmp_path = format!("{}.tmp", filepath);
+    File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(&sealed)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, filepath))
+        .and_then(|_| sync_parent_dir(filepath))
+        .map_err(|err| {
+            error!("Writing sealed file '{}' failed: {}", filepath, err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })
+}
+
+/// Flushes the directory entry of `filepath`, so a rename into it survives a crash
+fn sync_parent_dir(filepath: &str) -> std::io::Result<()> {
+    let dir = match Path::new(filepath).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    File::open(dir)?.sync_all()
+}
+
+/// Seals `data` into `filepath`, keeping the policy of the file it replaces.
+///
+/// New files, and files that were never re-sealed, are written in the legacy format so that
+/// enclaves that predate the versioned format can still read them
+pub fn seal(data: &[u8], filepath: &str) -> SgxResult<()> {
+    match read_header(filepath)? {
+        Some(header) => seal_with_policy(data, filepath, header.policy),
+        None => seal_legacy(data, filepath),
+    }
+}
+
+/// Unseals a file in either the versioned or the legacy format
+pub fn unseal(filepath: &str) -> SgxResult<Vec<u8>> {
+    let mut sealed = vec![];
+    File::open(filepath)
+        .and_then(|mut file| file.read_to_end(&mut sealed))
+        .map_err(|err| {
+            error!("Reading sealed file '{}' failed: {}", filepath, err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })?;
+
+    if !is_versioned(&sealed) {
+        return unseal_legacy(filepath);
+    }
+
+    let header = SealedFileHeader::parse(&sealed)?;
+    if sealed.len() < HEADER_SIZE + MAC_SIZE {
+        warn!("sealed file '{}' is truncated", filepath);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+
+    let key = header.seal_key()?;
+    let (aad, rest) = sealed.split_at(HEADER_SIZE);
+    let (mac, ciphertext) = rest.split_at(MAC_SIZE);
+    let mut tag = sgx_aes_gcm_128bit_tag_t::default();
+    tag.copy_from_slice(mac);
+
+    let mut data = vec![0u8; ciphertext.len()];
+    let res = unsafe {
+        sgx_rijndael128GCM_decrypt(
+            &key,
+            ciphertext.as_ptr(),
+            ciphertext.len() as u32,
+            data.as_mut_ptr(),
+            header.iv.as_ptr(),
+            IV_SIZE as u32,
+            aad.as_ptr(),
+            aad.len() as u32,
+            &tag,
+        )
+    };
+    if res != sgx_status_t::SGX_SUCCESS {
+        error!("Decrypting sealed file '{}' failed: {}", filepath, res);
+        return Err(res);
+    }
+
+    Ok(data)
+}
+
+fn seal_legacy(data: &[u8], filepath: &str) -> SgxResult<()> {
+    SgxFile::create(filepath)
+        .and_then(|mut file| file.write_all(data))
+        .map_err(|err| {
+            error!("Writing sealed file '{}' failed: {}", filepath, err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })
+}
+
+fn unseal_legacy(filepath: &str) -> SgxResult<Vec<u8>> {
+    let mut data = vec![];
+    SgxFile::open(filepath)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|err| {
+            error!("Reading sealed file '{}' failed: {}", filepath, err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })?;
+
+    Ok(data)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    const TEST_SEALED_FILE: &str = "sealing_test.sealed";
+
+    pub fn test_header_roundtrip() {
+        let header = SealedFileHeader {
+            version: SEALED_FILE_VERSION,
+            policy: SealingPolicy {
+                key_policy: KeyPolicy::MrEnclave,
+                pin_cpu_svn: false,
+            },
+            isv_svn: 3,
+            cpu_svn: [7u8; 16],
+            key_id: [1u8; 32],
+            iv: [2u8; IV_SIZE],
+        };
+
+        let bytes = header.to_bytes();
+        assert!(is_versioned(&bytes));
+        assert_eq!(SealedFileHeader::parse(&bytes), Ok(header));
+
+        assert!(SealedFileHeader::parse(&bytes[..HEADER_SIZE - 1]).is_err());
+
+        let mut future_version = bytes;
+        future_version[8] = 2;
+        assert!(SealedFileHeader::parse(&future_version).is_err());
+
+        let mut bad_policy = bytes;
+        bad_policy[10] = 0x80;
+        assert!(SealedFileHeader::parse(&bad_policy).is_err());
+    }
+
+    pub fn test_seal_unseal_with_policy() {
+        for key_policy in [KeyPolicy::MrEnclave, KeyPolicy::MrSigner].iter() {
+            for pin_cpu_svn in [false, true].iter() {
+                let policy = SealingPolicy {
+                    key_policy: *key_policy,
+                    pin_cpu_svn: *pin_cpu_svn,
+                };
+
+                seal_with_policy(b"secret", TEST_SEALED_FILE, policy).unwrap();
+                assert_eq!(unseal(TEST_SEALED_FILE).unwrap(), b"secret");
+
+                let header = read_header(TEST_SEALED_FILE).unwrap().unwrap();
+                assert_eq!(header.policy, policy);
+            }
+        }
+
+        let _ = fs::remove_file(TEST_SEALED_FILE);
+    }
+
+    pub fn test_seal_keeps_policy() {
+        let _ = fs::remove_file(TEST_SEALED_FILE);
+
+        seal(b"legacy", TEST_SEALED_FILE).unwrap();
+        assert_eq!(read_header(TEST_SEALED_FILE), Ok(None));
+        assert_eq!(unseal(TEST_SEALED_FILE).unwrap(), b"legacy");
+
+        let policy = SealingPolicy {
+            key_policy: KeyPolicy::MrEnclave,
+            pin_cpu_svn: false,
+        };
+        seal_with_policy(b"versioned", TEST_SEALED_FILE, policy).unwrap();
+        seal(b"updated", TEST_SEALED_FILE).unwrap();
+
+        let header = read_header(TEST_SEALED_FILE).unwrap().unwrap();
+        assert_eq!(header.policy, policy);
+        assert_eq!(unseal(TEST_SEALED_FILE).unwrap(), b"updated");
+
+        let _ = fs::remove_file(TEST_SEALED_FILE);
+    }
+
+    pub fn test_unseal_tampered() {
+        seal_with_policy(b"secret", TEST_SEALED_FILE, SealingPolicy::default()).unwrap();
+
+        let mut sealed = fs::read(TEST_SEALED_FILE).unwrap();
+
+        // the header is authenticated, so the policy can't be changed without the key
+        sealed[12] ^= FLAG_PIN_CPU_SVN as u8;
+        fs::write(TEST_SEALED_FILE, &sealed).unwrap();
+        assert!(unseal(TEST_SEALED_FILE).is_err());
+
+        sealed[12] ^= FLAG_PIN_CPU_SVN as u8;
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        fs::write(TEST_SEALED_FILE, &sealed).unwrap();
+        assert!(unseal(TEST_SEALED_FILE).is_err());
+
+        let _ = fs::remove_file(TEST_SEALED_FILE);
+    }
+}
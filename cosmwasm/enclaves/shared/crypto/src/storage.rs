@@ -1,10 +1,10 @@
 use crate::ed25519::Ed25519PrivateKey;
+#[cfg(not(feature = "host"))]
+use crate::sealing;
 use crate::traits::SealedKey;
 use crate::{AESKey, KeyPair, Seed, SECRET_KEY_SIZE};
 use enclave_ffi_types::EnclaveError;
 use log::*;
-use std::io::{Read, Write};
-use std::sgxfs::SgxFile;
 
 impl SealedKey for AESKey {
     fn seal(&self, filepath: &str) -> Result<(), EnclaveError> {
@@ -40,29 +40,35 @@ impl SealedKey for KeyPair {
     }
 }
 
+#[cfg(not(feature = "host"))]
 fn seal(data: &[u8; 32], filepath: &str) -> Result<(), EnclaveError> {
-    let mut file = SgxFile::create(filepath).map_err(|_err| {
-        error!("error creating file {}: {:?}", filepath, _err);
-        EnclaveError::FailedSeal
-    })?;
-
-    file.write_all(data).map_err(|_err| {
-        error!("error writing to path {}: {:?}", filepath, _err);
+    sealing::seal(data, filepath).map_err(|_err| {
+        error!("error sealing to path {}: {:?}", filepath, _err);
         EnclaveError::FailedSeal
     })
 }
 
+/// There's no sealing key on the host, so secrets only ever live in memory
+#[cfg(feature = "host")]
+fn seal(_data: &[u8; 32], filepath: &str) -> Result<(), EnclaveError> {
+    debug!("not sealing {} on the host", filepath);
+    Err(EnclaveError::FailedSeal)
+}
+
+#[cfg(feature = "host")]
+fn open(filepath: &str) -> Result<Ed25519PrivateKey, EnclaveError> {
+    debug!("not unsealing {} on the host", filepath);
+    Err(EnclaveError::FailedUnseal)
+}
+
+#[cfg(not(feature = "host"))]
 fn open(filepath: &str) -> Result<Ed25519PrivateKey, EnclaveError> {
-    let mut file = SgxFile::open(filepath).map_err(|err| {
+    let data = sealing::unseal(filepath).map_err(|err| {
         error!("failed to unseal file! {:?}", err);
         EnclaveError::FailedUnseal
     })?;
 
-    let mut buf = Ed25519PrivateKey::default();
-    let n = file
-        .read(buf.get_mut())
-        .map_err(|_err| EnclaveError::FailedUnseal)?;
-
+    let n = data.len();
     if n < SECRET_KEY_SIZE {
         error!(
             "[Enclave] Dramatic read from {} ended prematurely (n = {} < SECRET_KEY_SIZE = {})",
@@ -70,6 +76,9 @@ fn open(filepath: &str) -> Result<Ed25519PrivateKey, EnclaveError> {
         );
         return Err(EnclaveError::FailedUnseal);
     }
+
+    let mut buf = Ed25519PrivateKey::default();
+    buf.get_mut().copy_from_slice(&data[..SECRET_KEY_SIZE]);
     Ok(buf)
 }
 
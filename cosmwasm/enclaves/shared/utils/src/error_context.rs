@@ -0,0 +1,126 @@
+//! Safe to publish details of the error an ecall fails with.
+//!
+//! Many failures collapse into the same `EnclaveError`, so the code that detects a failure records
+//! what went wrong with `with_context`, and the ecall sends it to the host along with the error.
+//! Nothing secret may go in here: only the stage and one of the codes in `error_details`, and in
+//! non-production builds the backtrace of wasm traps.
+
+use std::cell::RefCell;
+
+use enclave_ffi_types::{EnclaveError, EnclaveErrorContext, EnclaveErrorStage};
+
+/// What was recorded during one ecall
+#[derive(Default)]
+struct Frame {
+    /// The last error recorded, identified by its `Debug` form, and its context
+    last_error: Option<(String, EnclaveErrorContext)>,
+    /// The wasm backtrace of the last trap, in non-production builds
+    last_backtrace: Option<String>,
+}
+
+thread_local! {
+    /// One frame per ecall in progress on this thread. Ecalls nest when a contract queries
+    /// another one, and the inner ecall must not touch what the outer one recorded.
+    static FRAMES: RefCell<Vec<Frame>> = RefCell::new(Vec::new());
+}
+
+/// Pops the frame of the ecall when it returns
+pub struct EcallFrame {
+    _private: (),
+}
+
+impl Drop for EcallFrame {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+/// Starts recording for a new ecall. Called when an ecall starts, and kept alive until it returns.
+#[must_use]
+pub fn enter() -> EcallFrame {
+    FRAMES.with(|frames| frames.borrow_mut().push(Frame::default()));
+
+    EcallFrame { _private: () }
+}
+
+fn with_frame<T>(f: impl FnOnce(&mut Frame) -> T) -> Option<T> {
+    FRAMES.with(|frames| frames.borrow_mut().last_mut().map(f))
+}
+
+/// Records the stage and detail code of `err`, and returns it
+pub fn with_context(err: EnclaveError, stage: EnclaveErrorStage, detail: u32) -> EnclaveError {
+    record(&err, stage, detail);
+
+    err
+}
+
+/// Records the stage and detail code of `err`, for when the error is returned by someone else
+pub fn record(err: &EnclaveError, stage: EnclaveErrorStage, detail: u32) {
+    let context = EnclaveErrorContext::new(stage, detail);
+    with_frame(|frame| frame.last_error = Some((format!("{:?}", err), context)));
+}
+
+/// Records the wasm backtrace of a trap. Only the names of the functions in the contract, which is
+/// public, may go in here.
+pub fn record_backtrace(backtrace: String) {
+    with_frame(|frame| frame.last_backtrace = Some(backtrace));
+}
+
+/// The wasm backtrace of the trap the ecall fails with, if there was one
+pub fn take_backtrace() -> Option<String> {
+    with_frame(|frame| frame.last_backtrace.take()).flatten()
+}
+
+/// The context of the error an ecall fails with. Errors that were recovered from along the way are
+/// ignored, since they aren't the same error, and errors nobody recorded get the default stage of
+/// the error.
+pub fn take(err: &EnclaveError) -> EnclaveErrorContext {
+    match with_frame(|frame| frame.last_error.take()).flatten() {
+        Some((recorded, context)) if recorded == format!("{:?}", err) => context,
+        _ => EnclaveErrorContext::from(err),
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_nested_ecall_keeps_outer_context() {
+        let _outer = enter();
+        record(
+            &EnclaveError::FailedGasMeteringInjection,
+            EnclaveErrorStage::Validation,
+            7,
+        );
+
+        {
+            let _inner = enter();
+            record(
+                &EnclaveError::FailedGasMeteringInjection,
+                EnclaveErrorStage::Parse,
+                1,
+            );
+            assert_eq!(
+                take(&EnclaveError::FailedGasMeteringInjection),
+                EnclaveErrorContext::new(EnclaveErrorStage::Parse, 1)
+            );
+        }
+
+        assert_eq!(
+            take(&EnclaveError::FailedGasMeteringInjection),
+            EnclaveErrorContext::new(EnclaveErrorStage::Validation, 7)
+        );
+    }
+
+    pub fn test_take_matches_whole_error() {
+        let _frame = enter();
+        record(
+            &EnclaveError::ExceededRecursionLimit { depth: 3, limit: 2 },
+            EnclaveErrorStage::Validation,
+            7,
+        );
+
+        let other = EnclaveError::ExceededRecursionLimit { depth: 4, limit: 2 };
+        assert_eq!(take(&other), EnclaveErrorContext::from(&other));
+    }
+}
@@ -1,21 +1,28 @@
 #![feature(btree_drain_filter)]
+#![cfg_attr(feature = "simulator", feature(alloc_error_hook))]
 
+#[cfg(not(feature = "simulator"))]
 extern crate sgx_trts;
 extern crate sgx_types;
 
 extern crate core;
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(not(target_env = "sgx"), not(feature = "simulator")))]
 extern crate sgx_tstd as std;
 
+pub mod error_context;
 pub mod kv_cache;
 pub mod logger;
 pub mod macros;
+pub mod metrics;
 pub mod oom_handler;
 pub mod pointers;
 pub mod recursion_depth;
 mod results;
+// Sealing needs the SGX protected file system, which the simulator doesn't have
+#[cfg(not(feature = "simulator"))]
 pub mod storage;
 pub mod tx_bytes;
+#[cfg(not(feature = "simulator"))]
 pub mod validator_set;
 
 #[cfg(feature = "random")]
@@ -0,0 +1,252 @@
+//! Counters and histograms of what the enclave is doing, for node operators.
+//!
+//! `ecall_get_enclave_metrics` returns a `MetricsSnapshot` of them as JSON. Nothing in the enclave
+//! ever reads them, so they can't affect the results of contract calls. Durations are measured
+//! with the clock of the host, which the enclave doesn't trust, and so are only as good as the
+//! host makes them.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+#[cfg(not(feature = "simulator"))]
+use std::untrusted::time::InstantEx;
+
+use serde::Serialize;
+
+/// Upper bounds of the buckets of every histogram, in microseconds. Anything slower only counts
+/// towards the total.
+pub const BUCKET_BOUNDS_MICROS: [u64; 8] =
+    [100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 1_000_000];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A histogram of durations, with the buckets of `BUCKET_BOUNDS_MICROS`
+#[derive(Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKET_BOUNDS_MICROS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [ZERO; BUCKET_BOUNDS_MICROS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, micros: u64) {
+        if let Some(bucket) = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|&bound| micros <= bound)
+        {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Starts measuring a duration, which is observed when the returned timer is dropped
+    pub fn start_timer(&'static self) -> Timer {
+        Timer {
+            histogram: self,
+            start: Instant::now(),
+        }
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        // buckets are reported cumulatively, like Prometheus does
+        let mut cumulative = 0;
+        let buckets = BUCKET_BOUNDS_MICROS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(&le_micros, bucket)| {
+                cumulative += bucket.load(Ordering::Relaxed);
+                BucketSnapshot {
+                    le_micros,
+                    count: cumulative,
+                }
+            })
+            .collect();
+
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct Timer {
+    histogram: &'static Histogram,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram
+            .observe(self.start.elapsed().as_micros() as u64);
+    }
+}
+
+/// How many times an ocall was made, and how many bytes crossed the enclave boundary for it, both
+/// ways
+#[derive(Default)]
+pub struct OcallMetrics {
+    calls: Counter,
+    bytes: Counter,
+}
+
+impl OcallMetrics {
+    pub const fn new() -> Self {
+        Self {
+            calls: Counter::new(),
+            bytes: Counter::new(),
+        }
+    }
+
+    pub fn record(&self, bytes: usize) {
+        self.calls.inc();
+        self.bytes.add(bytes as u64);
+    }
+
+    fn snapshot(&self) -> OcallSnapshot {
+        OcallSnapshot {
+            calls: self.calls.get(),
+            bytes: self.bytes.get(),
+        }
+    }
+}
+
+pub static MODULE_CACHE_HITS: Counter = Counter::new();
+pub static MODULE_CACHE_MISSES: Counter = Counter::new();
+pub static MODULE_CACHE_EVICTIONS: Counter = Counter::new();
+/// Time it takes to validate and instrument a module that isn't cached
+pub static INSTRUMENTATION: Histogram = Histogram::new();
+pub static OCALL_READ_DB: OcallMetrics = OcallMetrics::new();
+pub static OCALL_QUERY_CHAIN: OcallMetrics = OcallMetrics::new();
+pub static OCALL_MULTIPLE_WRITE_DB: OcallMetrics = OcallMetrics::new();
+/// Times the OOM handler freed the safety buffer to recover from running out of memory
+pub static OOM_ACTIVATIONS: Counter = Counter::new();
+/// Time it takes to verify the signatures and txs of a block
+pub static BLOCK_VERIFICATION: Histogram = Histogram::new();
+
+#[derive(Serialize, Debug)]
+pub struct MetricsSnapshot {
+    pub module_cache: ModuleCacheSnapshot,
+    pub instrumentation: HistogramSnapshot,
+    /// By the name of the ocall
+    pub ocalls: BTreeMap<&'static str, OcallSnapshot>,
+    pub oom_activations: u64,
+    pub block_verification: HistogramSnapshot,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModuleCacheSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<BucketSnapshot>,
+    pub count: u64,
+    pub sum_micros: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BucketSnapshot {
+    pub le_micros: u64,
+    /// Observations of at most `le_micros`
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OcallSnapshot {
+    pub calls: u64,
+    pub bytes: u64,
+}
+
+/// The current value of every metric
+pub fn snapshot() -> MetricsSnapshot {
+    let mut ocalls = BTreeMap::new();
+    ocalls.insert("ocall_read_db", OCALL_READ_DB.snapshot());
+    ocalls.insert("ocall_query_chain", OCALL_QUERY_CHAIN.snapshot());
+    ocalls.insert(
+        "ocall_multiple_write_db",
+        OCALL_MULTIPLE_WRITE_DB.snapshot(),
+    );
+
+    MetricsSnapshot {
+        module_cache: ModuleCacheSnapshot {
+            hits: MODULE_CACHE_HITS.get(),
+            misses: MODULE_CACHE_MISSES.get(),
+            evictions: MODULE_CACHE_EVICTIONS.get(),
+        },
+        instrumentation: INSTRUMENTATION.snapshot(),
+        ocalls,
+        oom_activations: OOM_ACTIVATIONS.get(),
+        block_verification: BLOCK_VERIFICATION.snapshot(),
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_histogram_snapshot_is_cumulative() {
+        let histogram = Histogram::new();
+        histogram.observe(50);
+        histogram.observe(100);
+        histogram.observe(700);
+        // slower than the last bucket
+        histogram.observe(2_000_000);
+
+        let snapshot = histogram.snapshot();
+        let buckets: Vec<(u64, u64)> = snapshot
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.le_micros, bucket.count))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (100, 2),
+                (500, 2),
+                (1_000, 3),
+                (5_000, 3),
+                (10_000, 3),
+                (50_000, 3),
+                (100_000, 3),
+                (1_000_000, 3),
+            ]
+        );
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum_micros, 2_000_850);
+    }
+}
@@ -2,9 +2,14 @@ use enclave_ffi_types::EnclaveError;
 use lazy_static::lazy_static;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-#[cfg(not(feature = "production"))]
+use crate::metrics;
+
+#[cfg(all(not(feature = "production"), not(feature = "simulator")))]
 use std::backtrace::{self, PrintFormat};
 
+#[cfg(feature = "simulator")]
+use std::sync::Mutex as SgxMutex;
+#[cfg(not(feature = "simulator"))]
 use std::sync::SgxMutex;
 /// SafetyBuffer is meant to occupy space on the heap, so when a memory
 /// allocation fails we will free this buffer to allow safe panic unwinding
@@ -85,16 +90,17 @@ thread_local! {
     static OOM_HAPPENED: AtomicBool = AtomicBool::new(false);
 }
 
-#[cfg(not(feature = "production"))]
+#[cfg(all(not(feature = "production"), not(feature = "simulator")))]
 fn enable_backtraces() {
     let _ = backtrace::enable_backtrace("librust_cosmwasm_enclave.signed.so", PrintFormat::Full);
 }
 
-#[cfg(feature = "production")]
+#[cfg(any(feature = "production", feature = "simulator"))]
 fn enable_backtraces() {}
 
 fn oom_handler(layout: std::alloc::Layout) {
     OOM_HAPPENED.with(|oom_happened| oom_happened.store(true, Ordering::SeqCst));
+    metrics::OOM_ACTIVATIONS.inc();
 
     {
         SAFETY_BUFFER.lock().unwrap().clear();
@@ -1,56 +1,70 @@
-// use std::cell::Cell;
-
-// use enclave_ffi_types::EnclaveError;
-
-const RECURSION_LIMIT: u32 = 10;
-
-// thread_local! {
-//     /// This counter tracks the recursion depth of queries,
-//     /// and effectively the amount of loaded instances of WASMI.
-//     ///
-//     /// It is incremented before each computation begins and is decremented after each computation ends.
-//     static RECURSION_DEPTH: Cell<u8> = Cell::new(0);
-// }
-//
-// fn increment() -> Result<(), EnclaveError> {
-//     RECURSION_DEPTH.with(|depth| {
-//         let d = depth.get();
-//         if d == RECURSION_LIMIT {
-//             return Err(EnclaveError::ExceededRecursionLimit);
-//         }
-//         depth.set(d.saturating_add(1));
-//         Ok(())
-//     })
-// }
-//
-// fn decrement() {
-//     RECURSION_DEPTH.with(|depth| {
-//         depth.set(depth.get().saturating_sub(1));
-//     })
-// }
-
-/// Returns whether or not this is the last possible level of recursion
-pub fn limit_reached(query_depth: u32) -> bool {
-    query_depth >= RECURSION_LIMIT
+//! Call depth accounting.
+//!
+//! Every contract call carries a `call_depth` in its env. The first call of a tx or of a query is at
+//! depth 1, and every submessage, reply and query that a contract call causes is one deeper than
+//! the call that caused it. The limit comes from the engine params of the chain, so it only changes
+//! at the height where the chain changes them. It bounds both the nested instances a chain of
+//! queries keeps alive inside the enclave and the length of execute -> submessage -> reply chains.
+//!
+//! The depth is reported by the host, and isn't part of the env the enclave verifies. A host that
+//! reports a wrong depth can only make its own node run a call that the rest of the network
+//! refuses, or refuse one it runs, so the limit is only as good as the host's report.
+
+use log::*;
+
+use enclave_ffi_types::{error_details, EnclaveError, EnclaveErrorStage};
+
+use crate::error_context::with_context;
+
+/// Returns whether or not this is the last possible level of recursion, i.e. whether a call at
+/// this depth may not make further calls
+pub fn limit_reached(call_depth: u32, limit: u32) -> bool {
+    call_depth >= limit
+}
+
+/// Fails if a call at this depth may not run
+pub fn check_call_depth(call_depth: u32, limit: u32) -> Result<(), EnclaveError> {
+    if call_depth > limit {
+        warn!(
+            "refusing contract call at depth {}, the limit is {}",
+            call_depth, limit
+        );
+        return Err(with_context(
+            EnclaveError::ExceededRecursionLimit {
+                depth: call_depth,
+                limit,
+            },
+            EnclaveErrorStage::Validation,
+            error_details::CALL_DEPTH_EXCEEDED,
+        ));
+    }
+    Ok(())
 }
 
-// pub struct RecursionGuard {
-//     _private: (), // prevent direct instantiation outside this module
-// }
-//
-// impl RecursionGuard {
-//     pub fn new() -> Result<Self, EnclaveError> {
-//         increment()?;
-//         Ok(Self { _private: () })
-//     }
-// }
-//
-// impl Drop for RecursionGuard {
-//     fn drop(&mut self) {
-//         decrement();
-//     }
-// }
-//
-// pub fn guard() -> Result<RecursionGuard, EnclaveError> {
-//     RecursionGuard::new()
-// }
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::error_context;
+
+    pub fn test_check_call_depth_fails_in_validation() {
+        let _frame = error_context::enter();
+
+        let err = check_call_depth(10, 10).and(check_call_depth(11, 10));
+        let err = err.unwrap_err();
+        assert!(matches!(
+            err,
+            EnclaveError::ExceededRecursionLimit {
+                depth: 11,
+                limit: 10
+            }
+        ));
+        assert_eq!(
+            error_context::take(&err),
+            enclave_ffi_types::EnclaveErrorContext::new(
+                EnclaveErrorStage::Validation,
+                error_details::CALL_DEPTH_EXCEEDED
+            )
+        );
+    }
+}
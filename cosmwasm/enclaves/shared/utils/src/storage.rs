@@ -2,8 +2,10 @@ use crate::results::UnwrapOrSgxErrorUnexpected;
 
 use core::mem;
 use core::ptr::null;
+use enclave_crypto::sealing;
 use log::*;
 use log::{error, info};
+use serde::Serialize;
 use std::io::{Read, Write};
 use std::path::Path;
 use std::ptr;
@@ -14,6 +16,8 @@ use sgx_types::*;
 use std::untrusted::fs;
 use std::untrusted::fs::File;
 
+pub use enclave_crypto::sealing::{KeyPolicy, SealingPolicy};
+
 pub const SCRT_SGX_STORAGE_ENV_VAR: &str = "SCRT_SGX_STORAGE";
 pub const DEFAULT_SGX_SECRET_PATH: &str = "/opt/secret/.sgx_secrets/";
 
@@ -24,23 +28,17 @@ pub fn write_to_untrusted(bytes: &[u8], filepath: &str) -> SgxResult<()> {
         .sgx_error_with_log("Writing File failed!")
 }
 
+/// Seals `data` into `filepath`, keeping the sealing policy of the file it replaces
 pub fn seal(data: &[u8], filepath: &str) -> SgxResult<()> {
-    let mut file = SgxFile::create(filepath)
-        .sgx_error_with_log(&format!("Creating sealed file '{}' failed", filepath))?;
+    sealing::seal(data, filepath)
+}
 
-    file.write_all(data)
-        .sgx_error_with_log("Writing sealed file failed!")
+pub fn seal_with_policy(data: &[u8], filepath: &str, policy: SealingPolicy) -> SgxResult<()> {
+    sealing::seal_with_policy(data, filepath, policy)
 }
 
 pub fn unseal(filepath: &str) -> SgxResult<Vec<u8>> {
-    let mut file = SgxFile::open(filepath)
-        .sgx_error_with_log(&format!("Opening sealed file '{}' failed", filepath))?;
-
-    let mut output = vec![];
-    file.read_to_end(&mut output)
-        .sgx_error_with_log(&format!("Reading sealed file '{}' failed", filepath))?;
-
-    Ok(output)
+    sealing::unseal(filepath)
 }
 
 pub fn rewrite_on_untrusted(bytes: &[u8], filepath: &str) -> SgxResult<()> {
@@ -54,6 +52,123 @@ pub fn rewrite_on_untrusted(bytes: &[u8], filepath: &str) -> SgxResult<()> {
     write_to_untrusted(bytes, filepath)
 }
 
+/// How a sealed file is protected, as listed by `ecall_list_sealed_files`
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SealedFileInfo {
+    pub path: String,
+    pub exists: bool,
+    /// 0 for files sealed with the SGX protected file system
+    pub version: Option<u16>,
+    pub key_policy: Option<&'static str>,
+    pub cpu_svn_pinned: Option<bool>,
+    pub isv_svn: Option<u16>,
+    pub error: Option<String>,
+}
+
+pub fn sealed_file_info(filepath: &str) -> SealedFileInfo {
+    let mut info = SealedFileInfo {
+        path: filepath.to_string(),
+        ..Default::default()
+    };
+
+    if !fs::try_exists(filepath).unwrap_or(false) {
+        return info;
+    }
+    info.exists = true;
+
+    let (version, policy, isv_svn) = match sealing::read_header(filepath) {
+        Ok(Some(header)) => (header.version, header.policy, header.isv_svn),
+        // protected files always use the default policy, see `SealingPolicy::default`
+        Ok(None) => match read_legacy_header(filepath) {
+            Ok(isv_svn) => (
+                sealing::LEGACY_SEALED_FILE_VERSION,
+                SealingPolicy::default(),
+                isv_svn,
+            ),
+            Err(e) => {
+                info.error = Some(e.to_string());
+                return info;
+            }
+        },
+        Err(e) => {
+            info.error = Some(e.to_string());
+            return info;
+        }
+    };
+
+    info.version = Some(version);
+    info.key_policy = Some(policy.key_policy.name());
+    info.cpu_svn_pinned = Some(policy.pin_cpu_svn);
+    info.isv_svn = Some(isv_svn);
+    info
+}
+
+/// Returns the ISVSVN from the plain header of a protected file
+fn read_legacy_header(filepath: &str) -> SgxResult<u16> {
+    let mut header = vec![];
+    File::open(filepath)
+        .and_then(|file| {
+            file.take(mem::size_of::<FileMdPlain>() as u64)
+                .read_to_end(&mut header)
+        })
+        .sgx_error_with_log(&format!("Reading sealed file '{}' failed", filepath))?;
+
+    if header.len() < mem::size_of::<FileMdPlain>() {
+        warn!("file too small");
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+
+    let plain = unsafe { ptr::read_unaligned(header.as_ptr() as *const FileMdPlain) };
+    if plain.file_id != SGX_FILE_ID {
+        warn!("{} is not a sealed file", filepath);
+        return Err(sgx_status_t::SGX_ERROR_FILE_BAD_STATUS);
+    }
+
+    Ok(plain.isv_svn)
+}
+
+/// Re-seals the file at `filepath` with `policy`, keeping a backup of the original next to it.
+/// This also rotates the sealing key, even if the policy didn't change
+pub fn reseal_file_safe(s_path: &str, policy: SealingPolicy) -> SgxResult<()> {
+    if !Path::new(s_path).exists() {
+        info!("File {} doesn't exist, skipping", s_path);
+        return Ok(());
+    }
+
+    let data = match unseal(s_path) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("Couldn't unseal file {}, {}", s_path, e);
+            return Err(e);
+        }
+    };
+
+    let s_path_bkp = s_path.to_string() + ".bkp";
+    if let Err(e) = fs::copy(s_path, &s_path_bkp) {
+        error!("Couldn't backup {} into {}, {}", s_path, s_path_bkp, e);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+
+    if let Err(e) = seal_with_policy(data.as_slice(), s_path, policy) {
+        error!("Couldn't RE-seal file {}, {}", s_path, e);
+        return Err(e);
+    }
+
+    // make sure we can still read the secret before the caller gets rid of the backup
+    if unseal(s_path)? != data {
+        error!("File {} changed while RE-sealing", s_path);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+
+    info!(
+        "File {} successfully RE-sealed with {} key policy",
+        s_path,
+        policy.key_policy.name()
+    );
+
+    Ok(())
+}
+
 //////////////
 #[repr(packed)]
 pub struct FileMdPlain {
@@ -71,6 +186,8 @@ pub struct FileMdPlain {
     pub update_flag: u8,
 }
 
+/// "SGX_FILE", the first field of every protected file
+const SGX_FILE_ID: u64 = 0x5347_585F_4649_4C45;
 const FILE_MD_ENCRYPTED_DATA_SIZE: usize = 3072;
 const FILE_MD_ENCRYPTED_FILENAME_SIZE: usize = 260;
 const FILE_NODE_DATA_NODES: usize = 96;
@@ -391,7 +508,7 @@ pub fn migrate_file_from_2_17_safe(
     should_check_fname: bool,
 ) -> Result<(), sgx_status_t> {
     if Path::new(s_path).exists() {
-        if SgxFile::open(s_path).is_ok() {
+        if sealing::read_header(s_path)?.is_some() || SgxFile::open(s_path).is_ok() {
             info!("File {} is already converted", s_path);
         } else {
             let data = match unseal_file_from_2_17(s_path, should_check_fname) {
@@ -0,0 +1,181 @@
+//! A chain in a box, whose contracts run in the simulated engine. See `app_harness` for how the
+//! app dispatches their messages and answers their queries.
+//!
+//! Like the rest of the simulator, `App` takes txs in the format the enclave gets them in: the
+//! message of a tx is encrypted to `io_exchange_pubkey()`, and its `sig_info` is signed by the
+//! sender. The outputs of the contracts are returned as the engine encrypted them, so they can be
+//! decrypted with `secret-client` and the key of the tx.
+
+use app_harness::{CallOutput, ContractCall, Engine};
+use enclave_contract_engine::with_host;
+
+use crate::{EnclaveError, MemoryStorage, SimulatorHost};
+
+pub type App = app_harness::App<SimulatedEngine>;
+pub type AppError = app_harness::AppError<EnclaveError>;
+pub type AppResult<T> = Result<T, AppError>;
+
+pub const SIMULATOR_CHAIN_ID: &str = "secret-simulator-1";
+
+/// Runs the calls of an `App` in the contract engine, with the storage of the called contract
+pub struct SimulatedEngine;
+
+impl SimulatedEngine {
+    /// An `App` on `SIMULATOR_CHAIN_ID`
+    pub fn app() -> App {
+        App::new(SimulatedEngine, SIMULATOR_CHAIN_ID)
+    }
+}
+
+impl Engine for SimulatedEngine {
+    type Storage = MemoryStorage;
+    type Error = EnclaveError;
+
+    fn run(
+        &self,
+        app: &App,
+        code: &[u8],
+        storage: &mut MemoryStorage,
+        gas_limit: u64,
+        gas_used: &mut u64,
+        call: ContractCall,
+    ) -> Result<CallOutput, EnclaveError> {
+        let mut host = ContractHost { app, storage };
+
+        let mut call_gas_used = 0;
+        let result = with_host(&mut host, |context| match call {
+            ContractCall::Init {
+                env,
+                msg,
+                sig_info,
+                admin,
+            } => enclave_contract_engine::init(
+                context,
+                gas_limit,
+                &mut call_gas_used,
+                code,
+                env,
+                msg,
+                sig_info,
+                admin,
+            )
+            .map(|success| CallOutput {
+                output: success.output,
+                contract_key: success.contract_key.to_vec(),
+            }),
+            ContractCall::Handle {
+                env,
+                msg,
+                sig_info,
+                handle_type,
+            } => enclave_contract_engine::handle(
+                context,
+                gas_limit,
+                &mut call_gas_used,
+                code,
+                env,
+                msg,
+                sig_info,
+                handle_type,
+            )
+            .map(|success| CallOutput {
+                output: success.output,
+                contract_key: vec![],
+            }),
+            ContractCall::Query { env, msg } => enclave_contract_engine::query(
+                context,
+                gas_limit,
+                &mut call_gas_used,
+                code,
+                env,
+                msg,
+            )
+            .map(|success| CallOutput {
+                output: success.output,
+                contract_key: vec![],
+            }),
+        });
+        *gas_used += call_gas_used;
+
+        result
+    }
+}
+
+/// Serves the ocalls of a contract call: its storage, and queries answered by the rest of the app
+struct ContractHost<'a> {
+    app: &'a App,
+    storage: &'a mut MemoryStorage,
+}
+
+impl SimulatorHost for ContractHost<'_> {
+    fn read_db(&mut self, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), String> {
+        Ok(self.storage.read(key))
+    }
+
+    fn write_db(&mut self, key: &[u8], value: &[u8]) -> Result<u64, String> {
+        Ok(self.storage.write(key, value))
+    }
+
+    fn remove_db(&mut self, key: &[u8]) -> Result<u64, String> {
+        Ok(self.storage.remove(key))
+    }
+
+    fn query_chain(
+        &mut self,
+        query: &[u8],
+        query_depth: u32,
+        gas_limit: u64,
+    ) -> Result<(Vec<u8>, u64), String> {
+        let mut gas_used = 0;
+        let answer = self
+            .app
+            .query_chain(query, query_depth, gas_limit, &mut gas_used);
+
+        let answer = serde_json::to_vec(&answer).map_err(|err| err.to_string())?;
+        Ok((answer, gas_used))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cw_types_v010::encoding::Binary;
+    use cw_types_v010::std_error::StdResult;
+    use cw_types_v010::system_error::{SystemError, SystemResult};
+    use serde_json::json;
+
+    use super::*;
+    use crate::Coin;
+
+    /// The answers of the app parse as the types the engine expects from `ocall_query_chain`
+    #[test]
+    fn answers_queries_in_the_format_of_the_engine() {
+        let app = SimulatedEngine::app();
+        app.update_balance("secret1alice", vec![Coin::new(100, "uscrt")]);
+        let mut storage = MemoryStorage::new();
+        let mut host = ContractHost {
+            app: &app,
+            storage: &mut storage,
+        };
+
+        let mut query_chain = |query: serde_json::Value| -> SystemResult<StdResult<Binary>> {
+            let (answer, _gas_used) = host
+                .query_chain(query.to_string().as_bytes(), 1, u64::MAX)
+                .unwrap();
+            serde_json::from_slice(&answer).unwrap()
+        };
+
+        let answer =
+            query_chain(json!({ "bank": { "all_balances": { "address": "secret1alice" } } }));
+        let balances: serde_json::Value =
+            serde_json::from_slice(answer.unwrap().unwrap().as_slice()).unwrap();
+        assert_eq!(
+            balances,
+            json!({ "amount": [{ "denom": "uscrt", "amount": "100" }] })
+        );
+
+        let answer = query_chain(
+            json!({ "wasm": { "contract_info": { "contract_addr": "secret1nobody" } } }),
+        );
+        assert!(matches!(answer, Err(SystemError::NoSuchContract { .. })));
+    }
+}
@@ -0,0 +1,54 @@
+//! Prints the cost the gas metering of the enclave gives each function of a contract.
+//!
+//! The cost of a function is what running each of its basic blocks once costs, with the default
+//! `WasmCosts` that calls are metered with once the chain set its engine params. Functions are listed most expensive first. Contracts built with stripped symbols
+//! (like `bench-contract`) have no names for their functions, so only their indices are printed.
+//!
+//! Usage: wasm-gas-report <contract.wasm>
+
+use std::process::exit;
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: wasm-gas-report <contract.wasm>");
+            exit(2);
+        }
+    };
+
+    let code = match std::fs::read(&path) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", path, err);
+            exit(1);
+        }
+    };
+
+    let mut costs = match enclave_simulator::function_costs(&code) {
+        Ok(costs) => costs,
+        Err(err) => {
+            eprintln!("Failed to analyze {}: {}", path, err);
+            exit(1);
+        }
+    };
+    costs.sort_by(|a, b| b.total_cost.cmp(&a.total_cost));
+
+    println!(
+        "{:>6} {:>12} {:>8} {:>10}  name",
+        "index", "cost", "blocks", "max block"
+    );
+    for cost in &costs {
+        println!(
+            "{:>6} {:>12} {:>8} {:>10}  {}",
+            cost.index,
+            cost.total_cost,
+            cost.basic_blocks,
+            cost.max_block_cost,
+            cost.name.as_deref().unwrap_or("")
+        );
+    }
+
+    let total: u64 = costs.iter().map(|cost| cost.total_cost).sum();
+    println!("{} functions, total cost {}", costs.len(), total);
+}
@@ -0,0 +1,98 @@
+//! Runs the contract engine on the host, without SGX.
+//!
+//! This is the engine the enclave runs, built with the `simulator` feature: contracts run in
+//! wasm3, their state is encrypted by `db.rs` and their outputs by `io.rs`, exactly like on chain.
+//! What's missing is the untrusted side of the node, so the storage and queries of a contract are
+//! served by a `SimulatorHost` instead of `x/compute`, and the consensus seed is the well known
+//! `SIMULATOR_CONSENSUS_SEED`. Nothing the simulator encrypts is secret.
+//!
+//! `App` runs a whole chain in the engine: it's the `app_harness::App` whose calls are made by
+//! `SimulatedEngine`, so it dispatches the submessages and replies of its contracts and answers the
+//! queries they make. `init`, `handle` and `query` run a single call with any other host.
+//!
+//! Messages are passed in the same format the enclave gets them in, so they have to be encrypted
+//! to `io_exchange_pubkey()` like a client would.
+
+mod app;
+mod storage;
+
+use enclave_contract_engine::with_host;
+use enclave_crypto::KEY_MANAGER;
+
+pub use app::{App, AppError, AppResult, SimulatedEngine, SIMULATOR_CHAIN_ID};
+pub use app_harness::{AppEvent, AppResponse, Block, Coin};
+pub use enclave_contract_engine::external::results::{HandleSuccess, InitSuccess, QuerySuccess};
+pub use enclave_contract_engine::wasm3::module_cache::function_costs;
+pub use enclave_contract_engine::wasm3::FunctionCost;
+pub use enclave_contract_engine::SimulatorHost;
+pub use enclave_crypto::consts::SIMULATOR_CONSENSUS_SEED;
+pub use enclave_ffi_types::EnclaveError;
+pub use storage::MemoryStorage;
+
+/// The public key clients encrypt their messages to, derived from `SIMULATOR_CONSENSUS_SEED`
+pub fn io_exchange_pubkey() -> Result<[u8; 32], EnclaveError> {
+    let io_key = KEY_MANAGER
+        .get_consensus_io_exchange_keypair()
+        .map_err(|_| EnclaveError::InternalError)?;
+
+    Ok(io_key.current.get_pubkey())
+}
+
+/// Instantiates `contract` with the storage of `host`. See `enclave_contract_engine::init`.
+#[allow(clippy::too_many_arguments)]
+pub fn init(
+    host: &mut dyn SimulatorHost,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8],
+    sig_info: &[u8],
+    admin: &[u8],
+) -> Result<InitSuccess, EnclaveError> {
+    with_host(host, |context| {
+        enclave_contract_engine::init(
+            context, gas_limit, used_gas, contract, env, msg, sig_info, admin,
+        )
+    })
+}
+
+/// Executes `contract` with the storage of `host`. See `enclave_contract_engine::handle`.
+#[allow(clippy::too_many_arguments)]
+pub fn handle(
+    host: &mut dyn SimulatorHost,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8],
+    sig_info: &[u8],
+    handle_type: u8,
+) -> Result<HandleSuccess, EnclaveError> {
+    with_host(host, |context| {
+        enclave_contract_engine::handle(
+            context,
+            gas_limit,
+            used_gas,
+            contract,
+            env,
+            msg,
+            sig_info,
+            handle_type,
+        )
+    })
+}
+
+/// Queries `contract` with the storage of `host`. See `enclave_contract_engine::query`.
+pub fn query(
+    host: &mut dyn SimulatorHost,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8],
+) -> Result<QuerySuccess, EnclaveError> {
+    with_host(host, |context| {
+        enclave_contract_engine::query(context, gas_limit, used_gas, contract, env, msg)
+    })
+}
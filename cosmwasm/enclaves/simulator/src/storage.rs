@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+// Same as the default KVGasConfig of the cosmos-sdk, so contracts use about as much gas as on chain
+const READ_COST_FLAT: u64 = 1000;
+const READ_COST_PER_BYTE: u64 = 3;
+const WRITE_COST_FLAT: u64 = 2000;
+const WRITE_COST_PER_BYTE: u64 = 30;
+const DELETE_COST: u64 = 1000;
+
+/// The storage of a single contract, kept in memory. `App` gives every contract one.
+#[derive(Default, Clone, Debug)]
+pub struct MemoryStorage {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of keys the contract has stored
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the value stored at `key`, and the gas used to read it
+    pub fn read(&self, key: &[u8]) -> (Option<Vec<u8>>, u64) {
+        let value = self.data.get(key).cloned();
+        let read_bytes = key.len() + value.as_ref().map_or(0, Vec::len);
+
+        (
+            value,
+            READ_COST_FLAT + READ_COST_PER_BYTE * read_bytes as u64,
+        )
+    }
+
+    /// Returns the gas used to write `value` to `key`
+    pub fn write(&mut self, key: &[u8], value: &[u8]) -> u64 {
+        self.data.insert(key.to_vec(), value.to_vec());
+
+        WRITE_COST_FLAT + WRITE_COST_PER_BYTE * (key.len() + value.len()) as u64
+    }
+
+    /// Returns the gas used to remove `key`
+    pub fn remove(&mut self, key: &[u8]) -> u64 {
+        self.data.remove(key);
+
+        DELETE_COST
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write_remove() {
+        let mut storage = MemoryStorage::new();
+
+        assert_eq!(storage.read(b"foo"), (None, 1009));
+        assert_eq!(storage.write(b"foo", b"bar"), 2180);
+        assert_eq!(storage.read(b"foo"), (Some(b"bar".to_vec()), 1018));
+        assert_eq!(storage.len(), 1);
+
+        assert_eq!(storage.remove(b"foo"), 1000);
+        assert!(storage.is_empty());
+    }
+}
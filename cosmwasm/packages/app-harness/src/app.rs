@@ -0,0 +1,1364 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::engine::{CallOutput, ContractCall, Engine, HANDLE_TYPE_EXECUTE, HANDLE_TYPE_REPLY};
+use crate::error::{AppError, AppResult};
+use crate::msgs::{
+    BankMsg, ContractResponse, CosmosMsg, DataWithReplyInfo, RawAttribute, RawOutput, RawResponse,
+    ReplyInfo, ReplyOn, SubMsg, WasmMsg,
+};
+use crate::types::{canonical_address, human_address, Binary, Coin};
+
+/// Same as `MaxGas` in `x/compute`
+pub(crate) const DEFAULT_GAS_LIMIT: u64 = 10_000_000_000;
+const BLOCK_TIME_NANOS: u64 = 5_000_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub height: u64,
+    /// Nanoseconds since the UNIX epoch
+    pub time: u64,
+    /// The seed the engine derives `env.block.random` from
+    pub random: [u8; 32],
+}
+
+/// What a tx returns
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AppResponse {
+    /// Encrypted, unless the contract that set it doesn't encrypt its outputs
+    pub data: Option<Vec<u8>>,
+    /// The events of every contract the tx called, in the order they were emitted. Their
+    /// attributes are encrypted unless the contract made them public.
+    pub events: Vec<AppEvent>,
+    pub gas_used: u64,
+}
+
+impl AppResponse {
+    /// The value of the first attribute named `key`, in any event
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.events
+            .iter()
+            .flat_map(|event| event.attributes.iter())
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppEvent {
+    /// `wasm` for the attributes of a contract, `wasm-<type>` for its custom events
+    pub ty: String,
+    pub contract: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+#[derive(Clone)]
+pub(crate) struct ContractState<S> {
+    pub code_id: u64,
+    pub creator: String,
+    pub admin: Option<String>,
+    pub label: String,
+    pub contract_key: Vec<u8>,
+    pub storage: S,
+}
+
+/// Everything a tx can change, so a failed tx or submessage can be reverted
+#[derive(Clone)]
+pub(crate) struct ChainState<S> {
+    pub contracts: BTreeMap<String, ContractState<S>>,
+    pub balances: BTreeMap<String, Vec<Coin>>,
+}
+
+pub(crate) struct AppState<S> {
+    chain_id: String,
+    block: Block,
+    gas_limit: u64,
+    codes: Vec<Vec<u8>>,
+    contract_count: u64,
+    pub chain: ChainState<S>,
+}
+
+impl<S> AppState<S> {
+    fn code<E>(&self, code_id: u64) -> AppResult<&Vec<u8>, E> {
+        code_id
+            .checked_sub(1)
+            .and_then(|index| self.codes.get(index as usize))
+            .ok_or_else(|| AppError::generic_err(format!("Code {} doesn't exist", code_id)))
+    }
+
+    pub fn contract<E>(&self, contract: &str) -> AppResult<&ContractState<S>, E> {
+        self.chain
+            .contracts
+            .get(contract)
+            .ok_or_else(|| AppError::generic_err(format!("Contract {} doesn't exist", contract)))
+    }
+
+    pub fn balance(&self, addr: &str) -> Vec<Coin> {
+        self.chain.balances.get(addr).cloned().unwrap_or_default()
+    }
+
+    /// The env the engine expects, as `types.NewEnv` builds it
+    fn env(
+        &self,
+        sender: &str,
+        sent_funds: &[Coin],
+        contract: &str,
+        contract_key: Option<&[u8]>,
+        call_depth: u32,
+    ) -> Vec<u8> {
+        let contract_key = match contract_key {
+            Some(key) => json!({ "og_contract_key": Binary(key.to_vec()) }),
+            None => json!({}),
+        };
+
+        let env = json!({
+            "block": {
+                "height": self.block.height,
+                "time": self.block.time,
+                "chain_id": self.chain_id,
+                "random": Binary(self.block.random.to_vec()),
+            },
+            "message": {
+                "sender": sender,
+                "sent_funds": sent_funds,
+            },
+            "contract": {
+                "address": contract,
+            },
+            "contract_key": contract_key,
+            "call_depth": call_depth,
+        });
+
+        serde_json::to_vec(&env).expect("the env serializes")
+    }
+
+    fn transfer<E>(&mut self, from: &str, to: &str, amount: &[Coin]) -> AppResult<(), E> {
+        for coin in amount {
+            let from_balance = self.chain.balances.entry(from.to_string()).or_default();
+            let position = from_balance
+                .iter()
+                .position(|c| c.denom == coin.denom && c.amount >= coin.amount)
+                .ok_or_else(|| AppError::generic_err(format!("{} has insufficient funds", from)))?;
+            from_balance[position].amount -= coin.amount;
+
+            let to_balance = self.chain.balances.entry(to.to_string()).or_default();
+            match to_balance.iter_mut().find(|c| c.denom == coin.denom) {
+                Some(c) => c.amount += coin.amount,
+                None => to_balance.push(coin.clone()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What every call of a tx needs to know about the tx
+struct TxContext {
+    /// The nonce and public key of the user's message, which prefix the replies
+    og_msg_prefix: Vec<u8>,
+    /// The `SigInfo` of the tx, as JSON
+    sig_info: Value,
+    gas_used: u64,
+    events: Vec<AppEvent>,
+    /// The call depth of the contract call that's running, like `types.CallDepth` in x/compute
+    call_depth: u32,
+}
+
+impl TxContext {
+    /// `msg` is the encrypted message of the tx, and `sig_info` its `SigInfo` as JSON
+    fn new<E>(msg: &[u8], sig_info: &[u8]) -> AppResult<Self, E> {
+        if msg.len() < 64 {
+            return Err(AppError::generic_err("The message isn't encrypted"));
+        }
+        let sig_info = serde_json::from_slice(sig_info)
+            .map_err(|err| AppError::generic_err(format!("Parsing sig_info: {}", err)))?;
+
+        Ok(TxContext {
+            og_msg_prefix: msg[..64].to_vec(),
+            sig_info,
+            gas_used: 0,
+            events: vec![],
+            call_depth: 1,
+        })
+    }
+
+    fn response(self, data: Option<Vec<u8>>) -> AppResponse {
+        AppResponse {
+            data,
+            events: self.events,
+            gas_used: self.gas_used,
+        }
+    }
+
+    /// Sig info of a message sent by a contract, which is only verified against the callback
+    /// signature the enclave gave the sending contract
+    fn callback_sig_info(&self, callback_sig: Vec<u8>) -> Value {
+        let mut sig_info = unsigned_sig_info();
+        sig_info["tx_bytes"] = self.sig_info["tx_bytes"].clone();
+        sig_info["callback_sig"] = json!(Binary(callback_sig));
+        sig_info
+    }
+}
+
+/// Sig info of a plaintext reply, which the enclave doesn't verify
+fn unsigned_sig_info() -> Value {
+    json!({
+        "tx_bytes": "",
+        "sign_bytes": "",
+        "sign_mode": "SIGN_MODE_UNSPECIFIED",
+        "mode_info": "",
+        "public_key": "",
+        "signature": "",
+        "callback_sig": null,
+    })
+}
+
+/// Why a call failed
+enum CallError<E> {
+    /// The contract returned an error, which its caller can get in a reply
+    Contract {
+        error: AppError<E>,
+        /// The encrypted error, as the caller sees it
+        encrypted_msg: String,
+        reply_info: ReplyInfo,
+    },
+    /// Anything else. The caller can't get these in a reply.
+    Other(AppError<E>),
+}
+
+type CallResult<T, E> = Result<T, CallError<E>>;
+
+impl<E> From<AppError<E>> for CallError<E> {
+    fn from(error: AppError<E>) -> Self {
+        CallError::Other(error)
+    }
+}
+
+impl<E> CallError<E> {
+    fn into_app_error(self) -> AppError<E> {
+        match self {
+            CallError::Contract { error, .. } | CallError::Other(error) => error,
+        }
+    }
+
+    /// Only the contract that was called can fail in a way its caller can reply to. Like in
+    /// `redactError`, the failures of its own messages are just failures.
+    fn into_other(self) -> Self {
+        CallError::Other(self.into_app_error())
+    }
+}
+
+/// Cloning an `App` gives another handle to the same chain, like the one an `Engine` gets to
+/// answer the queries of a contract while it runs.
+pub struct App<E: Engine> {
+    engine: Rc<E>,
+    pub(crate) state: Rc<RefCell<AppState<E::Storage>>>,
+}
+
+impl<E: Engine> Clone for App<E> {
+    fn clone(&self) -> Self {
+        App {
+            engine: Rc::clone(&self.engine),
+            state: Rc::clone(&self.state),
+        }
+    }
+}
+
+impl<E: Engine> App<E> {
+    pub fn new(engine: E, chain_id: &str) -> Self {
+        let state = AppState {
+            chain_id: chain_id.to_string(),
+            block: Block {
+                height: 1,
+                time: 1_571_797_419_879_305_533,
+                random: Sha256::digest(b"block 1").into(),
+            },
+            gas_limit: DEFAULT_GAS_LIMIT,
+            codes: vec![],
+            contract_count: 0,
+            chain: ChainState {
+                contracts: BTreeMap::new(),
+                balances: BTreeMap::new(),
+            },
+        };
+
+        App {
+            engine: Rc::new(engine),
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    pub fn chain_id(&self) -> String {
+        self.state.borrow().chain_id.clone()
+    }
+
+    pub fn block(&self) -> Block {
+        self.state.borrow().block.clone()
+    }
+
+    /// Sets the gas limit of every contract call
+    pub fn set_gas_limit(&self, gas_limit: u64) {
+        self.state.borrow_mut().gas_limit = gas_limit;
+    }
+
+    /// Moves to the next block, with a random seed derived from the previous one
+    pub fn next_block(&self) {
+        let random = {
+            let state = self.state.borrow();
+            let mut hasher = Sha256::new();
+            hasher.update(state.block.random);
+            hasher.update((state.block.height + 1).to_be_bytes());
+            hasher.finalize().into()
+        };
+
+        self.next_block_with_random(random);
+    }
+
+    pub fn next_block_with_random(&self, random: [u8; 32]) {
+        let block = &mut self.state.borrow_mut().block;
+        block.height += 1;
+        block.time += BLOCK_TIME_NANOS;
+        block.random = random;
+    }
+
+    /// Returns the code id of `wasm`
+    pub fn store_code(&self, wasm: &[u8]) -> u64 {
+        let mut state = self.state.borrow_mut();
+        state.codes.push(wasm.to_vec());
+        state.codes.len() as u64
+    }
+
+    pub fn code_hash(&self, code_id: u64) -> Option<String> {
+        let state = self.state.borrow();
+        state
+            .code::<E::Error>(code_id)
+            .ok()
+            .map(|code| hex::encode(Sha256::digest(code)))
+    }
+
+    pub fn contract_code_hash(&self, contract: &str) -> Option<String> {
+        let code_id = self
+            .state
+            .borrow()
+            .contract::<E::Error>(contract)
+            .ok()?
+            .code_id;
+        self.code_hash(code_id)
+    }
+
+    pub fn update_balance(&self, addr: &str, balance: Vec<Coin>) {
+        self.state
+            .borrow_mut()
+            .chain
+            .balances
+            .insert(addr.to_string(), balance);
+    }
+
+    pub fn balance(&self, addr: &str) -> Vec<Coin> {
+        self.state.borrow().balance(addr)
+    }
+
+    /// Runs a `MsgInstantiateContract` of `sender`, whose `msg` is encrypted and whose
+    /// `sig_info` is signed like a client would. Returns the address of the new contract.
+    #[allow(clippy::too_many_arguments)]
+    pub fn instantiate(
+        &self,
+        sender: &str,
+        code_id: u64,
+        msg: &[u8],
+        sig_info: &[u8],
+        sent_funds: &[Coin],
+        label: &str,
+        admin: Option<&str>,
+    ) -> AppResult<(String, AppResponse), E::Error> {
+        let mut tx = TxContext::new(msg, sig_info)?;
+
+        let (contract, data) = self.atomically(|| {
+            self.instantiate_contract(
+                &mut tx,
+                sender,
+                code_id,
+                msg,
+                sent_funds,
+                label,
+                admin.map(str::to_string),
+                sig_info,
+            )
+            .map_err(CallError::into_app_error)
+        })?;
+
+        Ok((contract, tx.response(data)))
+    }
+
+    /// Runs a `MsgExecuteContract` of `sender`, whose `msg` is encrypted and whose `sig_info` is
+    /// signed like a client would
+    pub fn execute(
+        &self,
+        sender: &str,
+        contract: &str,
+        msg: &[u8],
+        sig_info: &[u8],
+        sent_funds: &[Coin],
+    ) -> AppResult<AppResponse, E::Error> {
+        let mut tx = TxContext::new(msg, sig_info)?;
+
+        let data = self.atomically(|| {
+            self.execute_contract(&mut tx, sender, contract, msg, sent_funds, sig_info)
+                .map_err(CallError::into_app_error)
+        })?;
+
+        Ok(tx.response(data))
+    }
+
+    /// Queries `contract` with an encrypted `msg`. Returns the encrypted result, which
+    /// `secret_client::decrypt_data` decrypts.
+    pub fn query(&self, contract: &str, msg: &[u8]) -> AppResult<Vec<u8>, E::Error> {
+        let mut gas_used = 0;
+        match self.query_contract(contract, msg, 1, None, &mut gas_used)? {
+            Ok(result) => Ok(result),
+            Err(err) => Err(AppError::Contract {
+                contract: contract.to_string(),
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    /// Reverts the changes of `f` if it fails
+    fn atomically<T, X, F>(&self, f: F) -> Result<T, X>
+    where
+        F: FnOnce() -> Result<T, X>,
+    {
+        let snapshot = self.state.borrow().chain.clone();
+
+        let result = f();
+        if result.is_err() {
+            self.state.borrow_mut().chain = snapshot;
+        }
+
+        result
+    }
+
+    /// Runs `call` on `code_id` with `storage`. The gas limit is the one of the app, unless
+    /// `gas_limit` is lower.
+    fn run(
+        &self,
+        code_id: u64,
+        storage: &mut E::Storage,
+        gas_limit: Option<u64>,
+        gas_used: &mut u64,
+        call: ContractCall,
+    ) -> AppResult<CallOutput, E::Error> {
+        // the engine calls back into the app for the queries of the contract, so the state
+        // mustn't be borrowed while it runs
+        let (code, gas_limit) = {
+            let state = self.state.borrow();
+            let gas_limit = gas_limit.map_or(state.gas_limit, |limit| limit.min(state.gas_limit));
+            (state.code(code_id)?.clone(), gas_limit)
+        };
+
+        self.engine
+            .run(self, &code, storage, gas_limit, gas_used, call)
+            .map_err(AppError::Engine)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn instantiate_contract(
+        &self,
+        tx: &mut TxContext,
+        sender: &str,
+        code_id: u64,
+        msg: &[u8],
+        sent_funds: &[Coin],
+        label: &str,
+        admin: Option<String>,
+        sig_info: &[u8],
+    ) -> CallResult<(String, Option<Vec<u8>>), E::Error> {
+        let (contract, env) = {
+            let mut state = self.state.borrow_mut();
+            state.code::<E::Error>(code_id)?;
+            if state.chain.contracts.values().any(|c| c.label == label) {
+                return Err(AppError::generic_err(format!("Label {} is taken", label)).into());
+            }
+
+            state.contract_count += 1;
+            let canonical: [u8; 32] = Sha256::digest(
+                [
+                    &code_id.to_be_bytes()[..],
+                    &state.contract_count.to_be_bytes()[..],
+                ]
+                .concat(),
+            )
+            .into();
+            let contract = human_address(&canonical[..20]);
+
+            state.transfer(sender, &contract, sent_funds)?;
+            let env = state.env(sender, sent_funds, &contract, None, tx.call_depth);
+            (contract, env)
+        };
+
+        let admin_canonical = match &admin {
+            Some(admin) => canonical_address(admin)
+                .ok_or_else(|| AppError::generic_err(format!("Invalid address {}", admin)))?,
+            None => vec![],
+        };
+
+        let mut storage = E::Storage::default();
+        let output = self.run(
+            code_id,
+            &mut storage,
+            None,
+            &mut tx.gas_used,
+            ContractCall::Init {
+                env: &env,
+                msg,
+                sig_info,
+                admin: &admin_canonical,
+            },
+        )?;
+
+        let response = self.parse_output(tx, &contract, &output.output)?;
+
+        self.state.borrow_mut().chain.contracts.insert(
+            contract.clone(),
+            ContractState {
+                code_id,
+                creator: sender.to_string(),
+                admin,
+                label: label.to_string(),
+                contract_key: output.contract_key,
+                storage,
+            },
+        );
+
+        let data = self
+            .dispatch_submessages(tx, &contract, response)
+            .map_err(CallError::into_other)?;
+        Ok((contract, data))
+    }
+
+    fn execute_contract(
+        &self,
+        tx: &mut TxContext,
+        sender: &str,
+        contract: &str,
+        msg: &[u8],
+        sent_funds: &[Coin],
+        sig_info: &[u8],
+    ) -> CallResult<Option<Vec<u8>>, E::Error> {
+        self.state
+            .borrow_mut()
+            .transfer(sender, contract, sent_funds)?;
+
+        let output = self.call_handle(
+            tx,
+            sender,
+            contract,
+            sent_funds,
+            msg,
+            sig_info,
+            HANDLE_TYPE_EXECUTE,
+        )?;
+
+        let response = self.parse_output(tx, contract, &output)?;
+        self.dispatch_submessages(tx, contract, response)
+            .map_err(CallError::into_other)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn call_handle(
+        &self,
+        tx: &mut TxContext,
+        sender: &str,
+        contract: &str,
+        sent_funds: &[Coin],
+        msg: &[u8],
+        sig_info: &[u8],
+        handle_type: u8,
+    ) -> AppResult<Vec<u8>, E::Error> {
+        let (code_id, mut storage, env) = {
+            let state = self.state.borrow();
+            let contract_state = state.contract(contract)?;
+            let env = state.env(
+                sender,
+                sent_funds,
+                contract,
+                Some(&contract_state.contract_key),
+                tx.call_depth,
+            );
+            (contract_state.code_id, contract_state.storage.clone(), env)
+        };
+
+        let output = self.run(
+            code_id,
+            &mut storage,
+            None,
+            &mut tx.gas_used,
+            ContractCall::Handle {
+                env: &env,
+                msg,
+                sig_info,
+                handle_type,
+            },
+        )?;
+
+        if let Some(contract_state) = self.state.borrow_mut().chain.contracts.get_mut(contract) {
+            contract_state.storage = storage;
+        }
+
+        Ok(output.output)
+    }
+
+    /// Queries `contract`. Returns the encrypted result, or the error of the contract.
+    pub(crate) fn query_contract(
+        &self,
+        contract: &str,
+        msg: &[u8],
+        query_depth: u32,
+        gas_limit: Option<u64>,
+        gas_used: &mut u64,
+    ) -> AppResult<Result<Vec<u8>, Value>, E::Error> {
+        let (code_id, mut storage, env) = {
+            let state = self.state.borrow();
+            let contract_state = state.contract(contract)?;
+            let env = state.env(
+                "",
+                &[],
+                contract,
+                Some(&contract_state.contract_key),
+                query_depth,
+            );
+            (contract_state.code_id, contract_state.storage.clone(), env)
+        };
+
+        // queries can't change the storage of the contract, so it isn't written back
+        let output = self.run(
+            code_id,
+            &mut storage,
+            gas_limit,
+            gas_used,
+            ContractCall::Query { env: &env, msg },
+        )?;
+
+        let output: Value = serde_json::from_slice(&output.output)
+            .map_err(|err| AppError::generic_err(format!("Parsing query output: {}", err)))?;
+
+        if let Some(ok) = output["query"]["Ok"].as_str() {
+            let ciphertext = Binary::from_base64(ok)
+                .ok_or_else(|| AppError::generic_err("The query output isn't base64"))?;
+            return Ok(Ok(ciphertext.0));
+        }
+
+        match output["query"].get("Err") {
+            Some(err) => Ok(Err(err.clone())),
+            None => Err(AppError::generic_err(format!(
+                "Unknown query output: {}",
+                output
+            ))),
+        }
+    }
+
+    /// Parses a handle or init output. Errors of the contract are returned as `CallError::Contract`.
+    fn parse_output(
+        &self,
+        tx: &mut TxContext,
+        contract: &str,
+        output: &[u8],
+    ) -> CallResult<ContractResponse, E::Error> {
+        let output: RawOutput = serde_json::from_slice(output)
+            .map_err(|err| AppError::generic_err(format!("Parsing contract output: {}", err)))?;
+
+        let is_v010 = output.v010.is_some();
+        let result = output
+            .v010
+            .or(output.v1)
+            .ok_or_else(|| AppError::generic_err("Unknown contract output"))?;
+
+        if let Some(err) = result.err {
+            return Err(CallError::Contract {
+                error: AppError::Contract {
+                    contract: contract.to_string(),
+                    error: err.to_string(),
+                },
+                encrypted_msg: error_msg(&err).unwrap_or_default(),
+                reply_info: output.reply_info,
+            });
+        }
+
+        let response: RawResponse = match result.ok {
+            Some(ok) => serde_json::from_value(ok).map_err(|err| {
+                AppError::generic_err(format!("Parsing contract response: {}", err))
+            })?,
+            None => return Err(AppError::generic_err("Unknown contract output").into()),
+        };
+
+        let attributes = |attributes: &[RawAttribute]| {
+            attributes
+                .iter()
+                .map(|attr| (attr.key.clone(), attr.value.clone()))
+                .collect::<Vec<_>>()
+        };
+        tx.events.push(AppEvent {
+            ty: "wasm".to_string(),
+            contract: contract.to_string(),
+            attributes: attributes(&response.attributes),
+        });
+        for event in response.events.iter() {
+            tx.events.push(AppEvent {
+                ty: format!("wasm-{}", event.ty),
+                contract: contract.to_string(),
+                attributes: attributes(&event.attributes),
+            });
+        }
+
+        let messages: Vec<SubMsg> = if is_v010 {
+            response
+                .messages
+                .into_iter()
+                .map(|msg| SubMsg {
+                    id: 0,
+                    msg,
+                    reply_on: ReplyOn::Never,
+                    was_msg_encrypted: false,
+                })
+                .collect()
+        } else {
+            response
+                .messages
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<Result<_, _>>()
+                .map_err(|err| AppError::generic_err(format!("Parsing submessage: {}", err)))?
+        };
+
+        let reply_info = output.reply_info;
+        let data = if reply_info.internal_reply_enclave_sig.is_some()
+            && reply_info.internal_msg_id.is_some()
+        {
+            Some(to_json(&DataWithReplyInfo {
+                internal_reply_enclave_sig: reply_info.internal_reply_enclave_sig,
+                internal_msg_id: reply_info.internal_msg_id,
+                data: response.data,
+            })?)
+        } else {
+            response.data.map(|data| data.0)
+        };
+
+        Ok(ContractResponse { messages, data })
+    }
+
+    /// Dispatches the messages of `contract` and replies to it, like `DispatchSubmessages`.
+    /// Returns the data of the response, or the data of the last reply that returned any.
+    fn dispatch_submessages(
+        &self,
+        tx: &mut TxContext,
+        contract: &str,
+        response: ContractResponse,
+    ) -> CallResult<Option<Vec<u8>>, E::Error> {
+        let mut data = response.data;
+
+        for msg in response.messages {
+            // the submessage and the reply to it run one level deeper than `contract`
+            tx.call_depth += 1;
+            let reply_data = self.dispatch_submessage(tx, contract, &msg);
+            tx.call_depth -= 1;
+
+            if let Some(reply_data) = reply_data? {
+                data = Some(reply_data);
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Dispatches `msg` and replies to `contract` if it asked for it. Returns the data of the
+    /// reply, if any.
+    fn dispatch_submessage(
+        &self,
+        tx: &mut TxContext,
+        contract: &str,
+        msg: &SubMsg,
+    ) -> CallResult<Option<Vec<u8>>, E::Error> {
+        let events_len = tx.events.len();
+        let result = self.atomically(|| self.dispatch_msg(tx, contract, &msg.msg));
+        if result.is_err() {
+            tx.events.truncate(events_len);
+        }
+
+        // we only reply if requested
+        let result = match result {
+            Err(err) if matches!(msg.reply_on, ReplyOn::Success | ReplyOn::Never) => {
+                return Err(err)
+            }
+            result => result,
+        };
+        if msg.reply_on == ReplyOn::Never || (result.is_ok() && msg.reply_on == ReplyOn::Error) {
+            return Ok(None);
+        }
+
+        self.reply(tx, contract, msg, result)
+    }
+
+    /// Replies to `contract` with the result of `msg`, like `DispatchSubmessages` does. Returns the
+    /// data of the reply.
+    fn reply(
+        &self,
+        tx: &mut TxContext,
+        contract: &str,
+        msg: &SubMsg,
+        result: CallResult<Option<Vec<u8>>, E::Error>,
+    ) -> CallResult<Option<Vec<u8>>, E::Error> {
+        let is_reply_encrypted = msg.was_msg_encrypted && msg.msg.get("wasm").is_some();
+
+        let (result, reply_info) = match result {
+            Ok(sub_data) if is_reply_encrypted => {
+                let sub_data: DataWithReplyInfo = sub_data
+                    .and_then(|sub_data| serde_json::from_slice(&sub_data).ok())
+                    .ok_or_else(|| AppError::generic_err("The submessage has no reply info"))?;
+                let reply_info = ReplyInfo {
+                    internal_reply_enclave_sig: sub_data.internal_reply_enclave_sig,
+                    internal_msg_id: sub_data.internal_msg_id,
+                };
+                (sub_msg_ok(sub_data.data), reply_info)
+            }
+            Ok(sub_data) => (sub_msg_ok(sub_data.map(Binary)), ReplyInfo::default()),
+            Err(CallError::Contract {
+                encrypted_msg,
+                reply_info,
+                ..
+            }) => (json!({ "error": encrypted_msg }), reply_info),
+            Err(CallError::Other(err)) if is_reply_encrypted => {
+                return Err(AppError::generic_err(format!(
+                    "an sdk error occurred while sending a sub-message: {}",
+                    err
+                ))
+                .into());
+            }
+            Err(CallError::Other(err)) => {
+                (json!({ "error": err.to_string() }), ReplyInfo::default())
+            }
+        };
+
+        let (id, sig_info) = if is_reply_encrypted {
+            match (
+                reply_info.internal_msg_id,
+                reply_info.internal_reply_enclave_sig,
+            ) {
+                (Some(msg_id), Some(enclave_sig)) => {
+                    let mut sig_info = tx.sig_info.clone();
+                    sig_info["callback_sig"] = json!(enclave_sig);
+                    (msg_id, sig_info)
+                }
+                _ => {
+                    return Err(AppError::generic_err(
+                        "The output of an encrypted submessage has no reply info",
+                    )
+                    .into())
+                }
+            }
+        } else {
+            (Binary(msg.id.to_string().into_bytes()), unsigned_sig_info())
+        };
+
+        let reply = json!({
+            "id": id,
+            "result": result,
+            "was_orig_msg_encrypted": msg.was_msg_encrypted,
+            "is_encrypted": is_reply_encrypted,
+        });
+        let mut reply_msg = tx.og_msg_prefix.clone();
+        reply_msg.extend_from_slice(&to_json(&reply)?);
+
+        // errors of the reply itself fail the whole tx, so they're never replied with
+        let sig_info = to_json(&sig_info)?;
+        let output = self.call_handle(
+            tx,
+            contract,
+            contract,
+            &[],
+            &reply_msg,
+            &sig_info,
+            HANDLE_TYPE_REPLY,
+        )?;
+        let response = self
+            .parse_output(tx, contract, &output)
+            .map_err(CallError::into_other)?;
+
+        self.dispatch_submessages(tx, contract, response)
+            .map_err(CallError::into_other)
+    }
+
+    /// Dispatches a message sent by `sender`. Returns the data of the response.
+    fn dispatch_msg(
+        &self,
+        tx: &mut TxContext,
+        sender: &str,
+        msg: &Value,
+    ) -> CallResult<Option<Vec<u8>>, E::Error> {
+        let msg: CosmosMsg = serde_json::from_value(msg.clone())
+            .map_err(|_| AppError::generic_err(format!("Unsupported message: {}", msg)))?;
+
+        match msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                self.state
+                    .borrow_mut()
+                    .transfer(sender, &to_address, &amount)?;
+                Ok(None)
+            }
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                send,
+                callback_sig,
+            }) => {
+                let sig_info = match callback_sig {
+                    Some(callback_sig) => tx.callback_sig_info(callback_sig),
+                    None => tx.sig_info.clone(),
+                };
+                let sig_info = to_json(&sig_info)?;
+                self.execute_contract(tx, sender, &contract_addr, &msg.0, &send, &sig_info)
+            }
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                admin,
+                code_id,
+                msg,
+                send,
+                label,
+                callback_sig,
+            }) => {
+                let sig_info = match callback_sig {
+                    Some(callback_sig) => tx.callback_sig_info(callback_sig),
+                    None => tx.sig_info.clone(),
+                };
+                let sig_info = to_json(&sig_info)?;
+                let (contract, data) = self.instantiate_contract(
+                    tx, sender, code_id, &msg.0, &send, &label, admin, &sig_info,
+                )?;
+
+                Ok(data.or_else(|| canonical_address(&contract)))
+            }
+        }
+    }
+}
+
+/// A successful `SubMsgResult`. Events are redacted by the enclave anyway.
+fn sub_msg_ok(data: Option<Binary>) -> Value {
+    match data {
+        Some(data) => json!({ "ok": { "events": [], "data": data } }),
+        None => json!({ "ok": { "events": [] } }),
+    }
+}
+
+/// The encrypted message of a `generic_err`
+pub(crate) fn error_msg(err: &Value) -> Option<String> {
+    err["generic_err"]["msg"].as_str().map(str::to_string)
+}
+
+fn to_json<T: Serialize, E>(value: &T) -> AppResult<Vec<u8>, E> {
+    serde_json::to_vec(value).map_err(|err| AppError::generic_err(format!("Serializing: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::querier::{QueryAnswer, SystemError};
+
+    /// A call the fake engine got
+    #[derive(Debug, PartialEq)]
+    struct Call {
+        contract: String,
+        kind: &'static str,
+        call_depth: u64,
+    }
+
+    type FakeStorage = BTreeMap<Vec<u8>, Vec<u8>>;
+    type FakeRun =
+        dyn Fn(&App<FakeEngine>, &[u8], &ContractCall, &mut FakeStorage) -> Result<Vec<u8>, String>;
+
+    /// Stands in for the enclave, which the tests can't run. Every call uses 1 gas.
+    struct FakeEngine(Box<FakeRun>);
+
+    impl Engine for FakeEngine {
+        type Storage = FakeStorage;
+        type Error = String;
+
+        fn run(
+            &self,
+            app: &App<Self>,
+            code: &[u8],
+            storage: &mut FakeStorage,
+            _gas_limit: u64,
+            gas_used: &mut u64,
+            call: ContractCall,
+        ) -> Result<CallOutput, String> {
+            *gas_used += 1;
+            let output = (self.0)(app, code, &call, storage)?;
+
+            Ok(CallOutput {
+                output,
+                contract_key: vec![1u8; 64],
+            })
+        }
+    }
+
+    const CALLER: u64 = 1;
+    const CALLEE: u64 = 2;
+    const FAILING_CALLEE: u64 = 3;
+    const QUERIER: u64 = 4;
+
+    const ALICE: &str = "secret1alice";
+
+    fn ok_output(response: Value) -> Vec<u8> {
+        json!({ "v1": { "Ok": response } }).to_string().into_bytes()
+    }
+
+    fn attributes(action: &str) -> Value {
+        json!([{ "key": "action", "value": action }])
+    }
+
+    /// A message as the enclave gets it: a nonce and public key, then the ciphertext
+    fn secret_msg() -> Vec<u8> {
+        [vec![7u8; 64], b"{}".to_vec()].concat()
+    }
+
+    fn sig_info() -> Vec<u8> {
+        json!({ "tx_bytes": Binary(b"tx".to_vec()) })
+            .to_string()
+            .into_bytes()
+    }
+
+    /// The caller sends `{}` to the callee on every execute, with `reply_on`, and returns the
+    /// data it got in the reply. The callee writes to its storage, then returns data or fails.
+    /// The querier queries the callee while it runs, and returns the answer as its data.
+    struct Fixture {
+        app: App<FakeEngine>,
+        calls: Rc<RefCell<Vec<Call>>>,
+        replies: Rc<RefCell<Vec<Vec<u8>>>>,
+        caller: String,
+        callee: String,
+        querier: String,
+    }
+
+    fn fixture(callee_code_id: u64, reply_on: &'static str) -> Fixture {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let replies = Rc::new(RefCell::new(vec![]));
+        let callee = Rc::new(RefCell::new(String::new()));
+
+        let fake_calls = Rc::clone(&calls);
+        let fake_replies = Rc::clone(&replies);
+        let fake_callee = Rc::clone(&callee);
+        let fake_run = move |app: &App<FakeEngine>,
+                             code: &[u8],
+                             call: &ContractCall,
+                             storage: &mut FakeStorage|
+              -> Result<Vec<u8>, String> {
+            let (env, kind) = match call {
+                ContractCall::Init { env, .. } => (env, "init"),
+                ContractCall::Handle {
+                    env,
+                    msg,
+                    handle_type,
+                    ..
+                } if *handle_type == HANDLE_TYPE_REPLY => {
+                    fake_replies.borrow_mut().push(msg.to_vec());
+                    (env, "reply")
+                }
+                ContractCall::Handle { env, .. } => (env, "execute"),
+                ContractCall::Query { env, .. } => (env, "query"),
+            };
+            let env: Value = serde_json::from_slice(env).unwrap();
+            fake_calls.borrow_mut().push(Call {
+                contract: env["contract"]["address"].as_str().unwrap().to_string(),
+                kind,
+                call_depth: env["call_depth"].as_u64().unwrap(),
+            });
+
+            let output = match (code[0] as u64, kind) {
+                (_, "init") => ok_output(json!({ "messages": [], "attributes": [] })),
+                (_, "query") => json!({ "query": { "Ok": Binary(b"answer".to_vec()) } })
+                    .to_string()
+                    .into_bytes(),
+                (CALLER, "execute") => {
+                    storage.insert(b"executed".to_vec(), b"yes".to_vec());
+                    ok_output(json!({
+                        "messages": [{
+                            "id": 7,
+                            "msg": { "wasm": { "execute": {
+                                "contract_addr": *fake_callee.borrow(),
+                                "msg": Binary(secret_msg()),
+                            } } },
+                            "reply_on": reply_on,
+                        }],
+                        "attributes": attributes("call"),
+                    }))
+                }
+                (CALLER, "reply") => ok_output(json!({
+                    "messages": [],
+                    "attributes": attributes("reply"),
+                    "data": Binary(b"reply data".to_vec()),
+                })),
+                (CALLEE, "execute") => {
+                    storage.insert(b"called".to_vec(), b"yes".to_vec());
+                    ok_output(json!({
+                        "messages": [],
+                        "attributes": attributes("called"),
+                        "data": Binary(b"callee data".to_vec()),
+                    }))
+                }
+                (FAILING_CALLEE, "execute") => {
+                    storage.insert(b"called".to_vec(), b"yes".to_vec());
+                    json!({ "v1": { "Err": { "generic_err": { "msg": "boom" } } } })
+                        .to_string()
+                        .into_bytes()
+                }
+                (QUERIER, "execute") => {
+                    let query = json!({ "wasm": { "smart": {
+                        "contract_addr": *fake_callee.borrow(),
+                        "callback_code_hash": "",
+                        "msg": Binary(secret_msg()),
+                    } } });
+                    let mut gas_used = 0;
+                    let answer = app.query_chain(
+                        query.to_string().as_bytes(),
+                        2,
+                        DEFAULT_GAS_LIMIT,
+                        &mut gas_used,
+                    );
+                    assert_eq!(gas_used, 1);
+                    ok_output(json!({
+                        "messages": [],
+                        "attributes": attributes("queried"),
+                        "data": answer.unwrap().unwrap(),
+                    }))
+                }
+                call => panic!("unexpected call {:?}", call),
+            };
+
+            Ok(output)
+        };
+        let app = App::new(FakeEngine(Box::new(fake_run)), "secret-testnet-1");
+
+        // the fake engine tells the codes apart by their first byte, which is their code id
+        for code_id in [CALLER, CALLEE, FAILING_CALLEE, QUERIER] {
+            app.store_code(&[code_id as u8]);
+        }
+
+        let instantiate = |code_id: u64, label: &str| {
+            app.instantiate(ALICE, code_id, &secret_msg(), &sig_info(), &[], label, None)
+                .unwrap()
+                .0
+        };
+        let caller = instantiate(CALLER, "caller");
+        let callee_addr = instantiate(callee_code_id, "callee");
+        let querier = instantiate(QUERIER, "querier");
+        *callee.borrow_mut() = callee_addr.clone();
+        calls.borrow_mut().clear();
+
+        Fixture {
+            app,
+            calls,
+            replies,
+            caller,
+            callee: callee_addr,
+            querier,
+        }
+    }
+
+    impl Fixture {
+        fn execute(&self, contract: &str) -> AppResult<AppResponse, String> {
+            self.app
+                .execute(ALICE, contract, &secret_msg(), &sig_info(), &[])
+        }
+
+        fn stored(&self, contract: &str, key: &[u8]) -> Option<Vec<u8>> {
+            let state = self.app.state.borrow();
+            state.chain.contracts[contract].storage.get(key).cloned()
+        }
+
+        /// Makes a query the way a contract would
+        fn query_chain(&self, query: Value) -> QueryAnswer {
+            let mut gas_used = 0;
+            self.app.query_chain(
+                query.to_string().as_bytes(),
+                3,
+                DEFAULT_GAS_LIMIT,
+                &mut gas_used,
+            )
+        }
+    }
+
+    fn call(contract: &str, kind: &'static str, call_depth: u64) -> Call {
+        Call {
+            contract: contract.to_string(),
+            kind,
+            call_depth,
+        }
+    }
+
+    #[test]
+    fn dispatches_submessages_and_replies() {
+        let f = fixture(CALLEE, "always");
+
+        let response = f.execute(&f.caller).unwrap();
+
+        // the submessage and the reply to it run one level deeper than the caller
+        assert_eq!(
+            *f.calls.borrow(),
+            vec![
+                call(&f.caller, "execute", 1),
+                call(&f.callee, "execute", 2),
+                call(&f.caller, "reply", 2),
+            ]
+        );
+        assert_eq!(response.gas_used, 3);
+
+        // the reply is prefixed with the nonce and public key of the user's message
+        let replies = f.replies.borrow();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0][..64], secret_msg()[..64]);
+        let reply: Value = serde_json::from_slice(&replies[0][64..]).unwrap();
+        assert_eq!(
+            reply,
+            json!({
+                "id": Binary(b"7".to_vec()),
+                "result": { "ok": { "events": [], "data": Binary(b"callee data".to_vec()) } },
+                "was_orig_msg_encrypted": false,
+                "is_encrypted": false,
+            })
+        );
+
+        // the data of the reply replaces the data of the caller
+        assert_eq!(response.data, Some(b"reply data".to_vec()));
+        let actions: Vec<(&str, &str)> = response
+            .events
+            .iter()
+            .map(|event| (event.contract.as_str(), event.attributes[0].1.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (f.caller.as_str(), "call"),
+                (f.callee.as_str(), "called"),
+                (f.caller.as_str(), "reply")
+            ]
+        );
+
+        assert_eq!(f.stored(&f.caller, b"executed"), Some(b"yes".to_vec()));
+        assert_eq!(f.stored(&f.callee, b"called"), Some(b"yes".to_vec()));
+    }
+
+    #[test]
+    fn reverts_failed_submessages_and_replies_with_the_error() {
+        let f = fixture(FAILING_CALLEE, "error");
+
+        let response = f.execute(&f.caller).unwrap();
+        assert_eq!(response.data, Some(b"reply data".to_vec()));
+
+        let replies = f.replies.borrow();
+        let reply: Value = serde_json::from_slice(&replies[0][64..]).unwrap();
+        assert_eq!(reply["result"], json!({ "error": "boom" }));
+
+        // only the changes of the submessage are reverted
+        assert_eq!(f.stored(&f.caller, b"executed"), Some(b"yes".to_vec()));
+        assert_eq!(f.stored(&f.callee, b"called"), None);
+        assert!(response
+            .events
+            .iter()
+            .all(|event| event.contract != f.callee));
+    }
+
+    #[test]
+    fn failed_submessages_fail_the_tx_without_a_reply() {
+        let f = fixture(FAILING_CALLEE, "success");
+
+        match f.execute(&f.caller).unwrap_err() {
+            AppError::Contract { contract, error } => {
+                assert_eq!(contract, f.callee);
+                assert!(error.contains("boom"));
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        assert!(f.replies.borrow().is_empty());
+        assert_eq!(f.stored(&f.caller, b"executed"), None);
+        assert_eq!(f.stored(&f.callee, b"called"), None);
+    }
+
+    #[test]
+    fn contracts_query_each_other_while_they_run() {
+        let f = fixture(CALLEE, "never");
+
+        let response = f.execute(&f.querier).unwrap();
+
+        assert_eq!(
+            *f.calls.borrow(),
+            vec![call(&f.querier, "execute", 1), call(&f.callee, "query", 2)]
+        );
+        assert_eq!(response.data, Some(b"answer".to_vec()));
+        assert_eq!(response.attribute("action"), Some("queried"));
+    }
+
+    #[test]
+    fn answers_the_queries_of_contracts() {
+        let f = fixture(CALLEE, "never");
+
+        let answer = f.query_chain(json!({ "wasm": { "smart": {
+            "contract_addr": f.callee,
+            "callback_code_hash": "",
+            "msg": Binary(secret_msg()),
+        } } }));
+        assert_eq!(answer, Ok(Ok(Binary(b"answer".to_vec()))));
+        assert_eq!(*f.calls.borrow(), vec![call(&f.callee, "query", 3)]);
+
+        let answer =
+            f.query_chain(json!({ "wasm": { "contract_info": { "contract_addr": f.callee } } }));
+        let info: Value = serde_json::from_slice(&answer.unwrap().unwrap().0).unwrap();
+        assert_eq!(info["code_id"], json!(CALLEE));
+        assert_eq!(info["label"], json!("callee"));
+
+        let answer = f.query_chain(json!({ "wasm": { "smart": {
+            "contract_addr": "secret1nobody",
+            "callback_code_hash": "",
+            "msg": Binary(secret_msg()),
+        } } }));
+        assert!(matches!(answer, Err(SystemError::NoSuchContract { .. })));
+    }
+
+    #[test]
+    fn answers_bank_queries() {
+        let f = fixture(CALLEE, "never");
+        f.app.update_balance(ALICE, vec![Coin::new(100, "uscrt")]);
+
+        let answer =
+            f.query_chain(json!({ "bank": { "balance": { "address": ALICE, "denom": "uscrt" } } }));
+        let balance: Value = serde_json::from_slice(&answer.unwrap().unwrap().0).unwrap();
+        assert_eq!(
+            balance,
+            json!({ "amount": { "denom": "uscrt", "amount": "100" } })
+        );
+
+        let answer =
+            f.query_chain(json!({ "bank": { "all_balances": { "address": "secret1nobody" } } }));
+        let balances: Value = serde_json::from_slice(&answer.unwrap().unwrap().0).unwrap();
+        assert_eq!(balances, json!({ "amount": [] }));
+
+        let answer = f.query_chain(json!({ "staking": { "bonded_denom": {} } }));
+        assert!(matches!(
+            answer,
+            Err(SystemError::UnsupportedRequest { .. })
+        ));
+
+        // the answers serialize like `SystemResult<StdResult<Binary>>`
+        let answer = f.query_chain(
+            json!({ "wasm": { "contract_info": { "contract_addr": "secret1nobody" } } }),
+        );
+        assert_eq!(
+            serde_json::to_value(&answer).unwrap(),
+            json!({ "Err": { "no_such_contract": { "addr": "secret1nobody" } } })
+        );
+    }
+}
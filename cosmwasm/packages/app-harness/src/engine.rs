@@ -0,0 +1,52 @@
+use std::fmt;
+
+use crate::app::App;
+
+pub const HANDLE_TYPE_EXECUTE: u8 = 0;
+pub const HANDLE_TYPE_REPLY: u8 = 1;
+
+/// A call of a contract, with the arguments the enclave takes
+pub enum ContractCall<'a> {
+    Init {
+        env: &'a [u8],
+        msg: &'a [u8],
+        sig_info: &'a [u8],
+        admin: &'a [u8],
+    },
+    Handle {
+        env: &'a [u8],
+        msg: &'a [u8],
+        sig_info: &'a [u8],
+        handle_type: u8,
+    },
+    Query {
+        env: &'a [u8],
+        msg: &'a [u8],
+    },
+}
+
+/// What the engine returned for a call
+pub struct CallOutput {
+    pub output: Vec<u8>,
+    /// The key of the new contract. Only set by init.
+    pub contract_key: Vec<u8>,
+}
+
+/// Runs the calls an `App` makes
+pub trait Engine: Sized + 'static {
+    /// The storage of a single contract
+    type Storage: Clone + Default + 'static;
+    type Error: fmt::Debug + fmt::Display;
+
+    /// Runs `call` on `code` with `storage`, and adds the gas it used to `gas_used` whether it
+    /// succeeds or not. The queries the contract makes are answered by `app.query_chain`.
+    fn run(
+        &self,
+        app: &App<Self>,
+        code: &[u8],
+        storage: &mut Self::Storage,
+        gas_limit: u64,
+        gas_used: &mut u64,
+        call: ContractCall,
+    ) -> Result<CallOutput, Self::Error>;
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+pub type AppResult<T, E> = Result<T, AppError<E>>;
+
+/// `E` is the error of the `Engine`
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AppError<E> {
+    /// The contract returned an error. `error` is the `StdError` it returned, as JSON. The message
+    /// of a `generic_err` is still encrypted.
+    Contract { contract: String, error: String },
+    /// The engine failed the call
+    Engine(E),
+    /// Whenever the app itself rejects the tx, like the chain would
+    Generic(String),
+}
+
+impl<E> AppError<E> {
+    pub(crate) fn generic_err<S: Into<String>>(msg: S) -> Self {
+        AppError::Generic(msg.into())
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for AppError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Contract { contract, error } => {
+                write!(f, "Contract {} failed: {}", contract, error)
+            }
+            AppError::Engine(err) => write!(f, "Error in the engine: {}", err),
+            AppError::Generic(msg) => write!(f, "Generic error: {}", msg),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for AppError<E> {}
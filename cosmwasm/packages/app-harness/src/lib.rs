@@ -0,0 +1,28 @@
+//! A chain in a box, for testing contracts that call and query each other.
+//!
+//! `App` keeps the code, storage and balances of many contracts, and runs every call the way
+//! `x/compute` does: submessages and replies are dispatched like `DispatchSubmessages` does (each
+//! in its own snapshot), and the queries contracts make with `query_chain` are answered by the
+//! other contracts and the bank.
+//!
+//! The calls themselves are made by an `Engine`: `enclave-simulator` runs them in the contract
+//! engine built for the host, and `sgx-vm-app` runs them in the enclave. Either way `App` takes txs
+//! in the format the enclave gets them in: the message of a tx is encrypted to the IO exchange
+//! pubkey, and its `sig_info` is signed by the sender. The outputs of the contracts are returned
+//! as the enclave encrypted them, so they can be decrypted with `secret-client` and the key of the
+//! tx.
+//!
+//! Gas is metered per call, with the limit of `set_gas_limit`, and summed up for the whole tx.
+
+mod app;
+mod engine;
+mod error;
+mod msgs;
+mod querier;
+mod types;
+
+pub use app::{App, AppEvent, AppResponse, Block};
+pub use engine::{CallOutput, ContractCall, Engine, HANDLE_TYPE_EXECUTE, HANDLE_TYPE_REPLY};
+pub use error::{AppError, AppResult};
+pub use querier::{QueryAnswer, StdError, SystemError};
+pub use types::{canonical_address, human_address, Binary, Coin, BECH32_PREFIX};
@@ -0,0 +1,124 @@
+//! The outputs of contracts and the messages they send, as far as the app parses them
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::types::{Binary, Coin};
+
+/// Set by the enclave when the output of a call is addressed to an encrypted reply
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct ReplyInfo {
+    pub internal_reply_enclave_sig: Option<Binary>,
+    pub internal_msg_id: Option<Binary>,
+}
+
+/// `DataWithInternalReplyInfo` of `go-cosmwasm`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DataWithReplyInfo {
+    pub internal_reply_enclave_sig: Option<Binary>,
+    pub internal_msg_id: Option<Binary>,
+    pub data: Option<Binary>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawOutput {
+    pub v010: Option<RawResult>,
+    pub v1: Option<RawResult>,
+    #[serde(flatten)]
+    pub reply_info: ReplyInfo,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawResult {
+    #[serde(rename = "Ok")]
+    pub ok: Option<Value>,
+    #[serde(rename = "Err")]
+    pub err: Option<Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct RawResponse {
+    #[serde(default)]
+    pub messages: Vec<Value>,
+    /// `log` in v0.10, `attributes` in v1
+    #[serde(default, alias = "log")]
+    pub attributes: Vec<RawAttribute>,
+    #[serde(default)]
+    pub events: Vec<RawEvent>,
+    pub data: Option<Binary>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawAttribute {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawEvent {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub attributes: Vec<RawAttribute>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SubMsg {
+    pub id: u64,
+    pub msg: Value,
+    pub reply_on: ReplyOn,
+    #[serde(default)]
+    pub was_msg_encrypted: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReplyOn {
+    Always,
+    Error,
+    Success,
+    Never,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CosmosMsg {
+    Bank(BankMsg),
+    Wasm(WasmMsg),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BankMsg {
+    Send {
+        to_address: String,
+        amount: Vec<Coin>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WasmMsg {
+    Execute {
+        contract_addr: String,
+        msg: Binary,
+        #[serde(default)]
+        send: Vec<Coin>,
+        callback_sig: Option<Vec<u8>>,
+    },
+    Instantiate {
+        admin: Option<String>,
+        code_id: u64,
+        msg: Binary,
+        #[serde(default)]
+        send: Vec<Coin>,
+        label: String,
+        callback_sig: Option<Vec<u8>>,
+    },
+}
+
+/// A contract output, parsed like `go-cosmwasm` does
+pub(crate) struct ContractResponse {
+    pub messages: Vec<SubMsg>,
+    /// Encrypted. Wrapped in a `DataWithReplyInfo` if the output is addressed to a reply.
+    pub data: Option<Vec<u8>>,
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::app::{error_msg, App};
+use crate::engine::Engine;
+use crate::types::{Binary, Coin};
+
+/// `cosmwasm_std::SystemError`, as far as the app returns it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemError {
+    InvalidRequest { error: String, request: Binary },
+    NoSuchContract { addr: String },
+    UnsupportedRequest { kind: String },
+}
+
+/// `cosmwasm_std::StdError`, as far as the app returns it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StdError {
+    GenericErr { msg: String },
+}
+
+/// The answer to a query of a contract. Serializes like `SystemResult<StdResult<Binary>>`, which
+/// is what the enclave expects from `ocall_query_chain`.
+pub type QueryAnswer = Result<Result<Binary, StdError>, SystemError>;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum QueryRequest {
+    Bank(BankQuery),
+    Wasm(WasmQuery),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BankQuery {
+    Balance { address: String, denom: String },
+    AllBalances { address: String },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WasmQuery {
+    Smart { contract_addr: String, msg: Binary },
+    ContractInfo { contract_addr: String },
+}
+
+impl<E: Engine> App<E> {
+    /// Answers a query a contract made, like the query plugins of `x/compute`. The gas used by the
+    /// contracts it queries is added to `gas_used`.
+    pub fn query_chain(
+        &self,
+        request: &[u8],
+        query_depth: u32,
+        gas_limit: u64,
+        gas_used: &mut u64,
+    ) -> QueryAnswer {
+        let invalid_request = |error: String| SystemError::InvalidRequest {
+            error,
+            request: Binary(request.to_vec()),
+        };
+        let request: Value =
+            serde_json::from_slice(request).map_err(|err| invalid_request(err.to_string()))?;
+        let request: QueryRequest = match serde_json::from_value(request.clone()) {
+            Ok(request) => request,
+            Err(_) => {
+                return Err(SystemError::UnsupportedRequest {
+                    kind: request.to_string(),
+                })
+            }
+        };
+
+        let response = match request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+                if self
+                    .state
+                    .borrow()
+                    .contract::<E::Error>(&contract_addr)
+                    .is_err()
+                {
+                    return Err(SystemError::NoSuchContract {
+                        addr: contract_addr,
+                    });
+                }
+
+                return match self.query_contract(
+                    &contract_addr,
+                    &msg.0,
+                    query_depth,
+                    Some(gas_limit),
+                    gas_used,
+                ) {
+                    Ok(Ok(result)) => Ok(Ok(Binary(result))),
+                    Ok(Err(err)) => Ok(Err(StdError::GenericErr {
+                        msg: format!(
+                            "encrypted: {}: query contract failed",
+                            error_msg(&err).unwrap_or_default()
+                        ),
+                    })),
+                    Err(err) => Ok(Err(StdError::GenericErr {
+                        msg: err.to_string(),
+                    })),
+                };
+            }
+            QueryRequest::Wasm(WasmQuery::ContractInfo { contract_addr }) => {
+                let state = self.state.borrow();
+                let contract_state = match state.contract::<E::Error>(&contract_addr) {
+                    Ok(contract_state) => contract_state,
+                    Err(_) => {
+                        return Err(SystemError::NoSuchContract {
+                            addr: contract_addr,
+                        })
+                    }
+                };
+
+                json!({
+                    "code_id": contract_state.code_id,
+                    "creator": contract_state.creator,
+                    "admin": contract_state.admin,
+                    "pinned": false,
+                    "ibc_port": null,
+                    "label": contract_state.label,
+                })
+            }
+            QueryRequest::Bank(BankQuery::Balance { address, denom }) => {
+                let amount = self
+                    .balance(&address)
+                    .into_iter()
+                    .find(|coin| coin.denom == denom)
+                    .unwrap_or(Coin { denom, amount: 0 });
+                json!({ "amount": amount })
+            }
+            QueryRequest::Bank(BankQuery::AllBalances { address }) => {
+                json!({ "amount": self.balance(&address) })
+            }
+        };
+
+        Ok(Ok(Binary(response.to_string().into_bytes())))
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use bech32::{FromBase32, ToBase32, Variant};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+pub const BECH32_PREFIX: &str = "secret";
+
+/// Binary data, which is base64 encoded in JSON like `cosmwasm_std::Binary`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Binary(pub Vec<u8>);
+
+impl Binary {
+    pub fn from_base64(encoded: &str) -> Option<Self> {
+        STANDARD.decode(encoded).ok().map(Binary)
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(&self.0)
+    }
+}
+
+impl Serialize for Binary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_base64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Binary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        Binary::from_base64(&encoded)
+            .ok_or_else(|| de::Error::custom(format!("invalid base64: {}", encoded)))
+    }
+}
+
+/// Same as `cosmwasm_std::Coin`, whose amount is a decimal string in JSON
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Coin {
+    pub denom: String,
+    #[serde(with = "decimal")]
+    pub amount: u128,
+}
+
+impl Coin {
+    pub fn new(amount: u128, denom: &str) -> Self {
+        Coin {
+            denom: denom.to_string(),
+            amount,
+        }
+    }
+}
+
+impl fmt::Display for Coin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.denom)
+    }
+}
+
+mod decimal {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        let amount = String::deserialize(deserializer)?;
+        amount
+            .parse()
+            .map_err(|_| de::Error::custom(format!("invalid amount: {}", amount)))
+    }
+}
+
+/// The bech32 address of the canonical address `canonical`
+pub fn human_address(canonical: &[u8]) -> String {
+    bech32::encode(BECH32_PREFIX, canonical.to_base32(), Variant::Bech32)
+        .expect("the prefix is valid")
+}
+
+/// The canonical address of the bech32 address `human`. Returns `None` if it isn't one.
+pub fn canonical_address(human: &str) -> Option<Vec<u8>> {
+    let (prefix, data, _variant) = bech32::decode(human).ok()?;
+    if prefix != BECH32_PREFIX {
+        return None;
+    }
+
+    Vec::<u8>::from_base32(&data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_like_cosmwasm_std() {
+        let coin = Coin::new(100, "uscrt");
+        let json = serde_json::to_string(&coin).unwrap();
+        assert_eq!(json, r#"{"denom":"uscrt","amount":"100"}"#);
+        assert_eq!(serde_json::from_str::<Coin>(&json).unwrap(), coin);
+
+        let binary = Binary(b"hi".to_vec());
+        let json = serde_json::to_string(&binary).unwrap();
+        assert_eq!(json, r#""aGk=""#);
+        assert_eq!(serde_json::from_str::<Binary>(&json).unwrap(), binary);
+    }
+
+    #[test]
+    fn addresses_round_trip() {
+        let human = human_address(&[7u8; 20]);
+        assert!(human.starts_with("secret1"));
+        assert_eq!(canonical_address(&human), Some(vec![7u8; 20]));
+
+        assert_eq!(canonical_address("secret1nobody"), None);
+        assert_eq!(
+            canonical_address(
+                &bech32::encode("cosmos", [7u8; 20].to_base32(), Variant::Bech32).unwrap()
+            ),
+            None
+        );
+    }
+}
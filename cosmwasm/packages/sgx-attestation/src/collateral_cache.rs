@@ -0,0 +1,187 @@
+//! An on-disk cache of DCAP collateral, so a node can still attest while its PCCS is unreachable,
+//! and the configuration of where collateral is fetched from.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use log::*;
+use serde_json::json;
+
+/// Overrides the directory collateral is cached in. Defaults to
+/// `$SCRT_SGX_STORAGE/dcap_collateral`
+const CACHE_DIR_ENV_VAR: &str = "SCRT_DCAP_COLLATERAL_CACHE_DIR";
+/// How long (in seconds) cached collateral is used before it is fetched again
+const CACHE_TTL_ENV_VAR: &str = "SCRT_DCAP_COLLATERAL_CACHE_TTL";
+/// The PCCS to fetch collateral from. When unset the system wide QCNL configuration
+/// (`/etc/sgx_default_qcnl.conf`) is used
+const PCCS_URL_ENV_VAR: &str = "SCRT_PCCS_URL";
+/// Set to `false` to accept a PCCS with a self-signed certificate
+const PCCS_USE_SECURE_CERT_ENV_VAR: &str = "SCRT_PCCS_USE_SECURE_CERT";
+/// The quote provider library reads the path of its configuration file from this variable
+const QCNL_CONF_PATH_ENV_VAR: &str = "QCNL_CONF_PATH";
+
+const DEFAULT_SGX_SECRET_PATH: &str = "/opt/secret/.sgx_secrets/";
+const DEFAULT_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PccsConfig {
+    pub url: String,
+    pub use_secure_cert: bool,
+}
+
+/// Where DCAP collateral is cached, and which PCCS it is fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollateralConfig {
+    pub cache_dir: PathBuf,
+    pub cache_ttl: Duration,
+    /// `None` to use the system wide QCNL configuration
+    pub pccs: Option<PccsConfig>,
+}
+
+impl CollateralConfig {
+    pub fn from_env() -> Self {
+        let cache_dir = match env::var(CACHE_DIR_ENV_VAR) {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => PathBuf::from(
+                env::var("SCRT_SGX_STORAGE")
+                    .unwrap_or_else(|_| DEFAULT_SGX_SECRET_PATH.to_string()),
+            )
+            .join("dcap_collateral"),
+        };
+
+        let cache_ttl = env::var(CACHE_TTL_ENV_VAR)
+            .ok()
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+
+        let pccs = env::var(PCCS_URL_ENV_VAR).ok().map(|url| PccsConfig {
+            url,
+            use_secure_cert: env::var(PCCS_USE_SECURE_CERT_ENV_VAR)
+                .map(|v| v != "false")
+                .unwrap_or(true),
+        });
+
+        Self {
+            cache_dir,
+            cache_ttl: Duration::from_secs(cache_ttl),
+            pccs,
+        }
+    }
+
+    /// The QCNL configuration file that points the quote provider library at `pccs`
+    pub fn qcnl_config(&self) -> Option<String> {
+        self.pccs.as_ref().map(|pccs| {
+            json!({
+                "pccs_url": pccs.url,
+                "collateral_service": pccs.url,
+                "use_secure_cert": pccs.use_secure_cert,
+                "retry_times": 6,
+                "retry_delay": 10,
+            })
+            .to_string()
+        })
+    }
+}
+
+/// Points the quote provider library at the configured PCCS, by generating a QCNL
+/// configuration file for it.
+///
+/// The library only takes the path of that file from the environment, and reads it once, so
+/// this must be called on startup before any thread requests collateral.
+pub fn configure_pccs(config: &CollateralConfig) -> io::Result<()> {
+    let (qcnl_config, pccs) = match (config.qcnl_config(), &config.pccs) {
+        (Some(qcnl_config), Some(pccs)) => (qcnl_config, pccs),
+        _ => return Ok(()),
+    };
+
+    let path = config.cache_dir.join("sgx_qcnl.conf");
+    fs::create_dir_all(&config.cache_dir)?;
+    fs::write(&path, qcnl_config)?;
+
+    info!("Fetching DCAP collateral from PCCS {}", pccs.url);
+    env::set_var(QCNL_CONF_PATH_ENV_VAR, path);
+
+    Ok(())
+}
+
+/// Returns the cached collateral for `key`. Unless `allow_stale` is set, collateral that is
+/// older than the cache TTL is ignored.
+pub fn load(config: &CollateralConfig, key: &str, allow_stale: bool) -> Option<Vec<u8>> {
+    let path = config.cache_dir.join(key);
+
+    if !allow_stale {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > config.cache_ttl {
+            trace!("Cached collateral {} is {}s old", key, age.as_secs());
+            return None;
+        }
+    }
+
+    fs::read(path).ok()
+}
+
+pub fn store(config: &CollateralConfig, key: &str, collateral: &[u8]) {
+    let dir = &config.cache_dir;
+    if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join(key), collateral)) {
+        warn!("Failed to cache DCAP collateral: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(cache_dir: PathBuf, pccs: Option<PccsConfig>) -> CollateralConfig {
+        CollateralConfig {
+            cache_dir,
+            cache_ttl: Duration::from_secs(60),
+            pccs,
+        }
+    }
+
+    #[test]
+    fn qcnl_config_escapes_url() {
+        let config = config(
+            PathBuf::from("/tmp"),
+            Some(PccsConfig {
+                url: "https://pccs.example/\"quoted\"".to_string(),
+                use_secure_cert: false,
+            }),
+        );
+
+        let qcnl: serde_json::Value = serde_json::from_str(&config.qcnl_config().unwrap()).unwrap();
+        assert_eq!(qcnl["pccs_url"], "https://pccs.example/\"quoted\"");
+        assert_eq!(qcnl["use_secure_cert"], false);
+    }
+
+    #[test]
+    fn qcnl_config_without_pccs() {
+        assert_eq!(config(PathBuf::from("/tmp"), None).qcnl_config(), None);
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = env::temp_dir().join(format!("collateral_cache_test_{}", std::process::id()));
+        let config = config(dir.clone(), None);
+
+        assert_eq!(load(&config, "key", true), None);
+        store(&config, "key", b"collateral");
+        assert_eq!(load(&config, "key", false), Some(b"collateral".to_vec()));
+
+        let expired = CollateralConfig {
+            cache_ttl: Duration::from_secs(0),
+            ..config.clone()
+        };
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(load(&expired, "key", false), None);
+        assert_eq!(load(&expired, "key", true), Some(b"collateral".to_vec()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
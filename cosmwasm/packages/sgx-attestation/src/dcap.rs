@@ -0,0 +1,418 @@
+//! Quote and collateral handling for DCAP attestation on the host: serializing collateral so it
+//! can be passed to the enclave, caching it, and verifying quotes with the untrusted quote
+//! verification library.
+
+use core::mem;
+
+use std::ptr::{self, null_mut};
+
+use log::*;
+use sgx_types::*;
+
+use crate::collateral_cache::{self as cache, CollateralConfig};
+
+pub struct QlQveCollateral {
+    pub tee_type: u32, // 0x00000000: SGX or 0x00000081: TDX
+    pub pck_crl_issuer_chain_size: u32,
+    pub root_ca_crl_size: u32,
+    pub pck_crl_size: u32,
+    pub tcb_info_issuer_chain_size: u32,
+    pub tcb_info_size: u32,
+    pub qe_identity_issuer_chain_size: u32,
+    pub qe_identity_size: u32,
+}
+
+fn sgx_ql_qve_collateral_serialize(
+    p_col: *const u8,
+    n_col: u32,
+    p_res: *mut u8,
+    n_res: u32,
+) -> u32 {
+    if n_col < mem::size_of::<sgx_ql_qve_collateral_t>() as u32 {
+        return 0;
+    }
+
+    unsafe {
+        let p_ql_col = p_col as *const sgx_ql_qve_collateral_t;
+
+        let size_extra = (*p_ql_col).pck_crl_issuer_chain_size
+            + (*p_ql_col).root_ca_crl_size
+            + (*p_ql_col).pck_crl_size
+            + (*p_ql_col).tcb_info_issuer_chain_size
+            + (*p_ql_col).tcb_info_size
+            + (*p_ql_col).qe_identity_issuer_chain_size
+            + (*p_ql_col).qe_identity_size;
+
+        if n_col < mem::size_of::<sgx_ql_qve_collateral_t>() as u32 + size_extra {
+            return 0;
+        }
+
+        let out_size: u32 = mem::size_of::<QlQveCollateral>() as u32 + size_extra;
+
+        if n_res >= out_size {
+            let x = QlQveCollateral {
+                tee_type: (*p_ql_col).tee_type,
+                pck_crl_issuer_chain_size: (*p_ql_col).pck_crl_issuer_chain_size,
+                root_ca_crl_size: (*p_ql_col).root_ca_crl_size,
+                pck_crl_size: (*p_ql_col).pck_crl_size,
+                tcb_info_issuer_chain_size: (*p_ql_col).tcb_info_issuer_chain_size,
+                tcb_info_size: (*p_ql_col).tcb_info_size,
+                qe_identity_issuer_chain_size: (*p_ql_col).qe_identity_issuer_chain_size,
+                qe_identity_size: (*p_ql_col).qe_identity_size,
+            };
+
+            ptr::copy_nonoverlapping(
+                &x as *const QlQveCollateral as *const u8,
+                p_res,
+                mem::size_of::<QlQveCollateral>(),
+            );
+            let mut offs = mem::size_of::<QlQveCollateral>();
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).pck_crl_issuer_chain as *const u8,
+                p_res.add(offs),
+                x.pck_crl_issuer_chain_size as usize,
+            );
+            offs += x.pck_crl_issuer_chain_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).root_ca_crl as *const u8,
+                p_res.add(offs),
+                x.root_ca_crl_size as usize,
+            );
+            offs += x.root_ca_crl_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).pck_crl as *const u8,
+                p_res.add(offs),
+                x.pck_crl_size as usize,
+            );
+            offs += x.pck_crl_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).tcb_info_issuer_chain as *const u8,
+                p_res.add(offs),
+                x.tcb_info_issuer_chain_size as usize,
+            );
+            offs += x.tcb_info_issuer_chain_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).tcb_info as *const u8,
+                p_res.add(offs),
+                x.tcb_info_size as usize,
+            );
+            offs += x.tcb_info_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).qe_identity_issuer_chain as *const u8,
+                p_res.add(offs),
+                x.qe_identity_issuer_chain_size as usize,
+            );
+            offs += x.qe_identity_issuer_chain_size as usize;
+
+            ptr::copy_nonoverlapping(
+                (*p_ql_col).qe_identity as *const u8,
+                p_res.add(offs),
+                x.qe_identity_size as usize,
+            );
+        }
+
+        return out_size;
+    };
+}
+
+pub(crate) fn sgx_ql_qve_collateral_deserialize(
+    p_ser: *const u8,
+    n_ser: u32,
+) -> sgx_ql_qve_collateral_t {
+    let mut res = sgx_ql_qve_collateral_t {
+        version: 0,
+        tee_type: 0,
+        pck_crl_issuer_chain: null_mut(),
+        pck_crl_issuer_chain_size: 0,
+        root_ca_crl: null_mut(),
+        root_ca_crl_size: 0,
+        pck_crl: null_mut(),
+        pck_crl_size: 0,
+        tcb_info_issuer_chain: null_mut(),
+        tcb_info_issuer_chain_size: 0,
+        tcb_info: null_mut(),
+        tcb_info_size: 0,
+        qe_identity_issuer_chain: null_mut(),
+        qe_identity_issuer_chain_size: 0,
+        qe_identity: null_mut(),
+        qe_identity_size: 0,
+    };
+
+    if n_ser >= mem::size_of::<QlQveCollateral>() as u32 {
+        unsafe {
+            let p_ql_col = p_ser as *const QlQveCollateral;
+            let size_extra = (*p_ql_col).pck_crl_issuer_chain_size
+                + (*p_ql_col).root_ca_crl_size
+                + (*p_ql_col).pck_crl_size
+                + (*p_ql_col).tcb_info_issuer_chain_size
+                + (*p_ql_col).tcb_info_size
+                + (*p_ql_col).qe_identity_issuer_chain_size
+                + (*p_ql_col).qe_identity_size;
+
+            if n_ser >= mem::size_of::<QlQveCollateral>() as u32 + size_extra {
+                res.version = 1; // PCK Cert chain is in the Quote.
+                res.tee_type = (*p_ql_col).tee_type;
+                res.pck_crl_issuer_chain_size = (*p_ql_col).pck_crl_issuer_chain_size;
+                res.root_ca_crl_size = (*p_ql_col).root_ca_crl_size;
+                res.pck_crl_size = (*p_ql_col).pck_crl_size;
+                res.tcb_info_issuer_chain_size = (*p_ql_col).tcb_info_issuer_chain_size;
+                res.tcb_info_size = (*p_ql_col).tcb_info_size;
+                res.qe_identity_issuer_chain_size = (*p_ql_col).qe_identity_issuer_chain_size;
+                res.qe_identity_size = (*p_ql_col).qe_identity_size;
+
+                let mut offs = mem::size_of::<QlQveCollateral>();
+
+                res.pck_crl_issuer_chain = p_ser.add(offs) as *mut i8;
+                offs += res.pck_crl_issuer_chain_size as usize;
+
+                res.root_ca_crl = p_ser.add(offs) as *mut i8;
+                offs += res.root_ca_crl_size as usize;
+
+                res.pck_crl = p_ser.add(offs) as *mut i8;
+                offs += res.pck_crl_size as usize;
+
+                res.tcb_info_issuer_chain = p_ser.add(offs) as *mut i8;
+                offs += res.tcb_info_issuer_chain_size as usize;
+
+                res.tcb_info = p_ser.add(offs) as *mut i8;
+                offs += res.tcb_info_size as usize;
+
+                res.qe_identity_issuer_chain = p_ser.add(offs) as *mut i8;
+                offs += res.qe_identity_issuer_chain_size as usize;
+
+                res.qe_identity = p_ser.add(offs) as *mut i8;
+            }
+        }
+    };
+
+    return res; // unreachable
+}
+
+/// Offset of the signature data in an ECDSA (v3) quote: header (48) + report body (384) +
+/// signature data length (4)
+const QUOTE_SIG_DATA_OFFSET: usize = 436;
+/// ECDSA signature (64) + attestation key (64) + QE report (384) + QE report signature (64)
+const QUOTE_SIG_DATA_FIXED_SIZE: usize = 576;
+
+/// Returns the certification data (the PCK certificate chain) embedded in an ECDSA quote.
+///
+/// The collateral of a quote only depends on the platform that generated it, which is
+/// identified by its PCK certificate, so this is what collateral is cached by.
+pub fn quote_certification_data(quote: &[u8]) -> Option<&[u8]> {
+    let mut offs = QUOTE_SIG_DATA_OFFSET + QUOTE_SIG_DATA_FIXED_SIZE;
+
+    let auth_data_size = u16::from_le_bytes(quote.get(offs..offs + 2)?.try_into().ok()?) as usize;
+    offs += 2 + auth_data_size;
+
+    // certification data type (2) followed by its size (4)
+    let cert_data_size =
+        u32::from_le_bytes(quote.get(offs + 2..offs + 6)?.try_into().ok()?) as usize;
+    offs += 6;
+
+    quote.get(offs..offs + cert_data_size)
+}
+
+/// FNV-1a. The cache key doesn't need to be collision resistant since the collateral is
+/// verified by the QvE anyway
+pub fn collateral_cache_key(cert_data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in cert_data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Fetches the collateral of `quote` through the quote provider library
+fn fetch_collateral(quote: &[u8]) -> Option<Vec<u8>> {
+    let (p_quote, n_quote) = (quote.as_ptr(), quote.len() as u32);
+
+    let mut p_col_my: *mut u8 = null_mut();
+    let mut n_col_my: u32 = 0;
+
+    let ret = unsafe { tee_qv_get_collateral(p_quote, n_quote, &mut p_col_my, &mut n_col_my) };
+
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        trace!("tee_qv_get_collateral returned {}", ret);
+        return None;
+    }
+
+    let size = sgx_ql_qve_collateral_serialize(p_col_my, n_col_my, null_mut(), 0);
+    let mut collateral = vec![0u8; size as usize];
+    sgx_ql_qve_collateral_serialize(p_col_my, n_col_my, collateral.as_mut_ptr(), size);
+
+    unsafe { tee_qv_free_collateral(p_col_my) };
+
+    if size == 0 {
+        return None;
+    }
+
+    Some(collateral)
+}
+
+/// Returns the collateral of `quote`, from the cache if it is fresh enough
+pub fn get_collateral(config: &CollateralConfig, quote: &[u8]) -> Option<Vec<u8>> {
+    let cache_key = quote_certification_data(quote).map(collateral_cache_key);
+
+    if let Some(collateral) = cache_key
+        .as_deref()
+        .and_then(|key| cache::load(config, key, false))
+    {
+        return Some(collateral);
+    }
+
+    match fetch_collateral(quote) {
+        Some(collateral) => {
+            if let Some(key) = &cache_key {
+                cache::store(config, key, &collateral);
+            }
+            Some(collateral)
+        }
+        // the enclave checks the collateral expiration, so stale collateral is still better
+        // than failing outright while the PCCS is unreachable
+        None => {
+            let collateral = cache::load(config, cache_key.as_deref()?, true)?;
+            warn!("Failed to fetch DCAP collateral, using cached collateral");
+            Some(collateral)
+        }
+    }
+}
+
+/// Offset of `tcb_level_date_tag` in `sgx_ql_qv_supplemental_t`, same as in the enclave
+const SUPP_TCB_LEVEL_DATE_TAG_OFFSET: usize = 32;
+
+/// The report body follows the 48 byte quote header
+const QUOTE_REPORT_BODY_OFFSET: usize = 48;
+const QUOTE_REPORT_BODY_SIZE: usize = 384;
+
+/// Number of `u32` size fields in the header that precedes the collateral blobs
+const COLLATERAL_HEADER_FIELDS: usize = 8;
+
+pub struct QuoteVerification {
+    pub qv_result: sgx_ql_qv_result_t,
+    pub collateral_expired: bool,
+    /// The date of the TCB level the platform was matched to, as a unix timestamp
+    pub tcb_level_date_tag: i64,
+}
+
+/// Returns the enclave report body of an ECDSA quote
+pub fn quote_report_body(quote: &[u8]) -> Option<&[u8]> {
+    quote.get(QUOTE_REPORT_BODY_OFFSET..QUOTE_REPORT_BODY_OFFSET + QUOTE_REPORT_BODY_SIZE)
+}
+
+/// Checks that the blob sizes in the collateral header add up, before handing it to
+/// `sgx_ql_qve_collateral_deserialize`
+fn collateral_is_well_formed(collateral: &[u8]) -> bool {
+    let header_size = COLLATERAL_HEADER_FIELDS * 4;
+    if collateral.len() < header_size {
+        return false;
+    }
+
+    // the first field is the tee type, the rest are sizes
+    let blobs_size: u64 = collateral[4..header_size]
+        .chunks(4)
+        .map(|field| u32::from_le_bytes(field.try_into().unwrap()) as u64)
+        .sum();
+
+    header_size as u64 + blobs_size <= collateral.len() as u64
+}
+
+/// Verifies the quote signature and certificate chain up to Intel's root CA, and evaluates the
+/// platform's TCB against the collateral at `time_s`.
+///
+/// This uses the untrusted quote verification library, which is fine for auditing a certificate
+/// offline, but unlike the enclave it does not verify the result with the QvE.
+pub fn verify_quote(
+    quote: &[u8],
+    collateral: &[u8],
+    time_s: i64,
+) -> Result<QuoteVerification, sgx_quote3_error_t> {
+    if !collateral_is_well_formed(collateral) {
+        return Err(sgx_quote3_error_t::SGX_QL_ERROR_INVALID_PARAMETER);
+    }
+
+    let mut n_supp: u32 = 0;
+    let res = unsafe { sgx_qv_get_quote_supplemental_data_size(&mut n_supp) };
+    if res != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        return Err(res);
+    }
+
+    let mut p_supp = vec![0u8; n_supp as usize];
+    let mut exp_status: u32 = 0;
+    let mut qv_result = sgx_ql_qv_result_t::default();
+
+    let my_col = sgx_ql_qve_collateral_deserialize(collateral.as_ptr(), collateral.len() as u32);
+
+    let res = unsafe {
+        sgx_qv_verify_quote(
+            quote.as_ptr(),
+            quote.len() as u32,
+            &my_col,
+            time_s,
+            &mut exp_status,
+            &mut qv_result,
+            null_mut(),
+            n_supp,
+            p_supp.as_mut_ptr(),
+        )
+    };
+    if res != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        return Err(res);
+    }
+
+    let tcb_level_date_tag = p_supp
+        .get(SUPP_TCB_LEVEL_DATE_TAG_OFFSET..SUPP_TCB_LEVEL_DATE_TAG_OFFSET + 8)
+        .map(|date_tag| i64::from_le_bytes(date_tag.try_into().unwrap()))
+        .unwrap_or(0);
+
+    Ok(QuoteVerification {
+        qv_result,
+        collateral_expired: exp_status != 0,
+        tcb_level_date_tag,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote_with_cert_data(auth_data: &[u8], cert_data: &[u8]) -> Vec<u8> {
+        let mut quote = vec![0u8; QUOTE_SIG_DATA_OFFSET + QUOTE_SIG_DATA_FIXED_SIZE];
+        quote.extend_from_slice(&(auth_data.len() as u16).to_le_bytes());
+        quote.extend_from_slice(auth_data);
+        quote.extend_from_slice(&5u16.to_le_bytes());
+        quote.extend_from_slice(&(cert_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(cert_data);
+        quote
+    }
+
+    #[test]
+    fn quote_certification_data_works() {
+        let quote = quote_with_cert_data(&[1u8; 32], b"pck cert chain");
+        assert_eq!(
+            quote_certification_data(&quote),
+            Some(&b"pck cert chain"[..])
+        );
+    }
+
+    #[test]
+    fn quote_certification_data_truncated() {
+        let quote = quote_with_cert_data(&[1u8; 32], b"pck cert chain");
+        assert_eq!(quote_certification_data(&quote[..quote.len() - 1]), None);
+        assert_eq!(quote_certification_data(&quote[..100]), None);
+    }
+
+    #[test]
+    fn collateral_cache_key_depends_on_cert_data() {
+        let key = collateral_cache_key(b"pck cert chain");
+        assert_eq!(key.len(), 16);
+        assert_eq!(key, collateral_cache_key(b"pck cert chain"));
+        assert_ne!(key, collateral_cache_key(b"other cert chain"));
+    }
+}
@@ -0,0 +1,10 @@
+//! Host-side attestation code that is shared by the binaries that host the enclave (`secretd`
+//! through sgx-vm, and check-hw) and by verify-attestation.
+//!
+//! The parsing of attestation reports lives in `enclave_attestation_report`
+//! (`cosmwasm/enclaves/shared/attestation-report`), which builds both inside the enclave and on
+//! the host.
+
+pub mod collateral_cache;
+pub mod dcap;
+pub mod ocalls;
@@ -0,0 +1,168 @@
+//! The untrusted side of the enclave's DCAP ocalls. Every binary that hosts the enclave exports
+//! them under the names in the EDL with thin `#[no_mangle]` wrappers.
+
+use std::ptr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::*;
+use sgx_types::*;
+
+use crate::collateral_cache::CollateralConfig;
+use crate::dcap::{get_collateral, sgx_ql_qve_collateral_deserialize};
+
+/// Implements `ocall_get_quote_ecdsa_params`
+///
+/// # Safety
+/// All pointers must be valid for the sizes passed along with them, as checked by the
+/// untrusted bridge of the enclave
+pub unsafe fn get_quote_ecdsa_params(
+    p_qe_info: *mut sgx_target_info_t,
+    p_quote_size: *mut u32,
+) -> sgx_status_t {
+    let mut ret = sgx_qe_get_target_info(p_qe_info);
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        trace!("sgx_qe_get_target_info returned {}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    ret = sgx_qe_get_quote_size(p_quote_size);
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        trace!("sgx_qe_get_quote_size returned {}", ret);
+        return sgx_status_t::SGX_ERROR_BUSY;
+    }
+
+    trace!("*QuoteSize = {}", *p_quote_size);
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Implements `ocall_get_quote_ecdsa`
+///
+/// # Safety
+/// All pointers must be valid for the sizes passed along with them, as checked by the
+/// untrusted bridge of the enclave
+pub unsafe fn get_quote_ecdsa(
+    p_report: *const sgx_report_t,
+    p_quote: *mut u8,
+    n_quote: u32,
+) -> sgx_status_t {
+    trace!("Entering ocall_get_quote_ecdsa");
+
+    //let mut qe_target_info: sgx_target_info_t;
+    //sgx_qe_get_target_info(&qe_target_info);
+
+    let mut n_quote_act: u32 = 0;
+    let mut ret = sgx_qe_get_quote_size(&mut n_quote_act);
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        trace!("sgx_qe_get_quote_size returned {}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    if n_quote_act > n_quote {
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    ret = sgx_qe_get_quote(p_report, n_quote, p_quote);
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        trace!("sgx_qe_get_quote returned {}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Implements `ocall_get_quote_ecdsa_collateral`
+///
+/// # Safety
+/// All pointers must be valid for the sizes passed along with them, as checked by the
+/// untrusted bridge of the enclave
+pub unsafe fn get_quote_ecdsa_collateral(
+    config: &CollateralConfig,
+    p_quote: *const u8,
+    n_quote: u32,
+    p_col: *mut u8,
+    n_col: u32,
+    p_col_size: *mut u32,
+) -> sgx_status_t {
+    let quote = std::slice::from_raw_parts(p_quote, n_quote as usize);
+
+    let collateral = match get_collateral(config, quote) {
+        Some(collateral) => collateral,
+        None => return sgx_status_t::SGX_ERROR_UNEXPECTED,
+    };
+
+    *p_col_size = collateral.len() as u32;
+
+    if collateral.len() <= n_col as usize {
+        ptr::copy_nonoverlapping(collateral.as_ptr(), p_col, collateral.len());
+    }
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Implements `ocall_verify_quote_ecdsa`
+///
+/// # Safety
+/// All pointers must be valid for the sizes passed along with them, as checked by the
+/// untrusted bridge of the enclave
+pub unsafe fn verify_quote_ecdsa(
+    p_quote: *const u8,
+    n_quote: u32,
+    p_col: *const u8,
+    n_col: u32,
+    p_target_info: *const sgx_target_info_t,
+    time_s: i64,
+    p_qve_report_info: *mut sgx_ql_qe_report_info_t,
+    p_supp_data: *mut u8,
+    n_supp_data: u32,
+    p_supp_data_size: *mut u32,
+    p_time_s: *mut i64,
+    p_collateral_expiration_status: *mut u32,
+    p_qv_result: *mut sgx_ql_qv_result_t,
+) -> sgx_status_t {
+    let mut time_use_s: time_t = time_s;
+    if time_s == 0 {
+        time_use_s = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as time_t;
+    }
+
+    let res0 = sgx_qv_set_enclave_load_policy(sgx_ql_request_policy_t::SGX_QL_PERSISTENT);
+    if sgx_quote3_error_t::SGX_QL_SUCCESS != res0 {
+        warn!("sgx_qv_set_enclave_load_policy: {}", res0);
+    }
+
+    let res1 = sgx_qv_get_quote_supplemental_data_size(p_supp_data_size);
+    if sgx_quote3_error_t::SGX_QL_SUCCESS != res1 {
+        warn!("sgx_qv_get_quote_supplemental_data_size: {}", res1);
+    }
+
+    if *p_supp_data_size > n_supp_data {
+        warn!("supp data buf required: {}", *p_supp_data_size);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    (*p_qve_report_info).app_enclave_target_info = *p_target_info;
+
+    let my_col = sgx_ql_qve_collateral_deserialize(p_col, n_col);
+
+    let res2 = sgx_qv_verify_quote(
+        p_quote,
+        n_quote,
+        &my_col,
+        time_use_s,
+        p_collateral_expiration_status,
+        p_qv_result,
+        p_qve_report_info,
+        *p_supp_data_size,
+        p_supp_data,
+    );
+    if sgx_quote3_error_t::SGX_QL_SUCCESS != res2 {
+        warn!("sgx_qv_verify_quote: {}", res2);
+    }
+
+    *p_time_s = time_use_s;
+
+    sgx_status_t::SGX_SUCCESS
+}
@@ -0,0 +1,331 @@
+//! `MockApp` encrypts and signs the txs of its users with their wallets, and decrypts the outputs
+//! the enclave returns with the key of the tx.
+
+use std::ops::Deref;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use app_harness::{
+    canonical_address, App, AppError, AppResponse, Binary as AppBinary, CallOutput, Coin,
+    ContractCall, Engine,
+};
+use cosmwasm_sgx_vm::testing::{MockApi, MockStorage};
+use cosmwasm_sgx_vm::{Extern, FfiResult, GasInfo, Instance, Querier, VmError, VmResult};
+use cosmwasm_std::{Binary, StdResult, SystemResult};
+
+use crate::wallet::{decrypt_output, MockWallet};
+
+pub type AppResult<T> = Result<T, AppError<VmError>>;
+
+/// Runs the calls of an `App` in the enclave, through an sgx-vm `Instance`
+pub struct EnclaveEngine;
+
+impl Engine for EnclaveEngine {
+    type Storage = MockStorage;
+    type Error = VmError;
+
+    fn run(
+        &self,
+        app: &App<Self>,
+        code: &[u8],
+        storage: &mut MockStorage,
+        gas_limit: u64,
+        gas_used: &mut u64,
+        call: ContractCall,
+    ) -> VmResult<CallOutput> {
+        let deps = Extern {
+            storage: std::mem::take(storage),
+            api: MockApi::default(),
+            querier: AppQuerier { app: app.clone() },
+        };
+        let mut instance = Instance::from_code(code, deps, gas_limit)?;
+
+        let is_init = matches!(call, ContractCall::Init { .. });
+        let result = match call {
+            ContractCall::Init {
+                env,
+                msg,
+                sig_info,
+                admin,
+            } => instance.call_init(env, msg, sig_info, admin),
+            ContractCall::Handle {
+                env,
+                msg,
+                sig_info,
+                handle_type,
+            } => instance.call_handle(env, msg, sig_info, handle_type),
+            ContractCall::Query { env, msg } => instance.call_query(env, msg),
+        };
+        *gas_used += instance.get_gas_used();
+        let output = result?;
+
+        *storage = instance
+            .recycle()
+            .expect("the instance keeps its storage")
+            .storage;
+
+        if !is_init {
+            return Ok(CallOutput {
+                output,
+                contract_key: vec![],
+            });
+        }
+
+        // the output of init is prefixed with the key of the new contract and its code hash
+        assert!(
+            output.len() >= 96,
+            "the init output of the enclave is too short"
+        );
+        let (contract_key, output) = output.split_at(64);
+        Ok(CallOutput {
+            output: output[32..].to_vec(),
+            contract_key: contract_key.to_vec(),
+        })
+    }
+}
+
+/// Answers the queries contracts make with the rest of the app
+struct AppQuerier {
+    app: App<EnclaveEngine>,
+}
+
+impl Querier for AppQuerier {
+    fn query_raw(
+        &self,
+        request: &[u8],
+        query_depth: u32,
+        gas_limit: u64,
+    ) -> FfiResult<SystemResult<StdResult<Binary>>> {
+        let mut gas_used = 0;
+        let answer = self
+            .app
+            .query_chain(request, query_depth, gas_limit, &mut gas_used);
+
+        // the answers of the app serialize like the results of `cosmwasm_std`
+        let answer = serde_json::to_vec(&answer)
+            .ok()
+            .and_then(|answer| serde_json::from_slice(&answer).ok())
+            .expect("the answers of the app are valid query results");
+        (Ok(answer), GasInfo::with_externally_used(gas_used))
+    }
+}
+
+/// The app is reachable through `Deref`, for everything that doesn't need a wallet
+pub struct MockApp {
+    app: App<EnclaveEngine>,
+    io_exchange_pubkey: [u8; 32],
+}
+
+impl Deref for MockApp {
+    type Target = App<EnclaveEngine>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.app
+    }
+}
+
+impl MockApp {
+    /// `io_exchange_pubkey` is the key the enclave decrypts txs with, as returned by
+    /// `untrusted_init_bootstrap`
+    pub fn new(io_exchange_pubkey: [u8; 32]) -> Self {
+        MockApp {
+            app: App::new(EnclaveEngine, "secret-testnet-1"),
+            io_exchange_pubkey,
+        }
+    }
+
+    pub fn io_exchange_pubkey(&self) -> [u8; 32] {
+        self.io_exchange_pubkey
+    }
+
+    /// Sends a `MsgInstantiateContract` signed by `sender`. Returns the address of the new contract.
+    pub fn instantiate<T: Serialize>(
+        &self,
+        sender: &MockWallet,
+        code_id: u64,
+        msg: &T,
+        sent_funds: &[Coin],
+        label: &str,
+        admin: Option<&str>,
+    ) -> AppResult<(String, AppResponse)> {
+        let code_hash = self
+            .code_hash(code_id)
+            .ok_or_else(|| AppError::Generic(format!("Code {} doesn't exist", code_id)))?;
+        let (secret_msg, tx_key) = self.encrypt_tx(sender, &code_hash, msg)?;
+
+        let sig_info = sender.sign_instantiate(
+            &self.chain_id(),
+            code_id,
+            label,
+            &secret_msg,
+            sent_funds,
+            admin,
+        );
+        let (contract, response) = self
+            .app
+            .instantiate(
+                &sender.address(),
+                code_id,
+                &secret_msg,
+                &to_json(&sig_info)?,
+                sent_funds,
+                label,
+                admin,
+            )
+            .map_err(|err| tx_key.decrypt_error(err))?;
+
+        Ok((contract, tx_key.decrypt_response(response)))
+    }
+
+    /// Sends a `MsgExecuteContract` signed by `sender`
+    pub fn execute<T: Serialize>(
+        &self,
+        sender: &MockWallet,
+        contract: &str,
+        msg: &T,
+        sent_funds: &[Coin],
+    ) -> AppResult<AppResponse> {
+        let code_hash = self
+            .contract_code_hash(contract)
+            .ok_or_else(|| AppError::Generic(format!("Contract {} doesn't exist", contract)))?;
+        let (secret_msg, tx_key) = self.encrypt_tx(sender, &code_hash, msg)?;
+
+        let canonical_contract = canonical_address(contract)
+            .ok_or_else(|| AppError::Generic(format!("Invalid address {}", contract)))?;
+        let sig_info = sender.sign_execute(
+            &self.chain_id(),
+            &canonical_contract,
+            &secret_msg,
+            sent_funds,
+        );
+        let response = self
+            .app
+            .execute(
+                &sender.address(),
+                contract,
+                &secret_msg,
+                &to_json(&sig_info)?,
+                sent_funds,
+            )
+            .map_err(|err| tx_key.decrypt_error(err))?;
+
+        Ok(tx_key.decrypt_response(response))
+    }
+
+    /// Queries `contract` with a message encrypted by `sender`. Returns the decrypted result.
+    pub fn query<T: Serialize>(
+        &self,
+        sender: &MockWallet,
+        contract: &str,
+        msg: &T,
+    ) -> AppResult<Vec<u8>> {
+        let code_hash = self
+            .contract_code_hash(contract)
+            .ok_or_else(|| AppError::Generic(format!("Contract {} doesn't exist", contract)))?;
+        let (secret_msg, tx_key) = self.encrypt_tx(sender, &code_hash, msg)?;
+
+        let ciphertext = self
+            .app
+            .query(contract, &secret_msg)
+            .map_err(|err| tx_key.decrypt_error(err))?;
+
+        let result = tx_key
+            .decrypt(&ciphertext)
+            .ok_or_else(|| AppError::Generic("Failed to decrypt the query result".to_string()))?;
+        let result = String::from_utf8(result)
+            .ok()
+            .and_then(|result| AppBinary::from_base64(&result))
+            .ok_or_else(|| AppError::Generic("The query result isn't base64".to_string()))?;
+        Ok(result.0)
+    }
+
+    fn encrypt_tx<T: Serialize>(
+        &self,
+        sender: &MockWallet,
+        code_hash: &str,
+        msg: &T,
+    ) -> AppResult<(Vec<u8>, TxKey)> {
+        let msg = serde_json::to_vec(msg)
+            .map_err(|err| AppError::Generic(format!("Serializing msg: {}", err)))?;
+
+        let nonce = sender.next_nonce();
+        let secret_msg = sender.encrypt_msg(&self.io_exchange_pubkey, &nonce, code_hash, &msg);
+        let tx_key = TxKey(sender.tx_key(&self.io_exchange_pubkey, &nonce));
+
+        Ok((secret_msg, tx_key))
+    }
+}
+
+/// The key of a tx, which is also the key of every output of the tx
+struct TxKey([u8; 32]);
+
+impl TxKey {
+    fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        decrypt_output(&self.0, ciphertext)
+    }
+
+    /// Decrypts a base64 encoded output. Returns `None` if it isn't one.
+    fn decrypt_base64(&self, value: &str) -> Option<Vec<u8>> {
+        self.decrypt(&AppBinary::from_base64(value)?.0)
+    }
+
+    /// Decrypts the data and the attributes the contracts didn't make public
+    fn decrypt_response(&self, response: AppResponse) -> AppResponse {
+        let data = response.data.map(|data| {
+            self.decrypt(&data)
+                .and_then(|data| String::from_utf8(data).ok())
+                .and_then(|data| AppBinary::from_base64(&data))
+                .map(|data| data.0)
+                .unwrap_or(data)
+        });
+
+        let decrypt = |value: String| {
+            self.decrypt_base64(&value)
+                .and_then(|value| String::from_utf8(value).ok())
+                .unwrap_or(value)
+        };
+        let mut events = response.events;
+        for event in events.iter_mut() {
+            event.attributes = std::mem::take(&mut event.attributes)
+                .into_iter()
+                .map(|(key, value)| (decrypt(key), decrypt(value)))
+                .collect();
+        }
+
+        AppResponse {
+            data,
+            events,
+            gas_used: response.gas_used,
+        }
+    }
+
+    /// Decrypts the message of a `generic_err` a contract returned
+    fn decrypt_error(&self, err: AppError<VmError>) -> AppError<VmError> {
+        let (contract, error) = match err {
+            AppError::Contract { contract, error } => (contract, error),
+            err => return err,
+        };
+
+        let mut error_json: Value = match serde_json::from_str(&error) {
+            Ok(error_json) => error_json,
+            Err(_) => return AppError::Contract { contract, error },
+        };
+        let msg = error_json["generic_err"]["msg"]
+            .as_str()
+            .and_then(|msg| self.decrypt_base64(msg));
+        if let Some(msg) = msg {
+            error_json["generic_err"]["msg"] =
+                Value::String(String::from_utf8_lossy(&msg).to_string());
+        }
+
+        AppError::Contract {
+            contract,
+            error: error_json.to_string(),
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> AppResult<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|err| AppError::Generic(format!("Serializing: {}", err)))
+}
@@ -0,0 +1,14 @@
+//! `MockApp` runs many contracts in the enclave like a chain does: users sign and encrypt their
+//! txs with a `MockWallet`, and the contracts call and query each other through the
+//! `app_harness::App` it wraps.
+//!
+//! The enclave must be initialized before a `MockApp` is created (see `untrusted_init_bootstrap`,
+//! which returns the IO exchange pubkey the app needs), and built with the `go-tests` feature.
+//! Since the txs aren't in a real block, tests must run with `SKIP_LIGHT_CLIENT_VALIDATION=TRUE`.
+
+mod app;
+mod wallet;
+
+pub use app::{AppResult, EnclaveEngine, MockApp};
+pub use app_harness::{AppError, AppEvent, AppResponse, Block, Coin};
+pub use wallet::{MockWallet, SigInfo};
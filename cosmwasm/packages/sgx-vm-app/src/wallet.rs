@@ -0,0 +1,331 @@
+//! Test accounts that sign and encrypt their txs the way a client such as secret.js does, so the
+//! enclave verifies them like txs coming from the chain. Encryption is done by `secret-client`.
+
+use std::cell::Cell;
+
+use ripemd::Ripemd160;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use secret_client::{AESKey, EncryptionUtils};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use app_harness::{human_address, Binary, Coin};
+
+const EXECUTE_TYPE_URL: &str = "/secret.compute.v1beta1.MsgExecuteContract";
+const INSTANTIATE_TYPE_URL: &str = "/secret.compute.v1beta1.MsgInstantiateContract";
+const PUBKEY_TYPE_URL: &str = "/cosmos.crypto.secp256k1.PubKey";
+const SIGN_MODE_DIRECT: u64 = 1;
+
+/// The `SigInfo` passed to the enclave with every call, as `x/compute` serializes it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SigInfo {
+    pub tx_bytes: Binary,
+    pub sign_bytes: Binary,
+    pub sign_mode: String,
+    pub mode_info: Binary,
+    pub public_key: Binary,
+    pub signature: Binary,
+    pub callback_sig: Option<Binary>,
+}
+
+/// A user of the chain. The keys are derived from the name, so the same name always gives the
+/// same account.
+pub struct MockWallet {
+    signing_key: SecretKey,
+    public_key: PublicKey,
+    tx_encryption_seed: [u8; 32],
+    name: String,
+    sequence: Cell<u64>,
+    nonces: Cell<u64>,
+}
+
+impl MockWallet {
+    pub fn new(name: &str) -> Self {
+        let signing_key = SecretKey::from_slice(&sha256(&[name.as_bytes(), b"/signing"]))
+            .expect("a sha256 hash is a valid secp256k1 key");
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &signing_key);
+        let tx_encryption_seed = sha256(&[name.as_bytes(), b"/tx-encryption"]);
+
+        MockWallet {
+            signing_key,
+            public_key,
+            tx_encryption_seed,
+            name: name.to_string(),
+            sequence: Cell::new(0),
+            nonces: Cell::new(0),
+        }
+    }
+
+    pub fn canonical_address(&self) -> Vec<u8> {
+        Ripemd160::digest(Sha256::digest(self.public_key.serialize())).to_vec()
+    }
+
+    pub fn address(&self) -> String {
+        human_address(&self.canonical_address())
+    }
+
+    fn encryption(&self, io_exchange_pubkey: &[u8; 32]) -> EncryptionUtils {
+        EncryptionUtils::from_seed(*io_exchange_pubkey, &self.tx_encryption_seed)
+    }
+
+    /// The x25519 public key this wallet encrypts its messages with
+    pub fn tx_encryption_pubkey(&self) -> [u8; 32] {
+        // doesn't depend on the key of the chain
+        self.encryption(&[0u8; 32]).pubkey()
+    }
+
+    /// Nonces are deterministic, so test runs are reproducible
+    pub fn next_nonce(&self) -> [u8; 32] {
+        let count = self.nonces.get();
+        self.nonces.set(count + 1);
+
+        sha256(&[self.name.as_bytes(), b"/nonce", &count.to_be_bytes()])
+    }
+
+    /// The AES-SIV key of the tx encrypted with `nonce`, which is also the key of all its outputs
+    pub fn tx_key(&self, io_exchange_pubkey: &[u8; 32], nonce: &[u8; 32]) -> [u8; 32] {
+        *self
+            .encryption(io_exchange_pubkey)
+            .encryption_key(nonce)
+            .get()
+    }
+
+    /// Encrypts `msg` into a `SecretMessage` for a contract with code hash `code_hash`
+    pub fn encrypt_msg(
+        &self,
+        io_exchange_pubkey: &[u8; 32],
+        nonce: &[u8; 32],
+        code_hash: &str,
+        msg: &[u8],
+    ) -> Vec<u8> {
+        self.encryption(io_exchange_pubkey)
+            .encrypt_with_nonce(nonce, code_hash, msg)
+            .expect("code hashes of stored code are valid")
+    }
+
+    /// Decrypts an output of the tx encrypted with `nonce`. Returns `None` if it isn't one.
+    pub fn decrypt(
+        &self,
+        io_exchange_pubkey: &[u8; 32],
+        nonce: &[u8; 32],
+        ciphertext: &[u8],
+    ) -> Option<Vec<u8>> {
+        decrypt_output(&self.tx_key(io_exchange_pubkey, nonce), ciphertext)
+    }
+
+    pub(crate) fn sign_execute(
+        &self,
+        chain_id: &str,
+        contract: &[u8],
+        msg: &[u8],
+        sent_funds: &[Coin],
+    ) -> SigInfo {
+        let mut execute = vec![];
+        bytes_field(&mut execute, 1, &self.canonical_address());
+        bytes_field(&mut execute, 2, contract);
+        bytes_field(&mut execute, 3, msg);
+        for coin in sent_funds {
+            bytes_field(&mut execute, 5, &encode_coin(coin));
+        }
+
+        self.sign(chain_id, EXECUTE_TYPE_URL, &execute)
+    }
+
+    pub(crate) fn sign_instantiate(
+        &self,
+        chain_id: &str,
+        code_id: u64,
+        label: &str,
+        msg: &[u8],
+        sent_funds: &[Coin],
+        admin: Option<&str>,
+    ) -> SigInfo {
+        let mut instantiate = vec![];
+        bytes_field(&mut instantiate, 1, &self.canonical_address());
+        varint_field(&mut instantiate, 3, code_id);
+        bytes_field(&mut instantiate, 4, label.as_bytes());
+        bytes_field(&mut instantiate, 5, msg);
+        for coin in sent_funds {
+            bytes_field(&mut instantiate, 6, &encode_coin(coin));
+        }
+        if let Some(admin) = admin {
+            bytes_field(&mut instantiate, 8, admin.as_bytes());
+        }
+
+        self.sign(chain_id, INSTANTIATE_TYPE_URL, &instantiate)
+    }
+
+    /// Signs a tx with the single message `msg` in SIGN_MODE_DIRECT
+    fn sign(&self, chain_id: &str, type_url: &str, msg: &[u8]) -> SigInfo {
+        let sequence = self.sequence.get();
+        self.sequence.set(sequence + 1);
+
+        let mut body = vec![];
+        bytes_field(&mut body, 1, &encode_any(type_url, msg));
+
+        let mut pubkey = vec![];
+        bytes_field(&mut pubkey, 1, &self.public_key.serialize());
+        let pubkey = encode_any(PUBKEY_TYPE_URL, &pubkey);
+
+        let mut single = vec![];
+        varint_field(&mut single, 1, SIGN_MODE_DIRECT);
+        let mut mode_info = vec![];
+        bytes_field(&mut mode_info, 1, &single);
+
+        let mut signer_info = vec![];
+        bytes_field(&mut signer_info, 1, &pubkey);
+        bytes_field(&mut signer_info, 2, &mode_info);
+        varint_field(&mut signer_info, 3, sequence);
+
+        let mut fee = vec![];
+        varint_field(&mut fee, 2, 10_000_000);
+
+        let mut auth_info = vec![];
+        bytes_field(&mut auth_info, 1, &signer_info);
+        bytes_field(&mut auth_info, 2, &fee);
+
+        let mut sign_doc = vec![];
+        bytes_field(&mut sign_doc, 1, &body);
+        bytes_field(&mut sign_doc, 2, &auth_info);
+        bytes_field(&mut sign_doc, 3, chain_id.as_bytes());
+
+        let hash = Sha256::digest(&sign_doc);
+        let signature = Secp256k1::signing_only()
+            .sign_ecdsa(
+                &Message::from_slice(&hash).expect("a sha256 hash is a valid message"),
+                &self.signing_key,
+            )
+            .serialize_compact();
+
+        let mut tx_raw = vec![];
+        bytes_field(&mut tx_raw, 1, &body);
+        bytes_field(&mut tx_raw, 2, &auth_info);
+        bytes_field(&mut tx_raw, 3, &signature);
+
+        SigInfo {
+            tx_bytes: Binary(tx_raw),
+            sign_bytes: Binary(sign_doc),
+            sign_mode: "SIGN_MODE_DIRECT".to_string(),
+            mode_info: Binary(mode_info),
+            public_key: Binary(pubkey),
+            signature: Binary(signature.to_vec()),
+            callback_sig: None,
+        }
+    }
+}
+
+/// Decrypts an output of the tx with key `key`, and strips its padding
+pub(crate) fn decrypt_output(key: &[u8; 32], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    if ciphertext.is_empty() {
+        return None;
+    }
+
+    secret_client::decrypt(&AESKey::new_from_slice(key), ciphertext).ok()
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+// Just enough protobuf to build a tx. Fields with default values are skipped, like proto3 does.
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        varint(out, field << 3);
+        varint(out, value);
+    }
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u64, value: &[u8]) {
+    if !value.is_empty() {
+        varint(out, (field << 3) | 2);
+        varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+}
+
+fn encode_any(type_url: &str, value: &[u8]) -> Vec<u8> {
+    let mut any = vec![];
+    bytes_field(&mut any, 1, type_url.as_bytes());
+    bytes_field(&mut any, 2, value);
+    any
+}
+
+fn encode_coin(coin: &Coin) -> Vec<u8> {
+    let mut encoded = vec![];
+    bytes_field(&mut encoded, 1, coin.denom.as_bytes());
+    bytes_field(&mut encoded, 2, coin.amount.to_string().as_bytes());
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use app_harness::canonical_address;
+
+    use super::*;
+
+    #[test]
+    fn protobuf_encoding() {
+        let mut out = vec![];
+        varint_field(&mut out, 3, 300);
+        assert_eq!(out, vec![0x18, 0xac, 0x02]);
+
+        let mut out = vec![];
+        bytes_field(&mut out, 2, b"abc");
+        varint_field(&mut out, 4, 0);
+        bytes_field(&mut out, 5, b"");
+        assert_eq!(out, vec![0x12, 3, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn addresses_are_deterministic() {
+        let alice = MockWallet::new("alice");
+        let bob = MockWallet::new("bob");
+
+        assert_eq!(alice.address(), MockWallet::new("alice").address());
+        assert_ne!(alice.address(), bob.address());
+        assert!(alice.address().starts_with("secret1"));
+        assert_eq!(alice.canonical_address().len(), 20);
+        assert_eq!(
+            canonical_address(&alice.address()),
+            Some(alice.canonical_address())
+        );
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let alice = MockWallet::new("alice");
+        let io_exchange_pubkey = [7u8; 32];
+
+        let nonce = alice.next_nonce();
+        assert_ne!(nonce, alice.next_nonce());
+
+        let code_hash = "ab".repeat(32);
+        let secret_msg = alice.encrypt_msg(&io_exchange_pubkey, &nonce, &code_hash, b"{}");
+        assert_eq!(&secret_msg[..32], &nonce);
+        assert_eq!(&secret_msg[32..64], &alice.tx_encryption_pubkey());
+
+        let plaintext = alice
+            .decrypt(&io_exchange_pubkey, &nonce, &secret_msg[64..])
+            .unwrap();
+        assert_eq!(plaintext, format!("{}{{}}", code_hash).into_bytes());
+
+        assert_eq!(
+            alice.decrypt(&io_exchange_pubkey, &alice.next_nonce(), &secret_msg[64..]),
+            None
+        );
+        assert_eq!(alice.decrypt(&io_exchange_pubkey, &nonce, &[]), None);
+    }
+}
@@ -11,15 +11,19 @@ use crate::backends::{backend, compile};
 */
 use crate::checksum::Checksum;
 use crate::compatability::{check_wasm, check_wasm_exports, REQUIRED_IBC_EXPORTS};
+use crate::enclave_config::prewarm_enclave_module_cache;
 use crate::errors::{VmError, VmResult};
-use crate::features::required_features_from_module;
+use crate::features::{legacy_features, required_features_from_module};
+use crate::hot_modules::HotModules;
 use crate::instance::Instance;
 /*
 use crate::modules::FileSystemCache;
 */
 use crate::traits::{Api, Extern, Querier, Storage};
+use crate::wasmi::analyze_code;
 
 const WASM_DIR: &str = "wasm";
+const HOT_MODULES_FILE: &str = "hot_modules";
 /*
 const MODULES_DIR: &str = "modules";
 */
@@ -37,6 +41,7 @@ struct CosmCacheImpl {
     /*
     modules: FileSystemCache,
     */
+    hot_modules: HotModules,
     stats: Stats,
 }
 
@@ -89,6 +94,7 @@ where
                 /*
                 modules,
                 */
+                hot_modules: HotModules::load(base.join(HOT_MODULES_FILE)),
                 stats: Stats::default(),
             }),
             type_storage: PhantomData::<S>,
@@ -97,9 +103,11 @@ where
         })
     }
 
+    /// Saves code stored before the chain set its engine params, so it may only require the
+    /// features that existed back then
     pub fn save_wasm(&mut self, wasm: &[u8]) -> VmResult<Checksum> {
         let inner = self.inner.lock().unwrap();
-        check_wasm(wasm, &inner.supported_features)?;
+        check_wasm(wasm, &legacy_features(&inner.supported_features))?;
         let checksum = save_wasm_to_disk(&inner.wasm_path, wasm)?;
         /*
         let module = compile(wasm)?;
@@ -108,6 +116,15 @@ where
         Ok(checksum)
     }
 
+    /// Like `save_wasm`, but also runs the checks of the enclave under the given JSON encoded
+    /// engine params, so code the enclave would reject is never stored
+    pub fn save_analyzed_wasm(&mut self, wasm: &[u8], engine_params: &[u8]) -> VmResult<Checksum> {
+        let inner = self.inner.lock().unwrap();
+        check_wasm(wasm, &inner.supported_features)?;
+        analyze_code(wasm, engine_params)?;
+        save_wasm_to_disk(&inner.wasm_path, wasm)
+    }
+
     /// Retrieves a Wasm blob that was previously stored via save_wasm.
     /// When the cache is instantiated with the same base dir, this finds Wasm files on disc across multiple cache instances (i.e. node restarts).
     /// This function is public to allow a checksum to Wasm lookup in the blockchain.
@@ -172,9 +189,35 @@ where
 
         // fall back to wasm cache (and re-compiling) - this is for backends that don't support serialization
         let wasm = self.load_wasm(checksum)?;
-        self.inner.lock().unwrap().stats.misses += 1;
+        let hot_modules = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.stats.misses += 1;
+            inner.hot_modules.touch(checksum)
+        };
+        // Written outside of the lock, so other contract calls don't wait for the disk
+        if let Some(hot_modules) = hot_modules {
+            hot_modules.save();
+        }
         Instance::from_code(&wasm, deps, gas_limit)
     }
+
+    /// Asks the enclave to load the sealed modules of the contracts that ran most recently, so the
+    /// first calls after a restart don't have to instrument them again.
+    ///
+    /// This runs in the background, and contracts that are called before it gets to them are just
+    /// loaded on demand as usual.
+    pub fn prewarm_enclave_module_cache(&self) {
+        let checksums = self.inner.lock().unwrap().hot_modules.checksums().to_vec();
+        if checksums.is_empty() {
+            return;
+        }
+
+        std::thread::spawn(move || {
+            if let Err(err) = prewarm_enclave_module_cache(&checksums) {
+                log::warn!("couldn't prewarm the enclave module cache: {}", err);
+            }
+        });
+    }
 }
 
 /// save stores the wasm code in the given directory and returns an ID for lookup.
@@ -8,7 +8,7 @@ use crate::errors::VmResult;
 /*
 use crate::instance::{Func, Instance};
 */
-use crate::instance::Instance;
+use crate::instance::{HandleBatchCall, HandleBatchOutcome, Instance};
 // use crate::serde::{from_slice, to_vec};
 use crate::traits::{Api, Querier, Storage};
 // use schemars::JsonSchema;
@@ -138,6 +138,17 @@ pub fn call_handle_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'sta
     instance.call_handle(env, msg, sig_info, handle_type)
 }
 
+/// Calls Wasm export "handle" once per call in one entry into the enclave, and returns the raw
+/// data from the contract and the gas used by each call. All the calls must belong to the same
+/// block. A failed call doesn't stop the ones after it.
+pub fn call_handle_batch_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    calls: &[HandleBatchCall],
+) -> VmResult<Vec<HandleBatchOutcome>> {
+    instance.set_storage_readonly(false);
+    instance.call_handle_batch(calls)
+}
+
 /// Calls Wasm export "query" and returns raw data from the contract.
 /// The result is length limited to prevent abuse but otherwise unchecked.
 pub fn call_query_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
@@ -1,4 +1,6 @@
-use parity_wasm::elements::{deserialize_buffer, External, ImportEntry, Module};
+use parity_wasm::elements::{
+    deserialize_buffer, BulkInstruction, External, ImportEntry, Instruction, Module,
+};
 use std::collections::BTreeSet;
 use std::collections::HashSet;
 use std::iter::FromIterator;
@@ -44,6 +46,15 @@ const SUPPORTED_IMPORTS_V1: &[&str] = &[
     "env.ed25519_verify",
     "env.ed25519_batch_verify",
     "env.ed25519_sign",
+    "env.query_permit_verify",
+    "env.viewing_key_verify",
+    "env.contract_secret",
+    "env.contract_secp256k1_pubkey",
+    "env.contract_secp256k1_sign",
+    "env.contract_ed25519_pubkey",
+    "env.contract_ed25519_sign",
+    "env.contract_x25519_pubkey",
+    "env.contract_x25519_ecdh",
     "env.debug",
     "env.query_chain",
     #[cfg(feature = "iterator")]
@@ -101,6 +112,7 @@ pub fn check_wasm(wasm_code: &[u8], supported_features: &HashSet<String>) -> VmR
         }
     };
     check_wasm_memories(&module)?;
+    check_wasm_instructions(&module)?;
     check_wasm_features(&module, supported_features)?;
 
     let check_v010_exports_result = check_wasm_exports(&module, REQUIRED_EXPORTS_V010);
@@ -209,6 +221,32 @@ fn check_wasm_imports(module: &Module, supported_imports: &[&str]) -> VmResult<(
     Ok(())
 }
 
+/// Besides the MVP, contracts may use the sign extension and multi-value proposals, and
+/// `memory.copy` and `memory.fill` from the bulk memory proposal, which newer Rust toolchains emit
+/// by default. The rest of the bulk memory proposal isn't run by the enclave. Other proposals
+/// already fail to deserialize.
+fn check_wasm_instructions(module: &Module) -> VmResult<()> {
+    let bodies = module
+        .code_section()
+        .map_or(&[][..], |code_section| code_section.bodies());
+    for body in bodies {
+        for instruction in body.code().elements() {
+            match instruction {
+                Instruction::Bulk(BulkInstruction::MemoryCopy)
+                | Instruction::Bulk(BulkInstruction::MemoryFill) => {}
+                Instruction::Bulk(bulk_instruction) => {
+                    return Err(VmError::static_validation_err(format!(
+                        "Wasm contract uses unsupported bulk memory instruction: {:?}",
+                        bulk_instruction
+                    )))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
 fn check_wasm_features(module: &Module, supported_features: &HashSet<String>) -> VmResult<()> {
     let required_features = required_features_from_module(module);
     if !required_features.is_subset(supported_features) {
@@ -227,7 +265,7 @@ mod test {
     use super::*;
     use crate::errors::VmError;
     use std::iter::FromIterator;
-    use wabt::wat2wasm;
+    use wabt::{wat2wasm, wat2wasm_with_features, Features};
 
     static CONTRACT_0_6: &[u8] = include_bytes!("../testdata/contract_0.6.wasm");
     static CONTRACT_0_7: &[u8] = include_bytes!("../testdata/contract_0.7.wasm");
@@ -548,4 +586,66 @@ mod test {
             _ => panic!("Got unexpected error"),
         }
     }
+
+    fn post_mvp_features() -> Features {
+        let mut features = Features::new();
+        features.enable_sign_extension();
+        features.enable_multi_value();
+        features.enable_bulk_memory();
+        features
+    }
+
+    #[test]
+    fn test_check_wasm_instructions_ok() {
+        let wasm = wat2wasm_with_features(
+            r#"(module
+            (memory 1)
+            (func $swap (param i32 i32) (result i32 i32)
+                local.get 1
+                local.get 0)
+            (func (param i32) (result i32)
+                local.get 0
+                i32.extend8_s
+                i32.const 1
+                call $swap
+                i32.add)
+            (func (param i32 i32 i32)
+                local.get 0
+                local.get 1
+                local.get 2
+                memory.copy
+                local.get 0
+                local.get 1
+                local.get 2
+                memory.fill)
+        )"#,
+            post_mvp_features(),
+        )
+        .unwrap();
+        check_wasm_instructions(&deserialize_buffer(&wasm).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_check_wasm_instructions_unsupported_bulk_memory() {
+        let wasm = wat2wasm_with_features(
+            r#"(module
+            (memory 1)
+            (data "abc")
+            (func
+                i32.const 0
+                i32.const 0
+                i32.const 3
+                memory.init 0)
+        )"#,
+            post_mvp_features(),
+        )
+        .unwrap();
+        match check_wasm_instructions(&deserialize_buffer(&wasm).unwrap()) {
+            Err(VmError::StaticValidationErr { msg, .. }) => {
+                assert!(msg.starts_with("Wasm contract uses unsupported bulk memory instruction"))
+            }
+            Err(e) => panic!("Unexpected error {:?}", e),
+            Ok(_) => panic!("Didn't reject memory.init"),
+        }
+    }
 }
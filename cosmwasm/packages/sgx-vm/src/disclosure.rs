@@ -0,0 +1,62 @@
+use sgx_types::*;
+
+use crate::enclave::ENCLAVE_DOORBELL;
+
+/// Initial buffer size for the disclosure. It's retried with the exact size if it's too small
+const DISCLOSURE_LEN: usize = 16 * 1024;
+
+extern "C" {
+    pub fn ecall_disclose_tx_keys(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        permit: *const u8,
+        permit_len: usize,
+        out: *mut u8,
+        out_len: u32,
+        out_written: *mut u32,
+    ) -> sgx_status_t;
+}
+
+/// Verifies a disclosure permit, and returns the keys of the txs it lists encrypted to the auditor,
+/// as JSON
+pub fn untrusted_disclose_tx_keys(permit: &[u8]) -> SgxResult<Vec<u8>> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut disclosure = vec![0u8; DISCLOSURE_LEN];
+
+    loop {
+        let mut ret = sgx_status_t::SGX_SUCCESS;
+        let mut written: u32 = 0;
+        let status = unsafe {
+            ecall_disclose_tx_keys(
+                eid,
+                &mut ret,
+                permit.as_ptr(),
+                permit.len(),
+                disclosure.as_mut_ptr(),
+                disclosure.len() as u32,
+                &mut written,
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(status);
+        }
+
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(ret);
+        }
+
+        if written as usize <= disclosure.len() {
+            disclosure.truncate(written as usize);
+            return Ok(disclosure);
+        }
+        disclosure.resize(written as usize, 0);
+    }
+}
@@ -1,5 +1,6 @@
 use std::ops::Deref;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use std::{env, path::Path};
 
 use sgx_types::{
@@ -10,6 +11,7 @@ use sgx_urts::SgxEnclave;
 use lazy_static::lazy_static;
 use log::*;
 use parking_lot::{Condvar, Mutex};
+use serde::Serialize;
 
 #[cfg(feature = "production")]
 const ENCLAVE_DEBUG: i32 = 0;
@@ -56,6 +58,14 @@ fn init_enclave(enclave_file: &str) -> SgxResult<SgxEnclave> {
         sgx_status_t::SGX_ERROR_INVALID_ENCLAVE
     })?;
 
+    #[cfg(not(test))]
+    {
+        use crate::attestation_dcap::COLLATERAL_CONFIG;
+        if let Err(e) = sgx_attestation::collateral_cache::configure_pccs(&COLLATERAL_CONFIG) {
+            warn!("Failed to configure the PCCS for DCAP collateral: {}", e);
+        }
+    }
+
     SgxEnclave::create(
         enclave_file_path,
         debug,
@@ -82,11 +92,16 @@ lazy_static! {
 /// `EnclaveDoorbell` and `EnclaveAccessToken` help control this behavior.
 /// The depth of calls, which determines whether or not they are recursive, is managed by the
 /// `query_depth` parameter that is threaded through the context of each call.
+///
+/// The TCS are split between two lanes. Queries coming from outside of the chain (e.g. from RPC
+/// nodes) use the query lane, everything else uses the transaction lane. As long as no TCS are
+/// reserved for queries, the query lane is empty and queries share the transaction lane, so
+/// heavy query load can only be kept away from block execution by reserving TCS for queries.
 pub struct EnclaveDoorbell {
     enclave: SgxResult<SgxEnclave>,
-    condvar: Condvar,
-    /// Amount of tasks allowed to use the enclave at the same time.
-    count: Mutex<u8>,
+    transactions: DoorbellLane,
+    queries: DoorbellLane,
+    total_slots: u8,
 }
 
 impl EnclaveDoorbell {
@@ -94,47 +109,210 @@ impl EnclaveDoorbell {
         info!("Setting up enclave doorbell for up to {} threads", count);
         Self {
             enclave: init_enclave(enclave_file),
+            transactions: DoorbellLane::new(count),
+            queries: DoorbellLane::new(0),
+            total_slots: count,
+        }
+    }
+
+    /// Moves `slots` TCS from the transaction lane to the query lane. At least one TCS is always
+    /// left for transactions.
+    pub fn reserve_query_slots(&self, slots: u8) {
+        let slots = query_slots(slots, self.total_slots);
+
+        info!(
+            "Reserving {} of {} enclave threads for queries",
+            slots, self.total_slots
+        );
+        self.queries.set_slots(slots);
+        self.transactions.set_slots(self.total_slots - slots);
+    }
+
+    fn wait_for(
+        &'static self,
+        lane: &'static DoorbellLane,
+        duration: Duration,
+        query_depth: u32,
+    ) -> Option<EnclaveAccessToken> {
+        if query_depth != 1 {
+            return Some(EnclaveAccessToken::new(self, None));
+        }
+        if !lane.acquire(duration) {
+            return None;
+        }
+        Some(EnclaveAccessToken::new(self, Some(lane)))
+    }
+
+    pub fn get_access(&'static self, query_depth: u32) -> Option<EnclaveAccessToken> {
+        self.wait_for(
+            &self.transactions,
+            Duration::from_secs(ENCLAVE_LOCK_TIMEOUT),
+            query_depth,
+        )
+    }
+
+    /// Like `get_access`, but for queries, which use the query lane if any TCS are reserved for it
+    pub fn get_query_access(&'static self, query_depth: u32) -> Option<EnclaveAccessToken> {
+        let lane = if self.queries.slots() == 0 {
+            &self.transactions
+        } else {
+            &self.queries
+        };
+        self.wait_for(lane, Duration::from_secs(ENCLAVE_LOCK_TIMEOUT), query_depth)
+    }
+
+    pub fn metrics(&self) -> EnclaveDoorbellMetrics {
+        EnclaveDoorbellMetrics {
+            transactions: self.transactions.metrics(),
+            queries: self.queries.metrics(),
+        }
+    }
+}
+
+/// How many of `total_slots` TCS can be reserved for queries when `requested` are, leaving at
+/// least one for transactions
+fn query_slots(requested: u8, total_slots: u8) -> u8 {
+    let max_slots = total_slots.saturating_sub(1);
+    if requested > max_slots {
+        warn!(
+            "Can't reserve {} of {} enclave threads for queries, reserving {} instead",
+            requested, total_slots, max_slots
+        );
+        return max_slots;
+    }
+    requested
+}
+
+/// Backpressure metrics of the enclave doorbell, per lane
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EnclaveDoorbellMetrics {
+    pub transactions: DoorbellLaneMetrics,
+    pub queries: DoorbellLaneMetrics,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DoorbellLaneMetrics {
+    /// Amount of TCS in the lane
+    pub slots: u8,
+    /// Amount of TCS in the lane that are currently in use
+    pub in_use: u8,
+    /// Amount of tasks currently waiting for a TCS
+    pub queue_depth: u64,
+    /// Amount of times a task entered the enclave through this lane
+    pub entries: u64,
+    /// Amount of times a task had to wait for a TCS, whether it got one or not
+    pub waits: u64,
+    pub total_wait_micros: u64,
+    pub max_wait_micros: u64,
+    /// Amount of times a task gave up waiting for a TCS
+    pub timeouts: u64,
+}
+
+struct LaneState {
+    slots: u8,
+    in_use: u8,
+}
+
+/// A set of TCS that tasks wait on in turn
+struct DoorbellLane {
+    state: Mutex<LaneState>,
+    condvar: Condvar,
+    queue_depth: AtomicU64,
+    entries: AtomicU64,
+    waits: AtomicU64,
+    total_wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl DoorbellLane {
+    fn new(slots: u8) -> Self {
+        Self {
+            state: Mutex::new(LaneState { slots, in_use: 0 }),
             condvar: Condvar::new(),
-            count: Mutex::new(count),
+            queue_depth: AtomicU64::new(0),
+            entries: AtomicU64::new(0),
+            waits: AtomicU64::new(0),
+            total_wait_micros: AtomicU64::new(0),
+            max_wait_micros: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
         }
     }
 
-    fn wait_for(&'static self, duration: Duration, query_depth: u32) -> Option<EnclaveAccessToken> {
-        if query_depth == 1 {
-            let mut count = self.count.lock();
-            if *count == 0 {
-                // try to wait for other tasks to complete
-                let wait = self.condvar.wait_for(&mut count, duration);
-                // double check that the count is nonzero, so there's an available slot in the enclave.
-                if wait.timed_out() || *count == 0 {
-                    return None;
+    fn slots(&self) -> u8 {
+        self.state.lock().slots
+    }
+
+    /// Tasks that are already in the enclave keep their TCS when the lane shrinks, new tasks just
+    /// wait until enough of them leave.
+    fn set_slots(&self, slots: u8) {
+        self.state.lock().slots = slots;
+        self.condvar.notify_all();
+    }
+
+    fn acquire(&self, duration: Duration) -> bool {
+        let mut state = self.state.lock();
+        if state.in_use >= state.slots {
+            // try to wait for other tasks to complete
+            self.queue_depth.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            let deadline = started + duration;
+            while state.in_use >= state.slots {
+                if self.condvar.wait_until(&mut state, deadline).timed_out() {
+                    break;
                 }
             }
-            *count -= 1;
+            self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+
+            let waited = started.elapsed().as_micros() as u64;
+            self.waits.fetch_add(1, Ordering::Relaxed);
+            self.total_wait_micros.fetch_add(waited, Ordering::Relaxed);
+            self.max_wait_micros.fetch_max(waited, Ordering::Relaxed);
+
+            // double check that there's an available slot in the enclave.
+            if state.in_use >= state.slots {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
         }
-        Some(EnclaveAccessToken::new(self, query_depth))
+        state.in_use += 1;
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        true
     }
 
-    pub fn get_access(&'static self, query_depth: u32) -> Option<EnclaveAccessToken> {
-        self.wait_for(Duration::from_secs(ENCLAVE_LOCK_TIMEOUT), query_depth)
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.in_use -= 1;
+        drop(state);
+        self.condvar.notify_one();
+    }
+
+    fn metrics(&self) -> DoorbellLaneMetrics {
+        let state = self.state.lock();
+        DoorbellLaneMetrics {
+            slots: state.slots,
+            in_use: state.in_use,
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            entries: self.entries.load(Ordering::Relaxed),
+            waits: self.waits.load(Ordering::Relaxed),
+            total_wait_micros: self.total_wait_micros.load(Ordering::Relaxed),
+            max_wait_micros: self.max_wait_micros.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
     }
 }
 
 // NEVER add Clone or Copy
 pub struct EnclaveAccessToken {
-    doorbell: &'static EnclaveDoorbell,
     enclave: SgxResult<&'static SgxEnclave>,
-    query_depth: u32,
+    /// The lane this token holds a TCS of. Recursive calls don't hold one.
+    lane: Option<&'static DoorbellLane>,
 }
 
 impl EnclaveAccessToken {
-    fn new(doorbell: &'static EnclaveDoorbell, query_depth: u32) -> Self {
+    fn new(doorbell: &'static EnclaveDoorbell, lane: Option<&'static DoorbellLane>) -> Self {
         let enclave = doorbell.enclave.as_ref().map_err(|status| *status);
-        Self {
-            doorbell,
-            enclave,
-            query_depth,
-        }
+        Self { enclave, lane }
     }
 }
 
@@ -148,11 +326,83 @@ impl Deref for EnclaveAccessToken {
 
 impl Drop for EnclaveAccessToken {
     fn drop(&mut self) {
-        if self.query_depth == 1 {
-            let mut count = self.doorbell.count.lock();
-            *count += 1;
-            drop(count);
-            self.doorbell.condvar.notify_one();
+        if let Some(lane) = self.lane {
+            lane.release();
         }
     }
 }
+
+/// Returns the backpressure metrics of the enclave doorbell
+pub fn enclave_doorbell_metrics() -> EnclaveDoorbellMetrics {
+    ENCLAVE_DOORBELL.metrics()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_slots_leave_one_for_transactions() {
+        assert_eq!(query_slots(0, 8), 0);
+        assert_eq!(query_slots(3, 8), 3);
+        assert_eq!(query_slots(7, 8), 7);
+        assert_eq!(query_slots(8, 8), 7);
+        assert_eq!(query_slots(u8::MAX, 8), 7);
+        assert_eq!(query_slots(1, 1), 0);
+        assert_eq!(query_slots(1, 0), 0);
+    }
+
+    #[test]
+    fn lane_acquire_and_release() {
+        let lane = DoorbellLane::new(2);
+        assert!(lane.acquire(Duration::from_millis(10)));
+        assert!(lane.acquire(Duration::from_millis(10)));
+        assert!(!lane.acquire(Duration::from_millis(10)));
+
+        lane.release();
+        assert!(lane.acquire(Duration::from_millis(10)));
+
+        let metrics = lane.metrics();
+        assert_eq!(metrics.slots, 2);
+        assert_eq!(metrics.in_use, 2);
+        assert_eq!(metrics.queue_depth, 0);
+        assert_eq!(metrics.entries, 3);
+        assert_eq!(metrics.waits, 1);
+        assert_eq!(metrics.timeouts, 1);
+        assert!(metrics.max_wait_micros >= 10_000);
+    }
+
+    #[test]
+    fn lane_wakes_up_waiting_tasks() {
+        let lane = std::sync::Arc::new(DoorbellLane::new(1));
+        assert!(lane.acquire(Duration::from_millis(10)));
+
+        let waiting = {
+            let lane = lane.clone();
+            std::thread::spawn(move || lane.acquire(Duration::from_secs(10)))
+        };
+        while lane.metrics().queue_depth == 0 {
+            std::thread::yield_now();
+        }
+        lane.release();
+
+        assert!(waiting.join().unwrap());
+        let metrics = lane.metrics();
+        assert_eq!(metrics.in_use, 1);
+        assert_eq!(metrics.waits, 1);
+        assert_eq!(metrics.timeouts, 0);
+    }
+
+    #[test]
+    fn lane_shrinks_without_evicting() {
+        let lane = DoorbellLane::new(2);
+        assert!(lane.acquire(Duration::from_millis(10)));
+        assert!(lane.acquire(Duration::from_millis(10)));
+
+        lane.set_slots(1);
+        lane.release();
+        assert!(!lane.acquire(Duration::from_millis(10)));
+        lane.release();
+        assert!(lane.acquire(Duration::from_millis(10)));
+    }
+}
@@ -5,6 +5,7 @@ use sgx_types::{sgx_enclave_id_t, sgx_status_t, SgxResult};
 
 use enclave_ffi_types::RuntimeConfiguration;
 
+use crate::checksum::Checksum;
 use crate::enclave::ENCLAVE_DOORBELL;
 
 lazy_static! {
@@ -18,10 +19,20 @@ extern "C" {
         retval: *mut sgx_status_t,
         config: RuntimeConfiguration,
     ) -> sgx_status_t;
+
+    pub fn ecall_prewarm_module_cache(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        code_hashes: *const u8,
+        code_hashes_len: usize,
+    ) -> sgx_status_t;
 }
 
 pub struct EnclaveRuntimeConfig {
     pub module_cache_size: u32,
+    /// How many of the enclave's threads are reserved for queries, 0 to let queries share them
+    /// with transactions. This only affects the host.
+    pub query_slots: u8,
 }
 
 impl EnclaveRuntimeConfig {
@@ -40,6 +51,8 @@ pub fn configure_enclave(config: EnclaveRuntimeConfig) -> SgxResult<()> {
     *configured = true;
     drop(configured);
 
+    ENCLAVE_DOORBELL.reserve_query_slots(config.query_slots);
+
     // Bind the token to a local variable to ensure its
     // destructor runs in the end of the function
     let enclave_access_token = ENCLAVE_DOORBELL
@@ -62,3 +75,41 @@ pub fn configure_enclave(config: EnclaveRuntimeConfig) -> SgxResult<()> {
 
     Ok(())
 }
+
+/// Loads the sealed modules of the given contracts, hottest first, into the module cache of the
+/// enclave. Must be called after `configure_enclave`, since the enclave only fills the cache up to
+/// its configured size.
+pub fn prewarm_enclave_module_cache(checksums: &[Checksum]) -> SgxResult<()> {
+    let code_hashes: Vec<u8> = checksums
+        .iter()
+        .flat_map(|checksum| Into::<Vec<u8>>::into(*checksum))
+        .collect();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+
+    let status = unsafe {
+        ecall_prewarm_module_cache(
+            enclave.geteid(),
+            &mut retval,
+            code_hashes.as_ptr(),
+            code_hashes.len(),
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use sgx_types::*;
+
+use crate::enclave::ENCLAVE_DOORBELL;
+
+/// Initial buffer size for the metrics. It's retried with the exact size if it's too small
+const METRICS_LEN: usize = 4 * 1024;
+
+extern "C" {
+    pub fn ecall_get_enclave_metrics(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        out: *mut u8,
+        out_len: u32,
+        out_written: *mut u32,
+    ) -> sgx_status_t;
+}
+
+/// Returns the runtime metrics of the enclave, such as module cache hits, ocall counts and block
+/// verification times, as JSON. Like queries, this uses the query lane if any TCS are reserved
+/// for it, so it doesn't hold up transactions.
+pub fn untrusted_get_enclave_metrics() -> SgxResult<Vec<u8>> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_query_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut metrics = vec![0u8; METRICS_LEN];
+
+    loop {
+        let mut ret = sgx_status_t::SGX_SUCCESS;
+        let mut written: u32 = 0;
+        let status = unsafe {
+            ecall_get_enclave_metrics(
+                eid,
+                &mut ret,
+                metrics.as_mut_ptr(),
+                metrics.len() as u32,
+                &mut written,
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(status);
+        }
+
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(ret);
+        }
+
+        if written as usize <= metrics.len() {
+            metrics.truncate(written as usize);
+            return Ok(metrics);
+        }
+        metrics.resize(written as usize, 0);
+    }
+}
@@ -1,4 +1,5 @@
 use super::VmError;
+use enclave_ffi_types::EnclaveErrorContext;
 use snafu::{Backtrace, Snafu};
 
 /// An error in the communication with the enclave
@@ -8,6 +9,9 @@ pub enum EnclaveError {
     #[snafu(display("{}", error))]
     EnclaveErr {
         error: enclave_ffi_types::EnclaveError,
+        context: EnclaveErrorContext,
+        /// The wasm backtrace of a trap, from non-production enclaves
+        wasm_backtrace: Option<String>,
         backtrace: Backtrace,
     },
     #[snafu(display("SGX error: {:?}", status))]
@@ -19,7 +23,21 @@ pub enum EnclaveError {
 
 impl EnclaveError {
     pub fn enclave_err(error: enclave_ffi_types::EnclaveError) -> Self {
-        EnclaveErr { error }.build()
+        let context = EnclaveErrorContext::from(&error);
+        Self::enclave_err_with_context(error, context, None)
+    }
+
+    pub fn enclave_err_with_context(
+        error: enclave_ffi_types::EnclaveError,
+        context: EnclaveErrorContext,
+        wasm_backtrace: Option<String>,
+    ) -> Self {
+        EnclaveErr {
+            error,
+            context,
+            wasm_backtrace,
+        }
+        .build()
     }
 
     pub fn sdk_err(status: sgx_types::sgx_status_t) -> Self {
@@ -35,13 +53,45 @@ impl From<EnclaveError> for VmError {
 
 impl From<enclave_ffi_types::EnclaveError> for VmError {
     fn from(error: enclave_ffi_types::EnclaveError) -> Self {
+        let context = EnclaveErrorContext::from(&error);
+        VmError::from_enclave_failure(error, context, None)
+    }
+}
+
+impl VmError {
+    /// Converts an error returned by an ecall, along with the context the enclave reported for it
+    pub(crate) fn from_enclave_failure(
+        error: enclave_ffi_types::EnclaveError,
+        context: EnclaveErrorContext,
+        wasm_backtrace: Option<String>,
+    ) -> Self {
         match error {
             enclave_ffi_types::EnclaveError::OutOfGas => VmError::GasDepletion,
             enclave_ffi_types::EnclaveError::FailedOcall { vm_error }
                 if !vm_error.ptr.is_null() =>
             // This error is boxed during ocalls.
             unsafe { *Box::<VmError>::from_raw(vm_error.ptr as *mut _) }
-            other => EnclaveError::enclave_err(other).into(),
+            other => EnclaveError::enclave_err_with_context(other, context, wasm_backtrace).into(),
+        }
+    }
+
+    /// The stable code and the context of the error, if it was returned by the enclave
+    pub fn enclave_error_context(&self) -> Option<(u32, EnclaveErrorContext)> {
+        match self {
+            VmError::EnclaveErr {
+                source: EnclaveError::EnclaveErr { error, context, .. },
+            } => Some((error.code(), *context)),
+            _ => None,
+        }
+    }
+
+    /// The wasm backtrace of the error, if it was a trap in a non-production enclave
+    pub fn wasm_backtrace(&self) -> Option<&str> {
+        match self {
+            VmError::EnclaveErr {
+                source: EnclaveError::EnclaveErr { wasm_backtrace, .. },
+            } => wasm_backtrace.as_deref(),
+            _ => None,
         }
     }
 }
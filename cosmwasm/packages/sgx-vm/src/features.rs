@@ -5,6 +5,14 @@ use std::iter::FromIterator;
 
 const REQUIRES_PREFIX: &str = "requires_";
 
+/// Features that may be followed by a parameter, like `requires_padding_1024`. The enclave
+/// validates the parameter, so only the feature itself has to be supported.
+const PARAMETERIZED_FEATURES: &[&str] = &["padding"];
+
+/// Features the enclave only applies once the chain set its engine params. Code stored before then
+/// may not require them, like it couldn't before they existed.
+const ENGINE_PARAMS_FEATURES: &[&str] = &["padding", "nan_canonicalization", "softfloat"];
+
 /// Takes a comma-separated string, splits it by commas, removes empty elements and returns a set of features.
 /// This can be used e.g. to initialize the cache.
 pub fn features_from_csv(csv: &str) -> HashSet<String> {
@@ -15,6 +23,16 @@ pub fn features_from_csv(csv: &str) -> HashSet<String> {
     )
 }
 
+/// Returns the features of `supported_features` that code stored before the chain set its engine
+/// params may require
+pub fn legacy_features(supported_features: &HashSet<String>) -> HashSet<String> {
+    supported_features
+        .iter()
+        .filter(|feature| !ENGINE_PARAMS_FEATURES.contains(&feature.as_str()))
+        .cloned()
+        .collect()
+}
+
 /*
 pub fn required_features_from_wasmer_instance(wasmer_instance: &WasmerInstance) -> HashSet<String> {
     HashSet::from_iter(wasmer_instance.exports().filter_map(|(mut name, export)| {
@@ -40,7 +58,7 @@ pub fn required_features_from_module(module: &Module) -> HashSet<String> {
                     let name = entry.field();
                     if name.starts_with(REQUIRES_PREFIX) && name.len() > REQUIRES_PREFIX.len() {
                         let (_, required_feature) = name.split_at(REQUIRES_PREFIX.len());
-                        return Some(required_feature.to_string());
+                        return Some(without_parameter(required_feature).to_string());
                     }
                 }
                 None
@@ -49,6 +67,17 @@ pub fn required_features_from_module(module: &Module) -> HashSet<String> {
     }
 }
 
+fn without_parameter(feature: &str) -> &str {
+    PARAMETERIZED_FEATURES
+        .iter()
+        .find(|parameterized| {
+            feature.len() > parameterized.len()
+                && feature.starts_with(*parameterized)
+                && feature.as_bytes()[parameterized.len()] == b'_'
+        })
+        .map_or(feature, |parameterized| parameterized)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -78,6 +107,14 @@ mod test {
         assert!(set.contains("b"));
     }
 
+    #[test]
+    fn legacy_features_drops_engine_params_features() {
+        let set = legacy_features(&features_from_csv(
+            "random,padding,staking,nan_canonicalization,softfloat",
+        ));
+        assert_eq!(set, features_from_csv("random,staking"));
+    }
+
     #[test]
     fn required_features_from_module_works() {
         let wasm = wat2wasm(
@@ -102,6 +139,27 @@ mod test {
         assert!(required_features.contains("water"));
     }
 
+    #[test]
+    fn required_features_from_module_drops_parameters() {
+        let wasm = wat2wasm(
+            r#"(module
+            (type (func))
+            (func (type 0) nop)
+            (export "requires_padding_1024" (func 0))
+            (export "requires_paddingless" (func 0))
+            (export "requires_random" (func 0))
+            )"#,
+        )
+        .unwrap();
+        let module = deserialize_buffer(&wasm).unwrap();
+
+        let required_features = required_features_from_module(&module);
+        assert_eq!(required_features.len(), 3);
+        assert!(required_features.contains("padding"));
+        assert!(required_features.contains("paddingless"));
+        assert!(required_features.contains("random"));
+    }
+
     #[test]
     fn required_features_from_module_works_without_exports_section() {
         let wasm = wat2wasm(r#"(module)"#).unwrap();
@@ -0,0 +1,195 @@
+//! The contracts this node ran most recently, kept on disk so the enclave can load their sealed
+//! modules in the background after a restart instead of instrumenting them again on first use.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::*;
+
+use crate::checksum::Checksum;
+
+/// How many contracts are tracked. The enclave stops prewarming once its module cache is full,
+/// so this only needs to be larger than any sensible module cache size.
+pub const HOT_MODULES_LIMIT: usize = 100;
+
+const CHECKSUM_SIZE: usize = 32;
+
+pub struct HotModules {
+    path: PathBuf,
+    /// Most recently used first
+    checksums: Vec<Checksum>,
+    /// Bumped whenever the list needs saving
+    generation: u64,
+    /// The generation that's on disk
+    saved_generation: Arc<Mutex<u64>>,
+}
+
+/// A copy of the list to save. It's taken under whatever lock guards the `HotModules`, and saved
+/// after that lock is released.
+pub struct HotModulesSnapshot {
+    path: PathBuf,
+    checksums: Vec<Checksum>,
+    generation: u64,
+    saved_generation: Arc<Mutex<u64>>,
+}
+
+impl HotModules {
+    /// Loads the list from `path`. A missing or corrupted list just means nothing gets prewarmed.
+    pub fn load(path: PathBuf) -> Self {
+        let checksums = match fs::read(&path) {
+            Ok(bytes) if bytes.len() % CHECKSUM_SIZE == 0 => bytes
+                .chunks_exact(CHECKSUM_SIZE)
+                .map(|chunk| {
+                    let mut checksum = [0u8; CHECKSUM_SIZE];
+                    checksum.copy_from_slice(chunk);
+                    Checksum::from(checksum)
+                })
+                .take(HOT_MODULES_LIMIT)
+                .collect(),
+            Ok(_) => {
+                warn!(
+                    "ignoring corrupted list of hot modules at {}",
+                    path.display()
+                );
+                vec![]
+            }
+            Err(_) => vec![],
+        };
+
+        Self {
+            path,
+            checksums,
+            generation: 0,
+            saved_generation: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    pub fn checksums(&self) -> &[Checksum] {
+        &self.checksums
+    }
+
+    /// Marks the contract as used, and returns the list to save if a contract joined it. Changes to
+    /// the order of the contracts that are already in it aren't worth the I/O.
+    pub fn touch(&mut self, checksum: &Checksum) -> Option<HotModulesSnapshot> {
+        match self.checksums.iter().position(|hot| hot == checksum) {
+            Some(0) => None,
+            Some(index) => {
+                let checksum = self.checksums.remove(index);
+                self.checksums.insert(0, checksum);
+                None
+            }
+            None => {
+                self.checksums.insert(0, *checksum);
+                self.checksums.truncate(HOT_MODULES_LIMIT);
+                self.generation += 1;
+                Some(HotModulesSnapshot {
+                    path: self.path.clone(),
+                    checksums: self.checksums.clone(),
+                    generation: self.generation,
+                    saved_generation: self.saved_generation.clone(),
+                })
+            }
+        }
+    }
+}
+
+impl HotModulesSnapshot {
+    /// Writes the list to disk, unless a newer snapshot was saved in the meantime
+    pub fn save(self) {
+        let mut saved_generation = self.saved_generation.lock().unwrap();
+        if *saved_generation >= self.generation {
+            return;
+        }
+
+        let bytes: Vec<u8> = self
+            .checksums
+            .iter()
+            .flat_map(|checksum| Into::<Vec<u8>>::into(*checksum))
+            .collect();
+
+        match fs::write(&self.path, bytes) {
+            Ok(()) => *saved_generation = self.generation,
+            Err(err) => warn!(
+                "couldn't save the list of hot modules to {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn touch_keeps_most_recent_first() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut hot_modules = HotModules::load(tmp_dir.path().join("hot_modules"));
+        assert!(hot_modules.checksums().is_empty());
+
+        let first = Checksum::from([1u8; 32]);
+        let second = Checksum::from([2u8; 32]);
+        hot_modules.touch(&first);
+        hot_modules.touch(&second);
+        assert_eq!(hot_modules.checksums(), &[second, first]);
+
+        hot_modules.touch(&first);
+        assert_eq!(hot_modules.checksums(), &[first, second]);
+    }
+
+    #[test]
+    fn touch_is_limited() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut hot_modules = HotModules::load(tmp_dir.path().join("hot_modules"));
+
+        for i in 0..=HOT_MODULES_LIMIT {
+            hot_modules.touch(&Checksum::from([i as u8; 32]));
+        }
+        assert_eq!(hot_modules.checksums().len(), HOT_MODULES_LIMIT);
+        assert_eq!(
+            hot_modules.checksums()[0],
+            Checksum::from([HOT_MODULES_LIMIT as u8; 32])
+        );
+    }
+
+    #[test]
+    fn load_works_across_instances() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("hot_modules");
+
+        let checksum = Checksum::from([7u8; 32]);
+        HotModules::load(path.clone())
+            .touch(&checksum)
+            .unwrap()
+            .save();
+
+        assert_eq!(HotModules::load(path).checksums(), &[checksum]);
+    }
+
+    #[test]
+    fn load_ignores_corrupted_list() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("hot_modules");
+        fs::write(&path, [1u8; 33]).unwrap();
+
+        assert!(HotModules::load(path).checksums().is_empty());
+    }
+
+    #[test]
+    fn stale_snapshot_is_not_saved() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("hot_modules");
+        let mut hot_modules = HotModules::load(path.clone());
+
+        let first = Checksum::from([1u8; 32]);
+        let second = Checksum::from([2u8; 32]);
+        let stale = hot_modules.touch(&first).unwrap();
+        hot_modules.touch(&second).unwrap().save();
+        stale.save();
+
+        assert_eq!(HotModules::load(path).checksums(), &[second, first]);
+    }
+}
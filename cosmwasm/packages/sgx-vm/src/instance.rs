@@ -43,6 +43,22 @@ use crate::wasmi::Module;
 const WASM_PAGE_SIZE: u64 = 64 * 1024;
 */
 
+/// One call of a batch of handle calls to the same contract
+pub struct HandleBatchCall<'a> {
+    pub env: &'a [u8],
+    pub msg: &'a [u8],
+    pub sig_info: &'a [u8],
+    pub handle_type: u8,
+    /// The most gas the call may use. It can't use more than the calls before it left either.
+    pub gas_limit: u64,
+}
+
+/// The result of one call of a batch, along with the gas it used
+pub struct HandleBatchOutcome {
+    pub result: VmResult<Vec<u8>>,
+    pub gas_used: u64,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct GasReport {
     /// The original limit the instance was created with
@@ -378,6 +394,20 @@ where
         Ok(result.into_output())
     }
 
+    pub fn call_handle_batch(
+        &mut self,
+        calls: &[HandleBatchCall],
+    ) -> VmResult<Vec<HandleBatchOutcome>> {
+        let outcomes = self.inner.handle_batch(calls)?;
+        Ok(outcomes
+            .into_iter()
+            .map(|(result, gas_used)| HandleBatchOutcome {
+                result: result.map(|success| success.into_output()),
+                gas_used,
+            })
+            .collect())
+    }
+
     pub fn call_query(&mut self, env: &[u8], msg: &[u8]) -> VmResult<Vec<u8>> {
         let result = self.inner.query(env, msg)?;
         Ok(result.into_output())
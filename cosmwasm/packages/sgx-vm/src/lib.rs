@@ -8,6 +8,7 @@ mod conversion;
 mod errors;
 mod features;
 mod ffi;
+mod hot_modules;
 // mod imports;
 mod instance;
 // mod memory;
@@ -20,8 +21,10 @@ mod traits;
 // Secret Network specific modules
 mod attestation;
 mod attestation_dcap;
+mod disclosure;
 mod enclave;
 mod enclave_config;
+mod enclave_metrics;
 mod seed;
 mod wasmi;
 
@@ -29,7 +32,8 @@ mod random;
 
 pub use crate::cache::CosmCache;
 pub use crate::calls::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, call_update_admin_raw,
+    call_handle_batch_raw, call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw,
+    call_update_admin_raw,
 };
 pub use crate::checksum::Checksum;
 pub use crate::errors::{
@@ -38,8 +42,10 @@ pub use crate::errors::{
 };
 pub use crate::features::features_from_csv;
 pub use crate::ffi::{FfiError, FfiResult, GasInfo};
-pub use crate::instance::{GasReport, Instance};
+pub use crate::instance::{GasReport, HandleBatchCall, HandleBatchOutcome, Instance};
+pub use enclave::{enclave_doorbell_metrics, DoorbellLaneMetrics, EnclaveDoorbellMetrics};
 pub use enclave_config::{configure_enclave, EnclaveRuntimeConfig};
+pub use enclave_ffi_types::{error_details, EnclaveErrorContext, EnclaveErrorStage};
 /*
 pub use crate::modules::FileSystemCache;
 */
@@ -55,7 +61,9 @@ pub use crate::attestation::{
 };
 pub use crate::seed::{
     untrusted_health_check, untrusted_init_bootstrap, untrusted_init_node, untrusted_key_gen,
-    untrusted_migrate_sealing,
+    untrusted_list_sealed_files, untrusted_migrate_sealing, untrusted_reseal_secrets,
 };
 
+pub use crate::disclosure::untrusted_disclose_tx_keys;
+pub use crate::enclave_metrics::untrusted_get_enclave_metrics;
 pub use crate::random::untrusted_submit_block_signatures;
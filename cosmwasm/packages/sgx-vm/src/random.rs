@@ -98,7 +98,7 @@ fn submit_block_signature_impl(
             txs.len() as u32,
             encrypted_random.as_ptr(),
             encrypted_random.len() as u32,
-            &mut random_decrypted
+            &mut random_decrypted,
         )
     };
 
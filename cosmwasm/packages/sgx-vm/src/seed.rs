@@ -8,6 +8,11 @@ use log::{error, info};
 
 use crate::enclave::ENCLAVE_DOORBELL;
 
+/// Set in the `flags` of `ecall_reseal_secrets` to bind the sealing key to the current CPUSVN
+const RESEAL_FLAG_PIN_CPU_SVN: u32 = 1;
+/// Initial buffer size for the sealed files list. It's retried with the exact size if it's too small
+const SEALED_FILES_LIST_LEN: usize = 4 * 1024;
+
 extern "C" {
     pub fn ecall_init_node(
         eid: sgx_enclave_id_t,
@@ -38,6 +43,21 @@ extern "C" {
 
     pub fn ecall_migrate_sealing(eid: sgx_enclave_id_t, retval: *mut sgx_status_t) -> sgx_status_t;
 
+    pub fn ecall_reseal_secrets(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        key_policy: u32,
+        flags: u32,
+    ) -> sgx_status_t;
+
+    pub fn ecall_list_sealed_files(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        out: *mut u8,
+        out_len: u32,
+        out_written: *mut u32,
+    ) -> sgx_status_t;
+
     /// Trigger a query method in a wasm contract
     pub fn ecall_health_check(
         eid: sgx_enclave_id_t,
@@ -157,6 +177,77 @@ pub fn untrusted_migrate_sealing() -> SgxResult<()> {
     Ok(())
 }
 
+/// Re-seals every sealed secret with a fresh key. `key_policy` is either
+/// `SGX_KEYPOLICY_MRENCLAVE` or `SGX_KEYPOLICY_MRSIGNER`
+pub fn untrusted_reseal_secrets(key_policy: u16, pin_cpu_svn: bool) -> SgxResult<()> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let flags = if pin_cpu_svn {
+        RESEAL_FLAG_PIN_CPU_SVN
+    } else {
+        0
+    };
+    let status = unsafe { ecall_reseal_secrets(eid, &mut ret, key_policy as u32, flags) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(())
+}
+
+/// Returns a JSON list of the sealed secrets, with the format and policy each is sealed with
+pub fn untrusted_list_sealed_files() -> SgxResult<Vec<u8>> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut list = vec![0u8; SEALED_FILES_LIST_LEN];
+
+    loop {
+        let mut ret = sgx_status_t::SGX_SUCCESS;
+        let mut written: u32 = 0;
+        let status = unsafe {
+            ecall_list_sealed_files(
+                eid,
+                &mut ret,
+                list.as_mut_ptr(),
+                list.len() as u32,
+                &mut written,
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(status);
+        }
+
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(ret);
+        }
+
+        if written as usize <= list.len() {
+            list.truncate(written as usize);
+            return Ok(list);
+        }
+        list.resize(written as usize, 0);
+    }
+}
+
 pub fn untrusted_key_gen() -> SgxResult<[u8; 32]> {
     info!("Initializing enclave..");
 
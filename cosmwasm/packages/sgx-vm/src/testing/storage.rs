@@ -44,7 +44,7 @@ impl StorageIterator for MockIterator<'_> {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct MockStorage {
     data: BTreeMap<Vec<u8>, Vec<u8>>,
 }
@@ -5,7 +5,8 @@ use log::*;
 use sgx_types::{sgx_enclave_id_t, sgx_status_t, SgxResult};
 
 use enclave_ffi_types::{
-    Ctx, EnclaveBuffer, HandleResult, InitResult, MigrateResult, QueryResult, UpdateAdminResult,
+    AnalyzeCodeResult, Ctx, EnclaveBuffer, HandleBatchResult, HandleResult, InitResult,
+    MigrateResult, QueryResult, UpdateAdminResult,
 };
 
 use crate::enclave::ENCLAVE_DOORBELL;
@@ -19,6 +20,16 @@ extern "C" {
         length: usize,
     ) -> sgx_status_t;
 
+    /// Validate contract code that is being stored
+    pub fn ecall_analyze_code(
+        eid: sgx_enclave_id_t,
+        retval: *mut AnalyzeCodeResult,
+        code: *const u8,
+        code_len: usize,
+        engine_params: *const u8,
+        engine_params_len: usize,
+    ) -> sgx_status_t;
+
     pub fn ecall_migrate(
         eid: sgx_enclave_id_t,
         retval: *mut MigrateResult,
@@ -90,6 +101,22 @@ extern "C" {
         sig_info_len: usize,
         handle_type: u8,
     ) -> sgx_status_t;
+
+    /// Trigger the handle method of several messages in one enclave entry
+    pub fn ecall_handle_batch(
+        eid: sgx_enclave_id_t,
+        retval: *mut HandleBatchResult,
+        context: Ctx,
+        gas_left: u64,
+        gas_limits: *const u64,
+        contract: *const u8,
+        contract_len: usize,
+        batch: *const u8,
+        batch_len: usize,
+        batch_size: usize,
+        results: *mut HandleResult,
+        used_gas: *mut u64,
+    ) -> sgx_status_t;
 }
 
 extern "C" {
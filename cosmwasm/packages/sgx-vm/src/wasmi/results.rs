@@ -1,6 +1,9 @@
 use super::exports;
-use crate::VmResult;
-use enclave_ffi_types::{HandleResult, InitResult, MigrateResult, QueryResult, UpdateAdminResult};
+use crate::{VmError, VmResult};
+use enclave_ffi_types::{
+    AnalyzeCodeResult, EnclaveError, EnclaveErrorContext, HandleBatchResult, HandleResult,
+    InitResult, MigrateResult, QueryResult, UpdateAdminResult, UserSpaceBuffer,
+};
 
 /// This struct is returned from module initialization.
 pub struct InitSuccess {
@@ -31,7 +34,11 @@ pub fn init_result_to_vm_result(other: InitResult) -> VmResult<InitSuccess> {
             contract_key,
             admin_proof,
         }),
-        InitResult::Failure { err } => Err(err.into()),
+        InitResult::Failure {
+            err,
+            context,
+            backtrace,
+        } => Err(enclave_failure(err, context, backtrace)),
     }
 }
 
@@ -46,7 +53,11 @@ pub fn migrate_result_to_vm_result(other: MigrateResult) -> VmResult<MigrateSucc
             new_contract_key,
             new_contract_key_proof,
         }),
-        MigrateResult::Failure { err } => Err(err.into()),
+        MigrateResult::Failure {
+            err,
+            context,
+            backtrace,
+        } => Err(enclave_failure(err, context, backtrace)),
     }
 }
 
@@ -55,7 +66,9 @@ pub fn update_admin_result_to_vm_result(other: UpdateAdminResult) -> VmResult<Up
         UpdateAdminResult::UpdateAdminSuccess { new_admin_proof } => {
             Ok(UpdateAdminSuccess { new_admin_proof })
         }
-        UpdateAdminResult::UpdateAdminFailure { err } => Err(err.into()),
+        UpdateAdminResult::UpdateAdminFailure { err, context } => {
+            Err(VmError::from_enclave_failure(err, context, None))
+        }
     }
 }
 
@@ -104,7 +117,21 @@ pub fn handle_result_to_vm_result(other: HandleResult) -> VmResult<HandleSuccess
         HandleResult::Success { output } => Ok(HandleSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
         }),
-        HandleResult::Failure { err } => Err(err.into()),
+        HandleResult::Failure {
+            err,
+            context,
+            backtrace,
+        } => Err(enclave_failure(err, context, backtrace)),
+    }
+}
+
+/// Returns the amount of messages of the batch that were executed
+pub fn handle_batch_result_to_vm_result(other: HandleBatchResult) -> VmResult<usize> {
+    match other {
+        HandleBatchResult::Success { executed } => Ok(executed),
+        HandleBatchResult::Failure { err, context } => {
+            Err(VmError::from_enclave_failure(err, context, None))
+        }
     }
 }
 
@@ -125,6 +152,32 @@ pub fn query_result_to_vm_result(other: QueryResult) -> VmResult<QuerySuccess> {
         QueryResult::Success { output } => Ok(QuerySuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
         }),
-        QueryResult::Failure { err } => Err(err.into()),
+        QueryResult::Failure {
+            err,
+            context,
+            backtrace,
+        } => Err(enclave_failure(err, context, backtrace)),
+    }
+}
+
+pub fn analyze_code_result_to_vm_result(other: AnalyzeCodeResult) -> VmResult<()> {
+    match other {
+        AnalyzeCodeResult::Success => Ok(()),
+        AnalyzeCodeResult::Failure { err, context } => {
+            Err(VmError::from_enclave_failure(err, context, None))
+        }
     }
 }
+
+/// Converts the failure of an ecall that ran a contract, along with the wasm backtrace the enclave
+/// may have sent
+fn enclave_failure(
+    err: EnclaveError,
+    context: EnclaveErrorContext,
+    backtrace: UserSpaceBuffer,
+) -> VmError {
+    let backtrace = unsafe { exports::recover_buffer(backtrace) }
+        .map(|backtrace| String::from_utf8_lossy(&backtrace).into_owned());
+
+    VmError::from_enclave_failure(err, context, backtrace)
+}
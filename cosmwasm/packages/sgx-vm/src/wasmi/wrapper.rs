@@ -6,10 +6,12 @@ use std::mem::MaybeUninit;
 
 use crate::enclave::ENCLAVE_DOORBELL;
 use crate::errors::{EnclaveError, VmResult};
+use crate::instance::HandleBatchCall;
 use crate::{Querier, Storage, VmError};
 
 use enclave_ffi_types::{
-    Ctx, HandleResult, InitResult, MigrateResult, QueryResult, UpdateAdminResult,
+    AnalyzeCodeResult, Ctx, HandleBatchResult, HandleResult, InitResult, MigrateResult,
+    QueryResult, UpdateAdminResult,
 };
 
 use sgx_types::sgx_status_t;
@@ -23,8 +25,9 @@ use serde::Deserialize;
 use super::exports::FullContext;
 use super::imports;
 use super::results::{
-    handle_result_to_vm_result, init_result_to_vm_result, query_result_to_vm_result, HandleSuccess,
-    InitSuccess, QuerySuccess, UpdateAdminSuccess,
+    analyze_code_result_to_vm_result, handle_batch_result_to_vm_result, handle_result_to_vm_result,
+    init_result_to_vm_result, query_result_to_vm_result, HandleSuccess, InitSuccess, QuerySuccess,
+    UpdateAdminSuccess,
 };
 
 pub struct Module<S, Q>
@@ -327,6 +330,87 @@ where
         }
     }
 
+    /// Runs several handle calls of this contract in one enclave entry. Each call is limited to
+    /// its own gas limit and to the gas the calls before it left, so the batch as a whole never
+    /// uses more than the gas that was left when it started. The enclave stops after a call that
+    /// failed or whose response dispatches messages, so this returns the results and the gas used
+    /// of the calls that ran, which are at least the first one, in the same order as the calls.
+    pub fn handle_batch(
+        &mut self,
+        calls: &[HandleBatchCall],
+    ) -> VmResult<Vec<(VmResult<HandleSuccess>, u64)>> {
+        trace!(
+            "handle_batch() called with {} calls, gas_left: {}",
+            calls.len(),
+            self.gas_left()
+        );
+
+        if calls.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let batch = encode_handle_batch(calls);
+        let gas_limits: Vec<u64> = calls.iter().map(|call| call.gas_limit).collect();
+
+        let mut batch_result = MaybeUninit::<HandleBatchResult>::uninit();
+        let mut results = Vec::<HandleResult>::with_capacity(calls.len());
+        let mut used_gas = vec![0_u64; calls.len()];
+
+        // Bind the token to a local variable to ensure its
+        // destructor runs in the end of the function
+        let enclave_access_token = ENCLAVE_DOORBELL
+            .get_access(1) // This can never be recursive
+            .ok_or_else(Self::busy_enclave_err)?;
+        let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+        let status = unsafe {
+            imports::ecall_handle_batch(
+                enclave.geteid(),
+                batch_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                gas_limits.as_ptr(),
+                self.bytecode.as_ptr(),
+                self.bytecode.len(),
+                batch.as_ptr(),
+                batch.len(),
+                calls.len(),
+                results.as_mut_ptr(),
+                used_gas.as_mut_ptr(),
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(EnclaveError::sdk_err(status).into());
+        }
+        let executed = handle_batch_result_to_vm_result(unsafe { batch_result.assume_init() })?;
+        if executed == 0 || executed > calls.len() {
+            return Err(VmError::generic_err(format!(
+                "the enclave executed {} of the {} calls of the batch",
+                executed,
+                calls.len()
+            )));
+        }
+        // The enclave wrote a result for every call it executed
+        unsafe { results.set_len(executed) };
+        used_gas.truncate(executed);
+
+        trace!(
+            "handle_batch() returned with gas_used: {:?} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        for gas in used_gas.iter() {
+            self.consume_gas(*gas);
+        }
+
+        Ok(results
+            .into_iter()
+            .map(handle_result_to_vm_result)
+            .zip(used_gas)
+            .collect())
+    }
+
     pub fn query(&mut self, env: &[u8], msg: &[u8]) -> VmResult<QuerySuccess> {
         trace!(
             "query() called with env: {:?} msg: {:?}",
@@ -342,7 +426,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = doorbell
-            .get_access(get_query_depth(env)?)
+            .get_query_access(get_call_depth(env)?)
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -384,6 +468,21 @@ where
     }
 }
 
+/// Lays the calls out the way `ecall_handle_batch` expects them: the handle type, followed by the
+/// env, msg and sig_info, each prefixed by its length as a big endian u32. The contract is passed
+/// to the enclave once for the whole batch.
+fn encode_handle_batch(calls: &[HandleBatchCall]) -> Vec<u8> {
+    let mut batch = vec![];
+    for call in calls {
+        batch.push(call.handle_type);
+        for field in [call.env, call.msg, call.sig_info] {
+            batch.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            batch.extend_from_slice(field);
+        }
+    }
+    batch
+}
+
 impl<S, Q> Drop for Module<S, Q>
 where
     S: Storage,
@@ -395,24 +494,98 @@ where
     }
 }
 
-/// This type is used to extract the `query_depth` field which starts out at 1
-/// and is incremented every time a recursive query is called.
+/// Runs the checks the enclave has for code that is being stored, under the given JSON encoded
+/// engine params
+pub fn analyze_code(wasm: &[u8], engine_params: &[u8]) -> VmResult<()> {
+    let mut analyze_code_result = MaybeUninit::<AnalyzeCodeResult>::uninit();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or_else(|| VmError::generic_err("The enclave is too busy to analyze code"))?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status = unsafe {
+        imports::ecall_analyze_code(
+            enclave.geteid(),
+            analyze_code_result.as_mut_ptr(),
+            wasm.as_ptr(),
+            wasm.len(),
+            engine_params.as_ptr(),
+            engine_params.len(),
+        )
+    };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let analyze_code_result = unsafe { analyze_code_result.assume_init() };
+            analyze_code_result_to_vm_result(analyze_code_result)
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
+
+/// This type is used to extract the `call_depth` field which starts out at 1
+/// and is incremented for every query, submessage and reply a contract call causes.
+/// A query deeper than 1 comes from a contract inside the enclave, so it's recursive.
 /// We do not include the other fields of the Env here
 /// to reduce the need to keep this type in sync with the canonical `Env` type.
 #[derive(Debug, Deserialize)]
 struct Env {
     #[serde(default)]
-    query_depth: u32,
+    call_depth: u32,
 }
 
 /// This function parses the `env` parameter using the type above, and extracts the
-/// `recursive` field from it.
-fn get_query_depth(env: &[u8]) -> VmResult<u32> {
+/// `call_depth` field from it.
+fn get_call_depth(env: &[u8]) -> VmResult<u32> {
     match serde_json::from_slice::<Env>(env) {
-        Ok(env) => Ok(env.query_depth),
+        Ok(env) => Ok(env.call_depth),
         Err(_err) => Err(VmError::generic_err(format!(
             "could not parse the env parameter: {:?}",
             String::from_utf8_lossy(env)
         ))),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_handle_batch_prefixes_each_field() {
+        let calls = [
+            HandleBatchCall {
+                env: b"env",
+                msg: b"msg",
+                sig_info: b"sig",
+                handle_type: 0,
+                gas_limit: 10,
+            },
+            HandleBatchCall {
+                env: b"",
+                msg: b"m",
+                sig_info: b"s",
+                handle_type: 3,
+                gas_limit: 10,
+            },
+        ];
+
+        let mut expected = vec![0];
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        expected.extend_from_slice(b"env");
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        expected.extend_from_slice(b"msg");
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        expected.extend_from_slice(b"sig");
+        expected.push(3);
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        expected.extend_from_slice(&[0, 0, 0, 1]);
+        expected.extend_from_slice(b"m");
+        expected.extend_from_slice(&[0, 0, 0, 1]);
+        expected.extend_from_slice(b"s");
+
+        assert_eq!(encode_handle_batch(&calls), expected);
+    }
+}
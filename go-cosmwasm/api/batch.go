@@ -0,0 +1,95 @@
+package api
+
+import (
+	"encoding/binary"
+	"encoding/json"
+	"fmt"
+
+	"github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+)
+
+// HandleBatchCall is one call of a batch of handle calls to the same contract
+type HandleBatchCall struct {
+	Params     []byte
+	Msg        []byte
+	SigInfo    []byte
+	HandleType types.HandleType
+	// The most gas the call may use. It can't use more than the calls before it left either.
+	GasLimit uint64
+}
+
+// HandleBatchOutcome is what one call of a batch returned
+type HandleBatchOutcome struct {
+	Data    []byte
+	GasUsed uint64
+	Err     error
+}
+
+// encodeHandleBatchCalls lays the calls out the way handle_batch expects them: the handle type and
+// the gas limit, followed by the params, msg and sig info, each prefixed by its length. All the
+// numbers are big endian.
+func encodeHandleBatchCalls(calls []HandleBatchCall) []byte {
+	var encoded []byte
+	for _, call := range calls {
+		encoded = append(encoded, byte(call.HandleType))
+		encoded = binary.BigEndian.AppendUint64(encoded, call.GasLimit)
+		for _, field := range [][]byte{call.Params, call.Msg, call.SigInfo} {
+			encoded = binary.BigEndian.AppendUint32(encoded, uint32(len(field)))
+			encoded = append(encoded, field...)
+		}
+	}
+	return encoded
+}
+
+// decodeHandleBatchOutcomes reads what handle_batch returned for a batch of count calls: the gas
+// each call used and the errno it would have set, followed by its output or error message. The
+// batch stops after a call that failed or whose response dispatches messages, so there are
+// outcomes for the first calls only, but at least for one of them.
+func decodeHandleBatchOutcomes(encoded []byte, count int) ([]HandleBatchOutcome, error) {
+	const headerSize = 8 + 4 + 4
+
+	outcomes := make([]HandleBatchOutcome, 0, count)
+	for len(encoded) > 0 {
+		if len(encoded) < headerSize {
+			return nil, fmt.Errorf("batch outcomes ended in the middle of an outcome")
+		}
+		gasUsed := binary.BigEndian.Uint64(encoded[0:8])
+		errno := int32(binary.BigEndian.Uint32(encoded[8:12]))
+		length := int(binary.BigEndian.Uint32(encoded[12:16]))
+		encoded = encoded[headerSize:]
+		if len(encoded) < length {
+			return nil, fmt.Errorf("batch outcomes ended in the middle of an outcome")
+		}
+		data := encoded[:length]
+		encoded = encoded[length:]
+
+		outcome := HandleBatchOutcome{GasUsed: gasUsed}
+		if errno == 0 {
+			outcome.Data = data
+		} else {
+			outcome.Err = errorFromErrno(errno, data)
+		}
+		outcomes = append(outcomes, outcome)
+	}
+
+	if len(outcomes) == 0 || len(outcomes) > count {
+		return nil, fmt.Errorf("the batch returned %d outcomes for %d calls", len(outcomes), count)
+	}
+	return outcomes, nil
+}
+
+// errorFromErrno turns an errno set by the library and the message that came with it into an error
+func errorFromErrno(errno int32, msg []byte) error {
+	switch errno {
+	// this checks for out of gas as a special case
+	case 2:
+		return types.OutOfGasError{}
+	// enclave failures carry their context next to the message
+	case 3:
+		var enclaveErr types.EnclaveError
+		if json.Unmarshal(msg, &enclaveErr) == nil {
+			return enclaveErr
+		}
+	}
+	return fmt.Errorf("%s", string(msg))
+}
@@ -8,8 +8,8 @@ package api
 import "C"
 
 import (
+	"encoding/json"
 	"errors"
-	"fmt"
 	"runtime"
 	"syscall"
 
@@ -25,6 +25,7 @@ type (
 	i64    = C.int64_t
 	u64    = C.uint64_t
 	u32    = C.uint32_t
+	u16    = C.uint16_t
 	u8     = C.uint8_t
 	u8_ptr = *C.uint8_t
 	usize  = C.uintptr_t
@@ -105,6 +106,48 @@ func MigrateSealing() (bool, error) {
 	return true, nil
 }
 
+// Sealing key policies, same values as SGX_KEYPOLICY_MRENCLAVE and SGX_KEYPOLICY_MRSIGNER
+const (
+	KeyPolicyMrEnclave uint16 = 0x0001
+	KeyPolicyMrSigner  uint16 = 0x0002
+)
+
+func ResealSecrets(keyPolicy uint16, pinCpuSvn bool) (bool, error) {
+	ret, err := C.reseal_secrets(u16(keyPolicy), cbool(pinCpuSvn))
+	if err != nil {
+		return false, err
+	}
+	if !ret {
+		return false, errors.New("re-sealing failed")
+	}
+	return true, nil
+}
+
+// ListSealedFiles returns a JSON list of the sealed secrets, with the format and policy each is
+// sealed with
+func ListSealedFiles() ([]byte, error) {
+	errmsg := C.Buffer{}
+	res, err := C.list_sealed_files(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// DiscloseTxKeys verifies a disclosure permit and returns the IO keys of the txs it lists,
+// encrypted to the auditor, as JSON
+func DiscloseTxKeys(permit []byte) ([]byte, error) {
+	errmsg := C.Buffer{}
+	permitSlice := sendSlice(permit)
+	defer freeAfterSend(permitSlice)
+
+	res, err := C.disclose_tx_keys(permitSlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
 type Querier = types.Querier
 
 func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
@@ -125,11 +168,12 @@ func ReleaseCache(cache Cache) {
 	C.release_cache(cache.ptr)
 }
 
-func InitEnclaveRuntime(moduleCacheSize uint16) error {
+func InitEnclaveRuntime(moduleCacheSize uint16, querySlots uint8) error {
 	errmsg := C.Buffer{}
 
 	config := C.EnclaveRuntimeConfig{
 		module_cache_size: u32(moduleCacheSize),
+		query_slots:       u8(querySlots),
 	}
 	_, err := C.configure_enclave_runtime(config, &errmsg)
 	if err != nil {
@@ -139,11 +183,54 @@ func InitEnclaveRuntime(moduleCacheSize uint16) error {
 	return nil
 }
 
-func Create(cache Cache, wasm []byte) ([]byte, error) {
+// GetEnclaveDoorbellMetrics returns the backpressure metrics of the lanes that transactions and
+// queries use to enter the enclave
+func GetEnclaveDoorbellMetrics() (*types.EnclaveDoorbellMetrics, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_enclave_doorbell_metrics(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var metrics types.EnclaveDoorbellMetrics
+	if err := json.Unmarshal(receiveVector(res), &metrics); err != nil {
+		return nil, err
+	}
+	return &metrics, nil
+}
+
+// GetEnclaveMetrics returns the runtime metrics of the enclave, such as module cache hits, ocall
+// counts and block verification times
+func GetEnclaveMetrics() (*types.EnclaveMetrics, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_enclave_metrics(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var metrics types.EnclaveMetrics
+	if err := json.Unmarshal(receiveVector(res), &metrics); err != nil {
+		return nil, err
+	}
+	return &metrics, nil
+}
+
+func PrewarmEnclaveCache(cache Cache) error {
+	errmsg := C.Buffer{}
+	_, err := C.prewarm_enclave_cache(cache.ptr, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+func Create(cache Cache, wasm []byte, engineParams []byte) ([]byte, error) {
 	code := sendSlice(wasm)
 	defer freeAfterSend(code)
+	params := sendSlice(engineParams)
+	defer freeAfterSend(params)
 	errmsg := C.Buffer{}
-	id, err := C.create(cache.ptr, code, &errmsg)
+	id, err := C.create(cache.ptr, code, params, &errmsg)
 	if err != nil {
 		return nil, errorWithMessage(err, errmsg)
 	}
@@ -364,6 +451,48 @@ func Handle(
 	return receiveVector(res), uint64(gasUsed), nil
 }
 
+// HandleBatch runs several handle calls of the contract code_id in one entry into the enclave. The
+// batch stops after a call that failed or whose response dispatches messages, so this returns the
+// outcomes of the first calls in order, at least of one of them, along with the gas used by the
+// whole batch. It only fails if the batch couldn't run at all.
+func HandleBatch(
+	cache Cache,
+	code_id []byte,
+	calls []HandleBatchCall,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]HandleBatchOutcome, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	c := sendSlice(encodeHandleBatchCalls(calls))
+	defer freeAfterSend(c)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.handle_batch(cache.ptr, id, c, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	outcomes, err := decodeHandleBatchOutcomes(receiveVector(res), len(calls))
+	if err != nil {
+		return nil, uint64(gasUsed), err
+	}
+	return outcomes, uint64(gasUsed), nil
+}
+
 func Query(
 	cache Cache,
 	code_id []byte,
@@ -480,13 +609,14 @@ func GetEncryptedGenesisSeed(pk []byte) ([]byte, error) {
 /**** To error module ***/
 
 func errorWithMessage(err error, b C.Buffer) error {
+	errno, _ := err.(syscall.Errno)
 	// this checks for out of gas as a special case
-	if errno, ok := err.(syscall.Errno); ok && int(errno) == 2 {
+	if int(errno) == 2 {
 		return types.OutOfGasError{}
 	}
 	msg := receiveVector(b)
 	if msg == nil {
 		return err
 	}
-	return fmt.Errorf("%s", string(msg))
+	return errorFromErrno(int32(errno), msg)
 }
@@ -53,6 +53,23 @@ func MigrateSealing() (bool, error) {
 	return false, nil
 }
 
+const (
+	KeyPolicyMrEnclave uint16 = 0x0001
+	KeyPolicyMrSigner  uint16 = 0x0002
+)
+
+func ResealSecrets(keyPolicy uint16, pinCpuSvn bool) (bool, error) {
+	return false, nil
+}
+
+func ListSealedFiles() ([]byte, error) {
+	return nil, nil
+}
+
+func DiscloseTxKeys(permit []byte) ([]byte, error) {
+	return nil, nil
+}
+
 func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
 	//dir := sendSlice([]byte(dataDir))
 	//defer freeAfterSend(dir)
@@ -71,15 +88,29 @@ func ReleaseCache(cache Cache) {
 	// C.release_cache(cache.ptr)
 }
 
-func InitEnclaveRuntime(ModuleCacheSize uint16) error {
+func InitEnclaveRuntime(ModuleCacheSize uint16, QuerySlots uint8) error {
+	return nil
+}
+
+func GetEnclaveDoorbellMetrics() (*types.EnclaveDoorbellMetrics, error) {
+	return &types.EnclaveDoorbellMetrics{}, nil
+}
+
+func GetEnclaveMetrics() (*types.EnclaveMetrics, error) {
+	return &types.EnclaveMetrics{}, nil
+}
+
+func PrewarmEnclaveCache(cache Cache) error {
 	return nil
 }
 
-func Create(cache Cache, wasm []byte) ([]byte, error) {
+func Create(cache Cache, wasm []byte, engineParams []byte) ([]byte, error) {
 	//code := sendSlice(wasm)
 	//defer freeAfterSend(code)
+	//params := sendSlice(engineParams)
+	//defer freeAfterSend(params)
 	//errmsg := C.Buffer{}
-	//id, err := C.create(cache.ptr, code, &errmsg)
+	//id, err := C.create(cache.ptr, code, params, &errmsg)
 	//if err != nil {
 	//	return nil, errorWithMessage(err, errmsg)
 	//}
@@ -199,6 +230,19 @@ func Handle(
 	return nil, 0, nil
 }
 
+func HandleBatch(
+	cache Cache,
+	code_id []byte,
+	calls []HandleBatchCall,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]HandleBatchOutcome, uint64, error) {
+	return nil, 0, nil
+}
+
 func Query(
 	cache Cache,
 	code_id []byte,
@@ -22,12 +22,12 @@ func main() {
 		panic(err)
 	}
 
-	wasmer, err := wasm.NewWasmer("tmp", "staking,stargate,ibc3", 0, 15)
+	wasmer, err := wasm.NewWasmer("tmp", "staking,stargate,ibc3", 0, 15, 0)
 	if err != nil {
 		panic(err)
 	}
 
-	id, err := wasmer.Create(bz)
+	id, err := wasmer.Create(bz, nil)
 	if err != nil {
 		panic(err)
 	}
@@ -40,12 +40,18 @@ type Wasmer struct {
 // cacheSize sets the size of an optional in-memory LRU cache for prepared VMs.
 // They allow popular contracts to be executed very rapidly (no loading overhead),
 // but require ~32-64MB each in memory usage.
-func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64, moduleCacheSize uint16) (*Wasmer, error) {
+// querySlots is how many of the enclave's threads are reserved for queries, or 0 to let queries
+// share them with transactions.
+func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64, moduleCacheSize uint16, querySlots uint8) (*Wasmer, error) {
 	cache, err := api.InitCache(dataDir, supportedFeatures, cacheSize)
 	if err != nil {
 		return nil, err
 	}
-	err = api.InitEnclaveRuntime(moduleCacheSize)
+	err = api.InitEnclaveRuntime(moduleCacheSize, querySlots)
+	if err != nil {
+		return nil, err
+	}
+	err = api.PrewarmEnclaveCache(cache)
 	if err != nil {
 		return nil, err
 	}
@@ -58,6 +64,18 @@ func (w *Wasmer) Cleanup() {
 	api.ReleaseCache(w.cache)
 }
 
+// EnclaveDoorbellMetrics returns the backpressure metrics of the lanes that transactions and
+// queries use to enter the enclave
+func (w *Wasmer) EnclaveDoorbellMetrics() (*types.EnclaveDoorbellMetrics, error) {
+	return api.GetEnclaveDoorbellMetrics()
+}
+
+// EnclaveMetrics returns the runtime metrics of the enclave, such as module cache hits, ocall
+// counts and block verification times
+func (w *Wasmer) EnclaveMetrics() (*types.EnclaveMetrics, error) {
+	return api.GetEnclaveMetrics()
+}
+
 // Create will compile the wasm code, and store the resulting pre-compile
 // as well as the original code. Both can be referenced later via CodeID
 // This must be done one time for given code, after which it can be
@@ -67,9 +85,20 @@ func (w *Wasmer) Cleanup() {
 // This function stores the code for that contract only once, but it can
 // be instantiated with custom inputs in the future.
 //
+// The enclave also checks the code under the given engine params, if there are any.
+// Without them, the code is stored the way it was before the chain set its params.
+//
 // TODO: return gas cost? Add gas limit??? there is no metering here...
-func (w *Wasmer) Create(code WasmCode) (CodeHash, error) {
-	return api.Create(w.cache, code)
+func (w *Wasmer) Create(code WasmCode, engineParams *types.EngineParams) (CodeHash, error) {
+	var paramsBin []byte
+	if engineParams != nil {
+		var err error
+		paramsBin, err = json.Marshal(engineParams)
+		if err != nil {
+			return nil, err
+		}
+	}
+	return api.Create(w.cache, code, paramsBin)
 }
 
 // GetCode will load the original wasm code for the given code id.
@@ -259,11 +288,18 @@ func (w *Wasmer) Execute(
 		return nil, gasUsed, err
 	}
 
+	response, err := parseExecuteResponse(data)
+	return response, gasUsed, err
+}
+
+// parseExecuteResponse reads the response of a handle call, and returns the error the contract
+// returned if it did.
+func parseExecuteResponse(data []byte) (interface{}, error) {
 	var resp ContractExecResponse
-	err = json.Unmarshal(data, &resp)
+	err := json.Unmarshal(data, &resp)
 	if err != nil {
 		// unidentified response 🤷
-		return nil, gasUsed, fmt.Errorf("handle: cannot parse response from json: %w", err)
+		return nil, fmt.Errorf("handle: cannot parse response from json: %w", err)
 	}
 
 	isOutputAddressedToReply := len(resp.InternaReplyEnclaveSig) > 0 && len(resp.InternalMsgId) > 0
@@ -275,17 +311,17 @@ func (w *Wasmer) Execute(
 				InternalMsgId:          resp.InternalMsgId,
 				InternaReplyEnclaveSig: resp.InternaReplyEnclaveSig,
 				Data:                   []byte(resp.V010.Err.GenericErr.Msg),
-			}, gasUsed, fmt.Errorf("%+v", resp.V010.Err)
+			}, fmt.Errorf("%+v", resp.V010.Err)
 		} else if resp.V010.Ok != nil {
 			if isOutputAddressedToReply {
 				resp.V010.Ok.Data, err = AppendReplyInternalDataToData(resp.V010.Ok.Data, resp.InternaReplyEnclaveSig, resp.InternalMsgId)
 				if err != nil {
-					return nil, gasUsed, fmt.Errorf("cannot serialize v0.10 DataWithInternalReplyInfo into binary : %w", err)
+					return nil, fmt.Errorf("cannot serialize v0.10 DataWithInternalReplyInfo into binary : %w", err)
 				}
 			}
-			return resp.V010.Ok, gasUsed, nil
+			return resp.V010.Ok, nil
 		} else {
-			return nil, gasUsed, fmt.Errorf("cannot parse v0.10 handle response: %+v", resp)
+			return nil, fmt.Errorf("cannot parse v0.10 handle response: %+v", resp)
 		}
 	}
 
@@ -296,52 +332,120 @@ func (w *Wasmer) Execute(
 				InternalMsgId:          resp.InternalMsgId,
 				InternaReplyEnclaveSig: resp.InternaReplyEnclaveSig,
 				Data:                   []byte(resp.V1.Err.GenericErr.Msg),
-			}, gasUsed, fmt.Errorf("%+v", resp.V1.Err)
+			}, fmt.Errorf("%+v", resp.V1.Err)
 		} else if resp.V1.Ok != nil {
 			if isOutputAddressedToReply {
 				resp.V1.Ok.Data, err = AppendReplyInternalDataToData(resp.V1.Ok.Data, resp.InternaReplyEnclaveSig, resp.InternalMsgId)
 				if err != nil {
-					return nil, gasUsed, fmt.Errorf("cannot serialize v1 DataWithInternalReplyInfo into binary: %w", err)
+					return nil, fmt.Errorf("cannot serialize v1 DataWithInternalReplyInfo into binary: %w", err)
 				}
 			}
-			return resp.V1.Ok, gasUsed, nil
+			return resp.V1.Ok, nil
 		} else {
-			return nil, gasUsed, fmt.Errorf("cannot parse v1 handle response: %+v", resp)
+			return nil, fmt.Errorf("cannot parse v1 handle response: %+v", resp)
 		}
 	}
 
 	if resp.IBCBasic != nil {
 		if resp.IBCBasic.Err != nil { //nolint:gocritic
-			return nil, gasUsed, fmt.Errorf("%+v", resp.IBCBasic.Err)
+			return nil, fmt.Errorf("%+v", resp.IBCBasic.Err)
 		} else if resp.IBCBasic.Ok != nil {
-			return resp.IBCBasic.Ok, gasUsed, nil
+			return resp.IBCBasic.Ok, nil
 		} else {
-			return nil, gasUsed, fmt.Errorf("cannot parse IBCBasic response: %+v", resp)
+			return nil, fmt.Errorf("cannot parse IBCBasic response: %+v", resp)
 		}
 	}
 
 	if resp.IBCPacketReceive != nil {
 		if resp.IBCPacketReceive.Err != nil { //nolint:gocritic
-			return nil, gasUsed, fmt.Errorf("%+v", resp.IBCPacketReceive.Err)
+			return nil, fmt.Errorf("%+v", resp.IBCPacketReceive.Err)
 		} else if resp.IBCPacketReceive.Ok != nil {
-			return resp.IBCPacketReceive.Ok, gasUsed, nil
+			return resp.IBCPacketReceive.Ok, nil
 		} else {
-			return nil, gasUsed, fmt.Errorf("cannot parse IBCPacketReceive response: %+v", resp)
+			return nil, fmt.Errorf("cannot parse IBCPacketReceive response: %+v", resp)
 		}
 	}
 
 	if resp.IBCChannelOpen != nil {
 		if resp.IBCChannelOpen.Err != nil { //nolint:gocritic
-			return nil, gasUsed, fmt.Errorf("%+v", resp.IBCChannelOpen.Err)
+			return nil, fmt.Errorf("%+v", resp.IBCChannelOpen.Err)
 		} else if resp.IBCChannelOpen.Ok != nil {
 			// ibc_channel_open actually returns no data
-			return resp.IBCChannelOpen.Ok, gasUsed, nil
+			return resp.IBCChannelOpen.Ok, nil
 		} else {
-			return nil, gasUsed, fmt.Errorf("cannot parse IBCChannelOpen response: %+v", resp)
+			return nil, fmt.Errorf("cannot parse IBCChannelOpen response: %+v", resp)
+		}
+	}
+
+	return nil, fmt.Errorf("handle: cannot detect response type (v0.10 or v1)")
+}
+
+// ExecuteBatchCall is one call of a batch of calls to the same contract
+type ExecuteBatchCall struct {
+	Env        types.Env
+	ExecuteMsg []byte
+	SigInfo    types.SigInfo
+	HandleType types.HandleType
+	// The most gas the call may use. It can't use more than the calls before it left either.
+	GasLimit uint64
+}
+
+// ExecuteBatchResult is what one call of a batch returned, the same way Execute returns it
+type ExecuteBatchResult struct {
+	Response interface{}
+	GasUsed  uint64
+	Err      error
+}
+
+// ExecuteBatch calls a given contract once per call, like Execute, but in one entry into the
+// enclave. All the calls must belong to the same block. The calls run one after the other on the
+// same store, until one fails or returns messages to dispatch, since those have to be dealt with
+// before the next call runs. It returns the results of the calls that ran in order, which are at
+// least the first one, along with the gas used by the whole batch, which is never more than
+// gasLimit.
+func (w *Wasmer) ExecuteBatch(
+	code CodeHash,
+	calls []ExecuteBatchCall,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) ([]ExecuteBatchResult, uint64, error) {
+	handleCalls := make([]api.HandleBatchCall, len(calls))
+	for i, call := range calls {
+		paramBin, err := json.Marshal(call.Env)
+		if err != nil {
+			return nil, 0, err
+		}
+		sigInfoBin, err := json.Marshal(call.SigInfo)
+		if err != nil {
+			return nil, 0, err
+		}
+		handleCalls[i] = api.HandleBatchCall{
+			Params:     paramBin,
+			Msg:        call.ExecuteMsg,
+			SigInfo:    sigInfoBin,
+			HandleType: call.HandleType,
+			GasLimit:   call.GasLimit,
 		}
 	}
 
-	return nil, gasUsed, fmt.Errorf("handle: cannot detect response type (v0.10 or v1)")
+	outcomes, gasUsed, err := api.HandleBatch(w.cache, code, handleCalls, &gasMeter, store, &goapi, &querier, gasLimit)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	results := make([]ExecuteBatchResult, len(outcomes))
+	for i, outcome := range outcomes {
+		results[i].GasUsed = outcome.GasUsed
+		if outcome.Err != nil {
+			results[i].Err = outcome.Err
+			continue
+		}
+		results[i].Response, results[i].Err = parseExecuteResponse(outcome.Data)
+	}
+	return results, gasUsed, nil
 }
 
 // Query allows a client to execute a contract-specific query. If the result is not empty, it should be
@@ -0,0 +1,135 @@
+//! The encoding of the batches of handle calls that Go passes to `handle_batch`, and of their
+//! outcomes.
+//!
+//! Go encodes the calls one after the other, each as the handle type and the gas limit of the
+//! call, followed by the env, msg and sig_info, every one of them prefixed by its length.
+//! The outcomes come back in the same order, each as the gas the call used and the errno the call
+//! would have set, followed by the output of the call or the error message. All the numbers are
+//! big endian.
+
+use std::convert::TryInto;
+
+use cosmwasm_sgx_vm::{HandleBatchCall, HandleBatchOutcome};
+
+use crate::error::{error_parts, Error};
+
+pub fn decode_calls(mut calls: &[u8]) -> Result<Vec<HandleBatchCall>, Error> {
+    let mut decoded = vec![];
+
+    while !calls.is_empty() {
+        let handle_type = take(&mut calls, 1)?[0];
+        let gas_limit = u64::from_be_bytes(take(&mut calls, 8)?.try_into().unwrap());
+        let env = take_field(&mut calls)?;
+        let msg = take_field(&mut calls)?;
+        let sig_info = take_field(&mut calls)?;
+
+        decoded.push(HandleBatchCall {
+            env,
+            msg,
+            sig_info,
+            handle_type,
+            gas_limit,
+        });
+    }
+
+    Ok(decoded)
+}
+
+fn take<'a>(calls: &mut &'a [u8], length: usize) -> Result<&'a [u8], Error> {
+    if calls.len() < length {
+        return Err(Error::vm_err("batch ended in the middle of a call"));
+    }
+    let (taken, rest) = calls.split_at(length);
+    *calls = rest;
+    Ok(taken)
+}
+
+fn take_field<'a>(calls: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let length = u32::from_be_bytes(take(calls, 4)?.try_into().unwrap());
+    take(calls, length as usize)
+}
+
+pub fn encode_outcomes(outcomes: Vec<HandleBatchOutcome>) -> Vec<u8> {
+    let mut encoded = vec![];
+
+    for outcome in outcomes {
+        let (errno, data) = match outcome.result {
+            Ok(output) => (0, output),
+            Err(err) => error_parts(&Error::from(err)),
+        };
+        encoded.extend_from_slice(&outcome.gas_used.to_be_bytes());
+        encoded.extend_from_slice(&errno.to_be_bytes());
+        encoded.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(&data);
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cosmwasm_sgx_vm::VmError;
+
+    fn encode_call(handle_type: u8, gas_limit: u64, fields: [&[u8]; 3]) -> Vec<u8> {
+        let mut encoded = vec![handle_type];
+        encoded.extend_from_slice(&gas_limit.to_be_bytes());
+        for field in fields {
+            encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(field);
+        }
+        encoded
+    }
+
+    #[test]
+    fn decode_calls_works() {
+        let mut calls = encode_call(0, 100, [b"env", b"msg", b"sig"]);
+        calls.extend(encode_call(2, 50, [b"env2", b"", b"sig2"]));
+
+        let decoded = decode_calls(&calls).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].handle_type, 0);
+        assert_eq!(decoded[0].gas_limit, 100);
+        assert_eq!(decoded[0].env, b"env");
+        assert_eq!(decoded[0].msg, b"msg");
+        assert_eq!(decoded[0].sig_info, b"sig");
+        assert_eq!(decoded[1].handle_type, 2);
+        assert_eq!(decoded[1].gas_limit, 50);
+        assert_eq!(decoded[1].msg, b"");
+    }
+
+    #[test]
+    fn decode_calls_rejects_truncated_calls() {
+        let calls = encode_call(0, 100, [b"env", b"msg", b"sig"]);
+
+        assert!(decode_calls(&calls[..calls.len() - 1]).is_err());
+        assert!(decode_calls(&calls[..5]).is_err());
+    }
+
+    #[test]
+    fn encode_outcomes_works() {
+        let encoded = encode_outcomes(vec![
+            HandleBatchOutcome {
+                result: Ok(b"out".to_vec()),
+                gas_used: 7,
+            },
+            HandleBatchOutcome {
+                result: Err(VmError::GasDepletion),
+                gas_used: 9,
+            },
+        ]);
+
+        let mut expected = vec![];
+        expected.extend_from_slice(&7_u64.to_be_bytes());
+        expected.extend_from_slice(&0_i32.to_be_bytes());
+        expected.extend_from_slice(&3_u32.to_be_bytes());
+        expected.extend_from_slice(b"out");
+        let out_of_gas = b"Ran out of gas";
+        expected.extend_from_slice(&9_u64.to_be_bytes());
+        expected.extend_from_slice(&2_i32.to_be_bytes());
+        expected.extend_from_slice(&(out_of_gas.len() as u32).to_be_bytes());
+        expected.extend_from_slice(out_of_gas);
+        assert_eq!(encoded, expected);
+    }
+}
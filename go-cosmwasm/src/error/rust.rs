@@ -1,6 +1,6 @@
 use errno::{set_errno, Errno};
 
-use cosmwasm_sgx_vm::VmError;
+use cosmwasm_sgx_vm::{EnclaveErrorContext, VmError};
 use snafu::Snafu;
 
 use crate::memory::Buffer;
@@ -35,6 +35,17 @@ pub enum Error {
         #[cfg(feature = "backtraces")]
         backtrace: snafu::Backtrace,
     },
+    /// Same message as `VmErr`, along with the context the enclave reported for the error
+    #[snafu(display("Execution error: {}", msg))]
+    EnclaveFailure {
+        msg: String,
+        code: u32,
+        stage: String,
+        detail: u32,
+        wasm_backtrace: Option<String>,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
     #[snafu(display("{}", msg))]
     GoCwEnclaveError {
         msg: String,
@@ -66,6 +77,22 @@ impl Error {
         .build()
     }
 
+    pub fn enclave_failure<S: ToString>(
+        msg: S,
+        code: u32,
+        context: EnclaveErrorContext,
+        wasm_backtrace: Option<String>,
+    ) -> Self {
+        EnclaveFailure {
+            msg: msg.to_string(),
+            code,
+            stage: context.stage.to_string(),
+            detail: context.detail,
+            wasm_backtrace,
+        }
+        .build()
+    }
+
     pub fn enclave_err<S: ToString>(msg: S) -> Self {
         GoCwEnclaveError {
             msg: msg.to_string(),
@@ -82,7 +109,13 @@ impl From<VmError> for Error {
     fn from(source: VmError) -> Self {
         match source {
             VmError::GasDepletion => Error::out_of_gas(),
-            _ => Error::vm_err(source),
+            _ => match source.enclave_error_context() {
+                Some((code, context)) => {
+                    let wasm_backtrace = source.wasm_backtrace().map(String::from);
+                    Error::enclave_failure(&source, code, context, wasm_backtrace)
+                }
+                None => Error::vm_err(source),
+            },
         }
     }
 }
@@ -105,6 +138,8 @@ enum ErrnoValue {
     Success = 0,
     Other = 1,
     OutOfGas = 2,
+    /// The error buffer holds a JSON object with the message and the context of the error
+    EnclaveFailure = 3,
 }
 
 pub fn clear_error() {
@@ -112,15 +147,42 @@ pub fn clear_error() {
 }
 
 pub fn set_error(err: Error, errout: Option<&mut Buffer>) {
-    let msg = err.to_string();
+    let (errno, msg) = error_parts(&err);
     if let Some(mb) = errout {
-        *mb = Buffer::from_vec(msg.into_bytes());
+        *mb = Buffer::from_vec(msg);
     }
+    set_errno(Errno(errno));
+}
+
+/// The errno and the error buffer that `set_error` reports `err` with
+pub fn error_parts(err: &Error) -> (i32, Vec<u8>) {
     let errno = match err {
         Error::OutOfGas { .. } => ErrnoValue::OutOfGas,
+        Error::EnclaveFailure { .. } => ErrnoValue::EnclaveFailure,
         _ => ErrnoValue::Other,
     } as i32;
-    set_errno(Errno(errno));
+    (errno, error_message(err))
+}
+
+fn error_message(err: &Error) -> Vec<u8> {
+    match err {
+        Error::EnclaveFailure {
+            code,
+            stage,
+            detail,
+            wasm_backtrace,
+            ..
+        } => serde_json::json!({
+            "msg": err.to_string(),
+            "code": code,
+            "stage": stage,
+            "detail": detail,
+            "backtrace": wasm_backtrace,
+        })
+        .to_string()
+        .into_bytes(),
+        _ => err.to_string().into_bytes(),
+    }
 }
 
 /// If `result` is Ok, this returns the binary representation of the Ok value and clears the error in `errout`.
@@ -163,7 +225,7 @@ pub fn handle_c_error_default<T>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cosmwasm_sgx_vm::FfiError;
+    use cosmwasm_sgx_vm::{error_details, EnclaveErrorStage, FfiError};
     use std::str;
 
     #[test]
@@ -233,6 +295,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn enclave_failure_keeps_message() {
+        let context = EnclaveErrorContext::new(
+            EnclaveErrorStage::SignatureVerification,
+            error_details::SIGNER_MISMATCH,
+        );
+        let error = Error::enclave_failure("failed to verify transaction", 32, context, None);
+        assert_eq!(
+            error.to_string(),
+            "Execution error: failed to verify transaction"
+        );
+
+        let msg: serde_json::Value = serde_json::from_slice(&error_message(&error)).unwrap();
+        assert_eq!(
+            msg,
+            serde_json::json!({
+                "msg": "Execution error: failed to verify transaction",
+                "code": 32,
+                "stage": "signature verification",
+                "detail": error_details::SIGNER_MISMATCH,
+                "backtrace": null,
+            })
+        );
+    }
+
+    #[test]
+    fn enclave_failure_sends_wasm_backtrace() {
+        let context = EnclaveErrorContext::new(EnclaveErrorStage::WasmTrap, error_details::NONE);
+        let backtrace = "wasm backtrace:\n  0: execute".to_string();
+        let error = Error::enclave_failure("unreachable", 11, context, Some(backtrace.clone()));
+        assert_eq!(error.to_string(), "Execution error: unreachable");
+
+        let msg: serde_json::Value = serde_json::from_slice(&error_message(&error)).unwrap();
+        assert_eq!(msg["stage"], "wasm trap");
+        assert_eq!(msg["backtrace"], backtrace.as_str());
+    }
+
+    #[test]
+    fn error_message_is_plain_for_other_errors() {
+        let error = Error::vm_err("my text");
+        assert_eq!(error_message(&error), b"Execution error: my text".to_vec());
+    }
+
     // Tests of `impl From<X> for Error` converters
 
     #[test]
@@ -1,4 +1,5 @@
 mod api;
+mod batch;
 mod db;
 mod error;
 mod gas_meter;
@@ -21,13 +22,14 @@ use crate::error::{clear_error, handle_c_error, handle_c_error_default, set_erro
 
 use cosmwasm_sgx_vm::untrusted_init_bootstrap;
 use cosmwasm_sgx_vm::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, call_update_admin_raw,
-    features_from_csv, Checksum, CosmCache, Extern,
+    call_handle_batch_raw, call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw,
+    call_update_admin_raw, features_from_csv, Checksum, CosmCache, Extern,
 };
 use cosmwasm_sgx_vm::{
-    create_attestation_report_u, untrusted_get_encrypted_genesis_seed,
-    untrusted_get_encrypted_seed, untrusted_health_check, untrusted_init_node, untrusted_key_gen,
-    untrusted_migrate_sealing,
+    create_attestation_report_u, untrusted_disclose_tx_keys, untrusted_get_enclave_metrics,
+    untrusted_get_encrypted_genesis_seed, untrusted_get_encrypted_seed, untrusted_health_check,
+    untrusted_init_node, untrusted_key_gen, untrusted_list_sealed_files, untrusted_migrate_sealing,
+    untrusted_reseal_secrets,
 };
 
 use ctor::ctor;
@@ -335,6 +337,7 @@ static MSG_ARG: &str = "msg";
 static PARAMS_ARG: &str = "params";
 static GAS_USED_ARG: &str = "gas_used";
 static SIG_INFO_ARG: &str = "sig_info";
+static CALLS_ARG: &str = "calls";
 static CURRENT_ADMIN_ARG: &str = "current_admin";
 static CURRENT_ADMIN_PROOF_ARG: &str = "current_admin_proof";
 
@@ -371,12 +374,14 @@ pub extern "C" fn release_cache(cache: *mut cache_t) {
 #[repr(C)]
 pub struct EnclaveRuntimeConfig {
     pub module_cache_size: u32,
+    pub query_slots: u8,
 }
 
 impl EnclaveRuntimeConfig {
     fn to_sgx_vm(&self) -> cosmwasm_sgx_vm::EnclaveRuntimeConfig {
         cosmwasm_sgx_vm::EnclaveRuntimeConfig {
             module_cache_size: self.module_cache_size,
+            query_slots: self.query_slots,
         }
     }
 }
@@ -396,10 +401,60 @@ pub extern "C" fn configure_enclave_runtime(
     }
 }
 
+/// Returns the backpressure metrics of the lanes that transactions and queries use to enter the
+/// enclave, as JSON
 #[no_mangle]
-pub extern "C" fn create(cache: *mut cache_t, wasm: Buffer, err: Option<&mut Buffer>) -> Buffer {
+pub extern "C" fn get_enclave_doorbell_metrics(err: Option<&mut Buffer>) -> Buffer {
+    match serde_json::to_vec(&cosmwasm_sgx_vm::enclave_doorbell_metrics()) {
+        Err(e) => {
+            set_error(Error::vm_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(r) => {
+            clear_error();
+            Buffer::from_vec(r)
+        }
+    }
+}
+
+/// Returns the runtime metrics of the enclave, such as module cache hits, ocall counts and block
+/// verification times, as JSON
+#[no_mangle]
+pub extern "C" fn get_enclave_metrics(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_enclave_metrics() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(r) => {
+            clear_error();
+            Buffer::from_vec(r)
+        }
+    }
+}
+
+/// Loads the sealed modules of the contracts that ran most recently into the enclave, in the
+/// background. This should be called once the enclave runtime is configured.
+#[no_mangle]
+pub extern "C" fn prewarm_enclave_cache(cache: *mut cache_t, err: Option<&mut Buffer>) {
+    match to_cache(cache) {
+        Some(c) => {
+            c.prewarm_enclave_module_cache();
+            clear_error();
+        }
+        None => set_error(Error::empty_arg(CACHE_ARG), err),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn create(
+    cache: *mut cache_t,
+    wasm: Buffer,
+    engine_params: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_create(c, wasm)))
+        Some(c) => catch_unwind(AssertUnwindSafe(move || do_create(c, wasm, engine_params)))
             .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
@@ -407,9 +462,18 @@ pub extern "C" fn create(cache: *mut cache_t, wasm: Buffer, err: Option<&mut Buf
     Buffer::from_vec(data)
 }
 
-fn do_create(cache: &mut CosmCache<DB, GoApi, GoQuerier>, wasm: Buffer) -> Result<Checksum, Error> {
+fn do_create(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    wasm: Buffer,
+    engine_params: Buffer,
+) -> Result<Checksum, Error> {
     let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
-    let checksum = cache.save_wasm(wasm)?;
+    // Code is only analyzed once the chain set its engine params, so nodes replaying old blocks
+    // store exactly the code they stored back then
+    let checksum = match unsafe { engine_params.read() } {
+        Some(engine_params) => cache.save_analyzed_wasm(wasm, engine_params)?,
+        None => cache.save_wasm(wasm)?,
+    };
     Ok(checksum)
 }
 
@@ -724,6 +788,59 @@ fn do_handle(
     Ok(res?)
 }
 
+/// Runs a batch of handle calls of the same contract, encoded as described in `batch`, in one
+/// entry into the enclave. A failed call doesn't stop the ones after it, so this only fails if the
+/// batch couldn't run at all. `gas_used` is the gas used by the whole batch.
+#[no_mangle]
+pub extern "C" fn handle_batch(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    calls: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_handle_batch(c, code_id, calls, db, api, querier, gas_limit, gas_used)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_handle_batch(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    calls: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let calls = unsafe { calls.read() }.ok_or_else(|| Error::empty_arg(CALLS_ARG))?;
+    let calls = batch::decode_calls(calls)?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_handle_batch_raw(&mut instance, &calls);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(batch::encode_outcomes(res?))
+}
+
 #[no_mangle]
 pub extern "C" fn query(
     cache: *mut cache_t,
@@ -853,3 +970,50 @@ pub extern "C" fn migrate_sealing() -> bool {
     clear_error();
     true
 }
+
+#[no_mangle]
+pub extern "C" fn reseal_secrets(key_policy: u16, pin_cpu_svn: bool) -> bool {
+    if let Err(e) = untrusted_reseal_secrets(key_policy, pin_cpu_svn) {
+        error!("reseal_secrets error: {}", e);
+        return false;
+    }
+
+    clear_error();
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn list_sealed_files(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_list_sealed_files() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(r) => {
+            clear_error();
+            Buffer::from_vec(r)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn disclose_tx_keys(permit: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let permit_slice = match unsafe { permit.read() } {
+        None => {
+            set_error(Error::empty_arg("permit"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_disclose_tx_keys(permit_slice) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(r) => {
+            clear_error();
+            Buffer::from_vec(r)
+        }
+    }
+}
@@ -12,8 +12,10 @@ type Env struct {
 	Message     MessageInfo      `json:"message"`
 	Contract    ContractInfo     `json:"contract"`
 	Key         ContractKey      `json:"contract_key"`
-	QueryDepth  uint32           `json:"query_depth"`
+	CallDepth   uint32           `json:"call_depth"`
 	Transaction *TransactionInfo `json:"transaction,omitempty"`
+	// The engine params the call runs under, nil before the chain set them
+	EngineParams *EngineParams `json:"engine_params,omitempty"`
 }
 
 type ContractKey struct {
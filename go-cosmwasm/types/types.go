@@ -58,6 +58,91 @@ func (o OutOfGasError) Error() string {
 	return "Out of gas"
 }
 
+// EnclaveError is an error returned by the enclave, along with the context it reported.
+// Code is stable across releases, Stage is the step of the call that failed (e.g. "signature
+// verification") and Detail is one of the codes in `error_details` in the enclave, or 0.
+// Only details that depend on public inputs of the call are ever reported.
+// Backtrace is the wasm backtrace of a trap, and is only sent by non-production enclaves.
+type EnclaveError struct {
+	Msg       string `json:"msg"`
+	Code      uint32 `json:"code"`
+	Stage     string `json:"stage"`
+	Detail    uint32 `json:"detail"`
+	Backtrace string `json:"backtrace,omitempty"`
+}
+
+var _ error = EnclaveError{}
+
+// EnclaveErrorCodeExceededCallDepth is the Code of the EnclaveError the enclave returns for a
+// contract call that's deeper than the configured limit
+const EnclaveErrorCodeExceededCallDepth uint32 = 37
+
+func (e EnclaveError) Error() string {
+	return e.Msg
+}
+
+// EnclaveDoorbellMetrics are the backpressure metrics of the lanes that transactions and queries
+// use to enter the enclave. Queries use the transaction lane while no slots are reserved for them.
+type EnclaveDoorbellMetrics struct {
+	Transactions DoorbellLaneMetrics `json:"transactions"`
+	Queries      DoorbellLaneMetrics `json:"queries"`
+}
+
+type DoorbellLaneMetrics struct {
+	Slots           uint8  `json:"slots"`
+	InUse           uint8  `json:"in_use"`
+	QueueDepth      uint64 `json:"queue_depth"`
+	Entries         uint64 `json:"entries"`
+	Waits           uint64 `json:"waits"`
+	TotalWaitMicros uint64 `json:"total_wait_micros"`
+	MaxWaitMicros   uint64 `json:"max_wait_micros"`
+	Timeouts        uint64 `json:"timeouts"`
+}
+
+// EnclaveMetrics are the runtime metrics of the enclave. The counters count from the start of the
+// enclave.
+type EnclaveMetrics struct {
+	ModuleCache       ModuleCacheMetrics      `json:"module_cache"`
+	Instrumentation   HistogramMetrics        `json:"instrumentation"`
+	Ocalls            map[string]OcallMetrics `json:"ocalls"`
+	OOMActivations    uint64                  `json:"oom_activations"`
+	BlockVerification HistogramMetrics        `json:"block_verification"`
+}
+
+type ModuleCacheMetrics struct {
+	Hits      uint64 `json:"hits"`
+	Misses    uint64 `json:"misses"`
+	Evictions uint64 `json:"evictions"`
+}
+
+// HistogramMetrics are durations in buckets, which are cumulative like the buckets of Prometheus
+type HistogramMetrics struct {
+	Buckets   []HistogramBucket `json:"buckets"`
+	Count     uint64            `json:"count"`
+	SumMicros uint64            `json:"sum_micros"`
+}
+
+type HistogramBucket struct {
+	LeMicros uint64 `json:"le_micros"`
+	Count    uint64 `json:"count"`
+}
+
+type OcallMetrics struct {
+	Calls uint64 `json:"calls"`
+	Bytes uint64 `json:"bytes"`
+}
+
+// EngineParams are the consensus critical limits of the contract engine. The chain keeps them in
+// its state and the enclave enforces them. Calls made before the chain set them run under the
+// rules contracts always ran under.
+type EngineParams struct {
+	// MaxMemoryPages is the most wasm memory pages (64 KiB each) a contract may use
+	MaxMemoryPages uint32 `json:"max_memory_pages"`
+	// MaxCallDepth is the deepest contract call that may run, counting submessages, replies and
+	// queries
+	MaxCallDepth uint32 `json:"max_call_depth"`
+}
+
 type SigInfo struct {
 	TxBytes           []byte `json:"tx_bytes"`
 	SignBytes         []byte `json:"sign_bytes"`
@@ -0,0 +1,292 @@
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{App, Arg};
+use serde::Serialize;
+use sgx_types::sgx_ql_qv_result_t;
+
+use enclave_ffi_types::{split_combined_cert, NodeAuthResult};
+
+use enclave_attestation_report::report::{AttestationReport, SgxEnclaveReport, SgxQuoteStatus};
+use enclave_attestation_report::tcb_report::DcapTcbReport;
+use sgx_attestation::dcap;
+
+/// `enclave_crypto::consts::MRSIGNER` of a production enclave
+const PRODUCTION_MR_SIGNER: &str =
+    "845cf34814f45595c720f81f74794d785931484f4405d6e5036ef88713ff3fa6";
+
+const EXIT_REJECTED: i32 = 1;
+const EXIT_BAD_INPUT: i32 = 2;
+
+/// Everything we could learn about a certificate. Fields that are `None` could not be extracted,
+/// usually because verification failed before reaching them
+#[derive(Serialize, Default)]
+struct VerificationOutput {
+    attestation_type: &'static str,
+    /// The certificate would be accepted by `ecall_authenticate_new_node` of an enclave that
+    /// requires the expected MRSIGNER, the production one unless `--mr-signer` is passed
+    accepted: bool,
+    result: String,
+    mr_enclave: Option<String>,
+    mr_signer: Option<String>,
+    /// The registration public key embedded in the report data
+    public_key: Option<String>,
+    tcb_status: Option<String>,
+    tcb_date: Option<String>,
+    tcb_evaluation_data_number: Option<u64>,
+    advisory_ids: Vec<String>,
+    collateral_expired: Option<bool>,
+    /// Time of the IAS report, for EPID certificates
+    timestamp: Option<u64>,
+    error: Option<String>,
+}
+
+impl VerificationOutput {
+    fn new(attestation_type: &'static str) -> Self {
+        Self {
+            attestation_type,
+            ..Default::default()
+        }
+    }
+
+    fn set_result(&mut self, result: NodeAuthResult) {
+        self.accepted = result == NodeAuthResult::Success;
+        self.result = result.to_string();
+    }
+
+    fn set_enclave_report(&mut self, report: &SgxEnclaveReport) {
+        self.mr_enclave = Some(hex::encode(report.mr_enclave));
+        self.mr_signer = Some(hex::encode(report.mr_signer));
+        self.public_key = Some(hex::encode(&report.report_data[..32]));
+    }
+}
+
+struct Expected {
+    mr_signer: Vec<u8>,
+    mr_enclave: Option<Vec<u8>>,
+}
+
+impl Expected {
+    fn check(&self, report: &SgxEnclaveReport) -> NodeAuthResult {
+        if self.mr_signer.as_slice() != report.mr_signer {
+            return NodeAuthResult::MrSignerMismatch;
+        }
+        if let Some(mr_enclave) = &self.mr_enclave {
+            if mr_enclave.as_slice() != report.mr_enclave {
+                return NodeAuthResult::MrEnclaveMismatch;
+            }
+        }
+        NodeAuthResult::Success
+    }
+}
+
+/// Same policy as `verify_quote_status` in the enclave
+fn epid_quote_status_result(report: &AttestationReport) -> NodeAuthResult {
+    let status_ok = match report.sgx_quote_status {
+        SgxQuoteStatus::OK
+        | SgxQuoteStatus::SwHardeningNeeded
+        | SgxQuoteStatus::ConfigurationAndSwHardeningNeeded => true,
+        SgxQuoteStatus::GroupOutOfDate => !cfg!(feature = "production"),
+        _ => false,
+    };
+
+    if !status_ok {
+        return NodeAuthResult::from(&report.sgx_quote_status);
+    }
+
+    if cfg!(feature = "production") && !report.advisory_ids.vulnerable().is_empty() {
+        return NodeAuthResult::from(&report.sgx_quote_status);
+    }
+
+    NodeAuthResult::Success
+}
+
+fn verify_epid(cert: &[u8], expected: &Expected) -> VerificationOutput {
+    let mut output = VerificationOutput::new("epid");
+
+    let report = match AttestationReport::from_cert(cert) {
+        Ok(report) => report,
+        Err(e) => {
+            output.set_result(NodeAuthResult::InvalidCert);
+            output.error = Some(format!("Failed to verify the IAS report: {:?}", e));
+            return output;
+        }
+    };
+
+    let enclave_report = &report.sgx_quote_body.isv_enclave_report;
+    output.set_enclave_report(enclave_report);
+    output.tcb_status = Some(format!("{:?}", report.sgx_quote_status));
+    output.tcb_evaluation_data_number = Some(report.tcb_eval_data_number as u64);
+    output.advisory_ids = report.advisory_ids.0.clone();
+    output.timestamp = Some(report.timestamp);
+
+    let mut result = epid_quote_status_result(&report);
+    if result == NodeAuthResult::Success {
+        result = expected.check(enclave_report);
+    }
+    output.set_result(result);
+
+    output
+}
+
+fn verify_dcap(
+    quote: &[u8],
+    collateral: &[u8],
+    time_s: i64,
+    expected: &Expected,
+) -> VerificationOutput {
+    let mut output = VerificationOutput::new("dcap");
+
+    let enclave_report = match dcap::quote_report_body(quote).map(SgxEnclaveReport::parse_from) {
+        Some(Ok(report)) => report,
+        _ => {
+            output.set_result(NodeAuthResult::InvalidCert);
+            output.error = Some("Failed to parse the quote".to_string());
+            return output;
+        }
+    };
+
+    let verification = match dcap::verify_quote(quote, collateral, time_s) {
+        Ok(verification) => verification,
+        Err(e) => {
+            output.set_result(NodeAuthResult::InvalidCert);
+            output.error = Some(format!("Failed to verify the quote: {}", e));
+            return output;
+        }
+    };
+
+    let tcb_report = DcapTcbReport::from_verification(
+        verification.qv_result,
+        verification.collateral_expired,
+        verification.tcb_level_date_tag,
+        collateral,
+    );
+
+    // the report body can only be trusted once the quote signature was verified
+    output.set_enclave_report(&enclave_report);
+    output.tcb_status = Some(tcb_report.tcb_status);
+    output.tcb_date = tcb_report.tcb_date;
+    output.tcb_evaluation_data_number = tcb_report.tcb_evaluation_data_number;
+    output.advisory_ids = tcb_report.advisory_ids;
+    output.collateral_expired = Some(verification.collateral_expired);
+
+    // Same policy as `check_quote_verification` in the enclave
+    let result = match verification.qv_result {
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK
+        | sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED
+            if !verification.collateral_expired =>
+        {
+            expected.check(&enclave_report)
+        }
+        _ => NodeAuthResult::InvalidCert,
+    };
+    output.set_result(result);
+
+    output
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        process::exit(EXIT_BAD_INPUT);
+    })
+}
+
+fn parse_measurement(value: &str, name: &str) -> Vec<u8> {
+    match hex::decode(value) {
+        Ok(measurement) if measurement.len() == 32 => measurement,
+        _ => {
+            eprintln!("{} must be 32 hex encoded bytes", name);
+            process::exit(EXIT_BAD_INPUT);
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("Verify attestation")
+        .version("1.0")
+        .about("Verifies a registration certificate offline, and prints what it attests to as JSON")
+        .arg(
+            Arg::with_name("cert")
+                .help("A combined certificate (attestation_combined.bin) or an EPID certificate (attestation_cert.der)")
+                .required_unless("quote")
+                .conflicts_with("quote"),
+        )
+        .arg(
+            Arg::with_name("quote")
+                .long("quote")
+                .takes_value(true)
+                .requires("collateral")
+                .help("A DCAP quote, instead of a certificate"),
+        )
+        .arg(
+            Arg::with_name("collateral")
+                .long("collateral")
+                .takes_value(true)
+                .requires("quote")
+                .help("The collateral for --quote"),
+        )
+        .arg(
+            Arg::with_name("time")
+                .long("time")
+                .takes_value(true)
+                .help("Unix time to check DCAP collateral expiration at, e.g. the time of a registration block. Defaults to now"),
+        )
+        .arg(
+            Arg::with_name("mr-signer")
+                .long("mr-signer")
+                .takes_value(true)
+                .default_value(PRODUCTION_MR_SIGNER)
+                .help("Reject the certificate unless the enclave has this MRSIGNER (hex). Defaults to the MRSIGNER of production enclaves"),
+        )
+        .arg(
+            Arg::with_name("mr-enclave")
+                .long("mr-enclave")
+                .takes_value(true)
+                .help("Reject the certificate unless the enclave has this MRENCLAVE (hex)"),
+        )
+        .get_matches();
+
+    let expected = Expected {
+        mr_signer: parse_measurement(matches.value_of("mr-signer").unwrap(), "--mr-signer"),
+        mr_enclave: matches
+            .value_of("mr-enclave")
+            .map(|value| parse_measurement(value, "--mr-enclave")),
+    };
+
+    let time_s = match matches.value_of("time") {
+        Some(time) => time.parse::<i64>().unwrap_or_else(|_| {
+            eprintln!("--time must be a unix timestamp");
+            process::exit(EXIT_BAD_INPUT);
+        }),
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64,
+    };
+
+    let output = if let Some(quote_path) = matches.value_of("quote") {
+        let quote = read_file(quote_path);
+        let collateral = read_file(matches.value_of("collateral").unwrap());
+        verify_dcap(&quote, &collateral, time_s, &expected)
+    } else {
+        let cert = read_file(matches.value_of("cert").unwrap());
+        let (epid_cert, quote, collateral) = split_combined_cert(&cert);
+
+        // same precedence as ecall_authenticate_new_node
+        if !quote.is_empty() && !collateral.is_empty() {
+            verify_dcap(quote, collateral, time_s, &expected)
+        } else if !epid_cert.is_empty() {
+            verify_epid(epid_cert, &expected)
+        } else {
+            // not a combined certificate, so this should be a plain EPID certificate
+            verify_epid(&cert, &expected)
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+
+    if !output.accepted {
+        process::exit(EXIT_REJECTED);
+    }
+}
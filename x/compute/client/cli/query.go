@@ -52,6 +52,7 @@ func GetQueryCmd() *cobra.Command {
 		GetCmdCodeHashByCodeID(),
 		CmdDecryptText(),
 		GetCmdGetContractHistory(),
+		GetCmdDiscloseTxKeys(),
 	)
 	return queryCmd
 }
@@ -185,6 +186,40 @@ func GetCmdCodeHashByCodeID() *cobra.Command {
 	return cmd
 }
 
+// GetCmdDiscloseTxKeys returns the IO keys of the txs listed in a disclosure permit, encrypted to the auditor
+func GetCmdDiscloseTxKeys() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "disclose-tx-keys [permit_file]",
+		Short: "Disclose the keys of past txs to an auditor",
+		Long: `Verifies a disclosure permit signed by the tx sender, and prints the keys of the txs it lists,
+encrypted to the auditor's public key. The auditor decrypts each key with their own key and the nonce of the tx`,
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			clientCtx, err := client.GetClientQueryContext(cmd)
+			if err != nil {
+				return err
+			}
+
+			permit, err := os.ReadFile(args[0])
+			if err != nil {
+				return fmt.Errorf("error reading permit file: %s", err)
+			}
+
+			route := fmt.Sprintf("custom/%s/%s", types.QuerierRoute, keeper.QueryDiscloseTxKeys)
+			res, _, err := clientCtx.QueryWithData(route, permit)
+			if err != nil {
+				return fmt.Errorf("error querying disclosure: %s", err)
+			}
+
+			fmt.Println(string(res))
+			return nil
+		},
+	}
+
+	flags.AddQueryFlagsToCmd(cmd)
+	return cmd
+}
+
 // GetCmdListContractByCode lists all wasm code uploaded for given code id
 func GetCmdListContractByCode() *cobra.Command {
 	cmd := &cobra.Command{
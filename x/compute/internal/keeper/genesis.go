@@ -48,6 +48,7 @@ func InitGenesis(ctx sdk.Context, keeper Keeper, data types.GenesisState) error
 		return sdkerrors.Wrapf(types.ErrInvalid, "seq %s must be greater %d ", string(types.KeyLastInstanceID), maxContractID)
 	}
 	// keeper.setParams(ctx, data.Params)
+	keeper.SetEngineParams(ctx, types.DefaultEngineParams())
 
 	return nil
 }
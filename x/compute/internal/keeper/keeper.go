@@ -6,6 +6,7 @@ import (
 	"encoding/binary"
 	"encoding/hex"
 	"encoding/json"
+	"errors"
 	"fmt"
 	"path/filepath"
 	"strconv"
@@ -90,6 +91,38 @@ func moduleLogger(ctx sdk.Context) log.Logger {
 	return ctx.Logger().With("module", fmt.Sprintf("x/%s", types.ModuleName))
 }
 
+// logEnclaveError logs the context the enclave reported for the error of a contract call, which
+// only shows up in the error returned to the user as a message
+func logEnclaveError(ctx sdk.Context, contractAddress sdk.AccAddress, err error) {
+	var enclaveErr wasmTypes.EnclaveError
+	if !errors.As(err, &enclaveErr) {
+		return
+	}
+
+	logger := moduleLogger(ctx)
+	logger.Debug(
+		"contract call failed in the enclave",
+		"contract", contractAddress.String(),
+		"code", enclaveErr.Code,
+		"stage", enclaveErr.Stage,
+		"detail", fmt.Sprintf("0x%X", enclaveErr.Detail),
+	)
+	if enclaveErr.Backtrace != "" {
+		logger.Debug(enclaveErr.Backtrace, "contract", contractAddress.String())
+	}
+}
+
+// contractCallError wraps the error of a contract call in errType, unless the enclave refused the
+// call for being deeper than the call depth limit, in which case it's wrapped in
+// ErrExceedMaxCallDepth so the contract that sent the call can tell.
+func contractCallError(errType *sdkerrors.Error, err error) error {
+	var enclaveErr wasmTypes.EnclaveError
+	if errors.As(err, &enclaveErr) && enclaveErr.Code == wasmTypes.EnclaveErrorCodeExceededCallDepth {
+		return sdkerrors.Wrap(types.ErrExceedMaxCallDepth, err.Error())
+	}
+	return sdkerrors.Wrap(errType, err.Error())
+}
+
 // MessageRouter ADR 031 request type routing
 type MessageRouter interface {
 	Handler(msg sdk.Msg) baseapp.MsgServiceHandler
@@ -120,7 +153,7 @@ func NewKeeper(
 	customPlugins *QueryPlugins,
 	lastMsgManager *baseapp.LastMsgMarkerContainer,
 ) Keeper {
-	wasmer, err := wasm.NewWasmer(filepath.Join(homeDir, "wasm"), supportedFeatures, wasmConfig.CacheSize, wasmConfig.EnclaveCacheSize)
+	wasmer, err := wasm.NewWasmer(filepath.Join(homeDir, "wasm"), supportedFeatures, wasmConfig.CacheSize, wasmConfig.EnclaveCacheSize, wasmConfig.QueryEnclaveSlots)
 	if err != nil {
 		panic(err)
 	}
@@ -156,6 +189,10 @@ func NewKeeper(
 	return keeper
 }
 
+func (k Keeper) getMaxCallDepth() uint32 {
+	return k.maxCallDepth
+}
+
 func (k Keeper) GetLastMsgMarkerContainer() *baseapp.LastMsgMarkerContainer {
 	return k.LastMsgManager
 }
@@ -168,7 +205,7 @@ func (k Keeper) Create(ctx sdk.Context, creator sdk.AccAddress, wasmCode []byte,
 	}
 	ctx.GasMeter().ConsumeGas(types.CompileCost*uint64(len(wasmCode)), "Compiling WASM Bytecode")
 
-	codeHash, err := k.wasmer.Create(wasmCode)
+	codeHash, err := k.wasmer.Create(wasmCode, k.GetEngineParams(ctx))
 	if err != nil {
 		return 0, sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
 	}
@@ -187,7 +224,8 @@ func (k Keeper) importCode(ctx sdk.Context, codeID uint64, codeInfo types.CodeIn
 	if err != nil {
 		return sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
 	}
-	newCodeHash, err := k.wasmer.Create(wasmCode)
+	// Imported code was already accepted by the chain it comes from, so it isn't analyzed again
+	newCodeHash, err := k.wasmer.Create(wasmCode, nil)
 	if err != nil {
 		return sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
 	}
@@ -471,7 +509,7 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.A
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
 	// prepare env for contract instantiate call
-	env := types.NewEnv(ctx,
+	env := k.newEnv(ctx,
 		creator,
 		deposit,
 		contractAddress,
@@ -499,6 +537,7 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.A
 	consumeGas(ctx, gasUsed)
 
 	if initError != nil {
+		logEnclaveError(ctx, contractAddress, initError)
 		switch res := response.(type) { //nolint:gocritic
 		case v1wasmTypes.DataWithInternalReplyInfo:
 			result, jsonError := json.Marshal(res)
@@ -506,10 +545,10 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.A
 				return nil, nil, sdkerrors.Wrap(jsonError, "couldn't marshal internal reply info")
 			}
 
-			return contractAddress, result, sdkerrors.Wrap(types.ErrInstantiateFailed, initError.Error())
+			return contractAddress, result, contractCallError(types.ErrInstantiateFailed, initError)
 		}
 
-		return contractAddress, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, initError.Error())
+		return contractAddress, nil, contractCallError(types.ErrInstantiateFailed, initError)
 	}
 
 	switch res := response.(type) {
@@ -641,7 +680,7 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		return nil, err
 	}
 
-	env := types.NewEnv(ctx, caller, coins, contractAddress, contractKey, random)
+	env := k.newEnv(ctx, caller, coins, contractAddress, contractKey, random)
 
 	// prepare querier
 	querier := QueryHandler{
@@ -653,7 +692,143 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	response, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, env, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx), sigInfo, handleType)
 	consumeGas(ctx, gasUsed)
 
+	return k.handleExecuteResponse(ctx, contractAddress, contractInfo, msg, sigInfo, response, execErr)
+}
+
+// BatchedExecute is one message of a batch of messages to the same contract
+type BatchedExecute struct {
+	Caller      sdk.AccAddress
+	Msg         []byte
+	Coins       sdk.Coins
+	CallbackSig []byte
+	HandleType  wasmTypes.HandleType
+}
+
+// ExecuteBatch executes messages of the same block to the same contract in one entry into the
+// enclave, each one the same way Execute would. The funds of the first message are sent before it
+// runs, so only the messages after it that send no funds join the batch. The batch stops after a
+// message that failed or whose response dispatches messages, which are dispatched before the next
+// message runs. It returns the results of the messages that ran, at least of the first one, and
+// the caller continues with the rest of the messages.
+func (k Keeper) ExecuteBatch(ctx sdk.Context, contractAddress sdk.AccAddress, msgs []BatchedExecute) ([]*sdk.Result, error) {
+	defer telemetry.MeasureSince(time.Now(), "compute", "keeper", "execute_batch")
+
+	if len(msgs) == 0 {
+		return nil, sdkerrors.Wrap(types.ErrExecuteFailed, "empty batch")
+	}
+
+	ctx.GasMeter().ConsumeGas(types.InstanceCost, "Loading Compute module: execute")
+
+	count := 1
+	for count < len(msgs) && msgs[count].Coins.IsZero() {
+		count++
+	}
+	msgs = msgs[:count]
+
+	sigInfos := make([]wasmTypes.SigInfo, len(msgs))
+	for i, msg := range msgs {
+		signBytes := []byte{}
+		signMode := sdktxsigning.SignMode_SIGN_MODE_UNSPECIFIED
+		modeInfoBytes := []byte{}
+		pkBytes := []byte{}
+		signerSig := []byte{}
+		var err error
+
+		// If no callback signature - we should send the actual msg sender sign bytes and signature
+		if msg.CallbackSig == nil {
+			signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, msg.Caller)
+			if err != nil {
+				return nil, err
+			}
+		}
+
+		sigInfos[i] = types.NewSigInfo(ctx.TxBytes(), signBytes, signMode, modeInfoBytes, pkBytes, signerSig, msg.CallbackSig)
+	}
+
+	contractInfo, codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
+	// add more funds
+	if !msgs[0].Coins.IsZero() {
+		if k.bankKeeper.BlockedAddr(msgs[0].Caller) {
+			return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, "blocked address can not be used")
+		}
+
+		sdkerr := k.bankKeeper.SendCoins(ctx, msgs[0].Caller, contractAddress, msgs[0].Coins)
+		if sdkerr != nil {
+			return nil, sdkerr
+		}
+	}
+
+	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
+
+	contractKey, err := k.GetContractKey(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
+	// every message after the first one is charged the cost of loading the contract before it runs
+	gasLimit := gasForContract(ctx)
+	calls := make([]wasm.ExecuteBatchCall, len(msgs))
+	for i, msg := range msgs {
+		callGasLimit := gasLimit
+		loadCost := uint64(i) * types.InstanceCost * types.GasMultiplier
+		if loadCost < callGasLimit {
+			callGasLimit -= loadCost
+		} else {
+			callGasLimit = 0
+		}
+
+		calls[i] = wasm.ExecuteBatchCall{
+			Env:        k.newEnv(ctx, msg.Caller, msg.Coins, contractAddress, contractKey, random),
+			ExecuteMsg: msg.Msg,
+			SigInfo:    sigInfos[i],
+			HandleType: msg.HandleType,
+			GasLimit:   callGasLimit,
+		}
+	}
+
+	// prepare querier
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+		Caller:  contractAddress,
+	}
+
+	batchResults, gasUsed, batchErr := k.wasmer.ExecuteBatch(codeInfo.CodeHash, calls, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasLimit)
+	if batchErr != nil {
+		consumeGas(ctx, gasUsed)
+		logEnclaveError(ctx, contractAddress, batchErr)
+		return nil, contractCallError(types.ErrExecuteFailed, batchErr)
+	}
+	if len(batchResults) == 0 || len(batchResults) > len(msgs) {
+		consumeGas(ctx, gasUsed)
+		return nil, sdkerrors.Wrap(types.ErrExecuteFailed, fmt.Sprintf("the batch returned %d results for %d messages", len(batchResults), len(msgs)))
+	}
+
+	results := make([]*sdk.Result, 0, len(batchResults))
+	for i, batchResult := range batchResults {
+		if i > 0 {
+			ctx.GasMeter().ConsumeGas(types.InstanceCost, "Loading Compute module: execute")
+		}
+		consumeGas(ctx, batchResult.GasUsed)
+
+		result, err := k.handleExecuteResponse(ctx, contractAddress, contractInfo, msgs[i].Msg, sigInfos[i], batchResult.Response, batchResult.Err)
+		results = append(results, result)
+		if err != nil {
+			return results, err
+		}
+	}
+	return results, nil
+}
+
+// handleExecuteResponse turns what the contract returned for a message into the result of the
+// message, and dispatches the messages of the contract response
+func (k Keeper) handleExecuteResponse(ctx sdk.Context, contractAddress sdk.AccAddress, contractInfo types.ContractInfo, msg []byte, sigInfo wasmTypes.SigInfo, response interface{}, execErr error) (*sdk.Result, error) {
 	if execErr != nil {
+		logEnclaveError(ctx, contractAddress, execErr)
 		var result sdk.Result
 		var jsonError error
 		switch res := response.(type) { //nolint:gocritic
@@ -664,7 +839,7 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 			}
 		}
 
-		return &result, sdkerrors.Wrap(types.ErrExecuteFailed, execErr.Error())
+		return &result, contractCallError(types.ErrExecuteFailed, execErr)
 	}
 
 	switch res := response.(type) {
@@ -737,7 +912,7 @@ func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress,
 		return nil, err
 	}
 
-	params := types.NewEnv(
+	params := k.newEnv(
 		ctx,
 		sdk.AccAddress{}, /* empty because it's unused in queries */
 		sdk.NewCoins(),   /* empty because it's unused in queries */
@@ -745,7 +920,7 @@ func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress,
 		contractKey,
 		[]byte{0}, /* empty because it's unused in queries */
 	)
-	params.QueryDepth = queryDepth
+	params.CallDepth = queryDepth
 
 	queryResult, gasUsed, qErr := k.wasmer.Query(codeInfo.CodeHash, params, req, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
 	consumeGas(ctx, gasUsed)
@@ -753,11 +928,84 @@ func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress,
 	telemetry.SetGauge(float32(gasUsed), "compute", "keeper", "query", contractAddress.String(), "gasUsed")
 
 	if qErr != nil {
+		logEnclaveError(ctx, contractAddress, qErr)
 		return nil, sdkerrors.Wrap(types.ErrQueryFailed, qErr.Error())
 	}
 	return queryResult, nil
 }
 
+// ReportEnclaveDoorbellMetrics reports how busy the lanes that transactions and queries use to
+// enter the enclave are
+func (k Keeper) ReportEnclaveDoorbellMetrics(ctx sdk.Context) {
+	metrics, err := k.wasmer.EnclaveDoorbellMetrics()
+	if err != nil {
+		ctx.Logger().Error("failed to get the enclave doorbell metrics", "err", err)
+		return
+	}
+
+	lanes := map[string]wasmTypes.DoorbellLaneMetrics{
+		"transactions": metrics.Transactions,
+		"queries":      metrics.Queries,
+	}
+	for lane, m := range lanes {
+		telemetry.SetGauge(float32(m.Slots), "compute", "enclave", lane, "slots")
+		telemetry.SetGauge(float32(m.InUse), "compute", "enclave", lane, "in_use")
+		telemetry.SetGauge(float32(m.QueueDepth), "compute", "enclave", lane, "queue_depth")
+		telemetry.SetGauge(float32(m.Entries), "compute", "enclave", lane, "entries")
+		telemetry.SetGauge(float32(m.Waits), "compute", "enclave", lane, "waits")
+		telemetry.SetGauge(float32(m.TotalWaitMicros), "compute", "enclave", lane, "total_wait_micros")
+		telemetry.SetGauge(float32(m.MaxWaitMicros), "compute", "enclave", lane, "max_wait_micros")
+		telemetry.SetGauge(float32(m.Timeouts), "compute", "enclave", lane, "timeouts")
+	}
+}
+
+// StartEnclaveMetricsReporter reports the runtime metrics of the enclave every interval, for as
+// long as the node runs. Getting them enters the enclave, so they're reported in the background
+// and not from the block execution, and only while telemetry is enabled.
+func (k Keeper) StartEnclaveMetricsReporter(logger log.Logger, interval time.Duration) {
+	logger = logger.With("module", fmt.Sprintf("x/%s", types.ModuleName))
+	go func() {
+		ticker := time.NewTicker(interval)
+		defer ticker.Stop()
+		for range ticker.C {
+			if telemetry.IsTelemetryEnabled() {
+				k.ReportEnclaveMetrics(logger)
+			}
+		}
+	}()
+}
+
+// ReportEnclaveMetrics reports the runtime metrics of the enclave, such as module cache hits, ocall
+// counts and block verification times
+func (k Keeper) ReportEnclaveMetrics(logger log.Logger) {
+	metrics, err := k.wasmer.EnclaveMetrics()
+	if err != nil {
+		logger.Error("failed to get the enclave metrics", "err", err)
+		return
+	}
+
+	telemetry.SetGauge(float32(metrics.ModuleCache.Hits), "compute", "enclave", "module_cache", "hits")
+	telemetry.SetGauge(float32(metrics.ModuleCache.Misses), "compute", "enclave", "module_cache", "misses")
+	telemetry.SetGauge(float32(metrics.ModuleCache.Evictions), "compute", "enclave", "module_cache", "evictions")
+	for ocall, m := range metrics.Ocalls {
+		telemetry.SetGauge(float32(m.Calls), "compute", "enclave", ocall, "calls")
+		telemetry.SetGauge(float32(m.Bytes), "compute", "enclave", ocall, "bytes")
+	}
+	telemetry.SetGauge(float32(metrics.OOMActivations), "compute", "enclave", "oom_activations")
+	reportEnclaveHistogram("instrumentation", metrics.Instrumentation)
+	reportEnclaveHistogram("block_verification", metrics.BlockVerification)
+}
+
+func reportEnclaveHistogram(name string, h wasmTypes.HistogramMetrics) {
+	// the buckets are cumulative, like the buckets of Prometheus histograms
+	for _, bucket := range h.Buckets {
+		le := "le_" + strconv.FormatUint(bucket.LeMicros, 10)
+		telemetry.SetGauge(float32(bucket.Count), "compute", "enclave", name, "bucket", le)
+	}
+	telemetry.SetGauge(float32(h.Count), "compute", "enclave", name, "count")
+	telemetry.SetGauge(float32(h.SumMicros), "compute", "enclave", name, "sum_micros")
+}
+
 func checkAndIncreaseCallDepth(ctx sdk.Context, maxCallDepth uint32) (sdk.Context, error) {
 	var callDepth uint32
 	if size, ok := types.CallDepth(ctx); ok {
@@ -857,6 +1105,47 @@ func (k Keeper) SetRandomSeed(ctx sdk.Context, random []byte) {
 	store.Set(types.GetRandomKey(ctx.BlockHeight()), random)
 }
 
+// GetEngineParams returns the engine params of the chain, or nil before the chain set them, in
+// which case contracts run under the rules they always ran under
+func (k Keeper) GetEngineParams(ctx sdk.Context) *wasmTypes.EngineParams {
+	store := ctx.KVStore(k.storeKey)
+
+	bz := store.Get(types.EngineParamsKey)
+	if bz == nil {
+		return nil
+	}
+
+	var params wasmTypes.EngineParams
+	if err := json.Unmarshal(bz, &params); err != nil {
+		panic(err)
+	}
+	return &params
+}
+
+// newEnv returns the env of a contract call, which carries the engine params the call runs under
+func (k Keeper) newEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contractAddr sdk.AccAddress, contractKey types.ContractKey, random []byte) wasmTypes.Env {
+	env := types.NewEnv(ctx, creator, deposit, contractAddr, contractKey, random)
+	env.EngineParams = k.GetEngineParams(ctx)
+	if env.EngineParams == nil {
+		// before the chain set its engine params, only queries counted towards the depth the
+		// enclave limits, so every call from a tx was at depth 1
+		env.CallDepth = 1
+	}
+	return env
+}
+
+// SetEngineParams sets the engine params of the chain. They're consensus critical, so they're only
+// set in genesis and in upgrade handlers.
+func (k Keeper) SetEngineParams(ctx sdk.Context, params *wasmTypes.EngineParams) {
+	store := ctx.KVStore(k.storeKey)
+
+	bz, err := json.Marshal(params)
+	if err != nil {
+		panic(err)
+	}
+	store.Set(types.EngineParamsKey, bz)
+}
+
 func (k Keeper) GetContractAddress(ctx sdk.Context, label string) sdk.AccAddress {
 	store := ctx.KVStore(k.storeKey)
 
@@ -1189,7 +1478,7 @@ func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1w
 
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
-	env := types.NewEnv(ctx, contractAddress, sdk.Coins{}, contractAddress, contractKey, random)
+	env := k.newEnv(ctx, contractAddress, sdk.Coins{}, contractAddress, contractKey, random)
 
 	// prepare querier
 	querier := QueryHandler{
@@ -1209,7 +1498,8 @@ func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1w
 	consumeGas(ctx, gasUsed)
 
 	if execErr != nil {
-		return nil, sdkerrors.Wrap(types.ErrReplyFailed, execErr.Error())
+		logEnclaveError(ctx, contractAddress, execErr)
+		return nil, contractCallError(types.ErrReplyFailed, execErr)
 	}
 
 	switch res := response.(type) {
@@ -1267,7 +1557,7 @@ func (k Keeper) UpdateContractAdmin(ctx sdk.Context, contractAddress, caller, ne
 		return err
 	}
 
-	env := types.NewEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, nil)
+	env := k.newEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, nil)
 
 	currentAdminAddress, err := sdk.AccAddressFromBech32(contractInfo.Admin)
 	if err != nil {
@@ -1363,7 +1653,7 @@ func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
-	env := types.NewEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, random)
+	env := k.newEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, random)
 
 	adminProof := contractInfo.AdminProof
 	admin := contractInfo.Admin
@@ -1384,6 +1674,7 @@ func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	consumeGas(ctx, gasUsed)
 
 	if migrateErr != nil {
+		logEnclaveError(ctx, contractAddress, migrateErr)
 		var result []byte
 		var jsonError error
 		switch res := response.(type) { //nolint:gocritic
@@ -1394,7 +1685,7 @@ func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 			}
 		}
 
-		return result, sdkerrors.Wrap(types.ErrMigrationFailed, migrateErr.Error())
+		return result, contractCallError(types.ErrMigrationFailed, migrateErr)
 	}
 
 	// update contract key with new one
@@ -25,7 +25,7 @@ import (
 	reg "github.com/scrtlabs/SecretNetwork/x/registration"
 )
 
-const SupportedFeatures = "staking,stargate,ibc3,random"
+const SupportedFeatures = "staking,stargate,ibc3,random,padding,nan_canonicalization,softfloat"
 
 var wasmCtx = wasmUtils.WASMContext{
 	TestKeyPairPath: "/tmp/id_tx_io.json",
@@ -72,6 +72,32 @@ func TestNewKeeper(t *testing.T) {
 	require.NotNil(t, keepers.WasmKeeper)
 }
 
+func TestNewEnvCallDepthFollowsEngineParams(t *testing.T) {
+	encodingConfig := MakeEncodingConfig()
+	var transferPortSource types.ICS20TransferPortSource
+	transferPortSource = MockIBCTransferKeeper{GetPortFn: func(ctx sdk.Context) string {
+		return "myTransferPort"
+	}}
+	encoders := DefaultEncoders(transferPortSource, encodingConfig.Marshaler)
+	ctx, keepers := CreateTestInput(t, false, SupportedFeatures, &encoders, nil)
+	keeper := keepers.WasmKeeper
+
+	_, _, creator := keyPubAddr()
+	_, _, contractAddress := keyPubAddr()
+	// a call two submessages deep
+	ctx = types.WithCallDepth(ctx, 2)
+
+	// before the chain set its engine params, calls from a tx are all at depth 1
+	env := keeper.newEnv(ctx, creator, nil, contractAddress, types.ContractKey{}, nil)
+	require.Nil(t, env.EngineParams)
+	require.Equal(t, uint32(1), env.CallDepth)
+
+	keeper.SetEngineParams(ctx, types.DefaultEngineParams())
+	env = keeper.newEnv(ctx, creator, nil, contractAddress, types.ContractKey{}, nil)
+	require.Equal(t, types.DefaultEngineParams(), env.EngineParams)
+	require.Equal(t, uint32(3), env.CallDepth)
+}
+
 func TestCreate(t *testing.T) {
 	encodingConfig := MakeEncodingConfig()
 	var transferPortSource types.ICS20TransferPortSource
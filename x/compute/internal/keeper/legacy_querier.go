@@ -8,6 +8,7 @@ import (
 
 	sdk "github.com/cosmos/cosmos-sdk/types"
 	sdkerrors "github.com/cosmos/cosmos-sdk/types/errors"
+	"github.com/scrtlabs/SecretNetwork/go-cosmwasm/api"
 	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
 	abci "github.com/tendermint/tendermint/abci/types"
 )
@@ -22,6 +23,7 @@ const (
 	QueryContractKey          = "contract-key"
 	QueryContractHash         = "contract-hash"
 	QueryContractHashByCodeID = "contract-hash-by-id"
+	QueryDiscloseTxKeys       = "disclose-tx-keys"
 )
 
 const QueryMethodContractStateSmart = "smart"
@@ -99,6 +101,11 @@ func NewLegacyQuerier(keeper Keeper) sdk.Querier {
 			if err != nil {
 				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, err.Error())
 			}
+		case QueryDiscloseTxKeys:
+			bz, err = api.DiscloseTxKeys(req.Data)
+			if err != nil {
+				return nil, sdkerrors.Wrap(types.ErrInvalid, err.Error())
+			}
 		default:
 			return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, fmt.Sprintf("unknown data query endpoint %s", path[0]))
 		}
@@ -3,6 +3,7 @@ package keeper
 import (
 	"bytes"
 	"encoding/json"
+	"errors"
 	"fmt"
 	"os"
 	"sort"
@@ -26,10 +27,13 @@ type Messenger interface {
 	DispatchMsg(ctx sdk.Context, contractAddr sdk.AccAddress, contractIBCPortID string, msg v1wasmTypes.CosmosMsg) (events []sdk.Event, data [][]byte, err error)
 }
 
-// Replyer is a subset of keeper that can handle replies to submessages
+// Replyer is a subset of keeper that can handle replies to submessages and batches of them
 type Replyer interface {
 	reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1wasmTypes.Reply, ogTx []byte, ogSigInfo wasmTypes.SigInfo) ([]byte, error)
+	ExecuteBatch(ctx sdk.Context, contractAddress sdk.AccAddress, msgs []BatchedExecute) ([]*sdk.Result, error)
 	GetLastMsgMarkerContainer() *baseapp.LastMsgMarkerContainer
+	GetEngineParams(ctx sdk.Context) *wasmTypes.EngineParams
+	getMaxCallDepth() uint32
 }
 
 // MessageDispatcher coordinates message sending and submessage reply/ state commits
@@ -170,6 +174,12 @@ func redactError(err error) (bool, error) {
 		return false, err
 	}
 
+	// Do not redact call depth errors, so contracts can handle running out of call depth.
+	// They only depend on the depth of the call and on the limit, so they're deterministic
+	if errors.Is(err, types.ErrExceedMaxCallDepth) {
+		return true, err
+	}
+
 	// FIXME: do we want to hardcode some constant string mappings here as well?
 	// Or better document them? (SDK error string may change on a patch release to fix wording)
 	// sdk/11 is out of gas
@@ -185,11 +195,79 @@ func redactError(err error) (bool, error) {
 	return true, fmt.Errorf("the error was redacted (codespace: %s, code: %d). For more info use latest localsecret and reproduce the issue", codespace, code)
 }
 
+// batchedExecutes returns the executes that the submessages at the start of msgs send to the same
+// contract without a reply or a gas limit, the way the encoder would pass them to the handler
+func batchedExecutes(contractAddr sdk.AccAddress, msgs []v1wasmTypes.SubMsg) (sdk.AccAddress, []BatchedExecute) {
+	var contract sdk.AccAddress
+	var batch []BatchedExecute
+	for _, msg := range msgs {
+		if msg.ReplyOn != v1wasmTypes.ReplyNever || msg.GasLimit != nil || msg.Msg.Wasm == nil || msg.Msg.Wasm.Execute == nil {
+			break
+		}
+		execute := msg.Msg.Wasm.Execute
+
+		target, err := sdk.AccAddressFromBech32(execute.ContractAddr)
+		if err != nil || (contract != nil && !target.Equals(contract)) {
+			break
+		}
+		coins, err := convertWasmCoinsToSdkCoins(execute.Send)
+		if err != nil {
+			break
+		}
+		sdkMsg := types.MsgExecuteContract{
+			Sender:           contractAddr,
+			Contract:         target,
+			CallbackCodeHash: execute.CallbackCodeHash,
+			Msg:              execute.Msg,
+			SentFunds:        coins,
+			CallbackSig:      execute.CallbackSignature,
+		}
+		if sdkMsg.ValidateBasic() != nil {
+			break
+		}
+
+		contract = target
+		batch = append(batch, BatchedExecute{
+			Caller:      contractAddr,
+			Msg:         execute.Msg,
+			Coins:       coins,
+			CallbackSig: execute.CallbackSignature,
+			HandleType:  wasmTypes.HandleTypeExecute,
+		})
+	}
+	return contract, batch
+}
+
+// dispatchExecuteBatch runs the executes at the start of a batch like DispatchSubmessages would
+// dispatch them one by one, and returns how many of them ran. Since none of them wants a reply, a
+// failed execute fails the whole dispatch, so the state and the events of the batch are only kept
+// if all the executes that ran succeeded.
+func (d MessageDispatcher) dispatchExecuteBatch(ctx sdk.Context, contract sdk.AccAddress, batch []BatchedExecute) (int, error) {
+	subCtx, commit := ctx.CacheContext()
+	em := sdk.NewEventManager()
+	subCtx = subCtx.WithEventManager(em)
+
+	subCtx, err := checkAndIncreaseCallDepth(subCtx, d.keeper.getMaxCallDepth())
+	if err != nil {
+		return 0, err
+	}
+
+	results, err := d.keeper.ExecuteBatch(subCtx, contract, batch)
+	if err != nil {
+		return 0, err
+	}
+
+	commit()
+	ctx.EventManager().EmitEvents(filterEvents(em.Events()))
+	return len(results), nil
+}
+
 // DispatchSubmessages builds a sandbox to execute these messages and returns the execution result to the contract
 // that dispatched them, both on success as well as failure
 func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk.AccAddress, ibcPort string, msgs []v1wasmTypes.SubMsg, ogTx []byte, ogSigInfo wasmTypes.SigInfo) ([]byte, error) {
 	var rsp []byte
-	for _, msg := range msgs {
+	for i := 0; i < len(msgs); i++ {
+		msg := msgs[i]
 
 		if d.keeper.GetLastMsgMarkerContainer().GetMarker() {
 			return nil, sdkerrors.Wrap(sdkerrors.ErrLastTx, "Cannot send messages or submessages after last tx marker was set")
@@ -209,6 +287,20 @@ func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk
 			return nil, sdkerrors.Wrap(types.ErrInvalid, "ReplyOn value")
 		}
 
+		// a run of executes of the same contract that don't want a reply runs in one entry into the
+		// enclave, for as many of them as ExecuteBatch can run at once. Since the batch loads the
+		// contract once, it costs less gas, so this waits for the chain to set its engine params.
+		if d.keeper.GetEngineParams(ctx) != nil {
+			if contract, batch := batchedExecutes(contractAddr, msgs[i:]); len(batch) > 1 {
+				executed, err := d.dispatchExecuteBatch(ctx, contract, batch)
+				if err != nil {
+					return nil, err
+				}
+				i += executed - 1
+				continue
+			}
+		}
+
 		// first, we build a sub-context which we can use inside the submessages
 		subCtx, commit := ctx.CacheContext()
 		em := sdk.NewEventManager()
@@ -342,7 +434,18 @@ func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk
 			replySigInfo.CallbackSignature = dataWithInternalReplyInfo.InternaReplyEnclaveSig
 		}
 
-		rspData, err := d.keeper.reply(ctx, contractAddr, reply, ogTx, replySigInfo)
+		// the reply runs one level deeper than the contract that sent the submessage, like the
+		// submessage itself, so chains of submessages and replies count towards the call depth.
+		// Replies didn't count before the chain set its engine params.
+		replyCtx := ctx
+		if d.keeper.GetEngineParams(ctx) != nil {
+			replyCtx, err = checkAndIncreaseCallDepth(ctx, d.keeper.getMaxCallDepth())
+			if err != nil {
+				return nil, err
+			}
+		}
+
+		rspData, err := d.keeper.reply(replyCtx, contractAddr, reply, ogTx, replySigInfo)
 		switch {
 		case err != nil:
 			return nil, err
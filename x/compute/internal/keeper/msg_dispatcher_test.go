@@ -0,0 +1,51 @@
+package keeper
+
+import (
+	"context"
+	"errors"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+
+	sdk "github.com/cosmos/cosmos-sdk/types"
+
+	wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+
+	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
+)
+
+func TestContractCallErrorDetectsCallDepth(t *testing.T) {
+	depthErr := wasmTypes.EnclaveError{
+		Msg:  "depth of nested contract calls exceeded: call depth 11 is above the limit of 10",
+		Code: wasmTypes.EnclaveErrorCodeExceededCallDepth,
+	}
+	err := contractCallError(types.ErrExecuteFailed, depthErr)
+	require.True(t, errors.Is(err, types.ErrExceedMaxCallDepth))
+	require.Contains(t, err.Error(), "call depth 11 is above the limit of 10")
+
+	// the error is passed to the reply as it is
+	isSdkError, redacted := redactError(err)
+	require.True(t, isSdkError)
+	require.Equal(t, err.Error(), redacted.Error())
+
+	otherErr := wasmTypes.EnclaveError{Msg: "execution ran out of gas", Code: 9}
+	err = contractCallError(types.ErrExecuteFailed, otherErr)
+	require.True(t, errors.Is(err, types.ErrExecuteFailed))
+	require.False(t, errors.Is(err, types.ErrExceedMaxCallDepth))
+}
+
+func TestCheckAndIncreaseCallDepth(t *testing.T) {
+	ctx := sdk.Context{}.WithContext(context.Background())
+	require.Equal(t, uint32(1), types.ContractCallDepth(ctx))
+
+	ctx, err := checkAndIncreaseCallDepth(ctx, 2)
+	require.NoError(t, err)
+	require.Equal(t, uint32(2), types.ContractCallDepth(ctx))
+
+	ctx, err = checkAndIncreaseCallDepth(ctx, 2)
+	require.NoError(t, err)
+	require.Equal(t, uint32(3), types.ContractCallDepth(ctx))
+
+	_, err = checkAndIncreaseCallDepth(ctx, 2)
+	require.ErrorIs(t, err, types.ErrExceedMaxCallDepth)
+}
@@ -40,7 +40,7 @@ func (k Keeper) ibcContractCall(ctx sdk.Context,
 	}
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
-	env := types.NewEnv(
+	env := k.newEnv(
 		ctx,
 		sdk.AccAddress{}, /* there's no MessageInfo for IBC contract calls */
 		sdk.NewCoins(),   /* there's no MessageInfo for IBC contract calls */
@@ -2394,6 +2394,139 @@ func TestLastMsgMarkerMultipleMsgsInATx(t *testing.T) {
 	require.Equal(t, 1, len(results))
 }
 
+func prepareBatch(t *testing.T, codeHash string, caller sdk.AccAddress, execMsgs []string) ([][]byte, []BatchedExecute) {
+	var msgsBz [][]byte
+	var batch []BatchedExecute
+	for _, execMsg := range execMsgs {
+		msg := types.SecretMsg{
+			CodeHash: []byte(codeHash),
+			Msg:      []byte(execMsg),
+		}
+		msgBz, err := wasmCtx.Encrypt(msg.Serialize())
+		require.NoError(t, err)
+
+		msgsBz = append(msgsBz, msgBz)
+		batch = append(batch, BatchedExecute{
+			Caller:     caller,
+			Msg:        msgBz,
+			HandleType: cosmwasm.HandleTypeExecute,
+		})
+	}
+	return msgsBz, batch
+}
+
+func TestExecuteBatch(t *testing.T) {
+	ctx, keeper, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, TestContractPaths[v1Contract], sdk.NewCoins())
+
+	_, _, contractAddress, _, _ := initHelper(t, keeper, ctx, codeID, walletA, nil, privKeyA, `{"counter":{"counter":10, "expires":100}}`, true, true, defaultGasForTests)
+
+	msgsBz, batch := prepareBatch(t, codeHash, walletA, []string{`{"increment":{"addition":1}}`, `{"increment":{"addition":2}}`, `{"increment":{"addition":0}}`, `{"increment":{"addition":3}}`})
+
+	gasMeter := &WasmCounterGasMeter{0, sdk.NewGasMeter(math.MaxUint64)}
+	ctx = ctx.WithGasMeter(gasMeter)
+	ctx = PrepareExecSignedTxWithMultipleMsgs(t, keeper, ctx, walletA, privKeyA, msgsBz, contractAddress, sdk.NewCoins())
+
+	// the batch stops after the failed message
+	results, err := keeper.ExecuteBatch(ctx, contractAddress, batch)
+	require.Len(t, results, 3)
+	require.Contains(t, fmt.Sprintf("%+v", extractInnerError(t, err, msgsBz[2][0:32], true, true)), "got wrong counter on increment")
+
+	// the gas of every message is charged on its own, like with Execute
+	require.Equal(t, uint64(3), gasMeter.GetWasmCounter())
+
+	require.Equal(t, uint32(11), binary.BigEndian.Uint32(getDecryptedData(t, results[0].Data, msgsBz[0][0:32])))
+	require.Equal(t, uint32(13), binary.BigEndian.Uint32(getDecryptedData(t, results[1].Data, msgsBz[1][0:32])))
+
+	// the caller continues with the messages after it
+	results, err = keeper.ExecuteBatch(ctx, contractAddress, batch[3:])
+	require.NoError(t, err)
+	require.Len(t, results, 1)
+	require.Equal(t, uint32(16), binary.BigEndian.Uint32(getDecryptedData(t, results[0].Data, msgsBz[3][0:32])))
+
+	queryRes, qErr := queryHelper(t, keeper, ctx, contractAddress, `{"get":{}}`, true, true, math.MaxUint64)
+	require.Empty(t, qErr)
+
+	var resp v1QueryResponse
+	e := json.Unmarshal([]byte(queryRes), &resp)
+	require.NoError(t, e)
+	require.Equal(t, uint32(16), resp.Get.Count)
+}
+
+func TestExecuteBatchMatchesSequentialExecute(t *testing.T) {
+	ctx, keeper, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, TestContractPaths[v1Contract], sdk.NewCoins())
+
+	_, _, contractAddress, _, _ := initHelper(t, keeper, ctx, codeID, walletA, nil, privKeyA, `{"counter":{"counter":10, "expires":100}}`, true, true, defaultGasForTests)
+
+	msgsBz, batch := prepareBatch(t, codeHash, walletA, []string{
+		`{"add_attributes":{}}`,
+		`{"increment":{"addition":1}}`,
+		`{"add_attributes_with_submessage":{"id":0}}`,
+		`{"increment":{"addition":2}}`,
+		`{"add_attributes":{}}`,
+	})
+
+	ctx = ctx.WithGasMeter(sdk.NewGasMeter(math.MaxUint64))
+	ctx = PrepareExecSignedTxWithMultipleMsgs(t, keeper, ctx, walletA, privKeyA, msgsBz, contractAddress, sdk.NewCoins())
+
+	sequentialCtx, _ := ctx.CacheContext()
+	sequentialCtx = sequentialCtx.WithEventManager(sdk.NewEventManager())
+	var sequentialData [][]byte
+	for _, msg := range batch {
+		res, err := keeper.Execute(sequentialCtx, contractAddress, msg.Caller, msg.Msg, msg.Coins, msg.CallbackSig, msg.HandleType)
+		require.NoError(t, err)
+		sequentialData = append(sequentialData, res.Data)
+	}
+
+	// the message that dispatches a submessage ends the first batch
+	batchCtx, _ := ctx.CacheContext()
+	batchCtx = batchCtx.WithEventManager(sdk.NewEventManager())
+	var batchData [][]byte
+	var batchSizes []int
+	for rest := batch; len(rest) > 0; {
+		results, err := keeper.ExecuteBatch(batchCtx, contractAddress, rest)
+		require.NoError(t, err)
+		for _, res := range results {
+			batchData = append(batchData, res.Data)
+		}
+		batchSizes = append(batchSizes, len(results))
+		rest = rest[len(results):]
+	}
+	require.Equal(t, []int{3, 2}, batchSizes)
+
+	require.Equal(t, sequentialData, batchData)
+	require.Equal(t, sequentialCtx.EventManager().Events(), batchCtx.EventManager().Events())
+
+	for _, c := range []sdk.Context{sequentialCtx, batchCtx} {
+		queryRes, qErr := queryHelper(t, keeper, c, contractAddress, `{"get":{}}`, true, true, math.MaxUint64)
+		require.Empty(t, qErr)
+
+		var resp v1QueryResponse
+		e := json.Unmarshal([]byte(queryRes), &resp)
+		require.NoError(t, e)
+		require.Equal(t, uint32(13), resp.Get.Count)
+	}
+}
+
+func TestDispatchedExecutesRunInBatch(t *testing.T) {
+	ctx, keeper, codeID, _, walletA, privKeyA, _, _ := setupTest(t, TestContractPaths[v1Contract], sdk.NewCoins())
+
+	_, _, contractAddress, _, _ := initHelper(t, keeper, ctx, codeID, walletA, nil, privKeyA, `{"counter":{"counter":10, "expires":100}}`, true, true, defaultGasForTests)
+
+	keeper.SetEngineParams(ctx, types.DefaultEngineParams())
+
+	// the 5 increments the contract dispatches to itself are charged like 5 executes
+	_, err := execTxBuilderImpl(t, keeper, ctx, contractAddress, walletA, privKeyA, []string{`{"increment_times":{"times":5}}`}, true, true, math.MaxUint64, sdk.NewCoins(), 6)
+	require.Nil(t, err)
+
+	queryRes, qErr := queryHelper(t, keeper, ctx, contractAddress, `{"get":{}}`, true, true, math.MaxUint64)
+	require.Empty(t, qErr)
+
+	var resp v1QueryResponse
+	e := json.Unmarshal([]byte(queryRes), &resp)
+	require.NoError(t, e)
+	require.Equal(t, uint32(15), resp.Get.Count)
+}
+
 func TestIBCHooksIncomingTransfer(t *testing.T) {
 	for _, test := range []struct {
 		name string
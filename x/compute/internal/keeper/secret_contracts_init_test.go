@@ -521,6 +521,28 @@ func TestWasmTooHighInitialMemoryRuntimeFail(t *testing.T) {
 	require.Contains(t, err.GenericErr.Msg, "failed to initialize wasm memory")
 }
 
+func TestWasmTooHighInitialMemoryStoreFail(t *testing.T) {
+	encodingConfig := MakeEncodingConfig()
+	var transferPortSource types.ICS20TransferPortSource
+	transferPortSource = MockIBCTransferKeeper{GetPortFn: func(ctx sdk.Context) string {
+		return "myTransferPort"
+	}}
+	encoders := DefaultEncoders(transferPortSource, encodingConfig.Marshaler)
+	ctx, keepers := CreateTestInput(t, false, SupportedFeatures, &encoders, nil)
+	accKeeper, keeper := keepers.AccountKeeper, keepers.WasmKeeper
+
+	walletA, _ := CreateFakeFundedAccount(ctx, accKeeper, keeper.bankKeeper, sdk.NewCoins(sdk.NewInt64Coin("denom", 1)))
+
+	keeper.SetEngineParams(ctx, types.DefaultEngineParams())
+
+	wasmCode, err := os.ReadFile(TestContractPaths[tooHighMemoryContract])
+	require.NoError(t, err)
+
+	_, err = keeper.Create(ctx, walletA, wasmCode, "", "")
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "failed to initialize wasm memory")
+}
+
 func TestWasmTooHighInitialMemoryStaticFail(t *testing.T) {
 	encodingConfig := MakeEncodingConfig()
 	var transferPortSource types.ICS20TransferPortSource
@@ -553,6 +575,28 @@ func TestWasmWithFloatingPoints(t *testing.T) {
 	}
 }
 
+func TestWasmWithFloatingPointsStoreFail(t *testing.T) {
+	encodingConfig := MakeEncodingConfig()
+	var transferPortSource types.ICS20TransferPortSource
+	transferPortSource = MockIBCTransferKeeper{GetPortFn: func(ctx sdk.Context) string {
+		return "myTransferPort"
+	}}
+	encoders := DefaultEncoders(transferPortSource, encodingConfig.Marshaler)
+	ctx, keepers := CreateTestInput(t, false, SupportedFeatures, &encoders, nil)
+	accKeeper, keeper := keepers.AccountKeeper, keepers.WasmKeeper
+
+	walletA, _ := CreateFakeFundedAccount(ctx, accKeeper, keeper.bankKeeper, sdk.NewCoins(sdk.NewInt64Coin("denom", 1)))
+
+	keeper.SetEngineParams(ctx, types.DefaultEngineParams())
+
+	wasmCode, err := os.ReadFile(TestContractPaths[v010WithFloats])
+	require.NoError(t, err)
+
+	_, err = keeper.Create(ctx, walletA, wasmCode, "", "")
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "found floating point operation in module code")
+}
+
 func TestCodeHashInvalid(t *testing.T) {
 	for _, testContract := range testContracts {
 		t.Run(testContract.CosmWasmVersion, func(t *testing.T) {
@@ -14,3 +14,10 @@ func CallDepth(ctx sdk.Context) (uint32, bool) {
 	val, ok := ctx.Value(contextKeyCallDepth).(uint32)
 	return val, ok
 }
+
+// ContractCallDepth is the depth of a contract call made with ctx, as carried in its env: 1 for
+// calls that come straight from a tx, and one more for every submessage and reply on the way.
+func ContractCallDepth(ctx sdk.Context) uint32 {
+	depth, _ := CallDepth(ctx)
+	return depth + 1
+}
@@ -21,8 +21,30 @@ const (
 	defaultLRUCacheSize        = uint64(0)
 	defaultEnclaveLRUCacheSize = uint16(100)
 	defaultQueryGasLimit       = uint64(10_000_000)
+	defaultQueryEnclaveSlots   = uint8(0)
+
+	// DefaultMaxContractMemoryPages is the most wasm memory a contract may use, in 64 KiB pages (12 MiB),
+	// under the engine params of DefaultEngineParams. It's consensus critical, so it's part of the
+	// chain's state rather than of the node's config file.
+	DefaultMaxContractMemoryPages = uint32(192)
+
+	// DefaultMaxContractCallDepth is the deepest contract call the enclave runs under the engine
+	// params of DefaultEngineParams. A call that comes straight from a tx or a query is at depth 1,
+	// and every submessage, reply and query is one deeper than the call that caused it. Before the
+	// chain set its engine params, only nested queries were held to a limit of 10 in the enclave,
+	// and executes, submessages and replies only to DefaultMaxCallDepth.
+	DefaultMaxContractCallDepth = uint32(10)
 )
 
+// DefaultEngineParams are the engine params the chain sets in the upgrade handler that first
+// enforces them, and that new chains start with.
+func DefaultEngineParams() *wasmTypes.EngineParams {
+	return &wasmTypes.EngineParams{
+		MaxMemoryPages: DefaultMaxContractMemoryPages,
+		MaxCallDepth:   DefaultMaxContractCallDepth,
+	}
+}
+
 func (m Model) ValidateBasic() error {
 	if len(m.Key) == 0 {
 		return sdkerrors.Wrap(ErrEmpty, "key")
@@ -146,7 +168,7 @@ func NewEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contract
 		Contract: wasmTypes.ContractInfo{
 			Address: contractAddr.String(),
 		},
-		QueryDepth: 1,
+		CallDepth: ContractCallDepth(ctx),
 	}
 
 	env.Key = wasmTypes.ContractKey{
@@ -245,6 +267,7 @@ type WasmConfig struct {
 	SmartQueryGasLimit uint64
 	CacheSize          uint64
 	EnclaveCacheSize   uint16
+	QueryEnclaveSlots  uint8
 }
 
 // DefaultWasmConfig returns the default settings for WasmConfig
@@ -253,6 +276,7 @@ func DefaultWasmConfig() *WasmConfig {
 		SmartQueryGasLimit: defaultQueryGasLimit,
 		CacheSize:          defaultLRUCacheSize,
 		EnclaveCacheSize:   defaultEnclaveLRUCacheSize,
+		QueryEnclaveSlots:  defaultQueryEnclaveSlots,
 	}
 }
 
@@ -306,6 +330,11 @@ func GetConfig(appOpts servertypes.AppOptions) *WasmConfig {
 		config.EnclaveCacheSize = enclaveCacheSize
 	}
 
+	queryEnclaveSlots := cast.ToUint8(appOpts.Get("wasm.contract-query-enclave-slots"))
+	if queryEnclaveSlots > 0 {
+		config.QueryEnclaveSlots = queryEnclaveSlots
+	}
+
 	return config
 }
 
@@ -322,6 +351,12 @@ contract-memory-cache-size = "{{ .WASMConfig.CacheSize }}"
 
 # The WASM VM memory cache size in number of cached modules. Can safely go up to 15, but not recommended for validators
 contract-memory-enclave-cache-size = "{{ .WASMConfig.EnclaveCacheSize }}"
+
+# How many of the enclave's TCS (the threads that may be inside the enclave at once, TCSNum in its
+# config) are reserved for queries, so heavy query load can't starve block execution. At least one
+# is always left for transactions. 0 lets queries share all TCS with transactions. Recommended for
+# RPC nodes.
+contract-query-enclave-slots = "{{ .WASMConfig.QueryEnclaveSlots }}"
 `
 
 // ZeroSender is a valid 20 byte canonical address that's used to bypass the x/compute checks